@@ -6,4 +6,9 @@ fn main() {
 
     let profile = env::var("PROFILE").unwrap();
     println!("cargo:rustc-env=PROFILE={profile}");
+
+    // The public key to verify toolset manifests, check `manifest::trusted_public_keys`.
+    println!("cargo:rerun-if-env-changed=MANIFEST_PUBLIC_KEY");
+    // The default update root of `manager`, check `update::update_root`.
+    println!("cargo:rerun-if-env-changed=MANAGER_UPDATE_ROOT");
 }
@@ -1,3 +1,4 @@
 export * from './types/Component';
+export * from './types/PreflightReport';
 export * from './installConf';
 export * from './invokeCommand';
@@ -1,5 +1,9 @@
 import { ref, Ref } from 'vue';
-import type { Component, TauriComponent } from './types/Component';
+import type {
+  Component,
+  ExistingToolChoice,
+  TauriComponent,
+} from './types/Component';
 import { invokeCommand } from './invokeCommand';
 
 class InstallConf {
@@ -56,7 +60,13 @@ class InstallConf {
     return this.checkComponents.value
       .filter((i) => i.checked) // 筛选选中组件
       .map((item: CheckItem<Component>) => {
-        const { groupName, isToolchainComponent, desc, ...rest } = item.value;
+        const {
+          groupName,
+          isToolchainComponent,
+          desc,
+          existingChoice,
+          ...rest
+        } = item.value;
         return {
           ...rest,
           desc: desc.join(''),
@@ -66,6 +76,19 @@ class InstallConf {
       });
   }
 
+  // 已存在于本机的选中组件的处理方式，未选择的默认复用
+  getExistingChoices(): Record<string, ExistingToolChoice> {
+    return this.checkComponents.value
+      .filter((i) => i.checked && i.value.existing)
+      .reduce(
+        (acc, item) => {
+          acc[item.value.name] = item.value.existingChoice ?? 'reuse';
+          return acc;
+        },
+        {} as Record<string, ExistingToolChoice>
+      );
+  }
+
   async loadPath() {
     const defaultPath = await invokeCommand('default_install_dir');
     if (typeof defaultPath === 'string' && defaultPath.trim() !== '') {
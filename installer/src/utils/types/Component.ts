@@ -1,14 +1,25 @@
+export interface ExistingTool {
+  name: string;
+  path: string | null;
+  version: string | null;
+}
+
+/** What to do with a component that already exists on this machine. */
+export type ExistingToolChoice = 'reuse' | 'skip' | 'install';
+
 interface OriginComponent {
   id: number;
   name: string;
   required: boolean;
   optional: boolean;
   installed: boolean;
+  existing: ExistingTool | null;
 }
 export interface Component extends OriginComponent {
   desc: string[];
   groupName: string | null;
   isToolchainComponent: boolean;
+  existingChoice?: ExistingToolChoice;
 }
 
 export interface TauriComponent extends OriginComponent {
@@ -0,0 +1,20 @@
+import type { ExistingTool } from './Component';
+
+export interface DetectedProxy {
+  source: 'env' | 'system';
+  http: string | null;
+  https: string | null;
+  no_proxy: string | null;
+}
+
+export interface PreflightReport {
+  os: string;
+  os_version: string | null;
+  arch: string;
+  install_dir: string;
+  free_space: number | null;
+  writable: boolean;
+  existing_rust: ExistingTool[];
+  existing_tools: ExistingTool[];
+  proxy: DetectedProxy | null;
+}
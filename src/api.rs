@@ -0,0 +1,360 @@
+//! The library interface for frontends such as the GUI installer, which runs the same
+//! installation pipeline as the command line interface, taking and returning plain
+//! serializable types, so that a frontend does not need to know the internals of this crate.
+//!
+//! A typical frontend [lists the components](list_components) for the user to choose from,
+//! then [installs](install) the chosen ones while receiving the [`ProgressEvent`]s
+//! through a [`ProgressReporter`], such as a channel [`Sender`](std::sync::mpsc::Sender).
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::core::install::InstallConfiguration;
+use crate::core::lock::InstallLock;
+use crate::core::report::{ExistingTool, ExistingToolChoice, PreInstallReport};
+use crate::core::session::InstallSession;
+use crate::core::uninstall::UninstallConfiguration;
+use crate::manifest::{
+    add_requirements, baked_in_manifest, set_install_dir_var, Platform, ToolInfo, ToolMap,
+    ToolSelection, ToolsetManifest,
+};
+use crate::utils::{self, MultiThreadProgress, ProgressReporter};
+
+/// A component that can be chosen to install, which is either the Rust toolchain itself,
+/// an optional component of the toolchain, or a tool in the toolset manifest.
+///
+/// If you change any fields in this struct,
+/// make sure to change `installer/src/utils/types/Component.ts` as well.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Component {
+    /// A number identifying this component in the list, starting from `1`.
+    pub id: u32,
+    pub group_name: Option<String>,
+    pub name: String,
+    pub desc: String,
+    /// Whether this component is always installed.
+    pub required: bool,
+    /// Whether this component is not chosen by default.
+    pub optional: bool,
+    pub tool_installer: Option<ToolInfo>,
+    pub is_toolchain_component: bool,
+    /// Indicates whether this component was already installed or not.
+    pub installed: bool,
+    /// The installation of this component found elsewhere, such as the one installed by the user.
+    pub existing: Option<ExistingTool>,
+}
+
+impl Component {
+    fn new(name: &str, desc: Option<&str>, group_name: &str) -> Self {
+        Self {
+            id: 0,
+            group_name: Some(group_name.to_string()),
+            name: name.to_string(),
+            desc: desc.unwrap_or_default().to_string(),
+            required: false,
+            optional: false,
+            tool_installer: None,
+            is_toolchain_component: false,
+            installed: false,
+            existing: None,
+        }
+    }
+}
+
+/// The components chosen to install, by their names.
+///
+/// The [required](Component::required) components are always installed,
+/// whether they are listed here or not.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentSelection {
+    /// The optional components of the Rust toolchain, such as `rust-src`.
+    #[serde(default)]
+    pub toolchain_components: Vec<String>,
+    /// The tools in the toolset manifest.
+    #[serde(default)]
+    pub tools: Vec<String>,
+}
+
+impl ComponentSelection {
+    /// Select the given components, such as the ones that the user checked
+    /// in the list returned by [`list_components`].
+    pub fn from_components<'a, I>(components: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Component>,
+    {
+        let mut selection = Self::default();
+        for component in components {
+            if component.tool_installer.is_some() {
+                selection.tools.push(component.name.clone());
+            } else if component.is_toolchain_component && !component.required {
+                selection.toolchain_components.push(component.name.clone());
+            }
+        }
+        selection
+    }
+}
+
+/// Everything needed to run an installation, check [`install`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallRequest {
+    /// Path to install everything.
+    pub install_dir: PathBuf,
+    #[serde(default)]
+    pub components: ComponentSelection,
+    /// What to do with the selected tools that already exist on this machine,
+    /// the ones without a choice are [reused](ExistingToolChoice::Reuse).
+    #[serde(default)]
+    pub existing: HashMap<String, ExistingToolChoice>,
+    /// Keep the installed files when the installation failed, so that it can be resumed
+    /// by installing into the same directory again, instead of rolling it back.
+    #[serde(default)]
+    pub keep_on_failure: bool,
+}
+
+/// How an [`install`] ended without errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstallOutcome {
+    /// Everything was installed.
+    Finished,
+    /// The installation was [suspended](utils::suspend), which can be resumed by
+    /// installing into the same directory again.
+    Suspended,
+}
+
+/// Everything needed to run an uninstallation, check [`uninstall`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UninstallRequest {
+    /// Path of the installation to uninstall.
+    pub install_dir: PathBuf,
+    /// The tools to uninstall, everything (including the installation directory)
+    /// is uninstalled if this is empty.
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// Copy the user data, such as the crates installed by the user, into this directory
+    /// before uninstalling everything.
+    #[serde(default)]
+    pub keep_user_data: Option<PathBuf>,
+}
+
+fn load_manifest() -> Result<ToolsetManifest> {
+    // TODO: Download manifest form remote server for online build
+    let mut manifest = baked_in_manifest()?;
+    manifest.adjust_paths()?;
+    manifest.apply_toolchain_flavor();
+    Ok(manifest)
+}
+
+/// List the components that can be installed on this machine, the first one of which
+/// is the Rust toolchain, followed by its optional components, then the tools.
+pub fn list_components() -> Result<Vec<Component>> {
+    Ok(components_of(&load_manifest()?))
+}
+
+fn components_of(manifest: &ToolsetManifest) -> Vec<Component> {
+    let toolchain_group = manifest.toolchain_group_name();
+    let profile = manifest.toolchain_profile().cloned().unwrap_or_default();
+    let profile_name = profile.verbose_name.as_deref().unwrap_or(&profile.name);
+    let mut toolchain = Component::new(
+        profile_name,
+        profile.description.as_deref(),
+        toolchain_group,
+    );
+    toolchain.is_toolchain_component = true;
+    toolchain.required = true;
+    let mut components = vec![toolchain];
+
+    for name in manifest.optional_toolchain_components() {
+        let mut component =
+            Component::new(name, manifest.get_tool_description(name), toolchain_group);
+        component.is_toolchain_component = true;
+        component.optional = true;
+        components.push(component);
+    }
+
+    let already_installed_tools = manifest.already_installed_tools();
+    if let Some(tools) = manifest.current_target_tools() {
+        let report = PreInstallReport::new(tools);
+        for (name, info) in tools {
+            // Don't present the tools that cannot be installed on this machine.
+            if let Some(reason) = info.platform().and_then(Platform::mismatch) {
+                tracing::debug!("skipping '{name}' as it does not apply to this machine: {reason}");
+                continue;
+            }
+            // Tools in a `[group.<name>]` are presented as a selectable set of that group.
+            let selection = manifest.tool_selection(name);
+            let group_name = manifest
+                .group_name(name)
+                .or(selection.map(|(group, _)| group));
+            let selection = selection.map(|(_, sel)| sel);
+            components.push(Component {
+                group_name: group_name.map(ToOwned::to_owned),
+                tool_installer: Some(info.clone()),
+                required: info.is_required() || selection == Some(ToolSelection::Required),
+                optional: info.is_optional() || selection == Some(ToolSelection::Optional),
+                installed: already_installed_tools.contains(&name),
+                existing: report.existing_tool(name).cloned(),
+                ..Component::new(name, manifest.get_tool_description(name), "")
+            });
+        }
+    }
+
+    for (id, component) in components.iter_mut().enumerate() {
+        component.id = id as u32 + 1;
+    }
+    components
+}
+
+/// Get the tools to install, which are the required ones and the `selected` ones,
+/// along with the ones they [require](ToolInfo::requirements).
+fn selected_tools(components: &[Component], selected: &[String]) -> Result<ToolMap> {
+    if let Some(unknown) = selected.iter().find(|name| {
+        !components
+            .iter()
+            .any(|c| c.tool_installer.is_some() && &c.name == *name)
+    }) {
+        bail!("'{unknown}' is not a tool that can be installed on this machine");
+    }
+    let available: ToolMap = components
+        .iter()
+        .filter_map(|c| Some((c.name.clone(), c.tool_installer.clone()?)))
+        .collect();
+    let mut names = components
+        .iter()
+        .filter(|c| c.required || selected.contains(&c.name))
+        .map(|c| c.name.clone())
+        .collect::<HashSet<_>>();
+    add_requirements(&available, &mut names);
+    Ok(available
+        .into_iter()
+        .filter(|(name, _)| names.contains(name))
+        .collect())
+}
+
+/// Run an installation as requested, every [`ProgressEvent`](utils::ProgressEvent)
+/// of it is sent to `reporter`.
+///
+/// This blocks until the installation is done, it can be [cancelled](utils::cancel) or
+/// [suspended](utils::suspend) from another thread. Once it failed or was cancelled,
+/// the changes made by it are rolled back, unless
+/// [`keep_on_failure`](InstallRequest::keep_on_failure) was set.
+pub fn install<R>(request: &InstallRequest, reporter: R) -> Result<InstallOutcome>
+where
+    R: ProgressReporter + 'static,
+{
+    let install_dir = request.install_dir.as_path();
+    set_install_dir_var(install_dir.to_path_buf());
+    let manifest = load_manifest()?;
+    if let Some(rate) = manifest.limit_rate {
+        utils::set_download_rate_limit(rate);
+    }
+    if let Some(ca_cert) = &manifest.ca_cert {
+        utils::set_download_ca_cert(ca_cert)?;
+    }
+    let components = components_of(&manifest);
+    let mut tools = selected_tools(&components, &request.components.tools)?;
+    let existing_tools = PreInstallReport::new(&tools).apply(&request.existing, &mut tools);
+
+    let _reporter = utils::scoped_progress_reporter(reporter);
+    utils::reset_cancellation();
+    let _lock = InstallLock::acquire(install_dir, false)?;
+    let mut config = InstallConfiguration::init(install_dir, Some(&manifest), false)?
+        .existing_tools(existing_tools);
+    utils::set_log_file(&install_dir.join("logs").join("install.log"))?;
+
+    let mut progress = MultiThreadProgress::default();
+    let result = InstallSession::new(&mut config, &manifest)
+        .tools(&tools)
+        .optional_components(Some(request.components.toolchain_components.clone()))
+        .run_all(&mut progress);
+    if let Err(e) = result {
+        if utils::is_suspended() {
+            config.suspend()?;
+            return Ok(InstallOutcome::Suspended);
+        }
+        if request.keep_on_failure {
+            config.suspend()?;
+        } else if let Err(rollback_err) = config.rollback() {
+            tracing::error!("unable to roll back installation: {rollback_err:?}");
+        }
+        return Err(e);
+    }
+    config.finish_installation()?;
+    Ok(InstallOutcome::Finished)
+}
+
+/// Uninstall the requested tools of an installation, or the whole installation.
+///
+/// This blocks until the uninstallation is done, while holding the
+/// [lock](InstallLock) of the installation directory.
+pub fn uninstall(request: &UninstallRequest) -> Result<()> {
+    let _lock = InstallLock::acquire(&request.install_dir, false)?;
+    let mut config = UninstallConfiguration::init_at(&request.install_dir)?;
+    if request.tools.is_empty() {
+        config.uninstall_all(request.keep_user_data.as_deref())
+    } else {
+        config.uninstall_tools(&request.tools)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn select_components() {
+        let tool = |name: &str, required: bool| Component {
+            tool_installer: Some(ToolInfo::PlainVersion("1.0.0".into())),
+            required,
+            ..Component::new(name, None, "Tools")
+        };
+        let mut toolchain = Component::new("minimal", None, "Rust");
+        toolchain.is_toolchain_component = true;
+        toolchain.required = true;
+        let mut rust_src = Component::new("rust-src", None, "Rust");
+        rust_src.is_toolchain_component = true;
+        let components = [toolchain, rust_src, tool("foo", true), tool("bar", false)];
+
+        let selection = ComponentSelection::from_components(&components[1..]);
+        assert_eq!(selection.toolchain_components, ["rust-src"]);
+        assert_eq!(selection.tools, ["foo", "bar"]);
+
+        // Required tools are installed even if they are not selected.
+        let tools = selected_tools(&components, &[]).unwrap();
+        assert_eq!(tools.keys().collect::<Vec<_>>(), ["foo"]);
+        let tools = selected_tools(&components, &["bar".into()]).unwrap();
+        assert_eq!(tools.keys().collect::<Vec<_>>(), ["foo", "bar"]);
+        assert!(selected_tools(&components, &["rust-src".into()]).is_err());
+
+        // The tools required by the selected ones are installed as well.
+        let requiring = Component {
+            tool_installer: Some(toml::from_str("ver = '1.0.0'\nrequires = ['bar']").unwrap()),
+            ..Component::new("baz", None, "Tools")
+        };
+        let components = [tool("foo", true), tool("bar", false), requiring];
+        let tools = selected_tools(&components, &["baz".into()]).unwrap();
+        assert_eq!(tools.keys().collect::<Vec<_>>(), ["foo", "bar", "baz"]);
+    }
+
+    #[test]
+    fn deserialize_request() {
+        let request: InstallRequest = serde_json::from_str(
+            r#"{
+                "install_dir": "/opt/rust",
+                "components": { "tools": ["vscode"] },
+                "existing": { "vscode": "skip" }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(request.install_dir, Path::new("/opt/rust"));
+        assert_eq!(request.components.tools, ["vscode"]);
+        assert!(request.components.toolchain_components.is_empty());
+        assert_eq!(request.existing["vscode"], ExistingToolChoice::Skip);
+        assert!(!request.keep_on_failure);
+    }
+}
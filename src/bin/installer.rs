@@ -1,11 +1,21 @@
-use anyhow::Result;
+use std::process::ExitCode;
+
 use clap::Parser;
 use custom_rust::{cli, utils};
 
-fn main() -> Result<()> {
-    match utils::lowercase_program_name() {
+fn main() -> ExitCode {
+    // Shims of the installed tools are copies of this program, check `run_if_shim`.
+    custom_rust::run_if_shim();
+
+    let result = match utils::lowercase_program_name() {
         Some(s) if s.starts_with("manager") => cli::Manager::parse().execute(),
         // Every thing else will fallback to installer mode
         _ => cli::Installer::parse().execute(),
+    };
+    // The error was already reported by `execute`.
+    if result.is_ok() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
     }
 }
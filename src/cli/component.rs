@@ -0,0 +1,81 @@
+//! Separated module to handle adding and removing individual tools in command line.
+
+use super::{ComponentCommand, GlobalOpt, ManagerSubcommands};
+use crate::core::os::install_dir_from_exe_path;
+use crate::core::parser::fingerprint::InstallationRecord;
+use crate::core::session::{InstallSession, SessionStep};
+use crate::manifest::{add_requirements, baked_in_manifest, ToolMap};
+use crate::utils::{self, MultiThreadProgress, OutputEvent};
+
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+
+/// Execute `component` command.
+pub(super) fn execute(subcommand: &ManagerSubcommands, _opt: GlobalOpt) -> Result<()> {
+    let ManagerSubcommands::Component { command } = subcommand else {
+        return Ok(());
+    };
+
+    match command {
+        ComponentCommand::Add { names } => add_components(names, false),
+        ComponentCommand::Remove { names } => super::uninstall::uninstall_tools(names),
+    }
+}
+
+/// Install the tools listed in the built-in toolset manifest into current installation,
+/// the installed ones are reinstalled if `force` is `true`.
+pub(super) fn add_components(names: &[String], force: bool) -> Result<()> {
+    let install_dir = install_dir_from_exe_path()?;
+    let record = InstallationRecord::load_or_new(&install_dir)?;
+    crate::core::os::set_system_wide(record.system);
+    crate::core::os::ensure_privileges()?;
+    let mut manifest = baked_in_manifest()?;
+    manifest.adjust_paths()?;
+    manifest.apply_toolchain_flavor();
+
+    let available = manifest.current_target_tools().cloned().unwrap_or_default();
+    let unknown = names
+        .iter()
+        .filter(|name| !available.contains_key(name.as_str()))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        bail!(
+            "the following tool(s) are not available for current platform: {}",
+            unknown.join(", ")
+        );
+    }
+    // Along with the tools they require, unless those are installed already.
+    let mut selected = names.iter().cloned().collect::<HashSet<_>>();
+    add_requirements(&available, &mut selected);
+    selected.retain(|name| names.contains(name) || !record.tools.contains_key(name));
+    let tools: ToolMap = available
+        .into_iter()
+        .filter(|(name, _)| selected.contains(name))
+        .collect();
+
+    let mut config = super::update::install_config(&install_dir, &record)?.force(force);
+    let mut progress = MultiThreadProgress::default();
+    utils::cancel_on_ctrl_c();
+    let mut session = InstallSession::new(&mut config, &manifest).tools(&tools);
+    let result = session
+        .run_step(SessionStep::InstallTools, &mut progress)
+        .and_then(|()| session.run_step(SessionStep::CargoInstall, &mut progress));
+    if let Err(e) = result {
+        OutputEvent::Message {
+            text: "failed to add components, rolling back...",
+        }
+        .emit();
+        if let Err(rollback_err) = config.rollback() {
+            tracing::warn!("unable to roll back: {rollback_err:?}");
+        }
+        return Err(e);
+    }
+    config.finish_installation()?;
+    OutputEvent::Message {
+        text: &format!("successfully added {}", names.join(", ")),
+    }
+    .emit();
+    Ok(())
+}
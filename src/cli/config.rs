@@ -0,0 +1,102 @@
+//! Separated module to view and change the configurations of current installation in command line.
+
+use super::{ConfigCommand, ConfigKey, GlobalOpt, ManagerSubcommands};
+use crate::core::os::{self, install_dir_from_exe_path};
+use crate::core::parser::fingerprint::InstallationRecord;
+use crate::core::{RUSTUP_DIST_SERVER, RUSTUP_UPDATE_ROOT};
+use crate::utils::OutputEvent;
+
+use anyhow::{Context, Result};
+use url::Url;
+
+impl ConfigKey {
+    const ALL: [Self; 5] = [
+        Self::DistServer,
+        Self::UpdateRoot,
+        Self::HttpProxy,
+        Self::HttpsProxy,
+        Self::NoProxy,
+    ];
+
+    /// The name used in command line, such as `dist-server`.
+    fn name(self) -> &'static str {
+        match self {
+            Self::DistServer => "dist-server",
+            Self::UpdateRoot => "update-root",
+            Self::HttpProxy => "http-proxy",
+            Self::HttpsProxy => "https-proxy",
+            Self::NoProxy => "no-proxy",
+        }
+    }
+
+    /// The environment variable that stores this configuration.
+    fn env_var(self) -> &'static str {
+        match self {
+            Self::DistServer => RUSTUP_DIST_SERVER,
+            Self::UpdateRoot => RUSTUP_UPDATE_ROOT,
+            Self::HttpProxy => "http_proxy",
+            Self::HttpsProxy => "https_proxy",
+            Self::NoProxy => "no_proxy",
+        }
+    }
+
+    /// Whether the value of this configuration should be a url.
+    fn is_url(self) -> bool {
+        !matches!(self, Self::NoProxy)
+    }
+}
+
+/// Execute `config` command.
+pub(super) fn execute(subcommand: &ManagerSubcommands, _opt: GlobalOpt) -> Result<()> {
+    let ManagerSubcommands::Config { command } = subcommand else {
+        return Ok(());
+    };
+
+    match command {
+        None | Some(ConfigCommand::Show) => {
+            let install_dir = install_dir_from_exe_path()?;
+            let record = InstallationRecord::load_or_new(&install_dir)?;
+            os::set_system_wide(record.system);
+            for key in ConfigKey::ALL {
+                // Show what's persisted instead of the environment of this process,
+                // as a change is not visible there until the terminal is restarted.
+                // The values of older installations are not in the record.
+                let val = record
+                    .env_values
+                    .get(key.env_var())
+                    .cloned()
+                    .or_else(|| os::persistent_env_var(&install_dir, key.env_var()));
+                OutputEvent::Config {
+                    key: key.name(),
+                    value: val.as_deref().filter(|val| !val.is_empty()),
+                }
+                .emit();
+            }
+            Ok(())
+        }
+        Some(ConfigCommand::Set { key, value }) => {
+            if key.is_url() {
+                Url::parse(value)
+                    .with_context(|| format!("invalid value of `{}`: '{value}'", key.name()))?;
+            }
+            set_config(*key, Some(value))
+        }
+        Some(ConfigCommand::Unset { key }) => set_config(*key, None),
+    }
+}
+
+fn set_config(key: ConfigKey, value: Option<&str>) -> Result<()> {
+    let install_dir = install_dir_from_exe_path()?;
+    let record = InstallationRecord::load_or_new(&install_dir)?;
+    os::set_system_wide(record.system);
+    os::ensure_privileges()?;
+    os::set_env_var(&install_dir, key.env_var(), value)?;
+    OutputEvent::Message {
+        text: &format!(
+            "`{}` is updated, restart your terminal for the change to take effect",
+            key.name()
+        ),
+    }
+    .emit();
+    Ok(())
+}
@@ -0,0 +1,50 @@
+//! Separated module to view or switch the default version of a tool in command line.
+
+use super::{GlobalOpt, ManagerSubcommands};
+use crate::core::os::install_dir_from_exe_path;
+use crate::core::parser::fingerprint::InstallationRecord;
+use crate::core::tool_versions;
+use crate::utils::OutputEvent;
+
+use anyhow::{bail, Result};
+
+/// Execute `default` command.
+pub(super) fn execute(subcommand: &ManagerSubcommands, _opt: GlobalOpt) -> Result<()> {
+    let ManagerSubcommands::Default { name, version } = subcommand else {
+        return Ok(());
+    };
+
+    let install_dir = install_dir_from_exe_path()?;
+    let Some(version) = version else {
+        let record = InstallationRecord::load_or_new(&install_dir)?;
+        let Some(tool) = record.tools.get(name) else {
+            bail!("'{name}' is not installed");
+        };
+        for installed in &tool.versions {
+            let is_default = tool.version.as_ref() == Some(installed);
+            OutputEvent::Message {
+                text: &format!("{installed}{}", if is_default { " (default)" } else { "" }),
+            }
+            .emit();
+        }
+        if tool.versions.is_empty() {
+            OutputEvent::Message {
+                text: &format!(
+                    "'{name}' has a single version: {}",
+                    tool.version.as_deref().unwrap_or("unknown")
+                ),
+            }
+            .emit();
+        }
+        return Ok(());
+    };
+
+    let mut record = InstallationRecord::load_or_new(&install_dir)?;
+    tool_versions::set_default(&mut record, name, version)?;
+    record.write()?;
+    OutputEvent::Message {
+        text: &format!("'{name}' {version} is now the default"),
+    }
+    .emit();
+    Ok(())
+}
@@ -0,0 +1,12 @@
+use super::{GlobalOpt, ManagerSubcommands};
+use crate::core::{doctor, os::install_dir_from_exe_path};
+use anyhow::Result;
+
+/// Execute `doctor` command.
+pub(super) fn execute(subcommand: &ManagerSubcommands, _opt: GlobalOpt) -> Result<()> {
+    let ManagerSubcommands::Doctor { offline } = subcommand else {
+        return Ok(());
+    };
+
+    doctor::check_installation(&install_dir_from_exe_path()?, *offline)
+}
@@ -0,0 +1,34 @@
+//! Separated module to export the environment of current installation in command line.
+
+use super::{GlobalOpt, ManagerSubcommands};
+use crate::core::env::{EnvShell, EnvSnapshot};
+use crate::core::os::install_dir_from_exe_path;
+use crate::manifest::baked_in_manifest;
+use crate::utils::{self, OutputEvent};
+use anyhow::Result;
+
+/// Execute `env` command.
+pub(super) fn execute(subcommand: &ManagerSubcommands, opt: GlobalOpt) -> Result<()> {
+    let ManagerSubcommands::Env = subcommand else {
+        return Ok(());
+    };
+
+    let snapshot =
+        EnvSnapshot::of_installation(&install_dir_from_exe_path()?, &baked_in_manifest()?)?;
+    if !utils::is_json_output() {
+        let shell = opt.format.env_shell().unwrap_or_else(EnvShell::detect);
+        println!("{}", snapshot.script(shell));
+        return Ok(());
+    }
+    for (key, value) in &snapshot.vars {
+        OutputEvent::EnvVar { key, value }.emit();
+    }
+    if let Some(path) = snapshot.path_var() {
+        OutputEvent::EnvVar {
+            key: "PATH",
+            value: &path.to_string_lossy(),
+        }
+        .emit();
+    }
+    Ok(())
+}
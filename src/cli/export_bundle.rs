@@ -0,0 +1,15 @@
+//! Separated module to export current installation as a bundle in command line.
+
+use super::{GlobalOpt, ManagerSubcommands};
+use crate::core::os::install_dir_from_exe_path;
+use crate::core::snapshot;
+use anyhow::Result;
+
+/// Execute `export-bundle` command.
+pub(super) fn execute(subcommand: &ManagerSubcommands, _opt: GlobalOpt) -> Result<()> {
+    let ManagerSubcommands::ExportBundle { path } = subcommand else {
+        return Ok(());
+    };
+
+    snapshot::export_bundle(&install_dir_from_exe_path()?, path)
+}
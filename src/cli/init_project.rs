@@ -0,0 +1,17 @@
+use super::{GlobalOpt, ManagerSubcommands};
+use crate::core::os::install_dir_from_exe_path;
+use crate::core::project;
+use anyhow::Result;
+
+/// Execute `init-project` command.
+pub(super) fn execute(subcommand: &ManagerSubcommands, _opt: GlobalOpt) -> Result<()> {
+    let ManagerSubcommands::InitProject { dir, force } = subcommand else {
+        return Ok(());
+    };
+
+    let dir = match dir {
+        Some(dir) => dir.clone(),
+        None => std::env::current_dir()?,
+    };
+    project::init_project(&install_dir_from_exe_path()?, &dir, *force)
+}
@@ -1,77 +1,609 @@
 //! Separated module to handle installation related behaviors in command line.
 
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
 use crate::core::install::{
-    default_rustup_dist_server, default_rustup_update_root, EnvConfig, InstallConfiguration,
+    default_rustup_dist_server, default_rustup_update_root, InstallConfiguration,
 };
+use crate::core::lock::InstallLock;
+use crate::core::offline::Bundle;
+use crate::core::os;
+use crate::core::parser::{answer_file::AnswerFile, ManifestParser};
+use crate::core::preflight::collect_preflight_report;
+use crate::core::report::{ExistingToolChoice, PreInstallReport};
+use crate::core::rustup::{find_existing_rustup, select_dist_server};
+use crate::core::session::InstallSession;
+use crate::core::snapshot;
 use crate::core::try_it;
-use crate::manifest::baked_in_manifest;
-use crate::utils;
+use crate::manifest::{
+    self, baked_in_manifest, set_install_dir_var, set_manifest_public_keys, Proxy, ToolMap,
+    ToolsetManifest,
+};
+use crate::utils::{self, MultiThreadProgress, OutputEvent};
 
 use super::Installer;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use url::Url;
 
 /// Perform installer actions.
 ///
 /// This will setup the environment and install everything user selected components.
-pub(super) fn execute_installer(installer: &Installer) -> Result<()> {
+///
+/// The options not given in command line are taken from `answers`, and nothing will be asked
+/// if an answer file was given.
+pub(super) fn execute_installer(installer: &Installer, answers: &AnswerFile) -> Result<()> {
     let Installer {
         prefix,
         registry_url,
         registry_name,
         rustup_dist_server,
         rustup_update_root,
+        no_cache,
+        jobs,
+        download_jobs,
+        force,
+        no_binstall,
+        existing,
+        temp_dir,
+        keep_on_failure,
+        force_unlock,
+        reuse_existing_rustup,
+        manifest: manifest_path,
+        manifest_key,
+        offline,
+        from_bundle,
+        profile,
+        group,
+        dry_run,
+        preflight,
+        yes_to_all,
+        answer_file,
         ..
     } = installer;
 
-    let cargo_registry = registry_url
-        .as_ref()
-        .map(|u| (registry_name.clone(), u.clone()));
+    let cargo_registry = match (registry_url, &answers.registry) {
+        (Some(url), _) => Some((registry_name.clone(), url.clone())),
+        (None, Some(registry)) => Some((registry.name.clone(), registry.url.clone())),
+        (None, None) => None,
+    };
     let install_dir = prefix
         .clone()
-        .unwrap_or_else(utils::home_dir)
+        .or_else(|| answers.prefix.clone())
+        .unwrap_or_else(os::default_install_root)
         .join(env!("CARGO_PKG_NAME"));
+    if *preflight {
+        OutputEvent::Preflight(&collect_preflight_report(&install_dir)).emit();
+        return Ok(());
+    }
+    if let Some(profile) = &answers.profile {
+        manifest::parse_toolchain_profile(profile)?;
+    }
+    let profile = profile.as_ref().or(answers.profile.as_ref());
+    let group = if group.is_empty() {
+        &answers.groups
+    } else {
+        group
+    };
+
+    if let Some(archive) = from_bundle {
+        let keep_on_failure = *keep_on_failure || answers.keep_on_failure;
+        return install_from_bundle(installer, &install_dir, archive, keep_on_failure);
+    }
 
     // TODO: Download manifest form remote server for online build
-    let mut manifest = baked_in_manifest()?;
+    set_manifest_public_keys(manifest_key.clone());
+    set_install_dir_var(install_dir.clone());
+    let bundle = offline.as_deref().map(Bundle::new).transpose()?;
+    let bundled_manifest = match &bundle {
+        Some(bundle) => bundle.manifest()?,
+        None => None,
+    };
+    let mut manifest = match (manifest_path, bundled_manifest) {
+        (Some(path), _) => match remote_manifest_url(path) {
+            Some(url) => {
+                // Nothing should be written in dry-run mode, not even the cached manifest.
+                let dry_run_cache = if *dry_run {
+                    Some(match temp_dir {
+                        Some(dir) => tempfile::tempdir_in(dir)?,
+                        None => tempfile::tempdir()?,
+                    })
+                } else {
+                    None
+                };
+                let cache_root = dry_run_cache
+                    .as_ref()
+                    .map_or(&*install_dir, |dir| dir.path());
+                let proxy = Proxy::detected();
+                ToolsetManifest::from_url(&url, cache_root, proxy.as_ref())?
+            }
+            None => ToolsetManifest::load(path)?,
+        },
+        (None, Some(manifest)) => manifest,
+        (None, None) => baked_in_manifest()?,
+    };
     manifest.adjust_paths()?;
-
-    let mut config = InstallConfiguration::init(&install_dir, false)?
-        .cargo_registry(cargo_registry)
-        .rustup_dist_server(
+    manifest.apply_toolchain_flavor();
+    if let Some(proxy) = &answers.proxy {
+        manifest.proxy = Some(proxy.clone());
+    }
+    // The `--limit-rate` option was already set, so this is ignored in that case.
+    if let Some(rate) = manifest.limit_rate {
+        utils::set_download_rate_limit(rate);
+    }
+    if let Some(ca_cert) = &manifest.ca_cert {
+        utils::set_download_ca_cert(ca_cert)?;
+    }
+    // Make sure nothing is missing before making any changes.
+    if let Some(bundle) = &bundle {
+        bundle.apply_to(&mut manifest)?;
+    }
+    let mut tools = manifest.tools_of_groups(group)?;
+    let (rustup_dist_server, rustup_update_root) = match &bundle {
+        Some(bundle) => (bundle.rustup_server()?, bundle.rustup_server()?),
+        None => (
             rustup_dist_server
                 .clone()
+                .or_else(|| answers.rustup_dist_server.clone())
+                .or_else(|| select_dist_server(&manifest))
                 .unwrap_or_else(|| default_rustup_dist_server().clone()),
-        )
-        .rustup_update_root(
             rustup_update_root
                 .clone()
+                .or_else(|| answers.rustup_update_root.clone())
                 .unwrap_or_else(|| default_rustup_update_root().clone()),
-        );
-    config.config_env_vars(&manifest)?;
-    config.config_cargo()?;
-
-    // This step taking cares of requirements, such as `MSVC`, also third-party app such as `VS Code`.
-    config.install_tools(&manifest)?;
-    config.install_rust(&manifest)?;
-    // install third-party tools via cargo that got installed by rustup
-    config.cargo_install(&manifest)?;
-
-    println!(
-        "Rust is installed, \
-        this setup will soon create an example project at current directory for you to try Rust!"
+        ),
+    };
+
+    let unattended = *yes_to_all || answer_file.is_some();
+    let interactive = !unattended && !utils::is_json_output() && io::stdin().is_terminal();
+    let report = PreInstallReport::new(&tools);
+    let answered_existing = answers
+        .existing
+        .iter()
+        .filter(|(name, _)| !existing.iter().any(|(n, _)| n == *name))
+        .map(|(name, choice)| (name.clone(), *choice));
+    let existing = existing
+        .iter()
+        .cloned()
+        .chain(answered_existing)
+        .collect::<Vec<_>>();
+    let existing_tools = report.apply(
+        &existing_tool_choices(&report, &existing, interactive, *force)?,
+        &mut tools,
     );
+    let components = match &answers.components {
+        Some(components) => Some(answered_components(&manifest, components)?),
+        None if interactive => select_optional_components(&manifest)?,
+        None => None,
+    };
+    let reuse_rustup = if *reuse_existing_rustup {
+        Some(true)
+    } else {
+        answers.reuse_existing_rustup.or(unattended.then_some(true))
+    };
+    let keep_on_failure = *keep_on_failure || answers.keep_on_failure;
+    let include_gitconfig = if manifest.git.is_empty() {
+        false
+    } else {
+        match answers.include_gitconfig.or(unattended.then_some(true)) {
+            Some(yes) => yes,
+            None if interactive => super::confirm(
+                "the toolset comes with git settings (such as proxy and URL rewrites), \
+                do you want to include them in your global git configuration?",
+                true,
+            )?,
+            None => false,
+        }
+    };
+
+    // Prevent other instances from modifying the same installation until this one is done.
+    let _lock = if *dry_run {
+        None
+    } else {
+        Some(InstallLock::acquire(&install_dir, *force_unlock)?)
+    };
+    let mut config = InstallConfiguration::init(&install_dir, Some(&manifest), *dry_run)?
+        .cargo_registry(cargo_registry)
+        .no_cache(*no_cache)
+        .force(*force)
+        .no_binstall(*no_binstall)
+        .include_gitconfig(include_gitconfig)
+        .existing_tools(existing_tools)
+        .rustup_dist_server(rustup_dist_server)
+        .rustup_update_root(rustup_update_root)
+        .temp_dir(temp_dir.clone())?
+        .reuse_rustup(existing_rustup_to_reuse(&install_dir, reuse_rustup)?)?;
+    if !*dry_run {
+        utils::set_log_file(&install_dir.join("logs").join("install.log"))?;
+    }
+    if let Some(jobs) = jobs {
+        config = config.cargo_install_jobs(*jobs);
+    }
+    if let Some(jobs) = download_jobs {
+        config = config.download_jobs(*jobs);
+    }
+
+    // Stop the installation on `Ctrl-C`, then roll it back or suspend it,
+    // instead of leaving it half done.
+    utils::cancel_on_ctrl_c();
+    if let Err(e) = install_(
+        &mut config,
+        &manifest,
+        &tools,
+        profile.map(String::as_str),
+        components.as_deref(),
+        *no_cache,
+    ) {
+        // Nothing was changed in dry-run mode.
+        if *dry_run {
+            return Err(e);
+        }
+        let question = if utils::is_cancelled() {
+            "installation cancelled, do you want to roll back the changes made by this installation? \
+            (otherwise it can be resumed by running this installer again)"
+        } else {
+            "installation failed, do you want to roll back the changes made by this installation?"
+        };
+        if keep_on_failure || utils::is_suspended() {
+            OutputEvent::Message {
+                text: "the installed files are kept as requested",
+            }
+            .emit();
+            suspend(&config);
+        } else if unattended || utils::is_json_output() || super::confirm(question, true)? {
+            OutputEvent::Message {
+                text: "rolling back installation...",
+            }
+            .emit();
+            utils::move_log_to_temp_dir();
+            if let Err(rollback_err) = config.rollback() {
+                OutputEvent::Error {
+                    text: &format!("unable to roll back installation: {rollback_err:?}"),
+                    code: crate::error_code(&rollback_err),
+                }
+                .emit();
+            }
+        } else {
+            suspend(&config);
+        }
+        return Err(e);
+    }
+    config.finish_installation()?;
+    if *dry_run {
+        return Ok(());
+    }
+
+    OutputEvent::Message {
+        text: "Rust is installed, \
+        this setup will soon create an example project at current directory for you to try Rust!",
+    }
+    .emit();
     try_it::try_it(Some(&install_dir))?;
 
     Ok(())
 }
 
+/// Install everything from a bundle exported by `manager export-bundle`,
+/// there's nothing to choose, since the bundle is a copy of an existing installation.
+fn install_from_bundle(
+    installer: &Installer,
+    install_dir: &Path,
+    archive: &Path,
+    keep_on_failure: bool,
+) -> Result<()> {
+    let dry_run = installer.dry_run;
+    let _lock = if dry_run {
+        None
+    } else {
+        Some(InstallLock::acquire(install_dir, installer.force_unlock)?)
+    };
+    let mut config = InstallConfiguration::init(install_dir, None, dry_run)?
+        .temp_dir(installer.temp_dir.clone())?;
+    if !dry_run {
+        utils::set_log_file(&install_dir.join("logs").join("install.log"))?;
+    }
+
+    utils::cancel_on_ctrl_c();
+    if let Err(e) = snapshot::install_from_bundle(&mut config, archive) {
+        if dry_run {
+            return Err(e);
+        }
+        if keep_on_failure {
+            OutputEvent::Message {
+                text: "the installed files are kept as requested",
+            }
+            .emit();
+        } else {
+            OutputEvent::Message {
+                text: "rolling back installation...",
+            }
+            .emit();
+            utils::move_log_to_temp_dir();
+            if let Err(rollback_err) = config.rollback() {
+                OutputEvent::Error {
+                    text: &format!("unable to roll back installation: {rollback_err:?}"),
+                    code: crate::error_code(&rollback_err),
+                }
+                .emit();
+            }
+        }
+        return Err(e);
+    }
+    config.finish_installation()?;
+    if !dry_run {
+        OutputEvent::Message {
+            text: &format!("Rust is installed from the bundle '{}'", archive.display()),
+        }
+        .emit();
+    }
+    Ok(())
+}
+
+/// Suspend the failed installation instead of rolling it back,
+/// so that running this installer again resumes it.
+fn suspend(config: &InstallConfiguration) {
+    match config.suspend() {
+        Ok(()) => OutputEvent::Message {
+            text: "the installation is suspended, \
+            run this installer again with the same installation directory to resume it",
+        }
+        .emit(),
+        Err(e) => tracing::warn!("unable to suspend the installation: {e:?}"),
+    }
+}
+
+/// Get the url of the manifest if the `--manifest` argument is an `http(s)` url.
+fn remote_manifest_url(arg: &Path) -> Option<Url> {
+    let url = Url::parse(arg.to_str()?).ok()?;
+    matches!(url.scheme(), "http" | "https").then_some(url)
+}
+
+/// Check if there is a `rustup` installed elsewhere, and decide whether to reuse it.
+///
+/// Ask the user about it unless `reuse` is already decided or output format is JSON,
+/// in the latter case, an error will be returned suggesting `--reuse-existing-rustup`.
+fn existing_rustup_to_reuse(install_dir: &Path, reuse: Option<bool>) -> Result<Option<PathBuf>> {
+    let Some(rustup) = find_existing_rustup(install_dir) else {
+        return Ok(None);
+    };
+    match reuse {
+        Some(true) => {
+            OutputEvent::Message {
+                text: &format!("reusing existing rustup at '{}'", rustup.display()),
+            }
+            .emit();
+            return Ok(Some(rustup));
+        }
+        Some(false) => {
+            OutputEvent::Message {
+                text: &format!(
+                    "installing another rustup instead of the existing one at '{}'",
+                    rustup.display()
+                ),
+            }
+            .emit();
+            return Ok(None);
+        }
+        None => (),
+    }
+    if utils::is_json_output() {
+        bail!(
+            "found an existing rustup at '{}', \
+            pass `--reuse-existing-rustup` to install Rust toolchain with it",
+            rustup.display()
+        );
+    }
+    if super::confirm(
+        &format!(
+            "found an existing rustup at '{}', do you want to use it to install Rust toolchain?",
+            rustup.display()
+        ),
+        true,
+    )? {
+        Ok(Some(rustup))
+    } else {
+        tracing::warn!(
+            "installing another rustup, \
+            the existing one might be shadowed depending on the order of `PATH`"
+        );
+        Ok(None)
+    }
+}
+
+/// Tell the user about the tools that were already installed elsewhere, then decide what to do
+/// with each of them, which is either given by `--existing`, or asked if `interactive`.
+/// With `--force`, the ones without a given choice are replaced by the bundled copies.
+///
+/// The missing system packages are reported as well, and the tools needing them are only
+/// installed if the user explicitly wants them to, which is never assumed when unattended.
+fn existing_tool_choices(
+    report: &PreInstallReport,
+    given: &[(String, ExistingToolChoice)],
+    interactive: bool,
+    force: bool,
+) -> Result<HashMap<String, ExistingToolChoice>> {
+    let mut choices = given.iter().cloned().collect::<HashMap<_, _>>();
+    for tool in &report.existing_tools {
+        let name = &tool.name;
+        let version = tool
+            .version
+            .as_deref()
+            .map(|ver| format!(" {ver}"))
+            .unwrap_or_default();
+        let location = tool
+            .path
+            .as_deref()
+            .map(|path| format!(" at '{}'", path.display()))
+            .unwrap_or_default();
+        let found = format!("found an existing '{name}'{version}{location}");
+
+        if choices.contains_key(name) {
+            OutputEvent::Message { text: &found }.emit();
+        } else if force {
+            OutputEvent::Message {
+                text: &format!("{found}, installing the bundled one as `--force` was given"),
+            }
+            .emit();
+            choices.insert(name.clone(), ExistingToolChoice::Install);
+        } else if interactive {
+            let reuse = super::confirm(
+                &format!("{found}, do you want to use it instead of installing the bundled one?"),
+                true,
+            )?;
+            let choice = if reuse {
+                ExistingToolChoice::Reuse
+            } else {
+                ExistingToolChoice::Install
+            };
+            choices.insert(name.clone(), choice);
+        } else {
+            OutputEvent::Message {
+                text: &format!(
+                    "{found}, reusing it (pass `--existing {name}=install` to install the bundled one)"
+                ),
+            }
+            .emit();
+        }
+    }
+
+    for missing in &report.missing_packages {
+        let name = &missing.tool;
+        let found = format!(
+            "'{name}' needs the following system packages: {}",
+            missing.packages.join(", ")
+        );
+        if choices.contains_key(name) {
+            OutputEvent::Message { text: &found }.emit();
+        } else if interactive {
+            let install = super::confirm(
+                &format!(
+                    "{found}\ndo you want to install them using `{}`? \
+                    (administrator privileges are required)",
+                    missing.command
+                ),
+                false,
+            )?;
+            let choice = if install {
+                ExistingToolChoice::Install
+            } else {
+                ExistingToolChoice::Skip
+            };
+            choices.insert(name.clone(), choice);
+        } else {
+            OutputEvent::Message {
+                text: &format!(
+                    "{found}, skipping '{name}' (pass `--existing {name}=install` to install them using `{}`)",
+                    missing.command
+                ),
+            }
+            .emit();
+        }
+    }
+    Ok(choices)
+}
+
+/// Let the user choose which of the optional toolchain components to install.
+///
+/// Return `None` if the manifest has no optional components,
+/// otherwise the selected ones, which are none by default.
+fn select_optional_components(manifest: &ToolsetManifest) -> Result<Option<Vec<String>>> {
+    let optional = manifest.optional_toolchain_components();
+    if optional.is_empty() {
+        return Ok(None);
+    }
+
+    println!("The following optional components of Rust toolchain are available:");
+    for (idx, name) in optional.iter().enumerate() {
+        match manifest.get_tool_description(name) {
+            Some(desc) => println!("  {}) [ ] {name} - {desc}", idx + 1),
+            None => println!("  {}) [ ] {name}", idx + 1),
+        }
+    }
+
+    loop {
+        print!(
+            "Enter the numbers of the components to install, separated by spaces or commas \
+            (press Enter to skip): "
+        );
+        io::stdout().flush()?;
+
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        match parse_selection(&answer, optional.len()) {
+            Ok(selected) => {
+                return Ok(Some(
+                    selected.into_iter().map(|i| optional[i].clone()).collect(),
+                ))
+            }
+            Err(e) => println!("{e}"),
+        }
+    }
+}
+
+/// Check the optional toolchain components given by the answer file,
+/// which must be the ones available in `manifest`.
+fn answered_components(manifest: &ToolsetManifest, components: &[String]) -> Result<Vec<String>> {
+    let optional = manifest.optional_toolchain_components();
+    if let Some(unknown) = components.iter().find(|c| !optional.contains(c)) {
+        bail!(
+            "'{unknown}' is not an optional component of the toolchain, \
+            expecting one of: {}",
+            optional.join(", ")
+        );
+    }
+    Ok(components.to_vec())
+}
+
+/// Parse a list of 1-based numbers separated by spaces or commas,
+/// and return the 0-based indexes without duplication.
+fn parse_selection(input: &str, count: usize) -> Result<Vec<usize>> {
+    let mut selected = vec![];
+    for item in input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+    {
+        match item.parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => {
+                if !selected.contains(&(n - 1)) {
+                    selected.push(n - 1);
+                }
+            }
+            _ => bail!("invalid selection '{item}', expecting numbers from 1 to {count}"),
+        }
+    }
+    Ok(selected)
+}
+
+/// Perform the installation steps that can be rolled back when any of them fails.
+fn install_(
+    config: &mut InstallConfiguration,
+    manifest: &ToolsetManifest,
+    tools: &ToolMap,
+    profile: Option<&str>,
+    optional_components: Option<&[String]>,
+    no_cache: bool,
+) -> Result<()> {
+    let mut progress = MultiThreadProgress::default();
+
+    InstallSession::new(config, manifest)
+        .tools(tools)
+        .profile(profile.map(ToOwned::to_owned))
+        .optional_components(optional_components.map(<[String]>::to_vec))
+        .run_all(&mut progress)?;
+    if !no_cache {
+        config.prune_download_cache(manifest)?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
-    use super::InstallConfiguration;
-    use crate::{core::parser::TomlParser, manifest::ToolsetManifest, utils};
+    use super::{parse_selection, remote_manifest_url, InstallConfiguration};
+    use crate::{core::parser::ManifestParser, manifest::ToolsetManifest, utils};
 
     #[test]
     fn dry_run() {
@@ -82,7 +614,10 @@ mod tests {
         std::fs::create_dir_all(&cache_dir).unwrap();
 
         let install_root = tempfile::Builder::new().tempdir_in(&cache_dir).unwrap();
-        let _config = InstallConfiguration::init(install_root.path(), true).unwrap();
+        let install_dir = install_root.path().join("custom_rust");
+        let config = InstallConfiguration::init(&install_dir, None, true).unwrap();
+        assert!(config.is_dry_run());
+        assert!(!install_dir.exists());
         let _manifest = ToolsetManifest::from_str(
             &utils::read_to_string(
                 PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/toolset_manifest.toml"),
@@ -91,4 +626,27 @@ mod tests {
         )
         .unwrap();
     }
+
+    #[test]
+    fn parse_component_selection() {
+        assert!(parse_selection("", 3).unwrap().is_empty());
+        assert_eq!(parse_selection("1 3", 3).unwrap(), vec![0, 2]);
+        assert_eq!(parse_selection(" 2,1, 2 ", 3).unwrap(), vec![1, 0]);
+        assert!(parse_selection("0", 3).is_err());
+        assert!(parse_selection("4", 3).is_err());
+        assert!(parse_selection("rust-src", 3).is_err());
+    }
+
+    #[test]
+    fn manifest_argument_as_url() {
+        assert_eq!(
+            remote_manifest_url(Path::new("https://example.com/toolset.toml"))
+                .unwrap()
+                .as_str(),
+            "https://example.com/toolset.toml"
+        );
+        assert!(remote_manifest_url(Path::new("/path/to/toolset.toml")).is_none());
+        assert!(remote_manifest_url(Path::new("toolset.toml")).is_none());
+        assert!(remote_manifest_url(Path::new("C:\\path\\to\\toolset.toml")).is_none());
+    }
 }
@@ -0,0 +1,83 @@
+use super::{GlobalOpt, ManagerSubcommands};
+use crate::core::custom_instructions;
+use crate::core::install::InstallConfiguration;
+use crate::core::os::install_dir_from_exe_path;
+use crate::core::parser::fingerprint::InstallationRecord;
+use crate::manifest::baked_in_manifest;
+use crate::utils::OutputEvent;
+use anyhow::Result;
+
+/// Execute `list` command.
+pub(super) fn execute(subcommand: &ManagerSubcommands, _opt: GlobalOpt) -> Result<()> {
+    let ManagerSubcommands::List { installed } = subcommand else {
+        return Ok(());
+    };
+    if *installed {
+        return list_installed();
+    }
+
+    let manifest = baked_in_manifest()?;
+    manifest.apply_toolchain_flavor();
+    let Some(tools) = manifest.current_target_tools() else {
+        return Ok(());
+    };
+    // The installation directory is only used to check if a tool was installed,
+    // which is not required for listing, so don't fail if it cannot be determined.
+    let config = install_dir_from_exe_path()
+        .ok()
+        .and_then(|dir| InstallConfiguration::init(&dir, None, true).ok());
+
+    for (name, tool) in tools {
+        let custom = custom_instructions::is_supported(name);
+        let installed = match &config {
+            Some(config) => config.is_tool_installed(name, tool),
+            None => custom && custom_instructions::already_installed(name),
+        };
+        OutputEvent::Tool {
+            name,
+            kind: tool.source_kind(),
+            version: tool.version(),
+            installed,
+            custom,
+        }
+        .emit();
+    }
+
+    Ok(())
+}
+
+/// List the toolchain and tools recorded in the installation record.
+fn list_installed() -> Result<()> {
+    let record = InstallationRecord::load_or_new(&install_dir_from_exe_path()?)?;
+    if let Some(rust) = &record.rust {
+        OutputEvent::Tool {
+            name: "rust",
+            kind: "toolchain",
+            version: Some(&rust.version),
+            installed: true,
+            custom: false,
+        }
+        .emit();
+        for toolchain in &rust.toolchains {
+            OutputEvent::Tool {
+                name: toolchain,
+                kind: "toolchain",
+                version: None,
+                installed: true,
+                custom: false,
+            }
+            .emit();
+        }
+    }
+    for (name, tool) in &record.tools {
+        OutputEvent::Tool {
+            name,
+            kind: tool.source.as_ref().map_or("-", |source| source.kind()),
+            version: tool.version.as_deref(),
+            installed: true,
+            custom: tool.instruction.is_some() || custom_instructions::is_supported(name),
+        }
+        .emit();
+    }
+    Ok(())
+}
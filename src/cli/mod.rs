@@ -1,23 +1,39 @@
 //! Contains all the definition of command line arguments.
 
+mod component;
+mod config;
+mod default;
+mod doctor;
+mod env;
+mod export_bundle;
+mod init_project;
 mod install;
+mod list;
 mod tryit;
 mod uninstall;
+mod update;
+mod verify;
 
-use anyhow::Result;
-use clap::{Parser, Subcommand, ValueHint};
+use anyhow::{bail, Context, Result};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum, ValueHint};
 use std::path::{Path, PathBuf};
 use url::Url;
 
+use crate::core::env::EnvShell;
+use crate::core::lock::InstallLock;
+use crate::core::parser::{answer_file::AnswerFile, ManifestParser};
+use crate::manifest;
+use crate::utils::{self, OutputEvent, OutputFormat};
+
 /// Install rustup, rust toolchain, and various tools.
 // NOTE: If you changed anything in this struct, or any other child types that related to
 // this struct, make sure the README doc is updated as well,
 #[derive(Parser, Default, Debug)]
 #[command(version, about)]
 pub struct Installer {
-    /// Enable verbose output
-    #[arg(short, long, conflicts_with = "quiet")]
-    pub verbose: bool,
+    /// Enable verbose output, pass twice (`-vv`) for even more details
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
     /// Suppress non-critical messages
     #[arg(short, long, conflicts_with = "verbose")]
     pub quiet: bool,
@@ -28,10 +44,112 @@ pub struct Installer {
     /// Don't show GUI when running the program.
     #[arg(long)]
     pub no_gui: bool,
+    /// Specify the format of output messages, `json` emits line-delimited JSON events.
+    #[arg(long, alias = "output", value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    /// Install unattended using the options and the answers of every prompt in a TOML file,
+    /// the options given in command line take precedence over it.
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub answer_file: Option<PathBuf>,
 
+    /// Print the installation plan without modifying the system.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Print the system requirements and the existing installations found on this machine,
+    /// then exit without installing anything.
+    #[arg(long, conflicts_with = "dry_run")]
+    pub preflight: bool,
     /// Set another path to install Rust.
     #[arg(long, value_name = "PATH", value_hint = ValueHint::DirPath)]
     pub prefix: Option<PathBuf>,
+    /// Install for all users of this machine, which requires administrator (root) privileges.
+    /// Default to install under `Program Files` on Windows, or `/opt` on other systems.
+    #[arg(long)]
+    pub system: bool,
+    /// Always download tools instead of using the previously downloaded files.
+    #[arg(long)]
+    pub no_cache: bool,
+    /// Maximum number of tools to install concurrently using `cargo install`,
+    /// default to the number of CPUs (up to 4).
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<usize>,
+    /// Maximum number of tools to download concurrently,
+    /// default to the number of CPUs (up to 4).
+    #[arg(long, value_name = "N")]
+    pub download_jobs: Option<usize>,
+    /// Reinstall tools even if they are already installed,
+    /// including the ones found elsewhere on this machine unless `--existing` says otherwise.
+    #[arg(long)]
+    pub force: bool,
+    /// Always build cargo tools from source using `cargo install`,
+    /// instead of fetching their prebuilt binaries using `cargo binstall` when it is available.
+    #[arg(long)]
+    pub no_binstall: bool,
+    /// What to do with a tool that was already installed elsewhere, in the form of
+    /// `<NAME>=<CHOICE>`, where choice is one of `reuse`, `skip` or `install`,
+    /// can be specified multiple times. Default to `reuse` (or `install` with `--force`),
+    /// except for the tools needing system packages, which are skipped unless set to `install`.
+    #[arg(long, value_name = "NAME=CHOICE", value_parser = crate::parse_existing_tool_choice)]
+    pub existing: Vec<(String, crate::ExistingToolChoice)>,
+    /// Limit the download speed in bytes per second, with optional suffix 'K', 'M' or 'G',
+    /// i.e. `500K`. `0` means unlimited.
+    #[arg(long, value_name = "RATE", value_parser = utils::parse_rate_limit)]
+    pub limit_rate: Option<u64>,
+    /// Timeout of each download request in seconds, a mirror is skipped once timed out.
+    /// Default to 30 seconds.
+    #[arg(long, value_name = "SECS")]
+    pub download_timeout: Option<u64>,
+    /// Number of retries on transient download failures, such as timeouts and server errors.
+    /// Default to 3.
+    #[arg(long, value_name = "NUM")]
+    pub retries: Option<u32>,
+    /// Keep the installed files when the installation fails, instead of rolling it back.
+    #[arg(long)]
+    pub keep_on_failure: bool,
+    /// Take over the lock of the installation directory held by another process,
+    /// use this only if that process is stuck.
+    #[arg(long)]
+    pub force_unlock: bool,
+    /// Set another directory to store temporary files during installation.
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::DirPath)]
+    pub temp_dir: Option<PathBuf>,
+    /// Install the set of tools of another target instead of the detected one,
+    /// i.e. `x86_64-pc-windows-gnu`.
+    #[arg(long, value_name = "TRIPLE", value_parser = utils::parse_host_triple)]
+    pub host_triple: Option<String>,
+    /// Use the `rustup` found in `PATH` (if there is one) to install Rust toolchain,
+    /// instead of installing another `rustup`.
+    #[arg(long)]
+    pub reuse_existing_rustup: bool,
+    /// Install Rust toolchain with another profile instead of the one in the toolset manifest,
+    /// which is one of `minimal`, `default` or `complete`.
+    #[arg(long, value_name = "PROFILE", value_parser = manifest::parse_toolchain_profile)]
+    pub profile: Option<String>,
+    /// Only install the tools of this group (`[group.<NAME>]` in the toolset manifest),
+    /// can be specified multiple times. Default to every group.
+    #[arg(long, value_name = "NAME")]
+    pub group: Vec<String>,
+    /// Install the tools listed in the given toolset manifest
+    /// (a TOML or JSON file, or an http(s) url) instead of the built-in one.
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub manifest: Option<PathBuf>,
+    /// Trust this minisign public key when verifying the signature (`<PATH>.minisig`)
+    /// of the toolset manifest, can be specified multiple times.
+    #[arg(long, value_name = "KEY")]
+    pub manifest_key: Vec<String>,
+    /// Install everything from a local bundle directory without accessing the network,
+    /// check the documentation for the layout of a bundle.
+    #[arg(long, value_name = "BUNDLE", value_hint = ValueHint::DirPath)]
+    pub offline: Option<PathBuf>,
+    /// Install everything from an archive created by `manager export-bundle` on another machine,
+    /// without accessing the network.
+    #[arg(
+        long,
+        value_name = "ARCHIVE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = ["offline", "manifest"]
+    )]
+    pub from_bundle: Option<PathBuf>,
     /// Specify another cargo registry url to replace `crates.io`, could be `sparse+URL`.
     #[arg(hide = true, long)]
     pub registry_url: Option<String>,
@@ -53,9 +171,9 @@ pub struct Installer {
 #[command(version, about)]
 #[command(arg_required_else_help = true)]
 pub struct Manager {
-    /// Enable verbose output
-    #[arg(short, long, conflicts_with = "quiet")]
-    pub verbose: bool,
+    /// Enable verbose output, pass twice (`-vv`) for even more details
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
     /// Suppress non-critical messages
     #[arg(short, long, conflicts_with = "verbose")]
     pub quiet: bool,
@@ -66,17 +184,105 @@ pub struct Manager {
     /// Don't show GUI when running the program.
     #[arg(long)]
     pub no_gui: bool,
+    /// Specify the format of output, `json` emits line-delimited JSON events,
+    /// including the results of each command, such as the tools of `list`.
+    /// `env` also accepts the name of a shell to print the commands for.
+    #[arg(long, alias = "output", global = true, value_enum, default_value_t = ManagerFormat::Text)]
+    pub format: ManagerFormat,
+    /// Take over the lock of the installation directory held by another process,
+    /// use this only if that process is stuck.
+    #[arg(long, global = true)]
+    pub force_unlock: bool,
     #[command(subcommand)]
     pub command: Option<ManagerSubcommands>,
 }
 
+/// The values of `--format` of [`Manager`], which is either an [`OutputFormat`],
+/// or a shell that `env` prints the commands for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ManagerFormat {
+    /// Human readable text, or the commands for the current shell when used with `env`.
+    #[default]
+    Text,
+    /// Line-delimited JSON events, one event per line.
+    Json,
+    /// Commands of POSIX shells for `env`, such as `sh`, `bash` and `zsh`.
+    Sh,
+    /// Commands of `fish` for `env`.
+    Fish,
+    /// Commands of `nu` for `env`.
+    Nu,
+    /// Commands of PowerShell for `env`.
+    Powershell,
+    /// Commands of `cmd.exe` for `env`.
+    Cmd,
+}
+
+impl ManagerFormat {
+    fn output_format(self) -> OutputFormat {
+        match self {
+            Self::Json => OutputFormat::Json,
+            _ => OutputFormat::Text,
+        }
+    }
+
+    /// The shell that `env` prints the commands for, `None` if it's not a shell.
+    pub(crate) fn env_shell(self) -> Option<EnvShell> {
+        Some(match self {
+            Self::Text | Self::Json => return None,
+            Self::Sh => EnvShell::Sh,
+            Self::Fish => EnvShell::Fish,
+            Self::Nu => EnvShell::Nu,
+            Self::Powershell => EnvShell::Powershell,
+            Self::Cmd => EnvShell::Cmd,
+        })
+    }
+}
+
 impl Installer {
     pub fn install_dir(&self) -> Option<&Path> {
         self.prefix.as_deref()
     }
 
+    /// Load the answer file if there is one, otherwise return the empty answers.
+    fn answers(&self) -> Result<AnswerFile> {
+        match &self.answer_file {
+            Some(path) => AnswerFile::load(path)
+                .with_context(|| format!("invalid answer file '{}'", path.display())),
+            None => Ok(AnswerFile::default()),
+        }
+    }
+
     pub fn execute(&self) -> Result<()> {
-        install::execute_installer(self)
+        utils::set_output_format(self.format);
+        utils::init_logger(self.verbose, self.quiet);
+        if let Some(triple) = &self.host_triple {
+            utils::set_host_triple(triple.clone());
+        }
+        if let Some(rate) = self.limit_rate {
+            utils::set_download_rate_limit(rate);
+        }
+        if let Some(secs) = self.download_timeout {
+            utils::set_download_timeout(secs);
+        }
+        if let Some(retries) = self.retries {
+            utils::set_download_retry(utils::RetryPolicy {
+                retries,
+                ..Default::default()
+            });
+        }
+        let result = self.answers().and_then(|answers| {
+            crate::core::os::set_system_wide(self.system || answers.system);
+            // The report tells whether the installation directory is writable instead.
+            if !self.preflight {
+                crate::core::os::ensure_privileges()?;
+            }
+            install::execute_installer(self, &answers)
+        });
+        if let Err(e) = &result {
+            report_fatal_error(e);
+        }
+        result
     }
 }
 
@@ -86,13 +292,40 @@ impl Manager {
             verbose: self.verbose,
             quiet: self.quiet,
             yes: self.yes_to_all,
+            format: self.format,
         };
+        utils::set_output_format(self.format.output_format());
+        utils::init_logger(self.verbose, self.quiet);
+        // Clean up the executable replaced by a previous `self-update`.
+        crate::core::update::remove_old_exe();
 
-        if let Some(subcommand) = &self.command {
-            subcommand.execute(global_opt)
-        } else {
-            Ok(())
+        let Some(subcommand) = &self.command else {
+            return Ok(());
+        };
+        let install_dir = crate::core::os::install_dir_from_exe_path();
+        if let Ok(install_dir) = &install_dir {
+            // The manifests loaded by the manager expand `${INSTALL_DIR}` to this installation.
+            crate::manifest::set_install_dir_var(install_dir.clone());
+            if let Err(e) = utils::set_log_file(&install_dir.join("logs").join("manager.log")) {
+                tracing::debug!("unable to write log file: {e}");
+            }
+            // Downloads made by the manager go through the same corporate proxy as the installer.
+            if let Err(e) = crate::core::install::use_installed_ca_cert(install_dir) {
+                tracing::warn!("unable to use the installed CA certificate: {e:#}");
+            }
         }
+        let result = match &install_dir {
+            // Prevent other instances from modifying the installation at the same time.
+            Ok(install_dir) if subcommand.is_mutating() => {
+                InstallLock::acquire(install_dir, self.force_unlock)
+                    .and_then(|_lock| subcommand.execute(global_opt))
+            }
+            _ => subcommand.execute(global_opt),
+        };
+        if let Err(e) = &result {
+            report_fatal_error(e);
+        }
+        result
     }
 }
 
@@ -106,18 +339,142 @@ pub enum ManagerSubcommands {
         #[command(subcommand)]
         commands: Option<UninstallCommand>,
     },
+    /// Add or remove individual tools of the toolset.
+    Component {
+        #[command(subcommand)]
+        command: ComponentCommand,
+    },
+    /// Check the installation and report any problems found.
+    #[command(visible_alias = "check")]
+    Doctor {
+        /// Skip the checks that require network access.
+        #[arg(long)]
+        offline: bool,
+    },
+    /// Verify the installed files of each tool against the checksums recorded when installing,
+    /// and look for the foreign files in the `tools` and `cargo` bin directories.
+    Verify {
+        /// Reinstall the tools with missing or modified files.
+        #[arg(long)]
+        repair: bool,
+    },
+    /// List the tools that will be installed for current platform, and their status.
+    List {
+        /// Only list the installed toolchain and tools, along with their installed versions.
+        #[arg(long)]
+        installed: bool,
+    },
+    /// View or change the configurations of current installation, such as the servers and proxy.
+    Config {
+        #[command(subcommand)]
+        command: Option<ConfigCommand>,
+    },
+    /// Show the installed versions of a tool, or switch the default version of it,
+    /// such as rolling back an update that broke something.
+    Default {
+        /// The name of the tool.
+        #[arg(value_name = "TOOL")]
+        name: String,
+        /// The installed version to use by default.
+        version: Option<String>,
+    },
+    /// Print the commands that set up the environment of current installation in this session,
+    /// such as `eval "$(manager env)"` in CI jobs that don't load the persistent variables.
+    ///
+    /// The commands are for the current shell, or the one given by `--format`,
+    /// which can also be `json` to print the variables instead.
+    Env,
+    /// Pack the installed toolchain, tools and cargo registry cache into a single archive,
+    /// which can be installed on other machines with `installer --from-bundle`.
+    ExportBundle {
+        /// The path of the archive to write, which must end with `.tar.gz`.
+        #[arg(value_name = "PATH", value_hint = ValueHint::FilePath)]
+        path: PathBuf,
+    },
     /// A subcommand to create a new Rust project template and let you start coding with it.
     TryIt {
         /// Specify another directory to create project template, defaulting to current directory.
         #[arg(long, short, value_name = "PATH", value_hint = ValueHint::DirPath)]
         path: Option<PathBuf>,
     },
+    /// Pin a project to the installed toolchain and cargo registry, by writing its
+    /// `rust-toolchain.toml` and `.cargo/config.toml`.
+    InitProject {
+        /// The project directory, defaulting to current directory.
+        #[arg(value_name = "DIR", value_hint = ValueHint::DirPath)]
+        dir: Option<PathBuf>,
+        /// Overwrite the existing `rust-toolchain.toml`, and merge the registry configuration
+        /// into the existing `.cargo/config.toml`.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Update the outdated toolchain and tools to the versions in the toolset manifest.
+    Update {
+        /// Only show the outdated toolchain and tools, without updating.
+        #[arg(long, visible_alias = "dry-run")]
+        check: bool,
+        /// Specify another toolset manifest to compare against, instead of the built-in one.
+        #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+        manifest: Option<PathBuf>,
+    },
+    /// Update this program to the latest version.
+    SelfUpdate {
+        /// Only check if there is a newer version, without updating.
+        #[arg(long)]
+        check: bool,
+        /// Specify another server to download updates.
+        #[arg(long, value_name = "URL", value_hint = ValueHint::Url)]
+        update_root: Option<Url>,
+    },
 }
 
 impl ManagerSubcommands {
+    /// Check whether this command modifies the installation,
+    /// which requires the [lock](InstallLock) of the installation directory.
+    fn is_mutating(&self) -> bool {
+        match self {
+            Self::Uninstall { .. } | Self::Component { .. } => true,
+            Self::Config { command } => {
+                matches!(
+                    command,
+                    Some(ConfigCommand::Set { .. } | ConfigCommand::Unset { .. })
+                )
+            }
+            Self::Update { check, .. } | Self::SelfUpdate { check, .. } => !check,
+            Self::Verify { repair } => *repair,
+            Self::Default { version, .. } => version.is_some(),
+            Self::Doctor { .. }
+            | Self::Env
+            | Self::ExportBundle { .. }
+            | Self::List { .. }
+            | Self::TryIt { .. }
+            | Self::InitProject { .. } => false,
+        }
+    }
+
     pub(crate) fn execute(&self, opt: GlobalOpt) -> Result<()> {
+        if opt.format.env_shell().is_some() && !matches!(self, Self::Env) {
+            let format = opt
+                .format
+                .to_possible_value()
+                .unwrap_or_else(|| unreachable!());
+            bail!(
+                "`--format {}` can only be used with `env`",
+                format.get_name()
+            );
+        }
         uninstall::execute(self, opt)?;
+        component::execute(self, opt)?;
+        config::execute(self, opt)?;
+        default::execute(self, opt)?;
+        doctor::execute(self, opt)?;
+        verify::execute(self, opt)?;
+        env::execute(self, opt)?;
+        export_bundle::execute(self, opt)?;
+        list::execute(self, opt)?;
         tryit::execute(self, opt)?;
+        init_project::execute(self, opt)?;
+        update::execute(self, opt)?;
         Ok(())
     }
 }
@@ -126,7 +483,12 @@ impl ManagerSubcommands {
 #[command(arg_required_else_help = true)]
 pub enum UninstallCommand {
     /// Uninstall everything.
-    All,
+    All {
+        /// Move the registry cache, `config.toml` and the binaries installed by user
+        /// under `CARGO_HOME` into another directory, instead of removing them.
+        #[arg(long, value_name = "PATH", value_hint = ValueHint::DirPath)]
+        keep_user_data: Option<PathBuf>,
+    },
     /// Uninstall a list of individual tools, separated by space.
     Tool {
         #[arg(value_name = "TOOLS")]
@@ -134,14 +496,87 @@ pub enum UninstallCommand {
     },
 }
 
+#[derive(Subcommand, Debug)]
+#[command(arg_required_else_help = true)]
+pub enum ComponentCommand {
+    /// Install tools listed in the toolset manifest for current platform, separated by space.
+    Add {
+        #[arg(value_name = "TOOLS", required = true)]
+        names: Vec<String>,
+    },
+    /// Uninstall a list of individual tools, separated by space.
+    Remove {
+        #[arg(value_name = "TOOLS", required = true)]
+        names: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Show the current configurations, this is the default.
+    Show,
+    /// Change a configuration persistently.
+    Set { key: ConfigKey, value: String },
+    /// Remove a configuration, so that the default one is used.
+    Unset { key: ConfigKey },
+}
+
+/// The configurations that can be changed by `config` command.
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ConfigKey {
+    /// The server to download Rust toolchain from (`RUSTUP_DIST_SERVER`).
+    DistServer,
+    /// The server to download `rustup` from (`RUSTUP_UPDATE_ROOT`).
+    UpdateRoot,
+    /// The proxy of http requests (`http_proxy`).
+    HttpProxy,
+    /// The proxy of https requests (`https_proxy`).
+    HttpsProxy,
+    /// Comma separated hosts that should not use proxy (`no_proxy`).
+    NoProxy,
+}
+
 /// Contain options that are accessed globally.
 ///
 /// Such as `--verbose`, `--quiet`, `--yes`.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct GlobalOpt {
-    pub verbose: bool,
+    pub verbose: u8,
     pub quiet: bool,
     pub yes: bool,
+    pub format: ManagerFormat,
+}
+
+/// Report the error that stops the program, along with the location of the log file.
+fn report_fatal_error(err: &anyhow::Error) {
+    OutputEvent::Error {
+        text: &format!("{err:?}"),
+        code: crate::error_code(err),
+    }
+    .emit();
+    if let Some(path) = utils::log_file() {
+        OutputEvent::Message {
+            text: &format!("check the log file for details: '{}'", path.display()),
+        }
+        .emit();
+    }
+}
+
+/// Ask the user a yes/no question, then return the answer.
+///
+/// `default` will be used when the user answers nothing.
+pub(crate) fn confirm(question: &str, default: bool) -> Result<bool> {
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    print!("{question} {hint} ");
+    std::io::Write::flush(&mut std::io::stdout())?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(match answer.trim().to_lowercase().as_str() {
+        "" => default,
+        "y" | "yes" => true,
+        _ => false,
+    })
 }
 
 pub fn parse_installer_cli() -> Installer {
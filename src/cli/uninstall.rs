@@ -1,7 +1,7 @@
 //! Separated module to handle uninstallation in command line.
 
 use crate::cli::UninstallCommand;
-use crate::core::uninstall::{UninstallConfiguration, Uninstallation};
+use crate::core::uninstall::UninstallConfiguration;
 
 use super::{GlobalOpt, ManagerSubcommands};
 
@@ -17,17 +17,16 @@ pub(super) fn execute(subcommand: &ManagerSubcommands, _opt: GlobalOpt) -> Resul
     };
 
     match uninst_cmd {
-        UninstallCommand::All => {
-            let config = UninstallConfiguration;
-            config.remove_rustup_env_vars()?;
-            config.remove_tools()?;
-            config.remove_self()?;
-        }
-        UninstallCommand::Tool { names } => {
-            // TODO: remove a certain tool, or component
-            unimplemented!("attempt to remove '{names:?}', but this is not yet implemented.")
+        UninstallCommand::All { keep_user_data } => {
+            UninstallConfiguration::init()?.uninstall_all(keep_user_data.as_deref())?;
         }
+        UninstallCommand::Tool { names } => uninstall_tools(names)?,
     }
 
     Ok(())
 }
+
+/// Uninstall a list of individual tools of current installation.
+pub(super) fn uninstall_tools(names: &[String]) -> Result<()> {
+    UninstallConfiguration::init()?.uninstall_tools(names)
+}
@@ -0,0 +1,111 @@
+use super::{confirm, GlobalOpt, ManagerSubcommands};
+use crate::core::install::{
+    default_rustup_dist_server, default_rustup_update_root, InstallConfiguration,
+};
+use crate::core::os::install_dir_from_exe_path;
+use crate::core::parser::fingerprint::InstallationRecord;
+use crate::core::parser::ManifestParser;
+use crate::core::{update, RUSTUP_DIST_SERVER, RUSTUP_UPDATE_ROOT};
+use crate::manifest::{baked_in_manifest, ToolsetManifest};
+use crate::utils::OutputEvent;
+use anyhow::Result;
+use std::path::Path;
+use url::Url;
+
+/// Execute `update` and `self-update` command.
+pub(super) fn execute(subcommand: &ManagerSubcommands, opt: GlobalOpt) -> Result<()> {
+    match subcommand {
+        ManagerSubcommands::Update { check, manifest } => {
+            update_toolkit(*check, manifest.as_deref(), opt)
+        }
+        ManagerSubcommands::SelfUpdate { check, update_root } => {
+            self_update(*check, update_root.as_ref(), opt)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn update_toolkit(check: bool, manifest_path: Option<&Path>, opt: GlobalOpt) -> Result<()> {
+    let install_dir = install_dir_from_exe_path()?;
+    let record = InstallationRecord::load_or_new(&install_dir)?;
+    crate::core::os::set_system_wide(record.system);
+    let mut manifest = match manifest_path {
+        Some(path) => ToolsetManifest::load(path)?,
+        None => baked_in_manifest()?,
+    };
+    manifest.adjust_paths()?;
+    manifest.apply_toolchain_flavor();
+
+    let outdated = update::find_outdated(&record, &manifest);
+    if outdated.is_empty() {
+        OutputEvent::Message {
+            text: "everything is up to date",
+        }
+        .emit();
+        return Ok(());
+    }
+    OutputEvent::Message {
+        text: "the following toolchain and tools are outdated:",
+    }
+    .emit();
+    for item in &outdated {
+        item.to_event().emit();
+    }
+    if check || (!opt.yes && !confirm("update them now?", true)?) {
+        return Ok(());
+    }
+
+    let mut config = install_config(&install_dir, &record)?.force(true);
+    update::update_toolkit(&mut config, &manifest, &record, &outdated)?;
+    OutputEvent::Message {
+        text: "successfully updated",
+    }
+    .emit();
+    Ok(())
+}
+
+/// Create the configuration to install (or reinstall) things into an existing installation.
+pub(super) fn install_config(
+    install_dir: &Path,
+    record: &InstallationRecord,
+) -> Result<InstallConfiguration> {
+    // Keep using the servers that were configured by the installation.
+    let url_from_env = |key: &str| std::env::var(key).ok().and_then(|s| Url::parse(&s).ok());
+    let rustup_dist_server =
+        url_from_env(RUSTUP_DIST_SERVER).unwrap_or_else(|| default_rustup_dist_server().clone());
+    let rustup_update_root =
+        url_from_env(RUSTUP_UPDATE_ROOT).unwrap_or_else(|| default_rustup_update_root().clone());
+    crate::core::os::ensure_privileges()?;
+    InstallConfiguration::init(install_dir, None, false)?
+        .rustup_dist_server(rustup_dist_server)
+        .rustup_update_root(rustup_update_root)
+        .cargo_is_installed(record.rust.is_some())
+        .reuse_rustup(record.external_rustup.clone())
+}
+
+fn self_update(check: bool, update_root: Option<&Url>, opt: GlobalOpt) -> Result<()> {
+    let root = update::update_root(update_root)?;
+    let proxy = baked_in_manifest()?.proxy;
+    let current = env!("CARGO_PKG_VERSION");
+    let Some(latest) = update::check_update(&root, proxy.as_ref())? else {
+        OutputEvent::Message {
+            text: &format!("manager is up to date ({current})"),
+        }
+        .emit();
+        return Ok(());
+    };
+
+    OutputEvent::Message {
+        text: &format!("a new version of manager is available: {current} -> {latest}"),
+    }
+    .emit();
+    if check || (!opt.yes && !confirm("update now?", true)?) {
+        return Ok(());
+    }
+    update::update_self(&root, &latest, proxy.as_ref())?;
+    OutputEvent::Message {
+        text: &format!("manager successfully updated to {latest}"),
+    }
+    .emit();
+    Ok(())
+}
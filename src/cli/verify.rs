@@ -0,0 +1,60 @@
+//! Separated module to verify the integrity of current installation in command line.
+
+use super::{GlobalOpt, ManagerSubcommands};
+use crate::core::os::install_dir_from_exe_path;
+use crate::core::verify::{self, Problem};
+use crate::utils::OutputEvent;
+
+use anyhow::{bail, Result};
+
+/// Execute `verify` command.
+pub(super) fn execute(subcommand: &ManagerSubcommands, _opt: GlobalOpt) -> Result<()> {
+    let ManagerSubcommands::Verify { repair } = subcommand else {
+        return Ok(());
+    };
+
+    let report = verify::verify_installation(&install_dir_from_exe_path()?)?;
+    for (name, problems) in &report.tools {
+        for problem in problems {
+            let desc = match problem {
+                Problem::Missing(path) => format!("'{}' of tool '{name}' exists", path.display()),
+                Problem::Modified(path) => {
+                    format!("'{}' of tool '{name}' is not modified", path.display())
+                }
+            };
+            OutputEvent::Check {
+                desc: &desc,
+                ok: false,
+                fix: Some(&format!(
+                    "run `manager verify --repair` or `manager component add {name}` to reinstall it"
+                )),
+            }
+            .emit();
+        }
+    }
+    for path in &report.foreign {
+        OutputEvent::Check {
+            desc: &format!("'{}' belongs to the installation", path.display()),
+            ok: false,
+            fix: Some("remove it if it's not needed, it's not managed by this program"),
+        }
+        .emit();
+    }
+
+    if *repair && !report.tools.is_empty() {
+        let names = report.tools.keys().cloned().collect::<Vec<_>>();
+        return super::component::add_components(&names, true);
+    }
+    if !report.is_ok() {
+        bail!(
+            "{} tool(s) are broken and {} foreign file(s) were found",
+            report.tools.len(),
+            report.foreign.len()
+        );
+    }
+    OutputEvent::Message {
+        text: "all installed files are intact",
+    }
+    .emit();
+    Ok(())
+}
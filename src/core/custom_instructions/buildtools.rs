@@ -1,9 +1,19 @@
 use std::path::Path;
 use anyhow::Result;
 use crate::core::install::InstallConfiguration;
+use crate::manifest::ToolInfo;
 
+/// Install VS Build Tools using its installer (the bootstrapper) under `path`.
+///
+/// The components to install can be configured with [`ToolInfo::components`],
+/// otherwise only MSVC and (if missing) the Windows SDK are installed.
+/// The [channel](ToolInfo::channel) (such as `17` or `17.8`) pins the version to install.
 #[cfg(windows)]
-pub(super) fn install(path: &Path, config: &InstallConfiguration) -> Result<()> {
+pub(super) fn install(
+    path: &Path,
+    info: Option<&ToolInfo>,
+    config: &InstallConfiguration,
+) -> Result<()> {
     use std::path::PathBuf;
     use crate::utils;
     use anyhow::anyhow;
@@ -44,9 +54,19 @@ pub(super) fn install(path: &Path, config: &InstallConfiguration) -> Result<()>
         "--passive",
         "--focusedUi",
     ];
-    for component in windows_related::required_components() {
+    let channel_uri = info
+        .and_then(ToolInfo::channel)
+        .map(windows_related::channel_uri);
+    if let Some(uri) = &channel_uri {
+        cmd.push("--channelUri");
+        cmd.push(uri);
+    }
+    let components = windows_related::components_to_install(
+        info.map(ToolInfo::components).unwrap_or_default(),
+    );
+    for component in &components {
         cmd.push("--add");
-        cmd.push(component.component_id());
+        cmd.push(component);
     }
 
     // Step 2: Make a copy of this installer to the `tools` directory,
@@ -56,14 +76,22 @@ pub(super) fn install(path: &Path, config: &InstallConfiguration) -> Result<()>
     utils::copy_file_to(&buildtools_exe, &installer_dir)?;
 
     // Step 3: Invoke the install command.
-    println!("running VS BuildTools installer...");
+    tracing::info!("running VS BuildTools installer...");
     utils::execute(buildtools_exe, &cmd)?;
 
+    // Step 4: Report the components that were actually installed,
+    // as the installer might skip some of them without failing.
+    windows_related::report_installed_components(&components);
+
     Ok(())
 }
 
 #[cfg(not(windows))]
-pub(super) fn install(_path: &Path, _config: &InstallConfiguration) -> Result<()> {
+pub(super) fn install(
+    _path: &Path,
+    _info: Option<&ToolInfo>,
+    _config: &InstallConfiguration,
+) -> Result<()> {
     Ok(())
 }
 
@@ -80,6 +108,16 @@ pub(super) fn uninstall() -> Result<()> {
     Ok(())
 }
 
+#[cfg(windows)]
+pub(super) fn detect(_info: Option<&ToolInfo>) -> Option<super::Detection> {
+    windows_related::detect()
+}
+
+#[cfg(not(windows))]
+pub(super) fn detect(_info: Option<&ToolInfo>) -> Option<super::Detection> {
+    None
+}
+
 #[cfg(windows)]
 pub(super) fn already_installed() -> bool {
     windows_related::is_msvc_installed()
@@ -93,7 +131,14 @@ pub(super) fn already_installed() -> bool {
 #[cfg(windows)]
 // TODO: move these code that are copied... *ahem* inspired from `rustup` into `utils`
 mod windows_related {
+    use std::path::PathBuf;
+
     use cc::windows_registry;
+    use tracing::{info, warn};
+
+    use crate::utils;
+
+    const BUILDTOOLS_PRODUCT_ID: &str = "Microsoft.VisualStudio.Product.BuildTools";
 
     #[derive(Debug, Clone, Copy)]
     pub(crate) enum BuildToolsComponents {
@@ -106,6 +151,10 @@ mod windows_related {
         pub(crate) fn component_id(&self) -> &'static str {
             use BuildToolsComponents::*;
             match self {
+                // The ARM64 tools are needed by `aarch64-pc-windows-msvc`.
+                Msvc if utils::triple_arch(utils::host_triple()) == "aarch64" => {
+                    "Microsoft.VisualStudio.Component.VC.Tools.ARM64"
+                }
                 Msvc => "Microsoft.VisualStudio.Component.VC.Tools.x86.x64",
                 WinSDK => "Microsoft.VisualStudio.Component.Windows11SDK.22000",
             }
@@ -114,11 +163,11 @@ mod windows_related {
 
     pub(super) fn is_msvc_installed() -> bool {
         // Other targets don't need MSVC, so assume it has already installed
-        if !env!("TARGET").contains("msvc") {
+        if utils::triple_env(utils::host_triple()) != Some("msvc") {
             return true;
         }
 
-        windows_registry::find_tool(env!("TARGET"), "cl.exe").is_some()
+        windows_registry::find_tool(utils::host_triple(), "cl.exe").is_some()
     }
 
     fn is_windows_sdk_installed() -> bool {
@@ -139,4 +188,100 @@ mod windows_related {
             vec![BuildToolsComponents::Msvc, BuildToolsComponents::WinSDK]
         }
     }
+
+    /// Get the IDs of the components to install, which are the `declared` ones (if there are any)
+    /// plus MSVC, since it's always needed by Rust, or the [required ones](required_components).
+    pub(crate) fn components_to_install(declared: &[String]) -> Vec<String> {
+        if declared.is_empty() {
+            return required_components()
+                .iter()
+                .map(|c| c.component_id().to_string())
+                .collect();
+        }
+        let msvc = BuildToolsComponents::Msvc.component_id();
+        let mut components = declared.to_vec();
+        if !components.iter().any(|c| c == msvc) {
+            components.insert(0, msvc.to_string());
+        }
+        components
+    }
+
+    /// Get the URI of the channel manifest to install Build Tools from.
+    ///
+    /// A major version (such as `17`) maps to its current release channel, and a version
+    /// with minor part (such as `17.8`) maps to the long-term servicing channel of it.
+    pub(crate) fn channel_uri(channel: &str) -> String {
+        let mut parts = channel.trim().split('.');
+        let major = parts.next().unwrap_or_default();
+        match parts.next() {
+            Some(minor) => format!("https://aka.ms/vs/{major}/release.ltsc.{major}.{minor}/channel"),
+            None => format!("https://aka.ms/vs/{major}/release/channel"),
+        }
+    }
+
+    fn vswhere() -> Option<PathBuf> {
+        let program_files = std::env::var_os("ProgramFiles(x86)")?;
+        let path = PathBuf::from(program_files)
+            .join("Microsoft Visual Studio")
+            .join("Installer")
+            .join("vswhere.exe");
+        path.is_file().then_some(path)
+    }
+
+    /// Find an existing installation of Build Tools (or Visual Studio) that has MSVC in it.
+    ///
+    /// `vswhere` is used to get its installation path and version if possible,
+    /// otherwise this falls back to look for `cl.exe`.
+    pub(crate) fn detect() -> Option<super::super::Detection> {
+        let msvc = BuildToolsComponents::Msvc.component_id();
+        let query = |property: &str| {
+            let args = ["-latest", "-products", "*", "-requires", msvc, "-property", property];
+            let output = utils::execute_for_output(vswhere()?, &args).ok()?;
+            let value = output.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        };
+        if let Some(path) = query("installationPath") {
+            return Some(super::super::Detection {
+                path: Some(PathBuf::from(path)),
+                version: query("catalog_productDisplayVersion"),
+            });
+        }
+        let cl = windows_registry::find_tool(utils::host_triple(), "cl.exe")?;
+        Some(super::super::Detection {
+            path: Some(cl.path().to_path_buf()),
+            version: None,
+        })
+    }
+
+    /// Ask `vswhere` whether each of the `components` is installed, then print the result.
+    pub(crate) fn report_installed_components(components: &[String]) {
+        let Some(vswhere) = vswhere() else {
+            warn!("unable to find `vswhere.exe`, skip checking installed components");
+            return;
+        };
+        let (installed, missing): (Vec<&str>, Vec<&str>) = components
+            .iter()
+            .map(String::as_str)
+            .partition(|&id| {
+                let args = [
+                    "-products",
+                    BUILDTOOLS_PRODUCT_ID,
+                    "-requires",
+                    id,
+                    "-property",
+                    "instanceId",
+                ];
+                utils::execute_for_output(&vswhere, &args)
+                    .is_ok_and(|output| !output.trim().is_empty())
+            });
+        if !installed.is_empty() {
+            info!("installed VS BuildTools components: {}", installed.join(", "));
+        }
+        if !missing.is_empty() {
+            warn!(
+                "the following VS BuildTools components were not installed: {}",
+                missing.join(", ")
+            );
+        }
+    }
 }
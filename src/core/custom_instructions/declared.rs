@@ -0,0 +1,192 @@
+//! Install methods declared in the toolset manifest, check [`CustomInstruction`].
+//!
+//! Unlike the other instructions in this module, these are not compiled into the installer,
+//! which allows distributors to support new tools by only changing the manifest.
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::core::install::InstallConfiguration;
+use crate::core::TOOL_INSTALL_DIR;
+use crate::manifest::{CustomInstruction, InstructionStep};
+use crate::utils::{self, OutputEvent};
+
+/// Make sure the instruction can be executed, before anything gets installed.
+///
+/// Commands must not be empty, and only `run` steps are allowed when uninstalling,
+/// as the installer files are no longer available at that time.
+pub(crate) fn validate(instruction: &CustomInstruction) -> Result<()> {
+    let commands = instruction
+        .install
+        .iter()
+        .chain(&instruction.uninstall)
+        .filter_map(|step| match step {
+            InstructionStep::Run { run } => Some(run.as_str()),
+            _ => None,
+        })
+        .chain(instruction.detect.as_deref());
+    for cmd in commands {
+        if cmd.trim().is_empty() {
+            bail!("the instruction contains empty command");
+        }
+    }
+    if let Some(step) = instruction
+        .uninstall
+        .iter()
+        .find(|step| !matches!(step, InstructionStep::Run { .. }))
+    {
+        bail!("only `run` steps are supported when uninstalling, found: {step:?}");
+    }
+    Ok(())
+}
+
+/// Install a tool from its (extracted) installer at `path` into `tools_dir/<name>`,
+/// by executing the install steps of its instruction in order.
+pub(crate) fn install(
+    name: &str,
+    path: &Path,
+    instruction: &CustomInstruction,
+    config: &InstallConfiguration,
+) -> Result<()> {
+    let install_dir = config.tools_dir().join(name);
+    utils::ensure_dir(&install_dir)?;
+    let cwd = if path.is_dir() {
+        path
+    } else {
+        path.parent().unwrap_or(path)
+    };
+
+    for step in &instruction.install {
+        match step {
+            InstructionStep::Run { run } => run_command(name, run, cwd, &install_dir, instruction)?,
+            InstructionStep::Copy { copy, to } => {
+                let src = cwd.join(copy);
+                let dest = match to {
+                    Some(to) => install_dir.join(to),
+                    None => install_dir.clone(),
+                };
+                copy_to(&src, &dest).with_context(|| {
+                    format!("failed to copy '{}' of tool '{name}'", copy.display())
+                })?;
+            }
+            InstructionStep::AddPath { add_path } => {
+                config.add_to_path(&install_dir.join(add_path))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Uninstall a tool by executing the uninstall steps of its instruction in order,
+/// under its installation directory `install_dir`.
+///
+/// The installation directory itself is not removed here.
+pub(crate) fn uninstall(
+    name: &str,
+    install_dir: &Path,
+    instruction: &CustomInstruction,
+) -> Result<()> {
+    for step in &instruction.uninstall {
+        if let InstructionStep::Run { run } = step {
+            run_command(name, run, install_dir, install_dir, instruction)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check if a tool was already installed by running the `detect` command of its instruction,
+/// returns `false` if there isn't one.
+pub(crate) fn already_installed(instruction: &CustomInstruction) -> bool {
+    let Some(cmd) = instruction.detect.as_deref() else {
+        return false;
+    };
+    let vars = instruction
+        .env
+        .iter()
+        .map(|(key, val)| (key.as_str(), OsStr::new(val)));
+    utils::shell_succeeds(cmd, vars)
+}
+
+fn run_command(
+    name: &str,
+    cmd: &str,
+    cwd: &Path,
+    install_dir: &Path,
+    instruction: &CustomInstruction,
+) -> Result<()> {
+    OutputEvent::Message {
+        text: &format!("running install instruction for '{name}': {cmd}"),
+    }
+    .emit();
+    let mut vars = vec![(TOOL_INSTALL_DIR, install_dir.as_os_str())];
+    vars.extend(
+        instruction
+            .env
+            .iter()
+            .map(|(key, val)| (key.as_str(), OsStr::new(val))),
+    );
+    utils::shell_execute_in_dir_with_env(cwd, cmd, &[] as &[&str], vars)
+        .with_context(|| format!("failed to run command `{cmd}` of tool '{name}'"))
+}
+
+/// Copy a file or the content of a directory to `dest`.
+///
+/// A file is copied into `dest` if it is an existing directory, otherwise it's copied as `dest`.
+fn copy_to(src: &Path, dest: &Path) -> Result<()> {
+    if src.is_dir() {
+        utils::ensure_dir(dest)?;
+        for entry in utils::walk_dir(src, false)? {
+            utils::copy_into(&entry, dest)?;
+        }
+    } else if dest.is_dir() {
+        utils::copy_into(src, dest)?;
+    } else {
+        utils::ensure_parent_dir(dest)?;
+        utils::copy_as(src, dest)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_instructions() {
+        let parse = |s: &str| toml::from_str::<CustomInstruction>(s).unwrap();
+
+        let valid = parse(
+            r#"
+install = [{ copy = "sdk", to = "." }, { add-path = "bin" }, { run = "./setup.sh" }]
+uninstall = [{ run = "./cleanup.sh" }]
+detect = "sdk --version"
+"#,
+        );
+        assert!(validate(&valid).is_ok());
+
+        let empty_cmd = parse(r#"install = [{ run = " " }]"#);
+        assert!(validate(&empty_cmd).is_err());
+
+        let copy_on_uninstall = parse(r#"uninstall = [{ copy = "a" }]"#);
+        assert!(validate(&copy_on_uninstall).is_err());
+    }
+
+    #[test]
+    fn copy_files_and_dirs() {
+        let root = tempfile::tempdir().unwrap();
+        let src = root.path().join("src");
+        utils::ensure_dir(src.join("bin")).unwrap();
+        utils::write_file(src.join("bin").join("tool"), "", false).unwrap();
+        utils::write_file(src.join("README"), "", false).unwrap();
+
+        let dest = root.path().join("dest");
+        copy_to(&src, &dest).unwrap();
+        assert!(dest.join("bin").join("tool").is_file());
+        assert!(dest.join("README").is_file());
+
+        copy_to(&src.join("README"), &dest.join("docs").join("README.md")).unwrap();
+        assert!(dest.join("docs").join("README.md").is_file());
+    }
+}
@@ -0,0 +1,262 @@
+//! Custom install method for the packages that are needed to build many crates on Linux,
+//! such as a C compiler, `pkg-config` and the development files of OpenSSL.
+//!
+//! These are installed using the package manager of the distribution (`apt`, `dnf`, `pacman`
+//! or `zypper`), along with the extra packages listed as the `components` of this tool.
+//! Only the missing packages are installed, which were already reported to (and confirmed by)
+//! the user before installing, check [`PreInstallReport`](crate::core::report::PreInstallReport).
+//!
+//! The packages are never removed when uninstalling, as other programs might depend on them.
+
+use crate::core::install::InstallConfiguration;
+use crate::manifest::ToolInfo;
+use anyhow::Result;
+use tracing::info;
+use std::path::Path;
+
+/// The package managers that are supported, in the order of detection.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PackageManager {
+    Apt,
+    Dnf,
+    Pacman,
+    Zypper,
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+impl PackageManager {
+    const ALL: [Self; 4] = [Self::Apt, Self::Dnf, Self::Pacman, Self::Zypper];
+
+    /// Find the package manager of this system, which is the first one found in `PATH`.
+    pub(crate) fn detect() -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|pm| crate::utils::cmd_exist(pm.program()))
+    }
+
+    pub(crate) fn program(self) -> &'static str {
+        match self {
+            Self::Apt => "apt-get",
+            Self::Dnf => "dnf",
+            Self::Pacman => "pacman",
+            Self::Zypper => "zypper",
+        }
+    }
+
+    /// The packages providing a C/C++ compiler, `make`, `pkg-config` and OpenSSL headers.
+    pub(crate) fn default_packages(self) -> &'static [&'static str] {
+        match self {
+            Self::Apt => &["build-essential", "pkg-config", "libssl-dev"],
+            Self::Dnf => &[
+                "gcc",
+                "gcc-c++",
+                "make",
+                "pkgconf-pkg-config",
+                "openssl-devel",
+            ],
+            Self::Pacman => &["base-devel", "openssl"],
+            Self::Zypper => &["gcc", "gcc-c++", "make", "pkg-config", "libopenssl-devel"],
+        }
+    }
+
+    /// Get the packages to install, which are the default ones followed by the `extra` ones.
+    pub(crate) fn packages(self, extra: &[String]) -> Vec<String> {
+        let mut packages: Vec<String> = self
+            .default_packages()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        for pkg in extra {
+            if !packages.contains(pkg) {
+                packages.push(pkg.clone());
+            }
+        }
+        packages
+    }
+
+    /// Get the command (program followed by its arguments) to install `packages` without
+    /// asking for confirmation, this does not include `sudo`.
+    pub(crate) fn install_command(self, packages: &[String]) -> Vec<String> {
+        let args: &[&str] = match self {
+            Self::Apt => &["install", "-y"],
+            Self::Dnf => &["install", "-y"],
+            Self::Pacman => &["-S", "--needed", "--noconfirm"],
+            Self::Zypper => &["--non-interactive", "install"],
+        };
+        std::iter::once(self.program())
+            .chain(args.iter().copied())
+            .map(ToString::to_string)
+            .chain(packages.iter().cloned())
+            .collect()
+    }
+
+    /// Check if a package was installed by querying the package database.
+    #[cfg(target_os = "linux")]
+    fn is_installed(self, package: &str) -> bool {
+        use crate::utils::execute_for_output;
+
+        match self {
+            Self::Apt => execute_for_output("dpkg-query", &["-W", "-f=${Status}", package])
+                .is_ok_and(|status| status.contains("ok installed")),
+            Self::Dnf | Self::Zypper => execute_for_output("rpm", &["-q", package]).is_ok(),
+            Self::Pacman => execute_for_output("pacman", &["-Q", package]).is_ok(),
+        }
+    }
+}
+
+/// Get the package manager of this system and the packages that are not installed yet,
+/// including the extra ones listed as the [components](ToolInfo::components) of `info`.
+///
+/// Return `None` if the package manager is not supported (or not on Linux).
+#[cfg(target_os = "linux")]
+pub(crate) fn missing_packages(info: Option<&ToolInfo>) -> Option<(PackageManager, Vec<String>)> {
+    let pm = PackageManager::detect()?;
+    let extra = info.map(ToolInfo::components).unwrap_or_default();
+    let missing = pm
+        .packages(extra)
+        .into_iter()
+        .filter(|pkg| !pm.is_installed(pkg))
+        .collect();
+    Some((pm, missing))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn missing_packages(_info: Option<&ToolInfo>) -> Option<(PackageManager, Vec<String>)> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+pub(super) fn install(
+    _path: &Path,
+    info: Option<&ToolInfo>,
+    _config: &InstallConfiguration,
+) -> Result<()> {
+    use crate::utils::OutputEvent;
+    use anyhow::{bail, Context};
+    use std::io::IsTerminal;
+    use std::process::Command;
+
+    let Some((pm, missing)) = missing_packages(info) else {
+        bail!(
+            "unable to find a supported package manager (one of {}), \
+            please install a C compiler, `pkg-config` and the OpenSSL headers manually",
+            PackageManager::ALL.map(PackageManager::program).join(", ")
+        );
+    };
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let mut command = pm.install_command(&missing);
+    // SAFETY: `geteuid` is always successful.
+    if unsafe { libc::geteuid() } != 0 {
+        // `sudo` asks for password in the terminal, which is not available in GUI.
+        let elevate = if std::io::stdin().is_terminal() || !crate::utils::cmd_exist("pkexec") {
+            "sudo"
+        } else {
+            "pkexec"
+        };
+        command.insert(0, elevate.to_string());
+    }
+    let cmd_line = command.join(" ");
+    OutputEvent::Message {
+        text: &format!("installing system packages: `{cmd_line}`"),
+    }
+    .emit();
+    let status = Command::new(&command[0])
+        .args(&command[1..])
+        .status()
+        .with_context(|| format!("failed to run `{cmd_line}`"))?;
+    if !status.success() {
+        bail!("failed to install system packages, try running `{cmd_line}` manually");
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn install(
+    _path: &Path,
+    _info: Option<&ToolInfo>,
+    _config: &InstallConfiguration,
+) -> Result<()> {
+    Ok(())
+}
+
+pub(super) fn uninstall() -> Result<()> {
+    // Other programs might depend on these packages, leave them to the user.
+    #[cfg(target_os = "linux")]
+    info!("system packages are kept, remove them using your package manager if not needed");
+    Ok(())
+}
+
+/// Find the C compiler if none of the packages are missing.
+#[cfg(target_os = "linux")]
+pub(super) fn detect(info: Option<&ToolInfo>) -> Option<super::Detection> {
+    let (_, missing) = missing_packages(info)?;
+    if !missing.is_empty() {
+        return None;
+    }
+    let cc = crate::utils::find_in_path("cc")?;
+    let version = crate::utils::execute_for_output(&cc, &["-dumpversion"])
+        .ok()
+        .map(|out| out.trim().to_string());
+    Some(super::Detection {
+        path: Some(cc),
+        version,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn detect(_info: Option<&ToolInfo>) -> Option<super::Detection> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+pub(super) fn already_installed() -> bool {
+    missing_packages(None).is_some_and(|(_, missing)| missing.is_empty())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn already_installed() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packages_with_extra_ones() {
+        let extra = vec!["libudev-dev".to_string(), "pkg-config".to_string()];
+        assert_eq!(
+            PackageManager::Apt.packages(&extra),
+            ["build-essential", "pkg-config", "libssl-dev", "libudev-dev"]
+        );
+        assert_eq!(
+            PackageManager::Pacman.packages(&[]),
+            ["base-devel", "openssl"]
+        );
+    }
+
+    #[test]
+    fn install_commands() {
+        let packages = vec!["gcc".to_string(), "make".to_string()];
+        assert_eq!(
+            PackageManager::Apt.install_command(&packages).join(" "),
+            "apt-get install -y gcc make"
+        );
+        assert_eq!(
+            PackageManager::Dnf.install_command(&packages).join(" "),
+            "dnf install -y gcc make"
+        );
+        assert_eq!(
+            PackageManager::Pacman.install_command(&packages).join(" "),
+            "pacman -S --needed --noconfirm gcc make"
+        );
+        assert_eq!(
+            PackageManager::Zypper.install_command(&packages).join(" "),
+            "zypper --non-interactive install gcc make"
+        );
+    }
+}
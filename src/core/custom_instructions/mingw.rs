@@ -0,0 +1,101 @@
+//! Custom install method for the `MinGW-w64` toolchain, which is needed by `*-windows-gnu` targets.
+//!
+//! The toolchain is distributed as an archive (with a `mingw64` or `mingw32` root folder),
+//! so we just need to extract it into the tools directory, then add its `bin/` folder to path.
+
+use crate::core::install::InstallConfiguration;
+use crate::manifest::ToolInfo;
+use anyhow::Result;
+use std::path::Path;
+
+#[cfg(windows)]
+pub(super) fn install(
+    path: &Path,
+    _info: Option<&ToolInfo>,
+    config: &InstallConfiguration,
+) -> Result<()> {
+    use crate::utils;
+    use anyhow::anyhow;
+    use std::path::PathBuf;
+
+    /// Find the root of MinGW distribution, which is the one that has `bin/gcc.exe` in it.
+    fn find_mingw_root(path: &Path) -> Option<PathBuf> {
+        let is_root = |p: &Path| p.join("bin").join("gcc.exe").is_file();
+        if is_root(path) {
+            return Some(path.to_path_buf());
+        }
+        // Keep looking in sub dir, as the archive usually has a `mingw64` folder as its root.
+        let Ok(entries) = utils::walk_dir(path, false) else {
+            return None;
+        };
+        entries.into_iter().find(|p| p.is_dir() && is_root(p))
+    }
+
+    let mingw_root = find_mingw_root(path)
+        .ok_or_else(|| anyhow!("unable to find `gcc.exe` in the MinGW distribution."))?;
+
+    // Step 1: Move the root of the directory into `tools` directory
+    let mingw_dir = config.tools_dir().join("mingw");
+    utils::move_to(&mingw_root, &mingw_dir, true)?;
+
+    // Step 2: Add the `bin/` folder to path
+    config.add_to_path(&mingw_dir.join("bin"))?;
+
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub(super) fn install(
+    _path: &Path,
+    _info: Option<&ToolInfo>,
+    _config: &InstallConfiguration,
+) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(windows)]
+pub(super) fn uninstall() -> Result<()> {
+    use crate::core::os::install_dir_from_exe_path;
+    use crate::core::os::remove_from_path;
+
+    // We've added a path for MinGW at `<InstallDir>/tools/mingw/bin`, try removing it from `PATH`.
+    let mut mingw_bin = install_dir_from_exe_path()?;
+    mingw_bin.push("tools");
+    mingw_bin.push("mingw");
+    mingw_bin.push("bin");
+    remove_from_path(&mingw_bin)?;
+
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub(super) fn uninstall() -> Result<()> {
+    Ok(())
+}
+
+#[cfg(windows)]
+pub(super) fn detect(_info: Option<&ToolInfo>) -> Option<super::Detection> {
+    let gcc = crate::utils::find_in_path("gcc.exe")?;
+    let version = crate::utils::execute_for_output(&gcc, &["-dumpversion"])
+        .ok()
+        .map(|out| out.trim().to_string());
+    Some(super::Detection {
+        path: Some(gcc),
+        version,
+    })
+}
+
+#[cfg(not(windows))]
+pub(super) fn detect(_info: Option<&ToolInfo>) -> Option<super::Detection> {
+    None
+}
+
+#[cfg(windows)]
+pub(super) fn already_installed() -> bool {
+    crate::utils::cmd_exist("gcc.exe")
+}
+
+#[cfg(not(windows))]
+pub(super) fn already_installed() -> bool {
+    true
+}
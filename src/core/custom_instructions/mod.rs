@@ -3,10 +3,15 @@ macro_rules! declare_instrcutions {
         $(pub(crate) mod $name;)*
         pub(crate) static SUPPORTED_TOOLS: &[&str] = &[$(stringify!($name)),+];
 
-        pub(crate) fn install(tool: &str, path: &std::path::Path, config: &super::install::InstallConfiguration) -> anyhow::Result<()> {
+        pub(crate) fn install(
+            tool: &str,
+            path: &std::path::Path,
+            info: Option<&crate::manifest::ToolInfo>,
+            config: &super::install::InstallConfiguration,
+        ) -> anyhow::Result<()> {
             match tool.replace('-', "_").as_str() {
                 $(
-                    stringify!($name) => $name::install(path, config),
+                    stringify!($name) => $name::install(path, info, config),
                 )*
                 _ => anyhow::bail!("no custom install instruction for '{tool}'")
             }
@@ -21,6 +26,18 @@ macro_rules! declare_instrcutions {
             }
         }
 
+        pub(crate) fn detect(
+            tool: &str,
+            info: Option<&crate::manifest::ToolInfo>,
+        ) -> Option<Detection> {
+            match tool.replace('-', "_").as_str() {
+                $(
+                    stringify!($name) => $name::detect(info),
+                )*
+                _ => None
+            }
+        }
+
         pub(crate) fn already_installed(tool: &str) -> bool {
             match tool.replace('-', "_").as_str() {
                 $(
@@ -33,7 +50,33 @@ macro_rules! declare_instrcutions {
     };
 }
 
-declare_instrcutions!(buildtools, vscode);
+declare_instrcutions!(buildtools, linux_build_essentials, mingw, vscode);
+
+/// An existing installation of a tool found on this machine, such as the one installed by the user.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Detection {
+    /// Where the tool is installed, or where its main executable is.
+    pub(crate) path: Option<std::path::PathBuf>,
+    pub(crate) version: Option<String>,
+}
+
+pub(crate) mod declared;
+
+/// Get the system packages that a tool needs but are not installed yet, which are only
+/// reported for the [system tools](crate::manifest::ToolInfo::System), such as
+/// `linux-build-essentials`, along with the command to install them.
+pub(crate) fn missing_packages(
+    tool: &str,
+    info: &crate::manifest::ToolInfo,
+) -> Option<(Vec<String>, String)> {
+    match tool.replace('-', "_").as_str() {
+        "linux_build_essentials" => {
+            let (pm, missing) = linux_build_essentials::missing_packages(Some(info))?;
+            (!missing.is_empty()).then(|| (missing.clone(), pm.install_command(&missing).join(" ")))
+        }
+        _ => None,
+    }
+}
 
 pub(crate) fn is_supported(name: &str) -> bool {
     SUPPORTED_TOOLS.contains(&name.replace('-', "_").as_str())
@@ -4,11 +4,31 @@
 //! we need to extract it into the tools directory, set path variable with it,
 //! and then create a desktop shortcut. The last part is a bit harder to do,
 //! there's currently no suitable solution other than execute some commands to hack it.
+//!
+//! The extensions and settings declared in the manifest are installed and merged as well,
+//! check [`ToolInfo::components`] and [`ToolInfo::settings`].
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use crate::core::install::InstallConfiguration;
-use crate::{core::os::add_to_path, utils};
-use anyhow::Result;
+use crate::manifest::ToolInfo;
+use crate::utils;
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// The file under VSCode's directory to keep what we've changed outside of it,
+/// which is needed to revert these changes when uninstalling.
+const MANAGED_STATE: &str = "installer-managed.json";
+
+/// Extensions and settings that were added by us.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct ManagedState {
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    settings: Map<String, Value>,
+}
 
 #[derive(Debug)]
 pub(crate) struct VSCodeInstaller<'a> {
@@ -17,6 +37,9 @@ pub(crate) struct VSCodeInstaller<'a> {
     pub(crate) tool_name: &'a str,
     /// The full verbose name of this VSCode variant, used when creating desktop shortcut.
     pub(crate) verbose_name: &'a str,
+    /// The name of the user data folder under the config directory, such as `Code`,
+    /// which is where the user settings are stored.
+    pub(crate) data_dir_name: &'a str,
     /// The name of the main binary, which is located under the extracted folder,
     /// this is used to create desktop shortcut.
     #[cfg(windows)]
@@ -29,6 +52,7 @@ impl Default for VSCodeInstaller<'_> {
             cmd: "code",
             tool_name: "vscode",
             verbose_name: "Visual Studio Code",
+            data_dir_name: "Code",
             #[cfg(windows)]
             binary_name: "Code"
         }
@@ -36,23 +60,46 @@ impl Default for VSCodeInstaller<'_> {
 }
 
 impl VSCodeInstaller<'_> {
-    pub(crate) fn install(&self, path: &Path, config: &InstallConfiguration) -> Result<()> {
+    pub(crate) fn install(
+        &self,
+        path: &Path,
+        info: Option<&ToolInfo>,
+        config: &InstallConfiguration,
+    ) -> Result<()> {
         // Step 1: Move the root of the directory into `tools` directory
         let vscode_dir = config.tools_dir().join(self.tool_name);
         utils::move_to(path, &vscode_dir, true)?;
 
         // Step 2: Add the `bin/` folder to path
         let bin_dir = vscode_dir.join("bin");
-        add_to_path(&bin_dir)?;
+        config.add_to_path(&bin_dir)?;
+
+        // Step 3: Install extensions and merge settings declared in the manifest,
+        // then remember them so that they can be removed when uninstalling.
+        let mut state = ManagedState::default();
+        let code = self.cli_path(&vscode_dir);
+        for ext in info.map(ToolInfo::components).unwrap_or_default() {
+            info!("installing extension '{ext}' for '{}'", self.tool_name);
+            match utils::execute(&code, &["--install-extension", ext.as_str(), "--force"]) {
+                Ok(()) => state.extensions.push(ext.clone()),
+                // Extensions are not important, keep going even if it fails.
+                Err(e) => warn!("unable to install extension '{ext}': {e}"),
+            }
+        }
+        if let Some(fragment) = info.and_then(ToolInfo::settings) {
+            state.settings = self.merge_settings(&vscode_dir, fragment)?;
+        }
+        let state_json = serde_json::to_string_pretty(&state)?;
+        utils::write_file(vscode_dir.join(MANAGED_STATE), &state_json, false)?;
 
-        // Step 3: Create a shortcuts
+        // Step 4: Create a shortcuts
         // Shortcuts are not important, make sure it won't throw error even if it fails.
-        let show_no_folder_warning = || println!(
-            "warning: unable to determine which directory to put shortcut for '{}', skipping...",
+        let show_no_folder_warning = || warn!(
+            "unable to determine which directory to put shortcut for '{}', skipping...",
             self.tool_name
         );
-        let show_failure_warning = || println!(
-            "warning: unable to create a shortcut for '{}', skipping...",
+        let show_failure_warning = || warn!(
+            "unable to create a shortcut for '{}', skipping...",
             self.tool_name
         );
         #[cfg(windows)]
@@ -118,12 +165,11 @@ Keywords=vscode;
         use crate::core::os::install_dir_from_exe_path;
         use crate::core::os::remove_from_path;
 
+        let vscode_dir = install_dir_from_exe_path()?.join("tools").join(self.tool_name);
+        self.revert_managed_state(&vscode_dir);
+
         // We've added a path for VSCode at `<InstallDir>/tools/vscode/bin`, try removing it from `PATH`.
-        let mut vscode_path = install_dir_from_exe_path()?;
-        vscode_path.push("tools");
-        vscode_path.push(self.tool_name);
-        vscode_path.push("bin");
-        remove_from_path(&vscode_path)?;
+        remove_from_path(&vscode_dir.join("bin"))?;
 
         // TODO: Remove desktop shortcut and `%USERPROFILE%/.vscode`.
         // We need to see if the shortcut has the correct target before removing it,
@@ -139,7 +185,7 @@ Keywords=vscode;
             };
             if let Ok(content) = utils::read_to_string(&filepath) {
                 if content.contains(&format!("# Generated by {}", env!("CARGO_PKG_NAME"))) && utils::remove(&filepath).is_err() {
-                    println!("warning: unable to remove shortcut file '{}'", filepath.display());
+                    warn!("unable to remove shortcut file '{}'", filepath.display());
                     return Ok(());
                 }
             }
@@ -151,16 +197,261 @@ Keywords=vscode;
     pub(crate) fn already_installed(&self) -> bool {
         utils::cmd_exist(self.cmd)
     }
+
+    /// Find an existing installation of VSCode in `PATH`, along with its version.
+    pub(crate) fn detect(&self) -> Option<super::Detection> {
+        let cli = utils::find_in_path(&self.cli_name())?;
+        let version = utils::execute_for_output(&cli, &["--version"])
+            .ok()
+            .and_then(|out| out.lines().next().map(|line| line.trim().to_string()));
+        Some(super::Detection {
+            path: Some(cli),
+            version,
+        })
+    }
+
+    /// Get the file name of the command line interface, which is a script under `bin/`.
+    fn cli_name(&self) -> String {
+        if cfg!(windows) {
+            format!("{}.cmd", self.cmd)
+        } else {
+            self.cmd.to_string()
+        }
+    }
+
+    /// Get the path to the command line interface under `vscode_dir`.
+    fn cli_path(&self, vscode_dir: &Path) -> PathBuf {
+        vscode_dir.join("bin").join(self.cli_name())
+    }
+
+    /// Get the path to the user `settings.json`, which is under the `data` folder
+    /// of `vscode_dir` in portable mode.
+    fn settings_path(&self, vscode_dir: &Path) -> Option<PathBuf> {
+        let portable_data = vscode_dir.join("data");
+        let user_data = if portable_data.is_dir() {
+            portable_data.join("user-data")
+        } else {
+            dirs::config_dir()?.join(self.data_dir_name)
+        };
+        Some(user_data.join("User").join("settings.json"))
+    }
+
+    /// Merge the settings `fragment` into the user settings, then return the ones that were added.
+    ///
+    /// The settings that were already set by the user are kept unchanged.
+    fn merge_settings(&self, vscode_dir: &Path, fragment: &str) -> Result<Map<String, Value>> {
+        let fragment: Map<String, Value> = serde_json::from_str(fragment)
+            .with_context(|| format!("invalid settings of '{}' in the manifest", self.tool_name))?;
+        let Some(path) = self.settings_path(vscode_dir) else {
+            warn!(
+                "unable to determine where the settings of '{}' are, skipping...",
+                self.tool_name
+            );
+            return Ok(Map::new());
+        };
+        let mut settings = if path.is_file() {
+            // The settings file allows comments, which cannot be parsed, leave it to the user then.
+            match serde_json::from_str::<Map<String, Value>>(&utils::read_to_string(&path)?) {
+                Ok(settings) => settings,
+                Err(_) => {
+                    warn!(
+                        "unable to merge settings into '{}', skipping...",
+                        path.display()
+                    );
+                    return Ok(Map::new());
+                }
+            }
+        } else {
+            Map::new()
+        };
+
+        let added = fragment
+            .into_iter()
+            .filter(|(key, _)| !settings.contains_key(key))
+            .collect::<Map<_, _>>();
+        settings.extend(added.clone());
+        utils::ensure_parent_dir(&path)?;
+        utils::write_file(&path, &serde_json::to_string_pretty(&settings)?, false)?;
+        Ok(added)
+    }
+
+    /// Uninstall the extensions and remove the settings that were added when installing,
+    /// unless they have been changed since then.
+    ///
+    /// Failures are ignored (with warnings), as they should not block the uninstallation.
+    fn revert_managed_state(&self, vscode_dir: &Path) {
+        let Ok(content) = utils::read_to_string(vscode_dir.join(MANAGED_STATE)) else {
+            return;
+        };
+        let Ok(state) = serde_json::from_str::<ManagedState>(&content) else {
+            return;
+        };
+
+        let code = self.cli_path(vscode_dir);
+        for ext in &state.extensions {
+            info!("uninstalling extension '{ext}' for '{}'", self.tool_name);
+            if utils::execute(&code, &["--uninstall-extension", ext.as_str()]).is_err() {
+                warn!("unable to uninstall extension '{ext}', skipping...");
+            }
+        }
+
+        if state.settings.is_empty() {
+            return;
+        }
+        let Some(path) = self.settings_path(vscode_dir).filter(|p| p.is_file()) else {
+            return;
+        };
+        let Ok(mut settings) = utils::read_to_string(&path)
+            .and_then(|s| Ok(serde_json::from_str::<Map<String, Value>>(&s)?))
+        else {
+            warn!("unable to remove settings from '{}', skipping...", path.display());
+            return;
+        };
+        settings.retain(|key, val| state.settings.get(key) != Some(val));
+        let reverted = serde_json::to_string_pretty(&settings)
+            .map_err(anyhow::Error::from)
+            .and_then(|s| utils::write_file(&path, &s, false));
+        if reverted.is_err() {
+            warn!("unable to remove settings from '{}', skipping...", path.display());
+        }
+    }
 }
 
-pub(super) fn install(path: &Path, config: &InstallConfiguration) -> Result<()> {
-    VSCodeInstaller::default().install(path, config)
+pub(super) fn install(
+    path: &Path,
+    info: Option<&ToolInfo>,
+    config: &InstallConfiguration,
+) -> Result<()> {
+    VSCodeInstaller::default().install(path, info, config)
 }
 
 pub(super) fn uninstall() -> Result<()> {
     VSCodeInstaller::default().uninstall()
 }
 
+pub(super) fn detect(_info: Option<&ToolInfo>) -> Option<super::Detection> {
+    VSCodeInstaller::default().detect()
+}
+
 pub(super) fn already_installed() -> bool {
     VSCodeInstaller::default().already_installed()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Set up a portable VSCode directory, so that its settings are stored inside of it.
+    fn portable_vscode_dir(root: &Path, user_settings: Value) -> PathBuf {
+        let vscode_dir = root.join("vscode");
+        let settings = vscode_dir.join("data").join("user-data").join("User");
+        utils::ensure_dir(&settings).unwrap();
+        utils::write_file(
+            settings.join("settings.json"),
+            &serde_json::to_string_pretty(&user_settings).unwrap(),
+            false,
+        )
+        .unwrap();
+        vscode_dir
+    }
+
+    fn read_settings(installer: &VSCodeInstaller, vscode_dir: &Path) -> Value {
+        let path = installer.settings_path(vscode_dir).unwrap();
+        serde_json::from_str(&utils::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn save_state(vscode_dir: &Path, settings: Map<String, Value>) {
+        let state = ManagedState {
+            extensions: vec![],
+            settings,
+        };
+        let state_json = serde_json::to_string_pretty(&state).unwrap();
+        utils::write_file(vscode_dir.join(MANAGED_STATE), &state_json, false).unwrap();
+    }
+
+    #[test]
+    fn merge_then_revert_settings() {
+        let root = tempfile::tempdir().unwrap();
+        let installer = VSCodeInstaller::default();
+        let vscode_dir = portable_vscode_dir(
+            root.path(),
+            json!({ "editor.fontSize": 14, "files.eol": "\n" }),
+        );
+
+        let fragment = r#"{ "editor.fontSize": 12, "rust-analyzer.check.command": "clippy" }"#;
+        let added = installer.merge_settings(&vscode_dir, fragment).unwrap();
+        assert_eq!(
+            Value::Object(added.clone()),
+            json!({ "rust-analyzer.check.command": "clippy" })
+        );
+        // The value set by the user is not overridden.
+        assert_eq!(
+            read_settings(&installer, &vscode_dir),
+            json!({
+                "editor.fontSize": 14,
+                "files.eol": "\n",
+                "rust-analyzer.check.command": "clippy",
+            })
+        );
+
+        save_state(&vscode_dir, added);
+        installer.revert_managed_state(&vscode_dir);
+        assert_eq!(
+            read_settings(&installer, &vscode_dir),
+            json!({ "editor.fontSize": 14, "files.eol": "\n" })
+        );
+    }
+
+    #[test]
+    fn revert_settings_keeps_user_changes() {
+        let root = tempfile::tempdir().unwrap();
+        let installer = VSCodeInstaller::default();
+        let vscode_dir = portable_vscode_dir(root.path(), json!({ "editor.fontSize": 14 }));
+
+        let fragment = r#"{ "rust-analyzer.check.command": "clippy", "editor.formatOnSave": true }"#;
+        let added = installer.merge_settings(&vscode_dir, fragment).unwrap();
+        save_state(&vscode_dir, added);
+
+        // The user changes one of the added settings and adds a new one after installation.
+        let mut settings = read_settings(&installer, &vscode_dir);
+        settings["editor.formatOnSave"] = json!(false);
+        settings["workbench.colorTheme"] = json!("Default Dark+");
+        utils::write_file(
+            installer.settings_path(&vscode_dir).unwrap(),
+            &serde_json::to_string_pretty(&settings).unwrap(),
+            false,
+        )
+        .unwrap();
+
+        installer.revert_managed_state(&vscode_dir);
+        assert_eq!(
+            read_settings(&installer, &vscode_dir),
+            json!({
+                "editor.fontSize": 14,
+                "editor.formatOnSave": false,
+                "workbench.colorTheme": "Default Dark+",
+            })
+        );
+    }
+
+    #[test]
+    fn merge_into_missing_settings_then_revert() {
+        let root = tempfile::tempdir().unwrap();
+        let installer = VSCodeInstaller::default();
+        let vscode_dir = root.path().join("vscode");
+        utils::ensure_dir(vscode_dir.join("data")).unwrap();
+
+        let added = installer
+            .merge_settings(&vscode_dir, r#"{ "editor.formatOnSave": true }"#)
+            .unwrap();
+        assert_eq!(
+            read_settings(&installer, &vscode_dir),
+            json!({ "editor.formatOnSave": true })
+        );
+
+        save_state(&vscode_dir, added);
+        installer.revert_managed_state(&vscode_dir);
+        assert_eq!(read_settings(&installer, &vscode_dir), json!({}));
+    }
+}
@@ -0,0 +1,285 @@
+//! Self-diagnostic of an existing installation, base on the installation record.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use url::Url;
+
+use super::install::default_rustup_dist_server;
+use super::parser::{fingerprint::InstallationRecord, ManifestParser};
+use super::{CARGO_HOME, RUSTUP_DIST_SERVER, RUSTUP_HOME};
+use crate::manifest::{baked_in_manifest, Proxy};
+use crate::utils::{self, OutputEvent};
+
+/// Run a set of checks on the installation under `install_dir`, including:
+///
+/// - The `.cargo` and `.rustup` directories exist.
+/// - The recorded environment variables are set, with `CARGO_HOME` and `RUSTUP_HOME`
+///   pointing to this installation.
+/// - The `cargo` bin directory and the other recorded paths are in the `PATH` variable.
+/// - `cargo`, `rustc` and `rustup` can be executed and report their versions.
+/// - The rustup dist server and the cargo registry mirror are reachable,
+///   unless `offline` is `true`.
+/// - The files or directories of each recorded tool exist.
+///
+/// An [`OutputEvent::Check`] will be emitted for each check, with a suggested fix
+/// for each failed one.
+///
+/// # Errors
+///
+/// Return `Err` if the installation record cannot be read, or any of the checks fails.
+pub(crate) fn check_installation(install_dir: &Path, offline: bool) -> Result<()> {
+    let mut failures = 0_usize;
+    let mut report = |ok: bool, desc: String, fix: &dyn Fn() -> String| {
+        let fix = (!ok).then(fix);
+        OutputEvent::Check {
+            desc: &desc,
+            ok,
+            fix: fix.as_deref(),
+        }
+        .emit();
+        if !ok {
+            failures += 1;
+        }
+    };
+    let reinstall = || "reinstall the toolkit with the installer".to_string();
+    let restart =
+        || "restart the terminal (or log out and back in) to reload the environment".to_string();
+
+    let record_path = InstallationRecord::path_under(install_dir);
+    report(
+        record_path.is_file(),
+        format!("installation record '{}' exists", record_path.display()),
+        &reinstall,
+    );
+    let record = if record_path.is_file() {
+        InstallationRecord::load(&record_path)?
+    } else {
+        InstallationRecord::load_or_new(install_dir)?
+    };
+
+    let cargo_home = install_dir.join(".cargo");
+    let rustup_home = install_dir.join(".rustup");
+    for dir in [&cargo_home, &rustup_home] {
+        report(
+            dir.is_dir(),
+            format!("directory '{}' exists", dir.display()),
+            &reinstall,
+        );
+    }
+
+    for name in &record.env_vars {
+        report(
+            env::var_os(name).is_some(),
+            format!("environment variable `{name}` is set"),
+            &restart,
+        );
+    }
+    let mut homes = vec![(CARGO_HOME, &cargo_home)];
+    if record.external_rustup.is_none() {
+        homes.push((RUSTUP_HOME, &rustup_home));
+    }
+    for (name, expected) in homes {
+        let actual = env::var_os(name).map(PathBuf::from);
+        report(
+            actual.as_ref() == Some(expected),
+            format!("`{name}` points to '{}'", expected.display()),
+            &|| match &actual {
+                Some(other) => format!(
+                    "`{name}` is set to '{}', which might belong to another Rust installation, \
+                    unset it or set it to '{}'",
+                    other.display(),
+                    expected.display()
+                ),
+                None => restart(),
+            },
+        );
+    }
+
+    let cargo_bin = cargo_home.join("bin");
+    let path_var = env::var_os("PATH").unwrap_or_default();
+    let paths_in_env = env::split_paths(&path_var).collect::<Vec<_>>();
+    let mut paths = vec![&cargo_bin];
+    paths.extend(record.paths.iter().filter(|p| **p != cargo_bin));
+    for path in paths {
+        report(
+            paths_in_env.contains(path),
+            format!("'{}' is in `PATH`", path.display()),
+            &|| {
+                format!(
+                    "{}, or add '{}' to `PATH` manually",
+                    restart(),
+                    path.display()
+                )
+            },
+        );
+    }
+
+    let rustup = record
+        .external_rustup
+        .clone()
+        .unwrap_or_else(|| cargo_bin.join(format!("rustup{}", utils::EXE_EXT)));
+    let programs = ["cargo", "rustc"].map(|program| {
+        (
+            program,
+            cargo_bin.join(format!("{program}{}", utils::EXE_EXT)),
+        )
+    });
+    for (program, program_path) in programs.into_iter().chain([("rustup", rustup)]) {
+        report(
+            utils::execute(&program_path, &["--version"]).is_ok(),
+            format!("`{program} --version` runs successfully"),
+            &|| {
+                if program == "rustup" {
+                    reinstall()
+                } else {
+                    "run `rustup toolchain list` to check the default toolchain, \
+                    or run `manager update` to reinstall it"
+                        .to_string()
+                }
+            },
+        );
+    }
+
+    if !offline {
+        let proxy = baked_in_manifest().ok().and_then(|m| m.proxy_or_env());
+        let dist_server = env::var(RUSTUP_DIST_SERVER)
+            .ok()
+            .and_then(|s| Url::parse(&s).ok())
+            .unwrap_or_else(|| default_rustup_dist_server().clone());
+        let result = utils::check_reachable(&dist_server, proxy.as_ref());
+        report(
+            result.is_ok(),
+            format!("rustup dist server '{dist_server}' is reachable"),
+            &|| {
+                format!(
+                    "{}, check the network and proxy settings, \
+                    or change the server with `manager config set dist-server <URL>`",
+                    error_of(&result)
+                )
+            },
+        );
+
+        let config_path = cargo_home.join("config.toml");
+        match registry_mirror(&config_path) {
+            Ok(Some(mirror)) => {
+                let result = check_registry(&mirror, proxy.as_ref());
+                report(
+                    result.is_ok(),
+                    format!("cargo registry mirror '{mirror}' is reachable"),
+                    &|| {
+                        format!(
+                            "{}, check the network and proxy settings, \
+                            or change the registry in '{}'",
+                            error_of(&result),
+                            config_path.display()
+                        )
+                    },
+                );
+            }
+            Ok(None) => (),
+            Err(e) => report(
+                false,
+                format!("cargo config '{}' is valid", config_path.display()),
+                &|| format!("{e}, fix or remove the file"),
+            ),
+        }
+    }
+
+    for (name, tool) in &record.tools {
+        for path in &tool.paths {
+            report(
+                path.exists(),
+                format!("'{}' of tool '{name}' exists", path.display()),
+                &|| format!("reinstall it with `manager component add {name}`"),
+            );
+        }
+    }
+
+    if failures > 0 {
+        bail!("{failures} check(s) failed, the installation might be broken");
+    }
+    OutputEvent::Message {
+        text: "all checks passed",
+    }
+    .emit();
+    Ok(())
+}
+
+fn error_of(result: &Result<()>) -> String {
+    result
+        .as_ref()
+        .err()
+        .map(|e| format!("{e:#}"))
+        .unwrap_or_default()
+}
+
+/// Get the url of the registry that replaces `crates-io` in cargo's `config.toml`, if there is one.
+fn registry_mirror(config_path: &Path) -> Result<Option<String>> {
+    if !config_path.is_file() {
+        return Ok(None);
+    }
+    let config: toml::Table = toml::from_str(&utils::read_to_string(config_path)?)?;
+    let Some(sources) = config.get("source").and_then(|s| s.as_table()) else {
+        return Ok(None);
+    };
+    let Some(replace_with) = sources
+        .get("crates-io")
+        .and_then(|s| s.get("replace-with"))
+        .and_then(|s| s.as_str())
+    else {
+        return Ok(None);
+    };
+    sources
+        .get(replace_with)
+        .and_then(|s| s.get("registry"))
+        .and_then(|s| s.as_str())
+        .map(|s| Some(s.to_string()))
+        .ok_or_else(|| anyhow!("source '{replace_with}' replacing 'crates-io' has no `registry`"))
+}
+
+/// Check a registry index url, which is either a git repository or a `sparse+` one
+/// that must serve a `config.json`.
+fn check_registry(index: &str, proxy: Option<&Proxy>) -> Result<()> {
+    let url = match index.strip_prefix("sparse+") {
+        Some(sparse) => utils::force_url_join(&Url::parse(sparse)?, "config.json")?,
+        None => Url::parse(index)?,
+    };
+    utils::check_reachable(&url, proxy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_mirror_from_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        assert!(registry_mirror(&config_path).unwrap().is_none());
+
+        std::fs::write(
+            &config_path,
+            r#"
+[source.crates-io]
+replace-with = "mirror"
+
+[source.mirror]
+registry = "sparse+https://example.com/index/"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            registry_mirror(&config_path).unwrap().as_deref(),
+            Some("sparse+https://example.com/index/")
+        );
+
+        std::fs::write(
+            &config_path,
+            "[source.crates-io]\nreplace-with = \"missing\"\n",
+        )
+        .unwrap();
+        assert!(registry_mirror(&config_path).is_err());
+    }
+}
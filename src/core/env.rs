@@ -0,0 +1,296 @@
+//! Export the environment needed to use an installation in the current session, similar to
+//! the `env` script of rustup, such as for CI runners that don't load the persistent variables.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+use super::install::{default_rustup_dist_server, default_rustup_update_root};
+use super::parser::fingerprint::InstallationRecord;
+use super::parser::manifest::ToolsetManifest;
+use super::{CARGO_HOME, RUSTUP_DIST_SERVER, RUSTUP_HOME, RUSTUP_UPDATE_ROOT};
+
+/// The shells that [`EnvSnapshot`] can be written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EnvShell {
+    /// POSIX shells, such as `sh`, `bash` and `zsh`.
+    Sh,
+    Fish,
+    Nu,
+    Powershell,
+    Cmd,
+}
+
+impl EnvShell {
+    /// Detect the shell of current session, which is always PowerShell on Windows,
+    /// or judged by the `SHELL` environment variable on other systems, defaulting to `sh`.
+    pub fn detect() -> Self {
+        if cfg!(windows) {
+            return Self::Powershell;
+        }
+        match std::env::var("SHELL") {
+            Ok(sh) if sh.ends_with("fish") => Self::Fish,
+            Ok(sh) if sh.ends_with("/nu") => Self::Nu,
+            _ => Self::Sh,
+        }
+    }
+}
+
+/// The environment variables and the `PATH` entries of an installation.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct EnvSnapshot {
+    pub(crate) vars: Vec<(String, String)>,
+    /// Entries to prepend to `PATH`.
+    pub(crate) paths: Vec<PathBuf>,
+}
+
+impl EnvSnapshot {
+    /// Collect the environment of the installation under `install_dir` from its record.
+    ///
+    /// The paths of `CARGO_HOME` and `RUSTUP_HOME` are under `install_dir`, while the values of
+    /// other variables (such as `RUSTUP_DIST_SERVER`) are the recorded ones. Older installations
+    /// didn't record the values, which are taken from the `manifest` (or the defaults) instead,
+    /// and the ones that cannot be determined are skipped.
+    pub(crate) fn of_installation(install_dir: &Path, manifest: &ToolsetManifest) -> Result<Self> {
+        let record = InstallationRecord::load_or_new(install_dir)?;
+        // Older installations didn't record the names of the variables.
+        let names = if record.env_vars.is_empty() {
+            [
+                CARGO_HOME,
+                RUSTUP_HOME,
+                RUSTUP_DIST_SERVER,
+                RUSTUP_UPDATE_ROOT,
+            ]
+            .map(ToString::to_string)
+            .to_vec()
+        } else {
+            record.env_vars.clone()
+        };
+        let vars = names
+            .into_iter()
+            .filter_map(|name| {
+                let val = match name.as_str() {
+                    CARGO_HOME => install_dir.join(".cargo").to_string_lossy().into_owned(),
+                    RUSTUP_HOME if record.external_rustup.is_none() => {
+                        install_dir.join(".rustup").to_string_lossy().into_owned()
+                    }
+                    _ => match record.env_values.get(&name) {
+                        Some(val) => val.clone(),
+                        None => value_in_manifest(&name, manifest)?,
+                    },
+                };
+                Some((name, val))
+            })
+            .collect();
+        let paths = if record.paths.is_empty() {
+            vec![install_dir.join(".cargo").join("bin")]
+        } else {
+            record.paths.clone()
+        };
+        Ok(Self { vars, paths })
+    }
+
+    /// Get the value of `PATH` with the entries of this environment prepended to the existing
+    /// `PATH` of current process, or `None` if an entry contains the separator of `PATH`.
+    pub(crate) fn path_var(&self) -> Option<OsString> {
+        let existing = std::env::var_os("PATH").unwrap_or_default();
+        let paths = self
+            .paths
+            .iter()
+            .cloned()
+            .chain(std::env::split_paths(&existing));
+        std::env::join_paths(paths).ok()
+    }
+
+    /// Get the `PATH` entries as a single string joined by the separator of `shell`.
+    pub(crate) fn joined_paths(&self, shell: EnvShell) -> String {
+        let sep = match shell {
+            EnvShell::Powershell | EnvShell::Cmd => ";",
+            _ => ":",
+        };
+        self.paths
+            .iter()
+            .map(|p| p.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(sep)
+    }
+
+    /// Write the commands that set up this environment in `shell`.
+    pub(crate) fn script(&self, shell: EnvShell) -> String {
+        let mut lines = self
+            .vars
+            .iter()
+            .map(|(key, val)| match shell {
+                EnvShell::Sh => format!("export {key}={}", sh_quote(val)),
+                EnvShell::Fish => format!("set -gx {key} {}", fish_quote(val)),
+                EnvShell::Nu => format!("$env.{key} = {}", nu_quote(val)),
+                EnvShell::Powershell => format!("$env:{key} = {}", ps_quote(val)),
+                EnvShell::Cmd => format!("set \"{key}={val}\""),
+            })
+            .collect::<Vec<_>>();
+        if !self.paths.is_empty() {
+            let path = self.joined_paths(shell);
+            lines.push(match shell {
+                EnvShell::Sh => format!("export PATH={}:\"$PATH\"", sh_quote(&path)),
+                EnvShell::Fish => {
+                    let entries = self
+                        .paths
+                        .iter()
+                        .map(|p| fish_quote(&p.to_string_lossy()))
+                        .collect::<Vec<_>>();
+                    format!("set -gx PATH {} $PATH", entries.join(" "))
+                }
+                EnvShell::Nu => {
+                    let entries = self
+                        .paths
+                        .iter()
+                        .map(|p| nu_quote(&p.to_string_lossy()))
+                        .collect::<Vec<_>>();
+                    format!(
+                        "$env.PATH = ($env.PATH | split row (char esep) | prepend [{}])",
+                        entries.join(", ")
+                    )
+                }
+                EnvShell::Powershell => {
+                    format!("$env:Path = {} + ';' + $env:Path", ps_quote(&path))
+                }
+                EnvShell::Cmd => format!("set \"PATH={path};%PATH%\""),
+            });
+        }
+        lines.join("\n")
+    }
+}
+
+/// Get the value of an environment variable that an older installation would have set
+/// according to `manifest`, which didn't record the values.
+fn value_in_manifest(name: &str, manifest: &ToolsetManifest) -> Option<String> {
+    match name {
+        RUSTUP_DIST_SERVER => Some(
+            manifest
+                .rust
+                .rustup_dist_servers
+                .first()
+                .unwrap_or(default_rustup_dist_server())
+                .to_string(),
+        ),
+        RUSTUP_UPDATE_ROOT => Some(default_rustup_update_root().to_string()),
+        _ => manifest
+            .proxy
+            .as_ref()?
+            .env_vars()
+            .into_iter()
+            .find_map(|(key, val)| (key == name).then_some(val)),
+    }
+}
+
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+fn nu_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', r"\\").replace('"', "\\\""))
+}
+
+fn ps_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{EnvShell, EnvSnapshot};
+    use crate::core::parser::fingerprint::InstallationRecord;
+    use crate::manifest::ToolsetManifest;
+
+    #[test]
+    fn snapshot_from_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let install_dir = dir.path();
+        InstallationRecord::update(install_dir, |record| {
+            record.add_env_vars(["CARGO_HOME", "RUSTUP_DIST_SERVER", "RUSTUP_UPDATE_ROOT"]);
+            record.set_env_value("RUSTUP_DIST_SERVER", Some("https://dist.example.com"));
+            record.add_path(&install_dir.join(".cargo").join("bin"));
+        })
+        .unwrap();
+
+        let snapshot =
+            EnvSnapshot::of_installation(install_dir, &ToolsetManifest::default()).unwrap();
+        assert_eq!(
+            snapshot.vars,
+            [
+                (
+                    "CARGO_HOME".to_string(),
+                    install_dir.join(".cargo").to_string_lossy().into_owned()
+                ),
+                (
+                    "RUSTUP_DIST_SERVER".to_string(),
+                    "https://dist.example.com".to_string()
+                ),
+                (
+                    "RUSTUP_UPDATE_ROOT".to_string(),
+                    crate::core::install::default_rustup_update_root().to_string()
+                ),
+            ]
+        );
+        assert_eq!(snapshot.paths, [install_dir.join(".cargo").join("bin")]);
+        let path = snapshot.path_var().unwrap();
+        assert_eq!(
+            std::env::split_paths(&path).next(),
+            Some(install_dir.join(".cargo").join("bin"))
+        );
+    }
+
+    #[test]
+    fn env_scripts() {
+        let snapshot = EnvSnapshot {
+            vars: vec![
+                ("CARGO_HOME".into(), "/opt/rust/.cargo".into()),
+                (
+                    "RUSTUP_DIST_SERVER".into(),
+                    "https://it's.example.com".into(),
+                ),
+            ],
+            paths: vec![
+                PathBuf::from("/opt/rust/.cargo/bin"),
+                PathBuf::from("/opt/rust/tools"),
+            ],
+        };
+        assert_eq!(
+            snapshot.script(EnvShell::Sh),
+            r#"export CARGO_HOME='/opt/rust/.cargo'
+export RUSTUP_DIST_SERVER='https://it'\''s.example.com'
+export PATH='/opt/rust/.cargo/bin:/opt/rust/tools':"$PATH""#
+        );
+        assert_eq!(
+            snapshot.script(EnvShell::Fish),
+            r#"set -gx CARGO_HOME '/opt/rust/.cargo'
+set -gx RUSTUP_DIST_SERVER 'https://it\'s.example.com'
+set -gx PATH '/opt/rust/.cargo/bin' '/opt/rust/tools' $PATH"#
+        );
+        assert_eq!(
+            snapshot.script(EnvShell::Nu),
+            r#"$env.CARGO_HOME = "/opt/rust/.cargo"
+$env.RUSTUP_DIST_SERVER = "https://it's.example.com"
+$env.PATH = ($env.PATH | split row (char esep) | prepend ["/opt/rust/.cargo/bin", "/opt/rust/tools"])"#
+        );
+        assert_eq!(
+            snapshot.script(EnvShell::Powershell),
+            r#"$env:CARGO_HOME = '/opt/rust/.cargo'
+$env:RUSTUP_DIST_SERVER = 'https://it''s.example.com'
+$env:Path = '/opt/rust/.cargo/bin;/opt/rust/tools' + ';' + $env:Path"#
+        );
+        assert_eq!(
+            snapshot.script(EnvShell::Cmd),
+            r#"set "CARGO_HOME=/opt/rust/.cargo"
+set "RUSTUP_DIST_SERVER=https://it's.example.com"
+set "PATH=/opt/rust/.cargo/bin;/opt/rust/tools;%PATH%""#
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Errors of the main failure categories during installation.
+//!
+//! These are wrapped in [`anyhow::Error`] like any other errors, so the human readable
+//! messages are preserved, while the frontends can still tell them apart using [`error_code`].
+
+use std::path::PathBuf;
+
+use indicatif::HumanBytes;
+use thiserror::Error;
+use url::Url;
+
+#[derive(Debug, Error)]
+pub enum InstallError {
+    #[error("the installation directory cannot be a root directory")]
+    RootInstallDir,
+    #[error(
+        "the installation directory appears to be corrupted, \
+        try manually remove the following directory:\n\n{}\n",
+        .0.display()
+    )]
+    CorruptedInstallDir(PathBuf),
+    #[error("failed to download '{name}' from '{url}'")]
+    DownloadFailed { name: String, url: Url },
+    #[error(
+        "checksum mismatch for '{name}' downloaded from '{url}', \
+        expected '{expected}' but got '{actual}'"
+    )]
+    ChecksumMismatch {
+        name: String,
+        url: Url,
+        expected: String,
+        actual: String,
+    },
+    #[error(
+        "signature verification failed for '{name}', the file '{}' might have been tampered with",
+        .path.display()
+    )]
+    SignatureMismatch { name: String, path: PathBuf },
+    #[error("failed to install '{0}'")]
+    ToolInstallFailed(String),
+    /// Several tools failed to install while the others were still being installed,
+    /// sharing the code of [`ToolInstallFailed`](Self::ToolInstallFailed).
+    #[error("failed to install the following tool(s) using cargo: {}", .0.join(", "))]
+    ToolsInstallFailed(Vec<String>),
+    #[error("'{0}' is not a supported archive format")]
+    UnsupportedArchive(String),
+    #[error(
+        "the toolset manifest '{}' cannot be verified by any of the trusted public keys",
+        .0.display()
+    )]
+    UntrustedManifest(PathBuf),
+    #[error(
+        "the toolset manifest version {version} is not supported, \
+        this program only supports manifests up to version {supported}, please upgrade it"
+    )]
+    UnsupportedManifest { version: u32, supported: u32 },
+    #[error(
+        "not enough disk space to install into '{}', \
+        about {} is required but only {} is available, \
+        please free up some space or choose another installation directory",
+        .path.display(),
+        HumanBytes(*.required),
+        HumanBytes(*.available)
+    )]
+    InsufficientSpace {
+        path: PathBuf,
+        required: u64,
+        available: u64,
+    },
+    #[error(
+        "installing for all users requires administrator privileges, \
+        please run this program again as {}",
+        if cfg!(windows) { "administrator" } else { "root (i.e. using `sudo`)" }
+    )]
+    InsufficientPrivileges,
+    #[error(
+        "unable to add '{}' to PATH, as PATH would be {len} characters long, \
+        exceeding the limit of {max} characters, \
+        please remove some unused entries from it and try again{hints}",
+        .path.display()
+    )]
+    PathTooLong {
+        path: PathBuf,
+        len: usize,
+        max: usize,
+        /// Problems found in the existing PATH, each in a new line.
+        hints: String,
+    },
+    #[error("the installation was cancelled")]
+    Cancelled,
+    #[error(
+        "another operation{} is in progress in '{}', \
+        please wait for it to finish, or run again with `--force-unlock` if that process is stuck",
+        .pid.map(|pid| format!(" (process {pid})")).unwrap_or_default(),
+        .install_dir.display()
+    )]
+    Locked {
+        install_dir: PathBuf,
+        /// The PID of the process holding the lock, `None` if it cannot be read.
+        pid: Option<u32>,
+    },
+}
+
+impl InstallError {
+    /// A stable identifier of this kind of error, which is emitted in JSON output.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::RootInstallDir => "root-install-dir",
+            Self::CorruptedInstallDir(_) => "corrupted-install-dir",
+            Self::DownloadFailed { .. } => "download-failed",
+            Self::ChecksumMismatch { .. } => "checksum-mismatch",
+            Self::SignatureMismatch { .. } => "signature-mismatch",
+            Self::ToolInstallFailed(_) | Self::ToolsInstallFailed(_) => "tool-install-failed",
+            Self::UnsupportedArchive(_) => "unsupported-archive",
+            Self::UntrustedManifest(_) => "untrusted-manifest",
+            Self::UnsupportedManifest { .. } => "unsupported-manifest",
+            Self::InsufficientSpace { .. } => "insufficient-space",
+            Self::InsufficientPrivileges => "insufficient-privileges",
+            Self::PathTooLong { .. } => "path-too-long",
+            Self::Cancelled => "cancelled",
+            Self::Locked { .. } => "locked",
+        }
+    }
+}
+
+/// Get the [code](InstallError::code) of the [`InstallError`] in an error,
+/// including the ones that were wrapped with contexts.
+pub fn error_code(err: &anyhow::Error) -> Option<&'static str> {
+    err.downcast_ref::<InstallError>().map(InstallError::code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn error_code_through_contexts() {
+        let err = Err::<(), _>(InstallError::UnsupportedArchive("rar".into()))
+            .context("unable to extract")
+            .context("failed to install 'foo'")
+            .unwrap_err();
+        assert_eq!(error_code(&err), Some("unsupported-archive"));
+        assert_eq!(err.to_string(), "failed to install 'foo'");
+
+        let err = Err::<(), _>(anyhow::anyhow!("network is down"))
+            .with_context(|| InstallError::ToolInstallFailed("foo".into()))
+            .unwrap_err();
+        assert_eq!(error_code(&err), Some("tool-install-failed"));
+
+        let err = anyhow::Error::from(InstallError::ToolsInstallFailed(vec![
+            "foo".into(),
+            "bar".into(),
+        ]));
+        assert_eq!(error_code(&err), Some("tool-install-failed"));
+        assert_eq!(
+            err.to_string(),
+            "failed to install the following tool(s) using cargo: foo, bar"
+        );
+
+        let err = Err::<(), _>(anyhow::anyhow!("signature mismatch"))
+            .with_context(|| InstallError::SignatureMismatch {
+                name: "foo".into(),
+                path: PathBuf::from("foo.tar.gz"),
+            })
+            .unwrap_err();
+        assert_eq!(error_code(&err), Some("signature-mismatch"));
+
+        assert_eq!(error_code(&anyhow::anyhow!("oops")), None);
+    }
+}
@@ -1,24 +1,56 @@
 use super::{
+    custom_instructions,
+    error::InstallError,
+    lock,
     parser::{
         cargo_config::CargoConfig,
-        manifest::{ToolInfo, ToolsetManifest},
-        TomlParser,
+        fingerprint::{InstallStep, InstallationRecord, ToolRecord, ToolSource},
+        git_config,
+        manifest::{Patch, Signature, ToolInfo, ToolsetManifest},
+        ManifestParser,
     },
-    rustup::Rustup,
+    report::ExistingToolChoice,
+    rustup::{self, Rustup, RUSTUP},
+    shims,
+    store::Store,
+    tool_versions,
     tools::Tool,
-    CARGO_HOME, RUSTUP_DIST_SERVER, RUSTUP_HOME, RUSTUP_UPDATE_ROOT,
+    verify, CARGO_HOME, RUSTUP_DIST_SERVER, RUSTUP_HOME, RUSTUP_UPDATE_ROOT, TOOL_INSTALL_DIR,
 };
 use crate::{
     core::os::add_to_path,
     manifest::Proxy,
-    utils::{self, Extractable, MultiThreadProgress},
+    utils::{self, Extractable, ExtractableKind, MultiThreadProgress, OutputEvent, ProgressEvent},
 };
 use anyhow::{anyhow, bail, Context, Result};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
 use tempfile::TempDir;
 use url::Url;
 
+/// Entries under the temporary directory older than this are considered
+/// left over by a crashed run, which will be removed in [`InstallConfiguration::init`].
+const STALE_TEMP_ENTRY_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// The name of the CA certificate installed under `install_dir`, check [`ToolsetManifest::ca_cert`].
+const CA_CERT_FILENAME: &str = "ca-cert.pem";
+/// The name of the bundle of the installed CA certificate and the system root certificates,
+/// which is what `CARGO_HTTP_CAINFO` and `SSL_CERT_FILE` point to.
+const CA_BUNDLE_FILENAME: &str = "ca-bundle.pem";
+/// The name of the git configuration file written under `install_dir`, check [`GitSettings`].
+///
+/// [`GitSettings`]: super::parser::manifest::GitSettings
+const GITCONFIG_FILENAME: &str = "gitconfig";
+/// The rough size of an installed Rust toolchain, used to estimate the required disk space.
+const RUST_TOOLCHAIN_SIZE: u64 = 1 << 30;
+/// How many times larger the extracted files roughly are than the downloaded archives.
+const EXTRACTION_FACTOR: u64 = 3;
+
 macro_rules! declare_unfallible_url {
     ($($name:ident($global:ident) -> $val:literal);+) => {
         $(
@@ -44,10 +76,13 @@ macro_rules! declare_install_paths {
 
 /// Get the once-locked path under install_dir, and create that directory if it does not exists.
 macro_rules! get_path_and_create {
-    ($path_ident:ident, $init:expr) => {{
+    ($self:ident, $path_ident:ident, $init:expr) => {{
         let __path__ = $path_ident.get_or_init(|| $init);
-        $crate::utils::ensure_dir(__path__)
-            .expect("unable to create one of the directory under installation folder");
+        // Nothing should be created in dry-run mode.
+        if !$self.dry_run {
+            $crate::utils::ensure_dir(__path__)
+                .expect("unable to create one of the directory under installation folder");
+        }
         __path__
     }};
 }
@@ -62,7 +97,8 @@ declare_install_paths!(
     CARGO_BIN_DIR,
     RUSTUP_HOME_DIR,
     TEMP_DIR,
-    TOOLS_DIR
+    TOOLS_DIR,
+    DOWNLOAD_DIR
 );
 
 /// Contains definition of installation steps, including pre-install configs.
@@ -81,7 +117,12 @@ pub trait EnvConfig {
 // make sure to change `installer/src/utils/types/InstallConfiguration.ts` as well.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct InstallConfiguration {
+    /// The registry used to replace `crates-io`, in `(name, url)` form.
     pub cargo_registry: Option<(String, String)>,
+    /// Additional named registries in `(name, url)` form, which will be written
+    /// into cargo's `config.toml` as `[registries.<name>]`.
+    #[serde(default)]
+    pub cargo_registries: Vec<(String, String)>,
     /// Path to install everything.
     ///
     /// Note that this folder will includes `.cargo` and `.rustup` folders as well.
@@ -94,6 +135,53 @@ pub struct InstallConfiguration {
     pub rustup_update_root: Url,
     /// Indicates whether `cargo` was already installed, useful when installing third-party tools.
     cargo_is_installed: bool,
+    /// Do not use (or store) the downloaded files in [`download_cache_dir`](Self::download_cache_dir).
+    #[serde(default)]
+    no_cache: bool,
+    /// The maximum number of `cargo install` processes to run at the same time.
+    #[serde(default = "default_jobs")]
+    cargo_install_jobs: usize,
+    /// The maximum number of tools to download at the same time.
+    #[serde(default = "default_jobs")]
+    download_jobs: usize,
+    /// Reinstall tools even if they are already installed.
+    #[serde(default)]
+    force: bool,
+    /// Do not use `cargo binstall` to install cargo tools even if it is available.
+    #[serde(default)]
+    no_binstall: bool,
+    /// What to do with the tools that were already installed elsewhere,
+    /// check [`PreInstallReport`](super::report::PreInstallReport).
+    #[serde(default)]
+    existing_tools: HashMap<String, ExistingToolChoice>,
+    /// Use another directory to store temporary files instead of `install_dir/temp`.
+    #[serde(default)]
+    temp_dir: Option<PathBuf>,
+    /// Path to a pre-existing `rustup` binary to install toolchain with,
+    /// instead of installing a new one under `install_dir`.
+    #[serde(default)]
+    existing_rustup: Option<PathBuf>,
+    /// Include the git configurations in manifest in the global git configuration,
+    /// which the user must agree to, check [`config_git`](Self::config_git).
+    #[serde(default)]
+    include_gitconfig: bool,
+    /// Only print what would be done, without modifying the system.
+    #[serde(default)]
+    dry_run: bool,
+    /// Whether this resumes a previously [suspended](Self::suspend) installation.
+    #[serde(default)]
+    resuming: bool,
+    /// The [`SessionStep`](super::session::SessionStep)s completed before this installation
+    /// was suspended, and the tools installed by then, loaded once when resuming.
+    #[serde(skip)]
+    resumed: ResumedState,
+}
+
+/// What was done by a suspended installation, check [`InstallConfiguration::is_resuming`].
+#[derive(Debug, Default, Clone)]
+struct ResumedState {
+    completed_steps: HashSet<String>,
+    pending_tools: HashSet<String>,
 }
 
 impl Default for InstallConfiguration {
@@ -101,47 +189,150 @@ impl Default for InstallConfiguration {
         Self {
             install_dir: default_install_dir(),
             cargo_registry: None,
+            cargo_registries: vec![],
             rustup_dist_server: default_rustup_dist_server().clone(),
             rustup_update_root: default_rustup_update_root().clone(),
             cargo_is_installed: false,
+            no_cache: false,
+            cargo_install_jobs: default_jobs(),
+            download_jobs: default_jobs(),
+            force: false,
+            no_binstall: false,
+            existing_tools: HashMap::new(),
+            temp_dir: None,
+            existing_rustup: None,
+            include_gitconfig: false,
+            dry_run: false,
+            resuming: false,
+            resumed: ResumedState::default(),
         }
     }
 }
 
 impl InstallConfiguration {
-    pub fn init(install_dir: &Path, dry_run: bool) -> Result<Self> {
+    /// Initialize the configuration, creating the installation directory and record.
+    ///
+    /// If `dry_run` is `true`, nothing will be created, and the following installation steps
+    /// only print what they would do, check [`is_dry_run`](Self::is_dry_run).
+    ///
+    /// When a `manifest` is given, make sure there is enough free space to install its toolset
+    /// before anything is created, check [`estimate_required_space`] for how much is required.
+    pub fn init(
+        install_dir: &Path,
+        manifest: Option<&ToolsetManifest>,
+        dry_run: bool,
+    ) -> Result<Self> {
         if install_dir.parent().is_none() {
-            bail!("unable to install in root directory");
+            bail!(InstallError::RootInstallDir);
+        }
+        if let Some(manifest) = manifest {
+            check_disk_space(install_dir, manifest)?;
+        }
+        if dry_run {
+            print_plan(&format!(
+                "create installation directory '{}'",
+                install_dir.display()
+            ));
+            if env!("PROFILE") == "debug" {
+                print_plan("copy this program as manager into cargo's `bin` directory");
+                #[cfg(windows)]
+                print_plan(&format!(
+                    "add registry key '{}\\{}'",
+                    super::os::windows::root_key_name(),
+                    super::os::windows::uninstall_entry()
+                ));
+            }
+            return Ok(Self {
+                install_dir: install_dir.to_path_buf(),
+                dry_run,
+                ..Default::default()
+            });
         }
-        let this = Self {
-            install_dir: install_dir.to_path_buf(),
-            ..Default::default()
-        };
 
-        if !dry_run {
-            // Create a new folder to hold installation
-            let folder = &this.install_dir;
-            utils::ensure_dir(folder)?;
+        let this = Self::open(install_dir)?;
+        // TODO: remove this condition check after the uninstallation implementation is finished.
+        if env!("PROFILE") == "debug" {
+            // Create a copy of this binary to CARGO_HOME/bin
+            let self_exe = std::env::current_exe()?;
+            // promote this installer to manager
+            let manager_exe = manager_exe(this.cargo_bin())?;
+            // Don't copy onto itself when this is run by the `manager`, i.e. when updating.
+            if manager_exe != self_exe {
+                utils::copy_as(&self_exe, &manager_exe)?;
+            }
 
-            // TODO: remove this condition check after the uninstallation implementation is finished.
-            if env!("PROFILE") == "debug" {
-                // Create a copy of this binary to CARGO_HOME/bin
-                let self_exe = std::env::current_exe()?;
-                // promote this installer to manager
-                let manager_name = self_exe
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .map(|name| name.replace("installer", "manager"))
-                    .unwrap_or(format!("manager{}", utils::EXE_EXT));
-
-                let manager_exe = this.cargo_bin().join(manager_name);
-                utils::copy_as(self_exe, &manager_exe)?;
+            #[cfg(windows)]
+            // Create registry entry to add this program into "installed programs".
+            super::os::windows::do_add_to_programs(&manager_exe, &this.install_dir)?;
+            #[cfg(windows)]
+            InstallationRecord::update(&this.install_dir, |record| {
+                record.add_registry_key(super::os::windows::uninstall_entry());
+                record.add_step(InstallStep::ProgramsEntry);
+            })?;
+        }
 
-                #[cfg(windows)]
-                // Create registry entry to add this program into "installed programs".
-                super::os::windows::do_add_to_programs(&manager_exe)?;
+        Ok(this)
+    }
+
+    /// Create the installation directory and record, or keep the existing ones,
+    /// resuming the installation in it if it was [suspended](Self::suspend).
+    ///
+    /// Unlike [`init`](Self::init), this program is not copied into the installation.
+    pub(crate) fn open(install_dir: &Path) -> Result<Self> {
+        let mut this = Self {
+            install_dir: install_dir.to_path_buf(),
+            ..Default::default()
+        };
+        // Create a new folder to hold installation, note that it might be created by
+        // acquiring the lock, which happens before this.
+        let folder = install_dir;
+        let is_new_folder = !folder.exists()
+            || folder
+                .read_dir()?
+                .flatten()
+                .all(|entry| entry.file_name() == lock::FILENAME);
+        utils::ensure_dir(folder)?;
+        // Create an installation record (or keep the existing one),
+        // and start tracking the steps of this installation.
+        let mut resuming = false;
+        InstallationRecord::update(folder, |record| {
+            if record.suspended {
+                // Keep the steps done before the suspension, so that they can be skipped,
+                // or reverted along with the new ones if this installation fails.
+                record.suspended = false;
+                resuming = true;
+                this.resumed = ResumedState {
+                    completed_steps: record.completed_steps.iter().cloned().collect(),
+                    pending_tools: record.pending_tools().map(ToOwned::to_owned).collect(),
+                };
+            } else {
+                record.pending.clear();
+                record.completed_steps.clear();
+                record.session_fingerprint = None;
+            }
+            record.system = super::os::is_system_wide();
+            if is_new_folder {
+                record.add_step(InstallStep::CreateDir {
+                    path: folder.to_path_buf(),
+                });
+            }
+        })?;
+        if resuming {
+            OutputEvent::Message {
+                text: &format!(
+                    "resuming the suspended installation in '{}'",
+                    folder.display()
+                ),
             }
+            .emit();
         }
+        this.resuming = resuming;
+        // Clean up the temporary files left by previously crashed (or killed) runs.
+        // NB: Do not use `temp_root` here, as it might be overridden later.
+        remove_stale_entries(
+            &folder.join("temp"),
+            SystemTime::now() - STALE_TEMP_ENTRY_AGE,
+        );
 
         Ok(this)
     }
@@ -151,6 +342,11 @@ impl InstallConfiguration {
         self
     }
 
+    pub fn cargo_registries(mut self, registries: Vec<(String, String)>) -> Self {
+        self.cargo_registries = registries;
+        self
+    }
+
     pub fn rustup_dist_server(mut self, url: Url) -> Self {
         self.rustup_dist_server = url;
         self
@@ -161,73 +357,407 @@ impl InstallConfiguration {
         self
     }
 
+    pub fn no_cache(mut self, yes: bool) -> Self {
+        self.no_cache = yes;
+        self
+    }
+
+    /// Set the maximum number of tools to install concurrently using `cargo install`.
+    ///
+    /// `0` will be treated as `1`.
+    pub fn cargo_install_jobs(mut self, jobs: usize) -> Self {
+        self.cargo_install_jobs = jobs.max(1);
+        self
+    }
+
+    /// Set the maximum number of tools to download concurrently.
+    ///
+    /// `0` will be treated as `1`.
+    pub fn download_jobs(mut self, jobs: usize) -> Self {
+        self.download_jobs = jobs.max(1);
+        self
+    }
+
+    /// Always build cargo tools using `cargo install`, instead of fetching their prebuilt binaries
+    /// using `cargo binstall` when it is available, check [`ToolInfo::binstall_args`].
+    pub fn no_binstall(mut self, yes: bool) -> Self {
+        self.no_binstall = yes;
+        self
+    }
+
+    /// Allow including the git configurations in manifest in the user's global git configuration,
+    /// otherwise they are only written into `install_dir` for the user to include manually.
+    pub fn include_gitconfig(mut self, yes: bool) -> Self {
+        self.include_gitconfig = yes;
+        self
+    }
+
+    /// Decide what to do with the tools that were already installed elsewhere,
+    /// check [`PreInstallReport::apply`](super::report::PreInstallReport::apply).
+    ///
+    /// Those tools are not installed again unless the choice is [`ExistingToolChoice::Install`].
+    pub fn existing_tools(mut self, choices: HashMap<String, ExistingToolChoice>) -> Self {
+        self.existing_tools = choices;
+        self
+    }
+
+    /// Mark `cargo` as already installed, so that tools can be installed using `cargo install`
+    /// without installing the Rust toolchain first, such as when updating an installation.
+    pub(crate) fn cargo_is_installed(mut self, yes: bool) -> Self {
+        self.cargo_is_installed = yes;
+        self
+    }
+
+    pub fn force(mut self, yes: bool) -> Self {
+        self.force = yes;
+        self
+    }
+
+    /// Set another directory to store temporary files, such as downloads and extracted archives.
+    ///
+    /// # Errors
+    ///
+    /// Return error if the directory cannot be created or is not writable.
+    pub fn temp_dir(mut self, dir: Option<PathBuf>) -> Result<Self> {
+        if let Some(dir) = &dir {
+            utils::ensure_dir(dir)?;
+            tempfile::tempfile_in(dir).with_context(|| {
+                format!(
+                    "unable to use '{}' as temporary directory, make sure it is writable",
+                    dir.display()
+                )
+            })?;
+        }
+        self.temp_dir = dir;
+        Ok(self)
+    }
+
+    /// Use a pre-existing `rustup` (such as the one installed by the official `rustup-init`)
+    /// to install Rust toolchain, so that its toolchains and `RUSTUP_HOME` are shared.
+    ///
+    /// This is written into the installation record right away, so that
+    /// uninstallation and rollback know to leave the `RUSTUP_HOME` variable alone.
+    pub fn reuse_rustup(mut self, rustup: Option<PathBuf>) -> Result<Self> {
+        if !self.dry_run {
+            InstallationRecord::update(&self.install_dir, |record| {
+                record.external_rustup.clone_from(&rustup);
+            })?;
+        }
+        self.existing_rustup = rustup;
+        Ok(self)
+    }
+
+    /// Returns `true` if this configuration only prints the installation plan,
+    /// without modifying the system.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Get the path to the pre-existing `rustup` that is being reused, if there is one.
+    pub(crate) fn existing_rustup(&self) -> Option<&Path> {
+        self.existing_rustup.as_deref()
+    }
+
     pub(crate) fn cargo_home(&self) -> &Path {
-        get_path_and_create!(CARGO_HOME_DIR, self.install_dir.join(".cargo"))
+        get_path_and_create!(self, CARGO_HOME_DIR, self.install_dir.join(".cargo"))
     }
 
     pub(crate) fn cargo_bin(&self) -> &Path {
-        get_path_and_create!(CARGO_BIN_DIR, self.cargo_home().join("bin"))
+        get_path_and_create!(self, CARGO_BIN_DIR, self.cargo_home().join("bin"))
     }
 
     pub(crate) fn rustup_home(&self) -> &Path {
-        get_path_and_create!(RUSTUP_HOME_DIR, self.install_dir.join(".rustup"))
+        get_path_and_create!(self, RUSTUP_HOME_DIR, self.install_dir.join(".rustup"))
     }
 
     pub(crate) fn temp_root(&self) -> &Path {
-        get_path_and_create!(TEMP_DIR, self.install_dir.join("temp"))
+        get_path_and_create!(
+            self,
+            TEMP_DIR,
+            self.temp_dir
+                .clone()
+                .unwrap_or_else(|| self.install_dir.join("temp"))
+        )
     }
 
     pub(crate) fn tools_dir(&self) -> &Path {
-        get_path_and_create!(TOOLS_DIR, self.install_dir.join("tools"))
+        get_path_and_create!(self, TOOLS_DIR, self.install_dir.join("tools"))
     }
 
-    pub(crate) fn env_vars(
-        &self,
-        manifest: &ToolsetManifest,
-    ) -> Result<Vec<(&'static str, String)>> {
-        let cargo_home = self
-            .cargo_home()
-            .to_str()
-            .map(ToOwned::to_owned)
-            .context("`install-dir` cannot contains invalid unicodes")?;
-        // This `unwrap` is safe here because we've already make sure the `install_dir`'s path can be
-        // converted to string with the `cargo_home` variable.
-        let rustup_home = self.rustup_home().to_str().unwrap().to_string();
-
-        let mut env_vars: Vec<(&str, String)> = vec![
-            (RUSTUP_DIST_SERVER, self.rustup_dist_server.to_string()),
-            (RUSTUP_UPDATE_ROOT, self.rustup_update_root.to_string()),
-            (CARGO_HOME, cargo_home),
-            (RUSTUP_HOME, rustup_home),
-        ];
+    /// The directory to cache downloaded files, each file is stored in a sub folder
+    /// named after its SHA-256 checksum, i.e. `downloads/<sha256>/<filename>`.
+    pub(crate) fn download_cache_dir(&self) -> &Path {
+        get_path_and_create!(self, DOWNLOAD_DIR, self.install_dir.join("downloads"))
+    }
 
-        // Add proxy settings if has
-        if let Some(proxy) = &manifest.proxy {
-            if let Some(url) = &proxy.http {
-                env_vars.push(("http_proxy", url.to_string()));
+    /// Remove the cached downloads that are not referenced by any tool in the given manifest.
+    pub fn prune_download_cache(&self, manifest: &ToolsetManifest) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        let referenced = manifest
+            .all_tools()
+            .filter_map(|(_, tool)| tool.sha256())
+            .map(|checksum| checksum.to_lowercase())
+            .collect::<HashSet<_>>();
+
+        for entry in utils::walk_dir(self.download_cache_dir(), false)? {
+            let is_referenced = entry
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| referenced.contains(name));
+            if !is_referenced {
+                utils::remove(&entry)?;
             }
-            if let Some(url) = &proxy.https {
-                env_vars.push(("https_proxy", url.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Check if a tool is already installed, with the same version as the given one.
+    ///
+    /// This uses the dedicated check for tools that have custom install instructions,
+    /// otherwise this looks up the installation record and probes the recorded paths.
+    /// The record is looked up as well if the version of the detected one cannot be told.
+    pub(crate) fn is_tool_installed(&self, name: &str, tool: &ToolInfo) -> bool {
+        // System packages might be removed (or added) by the user at any time.
+        if let ToolInfo::System { .. } = tool {
+            return custom_instructions::detect(name, Some(tool)).is_some();
+        }
+        let version = tool.version();
+        if custom_instructions::is_supported(name) && custom_instructions::already_installed(name) {
+            let detected = version
+                .and_then(|_| custom_instructions::detect(name, Some(tool)))
+                .and_then(|detection| detection.version);
+            if let Some(matched) = matches_version(version, detected.as_deref()) {
+                return matched;
+            }
+        }
+        // The detect command tells nothing about the version.
+        if version.is_none()
+            && tool
+                .instruction()
+                .is_some_and(custom_instructions::declared::already_installed)
+        {
+            return true;
+        }
+        InstallationRecord::load_or_new(&self.install_dir)
+            .is_ok_and(|record| record.is_tool_installed(name, version))
+    }
+
+    /// Returns `true` if `cargo binstall` should be used to install cargo tools, which requires
+    /// `cargo-binstall` to be found in `cargo_bin` (such as installed as a part of the toolset)
+    /// or in `PATH`.
+    fn use_binstall(&self) -> bool {
+        let exe = format!("cargo-binstall{}", std::env::consts::EXE_SUFFIX);
+        !self.no_binstall
+            && (self.cargo_bin().join(&exe).is_file() || utils::find_in_path(&exe).is_some())
+    }
+
+    /// Returns `true` if the [prebuilt binary](ToolInfo::prebuilt) of a cargo tool was installed,
+    /// so that it doesn't need to be built using `cargo install`.
+    fn is_prebuilt_installed(&self, name: &str, tool: &ToolInfo) -> bool {
+        let Some(ToolInfo::Url { url, .. }) = tool.prebuilt() else {
+            return false;
+        };
+        InstallationRecord::load_or_new(&self.install_dir).is_ok_and(|record| {
+            record
+                .tools
+                .get(name)
+                .is_some_and(|rec| rec.source == Some(ToolSource::Url { url }))
+        })
+    }
+
+    /// Add a path to `PATH` environment variable, and keep it in the installation record.
+    pub(crate) fn add_to_path(&self, path: &Path) -> Result<()> {
+        if self.dry_run {
+            print_plan(&format!("add '{}' to PATH", path.display()));
+            return Ok(());
+        }
+        add_to_path(path, &self.install_dir)?;
+
+        InstallationRecord::update(&self.install_dir, |record| {
+            // Paths that were added by previous installations should be kept when rolling back.
+            if !record.paths.iter().any(|p| p == path) {
+                record.add_step(InstallStep::AddPath {
+                    path: path.to_path_buf(),
+                });
             }
-            if let Some(s) = &proxy.no_proxy {
-                env_vars.push(("no_proxy", s.to_string()));
+            record.add_path(path);
+        })
+    }
+
+    /// Keep track of a mutating step in the installation record,
+    /// so that it can be reverted by [`rollback`](Self::rollback) if the installation fails.
+    pub(crate) fn record_step(&self, step: InstallStep) -> Result<()> {
+        InstallationRecord::update(&self.install_dir, |record| record.add_step(step))
+    }
+
+    /// Record the persistent environment variables that were set, so that uninstallation
+    /// knows which of them to remove, along with their values that are valid unicode.
+    pub(crate) fn record_env_vars(&self, vars: &[(&str, OsString)]) -> Result<()> {
+        InstallationRecord::update(&self.install_dir, |record| {
+            record.add_env_vars(vars.iter().map(|(key, _)| *key));
+            for (key, val) in vars {
+                if let Some(val) = val.to_str() {
+                    record.set_env_value(key, Some(val));
+                }
             }
+            record.add_step(InstallStep::EnvVars);
+        })
+    }
+
+    /// Mark the current installation as finished, after which it can no longer be rolled back.
+    pub fn finish_installation(&self) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
         }
+        #[cfg(windows)]
+        super::os::windows::update_programs_entry_size(&self.install_dir)?;
+        InstallationRecord::update(&self.install_dir, |record| {
+            record.pending.clear();
+            record.completed_steps.clear();
+        })?;
+        let record = InstallationRecord::load_or_new(&self.install_dir)?;
+        if let Err(e) = Store::new(&self.install_dir).gc(&record) {
+            tracing::warn!("unable to clean up the store: {e:?}");
+        }
+        Ok(())
+    }
+
+    /// Suspend the current (unfinished) installation, instead of rolling it back,
+    /// so that the next installation into the same directory resumes it,
+    /// skipping the steps and tools that were already done.
+    pub fn suspend(&self) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        InstallationRecord::update(&self.install_dir, |record| record.suspended = true)
+    }
+
+    /// Check whether this resumes a previously [suspended](Self::suspend) installation.
+    pub fn is_resuming(&self) -> bool {
+        self.resuming
+    }
 
-        Ok(env_vars)
+    /// Check whether a [`SessionStep`](super::session::SessionStep) was completed
+    /// before this installation was suspended.
+    pub(crate) fn is_step_completed(&self, name: &str) -> bool {
+        self.resuming && self.resumed.completed_steps.contains(name)
     }
 
-    /// Steps to install third-party softwares (excluding the ones that requires `cargo install`).
-    pub(crate) fn install_tools(&self, manifest: &ToolsetManifest) -> Result<()> {
-        let Some(tools_to_install) = manifest.current_target_tools() else {
+    /// Make sure a resumed installation installs the same things as the suspended one,
+    /// which is identified by `fingerprint`, otherwise it starts over, as the steps done
+    /// before might no longer apply.
+    ///
+    /// The `fingerprint` is recorded, so that it can be checked once this is resumed.
+    pub(crate) fn check_session_fingerprint(&mut self, fingerprint: &str) -> Result<()> {
+        if self.dry_run {
             return Ok(());
-        };
-        let proxy = manifest.proxy.as_ref();
-        self.install_set_of_tools(
-            tools_to_install.iter(),
-            &mut MultiThreadProgress::default(),
-            proxy,
-        )
+        }
+        let mut restart = false;
+        InstallationRecord::update(&self.install_dir, |record| {
+            if self.resuming && record.session_fingerprint.as_deref() != Some(fingerprint) {
+                record.completed_steps.clear();
+                restart = true;
+            }
+            record.session_fingerprint = Some(fingerprint.to_string());
+        })?;
+        if restart {
+            OutputEvent::Message {
+                text: "the suspended installation was started with another toolset or \
+                    selection of components, starting over",
+            }
+            .emit();
+            self.resuming = false;
+            self.resumed = ResumedState::default();
+        }
+        Ok(())
+    }
+
+    /// Record a completed [`SessionStep`](super::session::SessionStep),
+    /// so that it can be skipped if this installation is suspended then resumed.
+    pub(crate) fn complete_step(&self, name: &str) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        InstallationRecord::update(&self.install_dir, |record| record.complete_step(name))
+    }
+
+    /// Check whether a tool was installed by the current (unfinished) installation,
+    /// i.e. before it was suspended.
+    fn is_pending_tool(&self, name: &str) -> bool {
+        self.resuming && self.resumed.pending_tools.contains(name)
+    }
+
+    /// Use the Rust toolchain installed before this installation was suspended,
+    /// instead of installing it again.
+    pub(crate) fn reuse_installed_rust(&mut self) -> Result<()> {
+        self.add_to_path(self.cargo_bin())?;
+        self.cargo_is_installed = true;
+        Ok(())
+    }
+
+    /// Revert every step done by the current (unfinished) installation.
+    pub fn rollback(&self) -> Result<()> {
+        super::rollback::rollback(&self.install_dir)
+    }
+
+    /// Get the environment variables to configure, the values are kept as [`OsString`],
+    /// because the paths of `install_dir` might not be valid unicode.
+    pub(crate) fn env_vars(&self, manifest: &ToolsetManifest) -> Vec<(&'static str, OsString)> {
+        let mut env_vars: Vec<(&str, OsString)> = vec![
+            (RUSTUP_DIST_SERVER, self.rustup_dist_server.as_str().into()),
+            (RUSTUP_UPDATE_ROOT, self.rustup_update_root.as_str().into()),
+            (CARGO_HOME, self.cargo_home().into()),
+        ];
+        // A reused `rustup` keeps using its own `RUSTUP_HOME`.
+        if self.existing_rustup.is_none() {
+            env_vars.push((RUSTUP_HOME, self.rustup_home().into()));
+        }
+
+        // Add proxy settings if has, fallback to the ambient ones
+        if let Some(proxy) = manifest.proxy_or_env() {
+            env_vars.extend(proxy.env_vars().into_iter().map(|(k, v)| (k, v.into())));
+        }
+
+        env_vars
+    }
+
+    /// Pass the proxy credentials (if there are any) to the child processes, such as
+    /// `rustup` and `cargo`, by setting the proxy variables of the current process.
+    ///
+    /// Unlike [`env_vars`](Self::env_vars), these are never persisted.
+    pub(crate) fn set_process_proxy_vars(&self, manifest: &ToolsetManifest) {
+        if self.dry_run {
+            return;
+        }
+        if let Some(proxy) = manifest.proxy_or_env() {
+            for (key, val) in proxy.env_vars_with_credentials() {
+                std::env::set_var(key, val);
+            }
+        }
+        // The `http.proxy` written by `config_cargo` takes precedence over the variables above,
+        // but it never has the credentials, which are passed to `cargo` through its env var.
+        if manifest.cargo.http.proxy.is_none() {
+            if let Some(url) = manifest
+                .proxy
+                .as_ref()
+                .and_then(Proxy::cargo_proxy_with_credentials)
+            {
+                std::env::set_var("CARGO_HTTP_PROXY", url);
+            }
+        }
+    }
+
+    /// Print the environment variables that would be configured, used in dry-run mode.
+    pub(crate) fn print_env_vars_plan(&self, manifest: &ToolsetManifest) {
+        for (key, val) in self.env_vars(manifest) {
+            print_plan(&format!(
+                "set environment variable `{key}` to '{}'",
+                val.to_string_lossy()
+            ));
+        }
     }
 
     pub fn install_set_of_tools<'a, M: IntoIterator<Item = (&'a String, &'a ToolInfo)>>(
@@ -236,59 +766,293 @@ impl InstallConfiguration {
         mt_prog: &mut MultiThreadProgress,
         proxy: Option<&Proxy>,
     ) -> Result<()> {
-        // Ignore tools that need to be installed using `cargo install`
-        let to_install = tools
+        // Ignore tools that need to be installed using `cargo install`, unless they have
+        // prebuilt binaries, which are tried here and left to `cargo install` if failed.
+        let tools = tools
             .into_iter()
-            .filter(|(_, t)| !t.is_cargo_tool())
+            .filter_map(|(name, tool)| match tool.prebuilt() {
+                Some(prebuilt) => Some((name, Cow::Owned(prebuilt))),
+                None => (!tool.is_cargo_tool()).then_some((name, Cow::Borrowed(tool))),
+            })
             .collect::<Vec<_>>();
+        let prebuilt_tools = tools
+            .iter()
+            .filter(|(_, tool)| matches!(tool, Cow::Owned(_)))
+            .map(|(name, _)| name.as_str())
+            .collect::<HashSet<_>>();
+        let to_install = tools
+            .iter()
+            .map(|(name, tool)| (*name, tool.as_ref()))
+            .collect::<Vec<_>>();
+        let to_install = sort_by_requirements(to_install)?;
         let sub_progress_delta = if to_install.is_empty() {
             return mt_prog.send_progress();
         } else {
             mt_prog.val / to_install.len()
         };
 
-        for (name, tool) in to_install {
-            send_and_print(&format!("installing '{name}'"), mt_prog)?;
-            install_tool(self, name, tool, proxy)?;
+        let (skipped, to_install): (Vec<_>, Vec<_>) =
+            to_install
+                .into_iter()
+                .partition(|(name, tool)| match self.existing_tools.get(*name) {
+                    Some(choice) => *choice != ExistingToolChoice::Install,
+                    None => {
+                        (!self.force || self.is_pending_tool(name))
+                            && self.is_tool_installed(name, tool)
+                    }
+                });
+        for (name, _) in &skipped {
+            let msg = match self.existing_tools.get(*name) {
+                Some(ExistingToolChoice::Skip) => format!("skipping '{name}' as requested"),
+                Some(_) => format!("skipping '{name}': reusing the existing installation"),
+                None => format!("skipping '{name}': already installed"),
+            };
+            send_and_print(&msg, mt_prog)?;
+            mt_prog.send_any_progress(sub_progress_delta)?;
+        }
+        if self.dry_run {
+            for (name, tool) in to_install {
+                print_tool_plan(name, tool);
+                mt_prog.send_any_progress(sub_progress_delta)?;
+            }
+            return Ok(());
+        }
+
+        // Download everything beforehand, while the installation itself stays in order.
+        let mut downloads = self.download_tools(&to_install, mt_prog, proxy)?;
+
+        let total = to_install.len();
+        for (idx, (name, tool)) in to_install.into_iter().enumerate() {
+            utils::check_cancelled()?;
+            let count = format!("{}/{total}", idx + 1);
+            send_and_print(&format!("installing '{name}' ({count})"), mt_prog)?;
+            let downloaded = downloads.remove(name.as_str()).transpose();
+            let result =
+                downloaded.and_then(|downloaded| install_tool(self, name, tool, proxy, downloaded));
+            match result {
+                Err(e) if prebuilt_tools.contains(name.as_str()) => {
+                    mt_prog.report(ProgressEvent::Warning {
+                        text: format!(
+                            "unable to install the prebuilt binary of '{name}', \
+                            building it using cargo instead: {e:#}"
+                        ),
+                    })?;
+                }
+                result => {
+                    result.with_context(|| InstallError::ToolInstallFailed(name.to_string()))?;
+                    mt_prog.report(ProgressEvent::ToolInstalled {
+                        name: name.to_string(),
+                    })?;
+                }
+            }
             mt_prog.send_any_progress(sub_progress_delta)?;
         }
 
         Ok(())
     }
 
-    /// Install rust's toolchain manager `rustup` with a default toolchain
-    pub(crate) fn install_rust(&mut self, manifest: &ToolsetManifest) -> Result<()> {
-        self.install_rust_with_optional_components(
-            manifest,
-            None,
-            &mut MultiThreadProgress::default(),
-        )
+    /// Download the given tools that need to be downloaded ([`ToolInfo::Url`]) concurrently,
+    /// using at most [`download_jobs`](Self::download_jobs) threads.
+    ///
+    /// Return the downloaded files (or the errors) by the names of tools, so that the
+    /// errors are reported once the installation reaches the failed tool.
+    fn download_tools<'t>(
+        &self,
+        tools: &[(&'t String, &'t ToolInfo)],
+        mt_prog: &mut MultiThreadProgress,
+        proxy: Option<&Proxy>,
+    ) -> Result<HashMap<&'t str, Result<Downloaded>>> {
+        let to_download = tools
+            .iter()
+            .filter_map(|&(name, tool)| match tool {
+                // Local files are installed from where they are.
+                ToolInfo::Url { .. } if tool.local_path().is_some_and(|p| p.exists()) => None,
+                ToolInfo::Url { .. } => Some((name.as_str(), tool)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mut downloads = HashMap::new();
+        if to_download.is_empty() {
+            return Ok(downloads);
+        }
+        let total = to_download.len();
+        let jobs = self.download_jobs.clamp(1, total);
+
+        send_and_print(
+            &format!("downloading {total} tool(s), with {jobs} job(s)"),
+            mt_prog,
+        )?;
+
+        let queue = Mutex::new(to_download.into_iter());
+        let (tx, rx) = mpsc::channel();
+
+        thread::scope(|s| -> Result<()> {
+            for _ in 0..jobs {
+                let tx = tx.clone();
+                let queue = &queue;
+                s.spawn(move || loop {
+                    let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                    let Some((name, tool)) = next else {
+                        break;
+                    };
+                    let result = download_tool(self, name, tool, proxy);
+                    // The receiver is gone, meaning the main thread has bailed.
+                    if tx.send((name, result)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(tx);
+
+            for (name, result) in rx {
+                utils::check_cancelled()?;
+                let status = if result.is_ok() { "done" } else { "failed" };
+                send_and_print(
+                    &format!(
+                        "downloading '{name}' {status} ({}/{total})",
+                        downloads.len() + 1
+                    ),
+                    mt_prog,
+                )?;
+                downloads.insert(name, result);
+            }
+            Ok(())
+        })?;
+
+        Ok(downloads)
     }
 
+    /// Install rust's toolchain manager `rustup` with a default toolchain,
+    /// along with the selected `override_components` if there are any.
+    ///
+    /// The toolchain is installed with `override_profile` if provided,
+    /// otherwise the profile in manifest (or `rustup`'s default) is used.
     pub fn install_rust_with_optional_components(
         &mut self,
         manifest: &ToolsetManifest,
+        override_profile: Option<&str>,
         override_components: Option<&[String]>,
         mt_prog: &mut MultiThreadProgress,
     ) -> Result<()> {
         send_and_print("installing rustup and rust toolchain", mt_prog)?;
 
-        Rustup::init().download_toolchain(self, manifest, override_components)?;
-        add_to_path(self.cargo_bin())?;
+        let rustup_home = self.install_dir.join(".rustup");
+        if self.dry_run {
+            self.print_rust_plan(manifest, override_profile, override_components);
+            self.add_to_path(self.cargo_bin())?;
+            self.cargo_is_installed = true;
+            return mt_prog.send_progress();
+        }
+        if self.existing_rustup.is_none() && !rustup_home.exists() {
+            self.record_step(InstallStep::CreateDir { path: rustup_home })?;
+        }
+        // Record the new toolchains before installing them, so that they can be removed
+        // even if the installation fails halfway, without touching the existing ones.
+        let rustup_bin = self
+            .existing_rustup
+            .clone()
+            .unwrap_or_else(|| self.cargo_bin().join(RUSTUP));
+        let existing = if rustup_bin.is_file() {
+            rustup::installed_toolchains(&rustup_bin)
+        } else {
+            vec![]
+        };
+        for name in std::iter::once(&manifest.rust.version).chain(manifest.rust.toolchains.keys()) {
+            if !rustup::is_toolchain_installed(&existing, name) {
+                self.record_step(InstallStep::Toolchain { name: name.clone() })?;
+            }
+        }
+        Rustup::init().download_toolchain(self, manifest, override_profile, override_components)?;
+        self.add_to_path(self.cargo_bin())?;
+        verify_toolchain()?;
         self.cargo_is_installed = true;
 
+        let components = manifest
+            .rust
+            .components
+            .iter()
+            .chain(override_components.unwrap_or_default())
+            .cloned()
+            .collect::<Vec<_>>();
+        InstallationRecord::update(&self.install_dir, |record| {
+            record.add_rust_record(&manifest.rust, &components);
+            record.add_step(InstallStep::Rust);
+        })?;
+
         mt_prog.send_progress()
     }
 
-    /// Steps to install `cargo` compatible softwares, should only be called after toolchain installation.
-    pub(crate) fn cargo_install(&self, manifest: &ToolsetManifest) -> Result<()> {
-        let Some(tools_to_install) = manifest.current_target_tools() else {
-            return Ok(());
-        };
-        self.cargo_install_set_of_tools(
-            tools_to_install.iter(),
-            &mut MultiThreadProgress::default(),
-        )
+    fn print_rust_plan(
+        &self,
+        manifest: &ToolsetManifest,
+        profile: Option<&str>,
+        optional_components: Option<&[String]>,
+    ) {
+        match &self.existing_rustup {
+            Some(rustup) => print_plan(&format!("use the existing rustup '{}'", rustup.display())),
+            None => match manifest.local_rustup_init() {
+                Some(local) => {
+                    print_plan(&format!("install rustup using '{}'", local.path.display()))
+                }
+                None => print_plan(&format!(
+                    "install rustup downloaded from '{}'",
+                    self.rustup_update_root
+                )),
+            },
+        }
+        let profile = profile.or(manifest.toolchain_profile().map(|p| p.name.as_str()));
+        print_plan(&format!(
+            "install Rust toolchain '{}' ({} profile) from '{}'",
+            manifest.rust.version,
+            profile.unwrap_or("default"),
+            self.rustup_dist_server
+        ));
+        let components = manifest
+            .rust
+            .components
+            .iter()
+            .chain(optional_components.unwrap_or_default())
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if !components.is_empty() {
+            print_plan(&format!("add components: {}", components.join(", ")));
+        }
+        if !manifest.rust.targets.is_empty() {
+            print_plan(&format!(
+                "add targets: {}",
+                manifest.rust.targets.join(", ")
+            ));
+        }
+        for (name, toolchain) in &manifest.rust.toolchains {
+            if let Some(source) = toolchain
+                .path
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .or(toolchain.channel.as_ref().map(Url::to_string))
+            {
+                print_plan(&format!(
+                    "link Rust toolchain '{name}' installed from '{source}'"
+                ));
+                continue;
+            }
+            let mut plan = format!("install Rust toolchain '{name}'");
+            if !toolchain.components.is_empty() {
+                plan.push_str(&format!(
+                    " with components: {}",
+                    toolchain.components.join(", ")
+                ));
+            }
+            if !toolchain.targets.is_empty() {
+                plan.push_str(&format!(" with targets: {}", toolchain.targets.join(", ")));
+            }
+            print_plan(&plan);
+        }
+        if self.existing_rustup.is_none() || manifest.rust.default.is_some() {
+            print_plan(&format!(
+                "use Rust toolchain '{}' by default",
+                manifest.rust.default_toolchain()
+            ));
+        }
     }
 
     pub fn cargo_install_set_of_tools<'a, M: IntoIterator<Item = (&'a String, &'a ToolInfo)>>(
@@ -298,42 +1062,252 @@ impl InstallConfiguration {
     ) -> Result<()> {
         let to_install = tools
             .into_iter()
-            .filter(|(_, t)| t.is_cargo_tool())
+            .filter(|(name, t)| {
+                t.is_cargo_tool()
+                    && !self.is_prebuilt_installed(name, t)
+                    && !self.is_pending_tool(name)
+            })
             .collect::<Vec<_>>();
-        let sub_progress_delta = if to_install.is_empty() {
+        if to_install.is_empty() || !self.cargo_is_installed {
             return mt_prog.send_progress();
-        } else {
-            mt_prog.val / to_install.len()
-        };
+        }
+        let binstall = self.use_binstall();
+        if self.dry_run {
+            for (name, tool) in to_install {
+                let args = match tool.binstall_args(name).filter(|_| binstall) {
+                    Some(args) => args,
+                    None => cargo_install_args(name, tool)?,
+                };
+                print_plan(&format!("run `cargo {}`", args.join(" ")));
+            }
+            return mt_prog.send_progress();
+        }
+        let total = to_install.len();
+        let sub_progress_delta = mt_prog.val / total;
+        let jobs = self.cargo_install_jobs.clamp(1, total);
 
-        for (name, tool) in to_install {
-            send_and_print(&format!("installing '{name}' using cargo"), mt_prog)?;
-            install_tool(self, name, tool, None)?;
-            mt_prog.send_any_progress(sub_progress_delta)?;
+        send_and_print(
+            &format!("installing {total} tool(s) using cargo, with {jobs} job(s)"),
+            mt_prog,
+        )?;
+
+        let queue = Mutex::new(to_install.into_iter());
+        let (tx, rx) = mpsc::channel();
+        let mut failed = vec![];
+        let mut done = 0;
+
+        thread::scope(|s| -> Result<()> {
+            for _ in 0..jobs {
+                let tx = tx.clone();
+                let queue = &queue;
+                s.spawn(move || loop {
+                    let next = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                    let Some((name, tool)) = next else {
+                        break;
+                    };
+                    let result = cargo_install_tool(name, tool, binstall);
+                    // The receiver is gone, meaning the main thread has bailed.
+                    if tx.send((name, tool, result)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(tx);
+
+            // Outputs are printed here as a whole once each tool is done,
+            // so that the output of different tools won't interleave.
+            for (name, tool, result) in rx {
+                utils::check_cancelled()?;
+                done += 1;
+                match result {
+                    Ok(output) => {
+                        OutputEvent::Message {
+                            text: output.trim_end(),
+                        }
+                        .emit();
+                        send_and_print(
+                            &format!("'{name}' installed using cargo ({done}/{total})"),
+                            mt_prog,
+                        )?;
+                        record_tool_installation(self, name, tool, vec![], None)?;
+                        mt_prog.report(ProgressEvent::ToolInstalled { name: name.clone() })?;
+                    }
+                    Err(e) => {
+                        OutputEvent::Error {
+                            text: &format!(
+                                "unable to install '{name}' using cargo ({done}/{total}): {e:?}"
+                            ),
+                            code: Some(InstallError::ToolInstallFailed(name.clone()).code()),
+                        }
+                        .emit();
+                        failed.push(name.as_str());
+                    }
+                }
+                mt_prog.send_any_progress(sub_progress_delta)?;
+            }
+            Ok(())
+        })?;
+
+        if !failed.is_empty() {
+            bail!(InstallError::ToolsInstallFailed(
+                failed.into_iter().map(str::to_string).collect()
+            ));
         }
 
         Ok(())
     }
 
-    /// Configuration options for `cargo`.
+    /// Merge the registries and proxy settings into `config.toml` under `CARGO_HOME`,
+    /// the existing configurations of user are preserved, check [`CargoConfig::merge_into`].
     ///
-    /// This will write a `config.toml` file to `CARGO_HOME`.
-    pub fn config_cargo(&self) -> Result<()> {
+    /// The keys added are recorded, so that they can be removed when uninstalling,
+    /// along with the original values of the overwritten ones, which are restored instead.
+    pub fn config_cargo(&self, manifest: &ToolsetManifest) -> Result<()> {
+        let ca_cert = self.install_ca_cert(manifest)?;
         let mut config = CargoConfig::new();
+        config.add_registries(
+            self.cargo_registries
+                .iter()
+                .map(|(name, url)| (name.as_str(), url.as_str())),
+            None,
+        );
+        for (name, registry) in &manifest.cargo.registries {
+            config.add_registry(name, &registry.index);
+            if let Some(provider) = &registry.credential_provider {
+                config.credential_provider(name, provider);
+            }
+        }
+        // The registry specified when installing takes precedence over the one in manifest.
         if let Some((name, url)) = &self.cargo_registry {
             config.add_source(name, url, true);
+        } else if let Some(name) = &manifest.cargo.replace_crates_io {
+            config.add_registries(std::iter::empty(), Some(name));
+        }
+        let http = &manifest.cargo.http;
+        if let Some(proxy) = http
+            .proxy
+            .clone()
+            .or_else(|| manifest.proxy.as_ref().and_then(Proxy::cargo_proxy))
+        {
+            config.proxy(&proxy);
+        }
+        if let Some(cainfo) = http
+            .cainfo
+            .clone()
+            .or_else(|| ca_cert.map(|path| path.to_string_lossy().into_owned()))
+        {
+            config.cainfo(&cainfo);
+        }
+        if let Some(yes) = http.check_revoke {
+            config.check_revoke(yes);
+        }
+        if let Some(yes) = manifest.cargo.git_fetch_with_cli {
+            config.git_fetch_with_cli(yes);
+        }
+
+        if config.to_toml()?.trim().is_empty() {
+            return Ok(());
+        }
+        let config_path = self.cargo_home().join("config.toml");
+        let existing = if config_path.is_file() {
+            utils::read_to_string(&config_path)?
+        } else {
+            String::new()
+        };
+        let (merged, keys) = config.merge_into(&existing)?;
+        if self.dry_run {
+            print_plan(&format!(
+                "write cargo configuration '{}':\n{}",
+                config_path.display(),
+                merged.trim_end()
+            ));
+            return Ok(());
         }
+        utils::write_file(config_path, merged.trim_end(), false)?;
+        InstallationRecord::update(&self.install_dir, |record| {
+            record.backup_cargo_config_values(keys.overwritten);
+            record.add_cargo_config_keys(keys.added);
+        })
+    }
 
-        let config_toml = config.to_toml()?;
-        if !config_toml.trim().is_empty() {
-            let config_path = self.cargo_home().join("config.toml");
-            utils::write_file(config_path, &config_toml, false)?;
+    /// Write the git configurations in manifest into `install_dir`, then include that file
+    /// in the global git configuration if [allowed](Self::include_gitconfig),
+    /// so that it can be reverted as a whole.
+    ///
+    /// Nothing happens if there's no `[git]` section in manifest, or `git` is not installed.
+    pub fn config_git(&self, manifest: &ToolsetManifest) -> Result<()> {
+        if manifest.git.is_empty() {
+            return Ok(());
+        }
+        let path = self.install_dir.join(GITCONFIG_FILENAME);
+        let content = git_config::to_gitconfig(&manifest.git);
+        if self.dry_run {
+            let include = if self.include_gitconfig {
+                " and include it in the global git configuration"
+            } else {
+                ""
+            };
+            print_plan(&format!(
+                "write git configuration '{}'{include}:\n{}",
+                path.display(),
+                content.trim_end()
+            ));
+            return Ok(());
         }
+        if !utils::cmd_exist(&format!("git{}", utils::EXE_EXT)) {
+            OutputEvent::Message {
+                text: "skipping git configuration because `git` is not installed",
+            }
+            .emit();
+            return Ok(());
+        }
+        utils::write_file(&path, &content, false)?;
+        if !self.include_gitconfig {
+            OutputEvent::Message {
+                text: &format!(
+                    "git configuration was written to '{0}', \
+                    run `git config --global --add include.path \"{0}\"` to use it",
+                    path.display()
+                ),
+            }
+            .emit();
+            return Ok(());
+        }
+        git_config::include(&path)?;
+        InstallationRecord::update(&self.install_dir, |record| {
+            record.git_include = Some(path);
+            record.add_step(InstallStep::GitInclude);
+        })
+    }
 
-        Ok(())
+    /// Copy the [CA certificate](ToolsetManifest::ca_cert) in manifest into `install_dir`,
+    /// so that it's still there after the installer is gone, along with a bundle of it and the
+    /// system root certificates, then point `CARGO_HTTP_CAINFO` and `SSL_CERT_FILE` of this
+    /// process to that bundle, which are used by `rustup` and `cargo`.
+    ///
+    /// Return the path of the bundle, or `None` if there's no such certificate.
+    fn install_ca_cert(&self, manifest: &ToolsetManifest) -> Result<Option<PathBuf>> {
+        let Some(src) = &manifest.ca_cert else {
+            return Ok(None);
+        };
+        let dest = self.install_dir.join(CA_CERT_FILENAME);
+        let bundle = self.install_dir.join(CA_BUNDLE_FILENAME);
+        if self.dry_run {
+            print_plan(&format!(
+                "install CA certificate '{}' to '{}'",
+                src.display(),
+                dest.display()
+            ));
+            return Ok(Some(bundle));
+        }
+        utils::copy_as(src, &dest)?;
+        utils::write_ca_bundle(&dest, &bundle)?;
+        set_ca_bundle_vars(&bundle);
+        Ok(Some(bundle))
     }
 
-    /// Creates a temporary directory under `install_dir/temp`, with a certain prefix.
+    /// Creates a temporary directory under `install_dir/temp` (or the one set by
+    /// [`temp_dir`](Self::temp_dir)), with a certain prefix.
     pub(crate) fn create_temp_dir(&self, prefix: &str) -> Result<TempDir> {
         let root = self.temp_root();
 
@@ -344,46 +1318,370 @@ impl InstallConfiguration {
     }
 }
 
+/// Trust the CA certificate installed under `install_dir` if there is one,
+/// both for downloads and for the `rustup` and `cargo` run by this process,
+/// check [`InstallConfiguration::config_cargo`].
+pub(crate) fn use_installed_ca_cert(install_dir: &Path) -> Result<()> {
+    let cert = install_dir.join(CA_CERT_FILENAME);
+    if !cert.is_file() {
+        return Ok(());
+    }
+    utils::set_download_ca_cert(&cert)?;
+    let bundle = install_dir.join(CA_BUNDLE_FILENAME);
+    if !bundle.is_file() {
+        utils::write_ca_bundle(&cert, &bundle)?;
+    }
+    set_ca_bundle_vars(&bundle);
+    Ok(())
+}
+
+fn set_ca_bundle_vars(bundle: &Path) {
+    std::env::set_var("CARGO_HTTP_CAINFO", bundle);
+    std::env::set_var("SSL_CERT_FILE", bundle);
+}
+
+/// Make sure `cargo` and `rustc` can actually be executed after the toolchain was installed,
+/// which uses the `PATH` of current process, as it was updated when installing.
+fn verify_toolchain() -> Result<()> {
+    for program in ["cargo", "rustc"] {
+        utils::execute(program, &["--version"]).with_context(|| {
+            format!(
+                "Rust toolchain was not installed correctly, \
+                because `{program} --version` failed to run"
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Remove the entries under `dir` that were last modified before `time`.
+///
+/// This never fails, entries that cannot be removed are simply skipped.
+fn remove_stale_entries(dir: &Path, time: SystemTime) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let is_stale = entry
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .is_ok_and(|modified| modified < time);
+        if is_stale {
+            let _ = utils::remove(entry.path());
+        }
+    }
+}
+
 fn send_and_print(msg: &str, sender: &mut MultiThreadProgress) -> Result<()> {
-    println!("{msg}");
-    sender.send_msg(msg.to_string())?;
+    sender.report(ProgressEvent::Message {
+        text: msg.to_string(),
+    })
+}
+
+fn default_jobs() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(4)
+}
+
+/// Estimate the disk space in bytes required to install the toolset in `manifest`.
+///
+/// This counts the Rust toolchain, and the tools of current target whose sizes are known,
+/// each of them takes up the space of its archive, plus [`EXTRACTION_FACTOR`] times of that
+/// once extracted.
+pub fn estimate_required_space(manifest: &ToolsetManifest) -> u64 {
+    let tools_size: u64 = manifest
+        .current_target_tools()
+        .into_iter()
+        .flat_map(|tools| tools.values())
+        .filter_map(ToolInfo::size)
+        .sum();
+    RUST_TOOLCHAIN_SIZE.saturating_add(tools_size.saturating_mul(1 + EXTRACTION_FACTOR))
+}
+
+/// Make sure the volume of `install_dir` has enough free space to install the toolset
+/// in `manifest`, so that the installation fails early instead of in the middle of it.
+///
+/// Nothing is checked if the free space cannot be determined.
+/// Check [`estimate_required_space`] for how the required space is estimated.
+fn check_disk_space(install_dir: &Path, manifest: &ToolsetManifest) -> Result<()> {
+    let Some(available) = super::os::free_space(install_dir) else {
+        return Ok(());
+    };
+    let required = estimate_required_space(manifest);
+    if available < required {
+        bail!(InstallError::InsufficientSpace {
+            path: install_dir.to_path_buf(),
+            required,
+            available,
+        });
+    }
     Ok(())
 }
 
+/// Check if the `detected` version of an installed tool is the `wanted` one, such as `1.91.1`
+/// in the output `1.91.1` or `tool v1.91.1 (abcdef)`. Any version is fine if none is wanted.
+///
+/// Returns `None` if it cannot be told, i.e. the installed version is unknown.
+fn matches_version(wanted: Option<&str>, detected: Option<&str>) -> Option<bool> {
+    let Some(wanted) = wanted else {
+        return Some(true);
+    };
+    let wanted = wanted.trim_start_matches('v');
+    let matched = detected?
+        .split_whitespace()
+        .any(|word| word.trim_start_matches('v') == wanted);
+    Some(matched)
+}
+
+/// Get the default installation directory, which is under the home directory,
+/// or the system-wide location for programs when installing for all users.
 pub fn default_install_dir() -> PathBuf {
-    utils::home_dir().join(env!("CARGO_PKG_NAME"))
+    super::os::default_install_root().join(env!("CARGO_PKG_NAME"))
 }
 
-// TODO: Write version info after installing each tool,
-// which is later used for updating.
+/// Install a single tool, `downloaded` is the file downloaded beforehand if there is one,
+/// otherwise a [`ToolInfo::Url`] will be downloaded here.
 fn install_tool(
     config: &InstallConfiguration,
     name: &str,
     tool: &ToolInfo,
     proxy: Option<&Proxy>,
+    downloaded: Option<Downloaded>,
 ) -> Result<()> {
-    match tool {
-        ToolInfo::PlainVersion(version) => {
-            if config.cargo_is_installed {
-                utils::execute("cargo", &["install", name, "--version", version])?;
+    // Validate hooks before installing anything.
+    if tool
+        .pre_install_commands()
+        .iter()
+        .chain(tool.post_install_commands())
+        .any(|cmd| cmd.trim().is_empty())
+    {
+        bail!("unable to install '{name}': the manifest contains empty install hook command");
+    }
+    if let Some(instruction) = tool.instruction() {
+        custom_instructions::declared::validate(instruction)
+            .with_context(|| format!("unable to install '{name}'"))?;
+    }
+    if tool.is_cargo_tool() && !config.cargo_is_installed {
+        return Ok(());
+    }
+
+    // Forget about the previous installation of this tool (if there is one), so that
+    // an interrupted (re)installation won't be mistaken as a completed one.
+    let mut previous = None;
+    InstallationRecord::update(&config.install_dir, |record| {
+        previous = record.tools.shift_remove(name);
+    })?;
+
+    let installed_paths = match tool {
+        ToolInfo::PlainVersion(_) | ToolInfo::DetailedVersion { .. } | ToolInfo::Git { .. } => {
+            let output = cargo_install_tool(name, tool, config.use_binstall())?;
+            OutputEvent::Message {
+                text: output.trim_end(),
+            }
+            .emit();
+            vec![]
+        }
+        ToolInfo::Path { path, .. } => {
+            let (install_dir, paths) = if tool.prefer_symlink()
+                && tool.installer_args().is_none()
+                && tool.instruction().is_none()
+                && utils::is_executable(path)
+            {
+                // Single executable file, just link it into `cargo_bin`.
+                let cwd = path.parent().unwrap_or(path);
+                run_install_hooks(
+                    "pre-install",
+                    name,
+                    tool.pre_install_commands(),
+                    cwd,
+                    config.cargo_bin(),
+                    proxy,
+                )?;
+                let link = utils::symlink_or_copy_file_to(path, config.cargo_bin())?;
+                (config.cargo_bin().to_path_buf(), vec![link])
+            } else {
+                try_install_from_path(config, name, tool, path, proxy)?
+            };
+            run_install_hooks(
+                "post-install",
+                name,
+                tool.post_install_commands(),
+                &install_dir,
+                &install_dir,
+                proxy,
+            )?;
+            paths
+        }
+        ToolInfo::Url {
+            url,
+            sha256,
+            signature,
+            ..
+        } => {
+            // Keep the temp dir (if there is one) alive until the tool is installed.
+            let (temp_dir, dest) = match (downloaded, tool.local_path()) {
+                (Some(downloaded), _) => downloaded,
+                // `file://` urls are installed from where they are, unless the file is missing,
+                // then the mirrors (if there are any) will be tried instead.
+                (None, Some(path)) if path.exists() => {
+                    if let Some(checksum) = sha256 {
+                        verify_local_checksum(name, url, &path, checksum)?;
+                    }
+                    (None, path)
+                }
+                (None, _) => download_tool(config, name, tool, proxy)?,
+            };
+            if let Some(sig) = signature {
+                verify_signature(config, name, &dest, sig, proxy)?;
+            }
+            let (install_dir, paths) = if temp_dir.is_some() && dest.is_dir() {
+                // Already extracted while downloading.
+                install_from_extracted(config, name, tool, &dest, proxy)?
+            } else {
+                try_install_from_path(config, name, tool, &dest, proxy)?
+            };
+            // Free the space taken by the downloaded file as soon as possible,
+            // instead of keeping it through the hooks.
+            drop(temp_dir);
+            run_install_hooks(
+                "post-install",
+                name,
+                tool.post_install_commands(),
+                &install_dir,
+                &install_dir,
+                proxy,
+            )?;
+            paths
+        }
+        ToolInfo::System { .. } => {
+            if !custom_instructions::is_supported(name) {
+                bail!("'{name}' cannot be installed using the package manager of the system");
             }
+            custom_instructions::install(name, Path::new(""), Some(tool), config)?;
+            vec![]
         }
-        ToolInfo::DetailedVersion { ver, .. } => {
-            if config.cargo_is_installed {
-                utils::execute("cargo", &["install", name, "--version", ver])?;
+    };
+    // The installers run for this tool might have changed the persistent environment variables.
+    #[cfg(windows)]
+    super::os::windows::refresh_process_env();
+
+    record_tool_installation(config, name, tool, installed_paths, previous)
+}
+
+/// Sort the tools so that each of them comes after the ones it [requires](ToolInfo::requirements),
+/// while keeping the original order otherwise.
+///
+/// The requirements that are not in `tools` are ignored, since they are either installed
+/// in another step (such as the tools installed using `cargo`), or installed already.
+fn sort_by_requirements<'a>(
+    tools: Vec<(&'a String, &'a ToolInfo)>,
+) -> Result<Vec<(&'a String, &'a ToolInfo)>> {
+    let names: HashSet<&str> = tools.iter().map(|(name, _)| name.as_str()).collect();
+    let mut placed = HashSet::new();
+    let mut sorted = Vec::with_capacity(tools.len());
+    let mut remaining = tools;
+    while !remaining.is_empty() {
+        let next = remaining.iter().position(|(_, tool)| {
+            tool.requirements()
+                .iter()
+                .all(|req| !names.contains(req.as_str()) || placed.contains(req.as_str()))
+        });
+        let Some(idx) = next else {
+            bail!(
+                "unable to decide the installation order because of circular requirements: {}",
+                find_requirement_cycle(&remaining).join(" -> ")
+            );
+        };
+        let (name, tool) = remaining.remove(idx);
+        placed.insert(name.as_str());
+        sorted.push((name, tool));
+    }
+    Ok(sorted)
+}
+
+/// Find a cycle among the given tools, each of which requires at least one of the others.
+///
+/// Return the names of the tools in the cycle, with the first one repeated at the end.
+fn find_requirement_cycle(tools: &[(&String, &ToolInfo)]) -> Vec<String> {
+    let tools: HashMap<&str, &ToolInfo> = tools
+        .iter()
+        .map(|(name, tool)| (name.as_str(), *tool))
+        .collect();
+    let mut path: Vec<&str> = tools.keys().min().into_iter().copied().collect();
+    while let Some(current) = path.last() {
+        let next = tools[current]
+            .requirements()
+            .iter()
+            .map(String::as_str)
+            .find(|req| tools.contains_key(req));
+        let Some(next) = next else {
+            break;
+        };
+        if let Some(start) = path.iter().position(|name| *name == next) {
+            return path[start..]
+                .iter()
+                .chain([&next])
+                .map(ToString::to_string)
+                .collect();
+        }
+        path.push(next);
+    }
+    path.into_iter().map(ToString::to_string).collect()
+}
+
+/// Install a tool using `cargo install`, and return the captured output of it.
+///
+/// If `binstall` is `true` and the tool [can be installed that way](ToolInfo::binstall_args),
+/// `cargo binstall` is tried first, falling back to `cargo install` if it failed.
+fn cargo_install_tool(name: &str, tool: &ToolInfo, binstall: bool) -> Result<String> {
+    if let Some(args) = tool.binstall_args(name).filter(|_| binstall) {
+        match utils::execute_for_output("cargo", &args) {
+            Ok(output) => return Ok(output),
+            Err(e) => OutputEvent::Message {
+                text: &format!(
+                    "unable to install '{name}' using cargo binstall, \
+                    building it using cargo install instead: {e:#}"
+                ),
             }
+            .emit(),
+        }
+    }
+    utils::execute_for_output("cargo", &cargo_install_args(name, tool)?)
+}
+
+/// Get the arguments of `cargo` to install a tool.
+fn cargo_install_args<'a>(name: &'a str, tool: &'a ToolInfo) -> Result<Vec<&'a str>> {
+    let (mut args, locked, features, no_default_features, bin) = match tool {
+        ToolInfo::PlainVersion(version) => {
+            return Ok(vec!["install", name, "--version", version]);
         }
+        ToolInfo::DetailedVersion {
+            ver,
+            locked,
+            features,
+            no_default_features,
+            bin,
+            ..
+        } => (
+            vec!["install", name, "--version", ver],
+            locked,
+            features,
+            no_default_features,
+            bin,
+        ),
         ToolInfo::Git {
             git,
             branch,
             tag,
             rev,
+            locked,
+            features,
+            no_default_features,
+            bin,
             ..
         } => {
-            if !config.cargo_is_installed {
-                return Ok(());
-            }
-
             let mut args = vec!["install", "--git", git.as_str()];
             if let Some(s) = &branch {
                 args.extend(["--branch", s]);
@@ -394,35 +1692,437 @@ fn install_tool(
             if let Some(s) = &rev {
                 args.extend(["--rev", s]);
             }
+            (args, locked, features, no_default_features, bin)
+        }
+        _ => bail!("'{name}' cannot be installed using cargo"),
+    };
+    if *locked {
+        args.push("--locked");
+    }
+    for feature in features {
+        args.extend(["--features", feature]);
+    }
+    if *no_default_features {
+        args.push("--no-default-features");
+    }
+    for bin in bin {
+        args.extend(["--bin", bin]);
+    }
+    Ok(args)
+}
+
+/// Print a step that would be done, used in dry-run mode.
+pub(crate) fn print_plan(step: &str) {
+    OutputEvent::Message {
+        text: &format!("[dry-run] {step}"),
+    }
+    .emit();
+}
+
+/// Get the path of the manager in `cargo_bin`, which is a copy of this program named after it,
+/// such as `xuanwu-manager` for `xuanwu-installer`.
+pub(crate) fn manager_exe(cargo_bin: &Path) -> Result<PathBuf> {
+    let self_exe = std::env::current_exe()?;
+    let manager_name = self_exe
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|name| name.replace("installer", "manager"))
+        .unwrap_or(format!("manager{}", utils::EXE_EXT));
+    Ok(cargo_bin.join(manager_name))
+}
+
+/// Print how a tool would be installed, used in dry-run mode.
+fn print_tool_plan(name: &str, tool: &ToolInfo) {
+    let version = tool
+        .version()
+        .map(|ver| format!(" {ver}"))
+        .unwrap_or_default();
+    let source = match tool {
+        ToolInfo::Url { url, .. } => format!(" from '{url}'"),
+        ToolInfo::Path { path, .. } => format!(" from '{}'", path.display()),
+        ToolInfo::System { .. } => " using the package manager of the system".to_string(),
+        _ => String::new(),
+    };
+    for cmd in tool.pre_install_commands() {
+        print_plan(&format!("run pre-install command of '{name}': `{cmd}`"));
+    }
+    print_plan(&format!("install '{name}'{version}{source}"));
+    for step in tool
+        .instruction()
+        .map(|i| i.install.as_slice())
+        .unwrap_or_default()
+    {
+        print_plan(&format!("{step} for '{name}'"));
+    }
+    for cmd in tool.post_install_commands() {
+        print_plan(&format!("run post-install command of '{name}': `{cmd}`"));
+    }
+}
+
+/// Write the information of an installed tool into the installation record,
+/// including its version, source, installed files and the time it was installed,
+/// which are later used for updating and uninstalling.
+fn record_tool_installation(
+    config: &InstallConfiguration,
+    name: &str,
+    tool: &ToolInfo,
+    paths: Vec<PathBuf>,
+    previous: Option<ToolRecord>,
+) -> Result<()> {
+    let source = match tool {
+        ToolInfo::PlainVersion(_) | ToolInfo::DetailedVersion { .. } => ToolSource::Cargo,
+        ToolInfo::Git { git, .. } => ToolSource::Git { url: git.clone() },
+        ToolInfo::Path { path, .. } => ToolSource::Path { path: path.clone() },
+        ToolInfo::Url { url, .. } => ToolSource::Url { url: url.clone() },
+        ToolInfo::System { .. } => ToolSource::System,
+    };
+    let installed_at = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .ok();
+    if let Some(previous) = &previous {
+        shims::remove_stale(&previous.paths, &paths, config.cargo_bin());
+    }
+    // Keep the other versions of this tool if it was installed with its version,
+    // check `tool_versions` module.
+    let versions = match tool.version() {
+        Some(version)
+            if paths.contains(&config.tools_dir().join(name))
+                && tool_versions::version_dir(config.tools_dir(), name, version).is_dir() =>
+        {
+            tool_versions::installed_versions(config.tools_dir(), name, version, previous.as_ref())
+        }
+        _ => vec![],
+    };
+    let checksums = verify::checksums_of(&paths);
+    // Files installed elsewhere (such as by `cargo install`) are not managed by the store.
+    Store::new(&config.install_dir).dedup(
+        checksums
+            .iter()
+            .filter(|(path, _)| path.starts_with(config.tools_dir())),
+    );
+    verify::write_checksums(&config.install_dir, name, &checksums)?;
+    InstallationRecord::update(&config.install_dir, |record| {
+        record.add_tool_record(
+            name,
+            ToolRecord {
+                version: tool.version().map(ToOwned::to_owned),
+                source: Some(source),
+                paths,
+                installed_at,
+                instruction: tool.instruction().cloned(),
+                versions,
+            },
+        );
+        record.add_step(InstallStep::Tool {
+            name: name.to_string(),
+        });
+    })
+}
+
+/// A downloaded file, along with the temporary directory it's in (if there is one),
+/// check [`download_tool`].
+type Downloaded = (Option<TempDir>, PathBuf);
+
+/// Download a tool from its urls, which are the mirrors tried in order,
+/// then return the path to the downloaded file.
+///
+/// If the `sha256` checksum is provided, the file will be verified after downloading,
+/// and stored in [`download_cache_dir`](InstallConfiguration::download_cache_dir),
+/// so that next installation can skip downloading it if the checksum still matches,
+/// or resume downloading it if the previous download was interrupted.
+/// Otherwise, the file will be downloaded into a temporary directory,
+/// which is returned along with the path, and it will be removed once dropped.
+///
+/// Compressed tarballs that are not in the cache yet (and don't need signature verification)
+/// are extracted while being downloaded, in which case the path to the extracted directory
+/// is returned instead, check [`download_and_extract_tool`].
+fn download_tool(
+    config: &InstallConfiguration,
+    name: &str,
+    tool: &ToolInfo,
+    proxy: Option<&Proxy>,
+) -> Result<Downloaded> {
+    let urls = tool.download_urls();
+    let sha256 = tool.sha256();
+    let Some(&url) = urls.first() else {
+        bail!("no url to download '{name}' from");
+    };
+    let downloaded_file_name = url
+        .path_segments()
+        .ok_or_else(|| anyhow!("unsupported url format '{url}'"))?
+        .next_back()
+        // Sadly, a path segment could be empty string, so we need to filter that out
+        .filter(|seg| !seg.is_empty())
+        .ok_or_else(|| anyhow!("'{url}' doesn't appear to be a downloadable file"))?;
 
-            utils::execute("cargo", &args)?;
+    let cached = match sha256 {
+        Some(checksum) if !config.no_cache => {
+            let cache_dir = config.download_cache_dir().join(checksum.to_lowercase());
+            utils::ensure_dir(&cache_dir)?;
+            Some((cache_dir.join(downloaded_file_name), checksum))
         }
-        ToolInfo::Path { path, .. } => try_install_from_path(config, name, path)?,
-        // FIXME: Have a dedicated download folder, do not use temp dir to store downloaded artifacts,
-        // so then we can have the `resume download` feature.
-        ToolInfo::Url { url, .. } => {
-            // TODO: Download first
-            let temp_dir = config.create_temp_dir("download")?;
+        _ => None,
+    };
+
+    if let Some((dest, checksum)) = &cached {
+        if dest.is_file() && utils::sha256_file(dest)?.eq_ignore_ascii_case(checksum) {
+            OutputEvent::Message {
+                text: &format!("using cached file of '{name}': {}", dest.display()),
+            }
+            .emit();
+            return Ok((None, dest.clone()));
+        }
+        // Reconstruct the file from the cached one of a previous version if there's a patch
+        // for it, which is much smaller to download.
+        if download_with_patch(config, name, tool, dest, checksum, proxy) {
+            return Ok((None, dest.clone()));
+        }
+    }
 
-            let downloaded_file_name = url
-                .path_segments()
-                .ok_or_else(|| anyhow!("unsupported url format '{url}'"))?
-                .last()
-                // Sadly, a path segment could be empty string, so we need to filter that out
-                .filter(|seg| !seg.is_empty())
-                .ok_or_else(|| anyhow!("'{url}' doesn't appear to be a downloadable file"))?;
+    // The tarball is still written to the cache while being extracted, if it's enabled.
+    if let Some(kind) =
+        ExtractableKind::streamable(downloaded_file_name).filter(|_| tool.signature().is_none())
+    {
+        let cache = cached.as_ref().map(|(dest, _)| dest.as_path());
+        return download_and_extract_tool(config, name, &urls, kind, sha256, cache, proxy);
+    }
 
+    let (temp_dir, dest) = match cached {
+        Some((dest, _)) => (None, dest),
+        None => {
+            let temp_dir = config.create_temp_dir("download")?;
             let dest = temp_dir.path().join(downloaded_file_name);
+            (Some(temp_dir), dest)
+        }
+    };
 
-            utils::download(name, url, &dest, proxy)?;
-            // TODO: Then do the `extract or copy to` like `ToolInfo::Path`
-            try_install_from_path(config, name, &dest)?;
+    // Files that are cached can be resumed if the previous download was interrupted.
+    let resume = temp_dir.is_none();
+    let used_url =
+        utils::download_from_mirrors(name, &urls, &dest, proxy, resume).with_context(|| {
+            InstallError::DownloadFailed {
+                name: name.to_string(),
+                url: url.clone(),
+            }
+        })?;
+
+    if let Some(checksum) = sha256 {
+        let actual = utils::sha256_file(&dest)?;
+        if !actual.eq_ignore_ascii_case(checksum) {
+            utils::remove(&dest)?;
+            bail!(InstallError::ChecksumMismatch {
+                name: name.to_string(),
+                url: used_url,
+                expected: checksum.to_string(),
+                actual,
+            });
         }
     }
+
+    Ok((temp_dir, dest))
+}
+
+/// Try to reconstruct the file of a tool at `dest` using one of its [patches](ToolInfo::patches),
+/// whose old file is still in the download cache.
+///
+/// Return `false` if none of the patches can be used, in which case the whole file should be
+/// downloaded instead, the failures of applying patches are reported as warnings.
+fn download_with_patch(
+    config: &InstallConfiguration,
+    name: &str,
+    tool: &ToolInfo,
+    dest: &Path,
+    checksum: &str,
+    proxy: Option<&Proxy>,
+) -> bool {
+    for patch in tool.patches() {
+        let cache_dir = config
+            .download_cache_dir()
+            .join(patch.from_sha256.to_lowercase());
+        let Some(old) = utils::walk_dir(&cache_dir, false)
+            .ok()
+            .and_then(|files| files.into_iter().find(|file| file.is_file()))
+        else {
+            continue;
+        };
+        match apply_downloaded_patch(config, name, patch, &old, dest, checksum, proxy) {
+            Ok(()) => return true,
+            Err(e) => {
+                let _ = utils::remove(dest);
+                tracing::warn!(
+                    "unable to update '{name}' from version {} using patch, \
+                    downloading the whole file instead: {e:?}",
+                    patch.from
+                );
+            }
+        }
+    }
+    false
+}
+
+/// Download a patch and apply it to the `old` file of a tool, writing the result to `dest`,
+/// then verify the result against the expected `checksum`.
+fn apply_downloaded_patch(
+    config: &InstallConfiguration,
+    name: &str,
+    patch: &Patch,
+    old: &Path,
+    dest: &Path,
+    checksum: &str,
+    proxy: Option<&Proxy>,
+) -> Result<()> {
+    let temp_dir = config.create_temp_dir("patch")?;
+    let patch_file = temp_dir.path().join("patch.zst");
+    let used_url = utils::download_from_mirrors(
+        format!("{name} (patch)"),
+        &[&patch.url],
+        &patch_file,
+        proxy,
+        false,
+    )?;
+    if let Some(expected) = &patch.sha256 {
+        verify_local_checksum(name, &used_url, &patch_file, expected)?;
+    }
+    OutputEvent::Message {
+        text: &format!("updating '{name}' from version {} using patch", patch.from),
+    }
+    .emit();
+    utils::apply_patch(old, &patch_file, dest)?;
+    verify_local_checksum(name, &used_url, dest, checksum)
+}
+
+/// Download a compressed tarball and extract it into a temporary directory on the fly,
+/// which avoids storing the tarball unless it should be kept in the `cache`,
+/// then return the path to the extracted directory.
+///
+/// If the `sha256` checksum is provided, it's verified before the extracted files are used,
+/// and the next mirror is tried if it doesn't match.
+fn download_and_extract_tool(
+    config: &InstallConfiguration,
+    name: &str,
+    urls: &[&Url],
+    kind: ExtractableKind,
+    sha256: Option<&str>,
+    cache: Option<&Path>,
+    proxy: Option<&Proxy>,
+) -> Result<Downloaded> {
+    let temp_dir = config.create_temp_dir("download")?;
+    let dest = temp_dir.path().join(name);
+    let verify = |url: &Url, actual: &str| -> Result<()> {
+        match sha256 {
+            Some(expected) if !actual.eq_ignore_ascii_case(expected) => {
+                bail!(InstallError::ChecksumMismatch {
+                    name: name.to_string(),
+                    url: url.clone(),
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    };
+    utils::download_and_extract_from_mirrors(name, urls, kind, &dest, cache, proxy, &verify)
+        .with_context(|| InstallError::DownloadFailed {
+            name: name.to_string(),
+            url: urls[0].clone(),
+        })?;
+
+    Ok((Some(temp_dir), dest))
+}
+
+/// Verify the checksum of a tool that is installed from a local file.
+pub(crate) fn verify_local_checksum(
+    name: &str,
+    url: &Url,
+    path: &Path,
+    expected: &str,
+) -> Result<()> {
+    let actual = utils::sha256_file(path)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        bail!(InstallError::ChecksumMismatch {
+            name: name.to_string(),
+            url: url.clone(),
+            expected: expected.to_string(),
+            actual,
+        });
+    }
     Ok(())
 }
 
-fn try_install_from_path(config: &InstallConfiguration, name: &str, path: &Path) -> Result<()> {
+/// Download the detached signature, then use it to verify the given file.
+///
+/// Note this returns a different error than checksum mismatches,
+/// making it clear that the authenticity of the file cannot be confirmed.
+pub(crate) fn verify_signature(
+    config: &InstallConfiguration,
+    name: &str,
+    file: &Path,
+    signature: &Signature,
+    proxy: Option<&Proxy>,
+) -> Result<()> {
+    let temp_dir = config.create_temp_dir("signature")?;
+    let sig_path = temp_dir.path().join(format!("{name}.minisig"));
+    utils::download(
+        format!("{name} signature"),
+        &signature.url,
+        &sig_path,
+        proxy,
+    )
+    .with_context(|| format!("unable to download the signature of '{name}'"))?;
+
+    utils::verify_minisign(file, &sig_path, &signature.public_key).with_context(|| {
+        InstallError::SignatureMismatch {
+            name: name.to_string(),
+            path: file.to_path_buf(),
+        }
+    })
+}
+
+/// Run the `pre-install` or `post-install` commands (depending on `stage`)
+/// declared in the manifest for a certain tool, check [`ToolInfo::pre_install_commands`]
+/// and [`ToolInfo::post_install_commands`].
+///
+/// Each command will be executed in a shell under `cwd`, with the environment variable
+/// [`TOOL_INSTALL_DIR`] pointing to the tool's installation directory,
+/// along with the proxy settings (if there are any).
+///
+/// NB: Only the commands declared in the manifest will be executed.
+fn run_install_hooks(
+    stage: &str,
+    name: &str,
+    hooks: &[String],
+    cwd: &Path,
+    install_dir: &Path,
+    proxy: Option<&Proxy>,
+) -> Result<()> {
+    let proxy_vars = proxy
+        .map(|p| p.env_vars_with_credentials())
+        .unwrap_or_default();
+    let mut env_vars = vec![(TOOL_INSTALL_DIR, install_dir.as_os_str())];
+    env_vars.extend(proxy_vars.iter().map(|(k, v)| (*k, OsStr::new(v))));
+
+    for cmd in hooks {
+        OutputEvent::Message {
+            text: &format!("running {stage} command for '{name}': {cmd}"),
+        }
+        .emit();
+        utils::shell_execute_in_dir_with_env(cwd, cmd, &[] as &[&str], env_vars.clone())
+            .with_context(|| format!("failed to run {stage} command `{cmd}` of tool '{name}'"))?;
+    }
+
+    Ok(())
+}
+
+/// Install a tool from a local path, then return the directory where it was installed,
+/// along with a list of installed files or directories.
+fn try_install_from_path(
+    config: &InstallConfiguration,
+    name: &str,
+    tool: &ToolInfo,
+    path: &Path,
+    proxy: Option<&Proxy>,
+) -> Result<(PathBuf, Vec<PathBuf>)> {
     if !path.exists() {
         bail!(
             "unable to install '{name}' because the path to it's installer '{}' does not exist.",
@@ -432,10 +2132,49 @@ fn try_install_from_path(config: &InstallConfiguration, name: &str, path: &Path)
 
     let temp_dir = config.create_temp_dir(name)?;
     let tool_installer_path = extract_or_copy_to(path, temp_dir.path())?;
-    let tool_installer = Tool::from_path(name, &tool_installer_path)
-        .with_context(|| format!("no install method for tool '{name}'"))?;
+    install_from_extracted(config, name, tool, &tool_installer_path, proxy)
+}
+
+/// Install a tool from its extracted (or copied) installer, which is a temporary
+/// file or directory, then return the installation directory and the installed paths.
+fn install_from_extracted(
+    config: &InstallConfiguration,
+    name: &str,
+    tool: &ToolInfo,
+    tool_installer_path: &Path,
+    proxy: Option<&Proxy>,
+) -> Result<(PathBuf, Vec<PathBuf>)> {
+    let tool_installer = if let Some(instruction) = tool.instruction() {
+        Tool::Declared {
+            name: name.to_string(),
+            path: tool_installer_path,
+            instruction,
+        }
+    } else {
+        Tool::from_path(name, tool_installer_path)
+            .with_context(|| format!("no install method for tool '{name}'"))?
+            .with_installer_args(tool.installer_args())
+            .with_tool_info(tool)
+    };
+    let cwd = if tool_installer_path.is_dir() {
+        tool_installer_path
+    } else {
+        tool_installer_path.parent().unwrap_or(tool_installer_path)
+    };
+    let install_dir = tool_installer.install_dir(config);
+    run_install_hooks(
+        "pre-install",
+        name,
+        tool.pre_install_commands(),
+        cwd,
+        &install_dir,
+        proxy,
+    )?;
     tool_installer.install(config)?;
-    Ok(())
+    Ok((
+        tool_installer.install_dir(config),
+        tool_installer.installed_paths(config),
+    ))
 }
 
 /// Perform extraction or copy action base on the given path.
@@ -469,4 +2208,238 @@ mod tests {
             "https://mirrors.tuna.tsinghua.edu.cn/rustup/rustup"
         );
     }
+
+    #[test]
+    fn sort_tools_by_requirements() {
+        let input = r#"
+[rust]
+version = "1.0.0"
+
+[tools.target.x86_64-unknown-linux-gnu]
+vscode-ext = { path = "/path/to/ext.vsix", requires = ["vscode"] }
+plugin = { path = "/path/to/plugin", requires = ["vscode-ext", "vscode", "cargo-tool"] }
+vscode = { path = "/path/to/vscode" }
+standalone = { path = "/path/to/standalone" }
+cargo-tool = "0.1.0"
+"#;
+        let manifest = ToolsetManifest::from_str(input).unwrap();
+        let tools = manifest
+            .target_tools("x86_64-unknown-linux-gnu")
+            .unwrap()
+            .iter()
+            .filter(|(_, tool)| !tool.is_cargo_tool())
+            .collect::<Vec<_>>();
+        let sorted = sort_by_requirements(tools)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(sorted, ["vscode", "vscode-ext", "plugin", "standalone"]);
+    }
+
+    #[test]
+    fn circular_requirements() {
+        let input = r#"
+[rust]
+version = "1.0.0"
+
+[tools.target.x86_64-unknown-linux-gnu]
+a = { path = "/path/to/a", requires = ["b"] }
+b = { path = "/path/to/b", requires = ["c"] }
+c = { path = "/path/to/c", requires = ["a"] }
+d = { path = "/path/to/d" }
+"#;
+        let manifest = ToolsetManifest::from_str(input).unwrap();
+        let tools = manifest
+            .target_tools("x86_64-unknown-linux-gnu")
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        let err = sort_by_requirements(tools).unwrap_err();
+        assert!(err.to_string().ends_with("a -> b -> c -> a"), "{err}");
+    }
+
+    #[test]
+    fn download_tools_concurrently() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+        use std::time::{Duration, Instant};
+
+        // None of the requests is answered until both of them are received,
+        // which never happens if the tools are downloaded one after another.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        thread::spawn(move || {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            let mut streams = vec![];
+            while streams.len() < 2 && Instant::now() < deadline {
+                match listener.accept() {
+                    Ok((stream, _)) => streams.push(stream),
+                    Err(_) => thread::sleep(Duration::from_millis(10)),
+                }
+            }
+            let concurrent = streams.len() == 2;
+            for mut stream in streams {
+                stream.set_nonblocking(false).unwrap();
+                let mut lines = BufReader::new(&stream).lines().map(|line| line.unwrap());
+                let request = lines.next().unwrap();
+                lines.find(|line| line.is_empty());
+                // Serve the requested path as the content, like `GET /a.bin HTTP/1.1`.
+                let body = request.split(' ').nth(1).unwrap().to_string();
+                let status = if concurrent {
+                    "200 OK"
+                } else {
+                    "404 Not Found"
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+
+        let input = format!(
+            r#"
+[rust]
+version = "1.0.0"
+
+[tools.target.x86_64-unknown-linux-gnu]
+a = {{ url = "http://{addr}/a.bin" }}
+b = {{ url = "http://{addr}/b.bin" }}
+local = {{ path = "/path/to/local" }}
+"#
+        );
+        let manifest = ToolsetManifest::from_str(&input).unwrap();
+        let tools = manifest
+            .target_tools("x86_64-unknown-linux-gnu")
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        let dir = tempfile::tempdir().unwrap();
+        let config = InstallConfiguration::default()
+            .temp_dir(Some(dir.path().to_path_buf()))
+            .unwrap()
+            .download_jobs(2);
+
+        let mut downloads = config
+            .download_tools(&tools, &mut MultiThreadProgress::default(), None)
+            .unwrap();
+        assert_eq!(downloads.len(), 2);
+        for name in ["a", "b"] {
+            let (temp_dir, dest) = downloads.remove(name).unwrap().unwrap();
+            assert_eq!(
+                std::fs::read_to_string(&dest).unwrap(),
+                format!("/{name}.bin")
+            );
+            // The downloaded file is removed along with its temp dir.
+            drop(temp_dir);
+            assert!(!dest.exists());
+        }
+    }
+
+    #[test]
+    fn cargo_install_options() {
+        let input = r#"
+[rust]
+version = "1.0.0"
+
+[tools.target.x86_64-unknown-linux-gnu]
+a = "0.1.0"
+b = { ver = "0.2.0", locked = true, features = ["f1", "f2"], no-default-features = true }
+c = { git = "https://example.com/c.git", tag = "v1", bin = ["c-cli"] }
+"#;
+        let manifest = ToolsetManifest::from_str(input).unwrap();
+        let tools = manifest.target_tools("x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(
+            cargo_install_args("a", &tools["a"]).unwrap(),
+            ["install", "a", "--version", "0.1.0"]
+        );
+        assert_eq!(
+            cargo_install_args("b", &tools["b"]).unwrap(),
+            [
+                "install",
+                "b",
+                "--version",
+                "0.2.0",
+                "--locked",
+                "--features",
+                "f1",
+                "--features",
+                "f2",
+                "--no-default-features"
+            ]
+        );
+        assert_eq!(
+            cargo_install_args("c", &tools["c"]).unwrap(),
+            [
+                "install",
+                "--git",
+                "https://example.com/c.git",
+                "--tag",
+                "v1",
+                "--bin",
+                "c-cli"
+            ]
+        );
+    }
+
+    #[test]
+    fn estimate_space_with_tool_sizes() {
+        let input = format!(
+            r#"
+[rust]
+version = "1.0.0"
+
+[tools.target.{}]
+t1 = {{ url = "https://example.com/t1.tar.gz", size = 1000 }}
+t2 = {{ url = "https://example.com/t2.tar.gz" }}
+t3 = "1.0.0"
+"#,
+            utils::host_triple()
+        );
+        let manifest = ToolsetManifest::from_str(&input).unwrap();
+        assert_eq!(
+            estimate_required_space(&manifest),
+            RUST_TOOLCHAIN_SIZE + 1000 * (1 + EXTRACTION_FACTOR)
+        );
+    }
+
+    #[test]
+    fn prune_stale_temp_entries() {
+        let temp_root = tempfile::tempdir().unwrap();
+        let stale_dir = temp_root.path().join("download_abc123");
+        let stale_file = temp_root.path().join("rustup-init_def456");
+        utils::ensure_dir(stale_dir.join("partially_extracted")).unwrap();
+        utils::write_file(&stale_file, "", false).unwrap();
+
+        // Nothing is older than an hour ago.
+        remove_stale_entries(
+            temp_root.path(),
+            SystemTime::now() - Duration::from_secs(3600),
+        );
+        assert!(stale_dir.exists());
+        assert!(stale_file.exists());
+
+        remove_stale_entries(temp_root.path(), SystemTime::now() + Duration::from_secs(1));
+        assert!(!stale_dir.exists());
+        assert!(!stale_file.exists());
+        assert!(temp_root.path().is_dir());
+    }
+
+    #[test]
+    fn match_detected_versions() {
+        assert_eq!(matches_version(None, None), Some(true));
+        assert_eq!(matches_version(None, Some("1.0.0")), Some(true));
+        assert_eq!(matches_version(Some("1.91.1"), None), None);
+        assert_eq!(matches_version(Some("1.91.1"), Some("1.91.1")), Some(true));
+        assert_eq!(
+            matches_version(Some("1.91.1"), Some("tool v1.91.1 (abcdef)")),
+            Some(true)
+        );
+        assert_eq!(matches_version(Some("1.91.1"), Some("1.90.0")), Some(false));
+        assert_eq!(matches_version(Some("1.9"), Some("1.91.1")), Some(false));
+    }
 }
@@ -0,0 +1,178 @@
+//! A lock file under the installation directory, which is held by the operations that modify
+//! an installation, such as installing, updating and uninstalling,
+//! so that two instances of the installer (or manager) won't clobber each other.
+
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use super::error::InstallError;
+use super::os::is_process_alive;
+use crate::utils;
+
+/// The file name of the lock, located under `install_dir`, which contains the PID of its holder.
+pub(crate) const FILENAME: &str = ".lock";
+
+/// The lock of an installation directory, which is released once dropped.
+#[derive(Debug)]
+pub struct InstallLock {
+    path: PathBuf,
+}
+
+impl InstallLock {
+    /// Acquire the lock of `install_dir` (which is created if it doesn't exist),
+    /// or return [`InstallError::Locked`] if it's held by another running process.
+    ///
+    /// A lock held by a process that is no longer running is stale, which is taken over.
+    /// A lock whose holder cannot be read is considered held, since it might be written
+    /// by another process right now.
+    /// If `force` is `true`, the existing lock is taken over regardless.
+    pub fn acquire(install_dir: &Path, force: bool) -> Result<Self> {
+        utils::ensure_dir(install_dir)?;
+        let path = install_dir.join(FILENAME);
+        // The lock is linked from a temporary file with the PID already in it,
+        // so that others never see a lock without its holder.
+        let mut temp = tempfile::Builder::new()
+            .prefix(FILENAME)
+            .tempfile_in(install_dir)
+            .with_context(|| format!("unable to create lock file '{}'", path.display()))?;
+        write!(temp, "{}", std::process::id())?;
+        // Try again once the stale lock is removed.
+        for _ in 0..2 {
+            match fs::hard_link(temp.path(), &path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let holder = read_holder(&path);
+                    let is_stale = holder.is_some_and(|pid| !is_process_alive(pid));
+                    if !force && !is_stale {
+                        return Err(InstallError::Locked {
+                            install_dir: install_dir.to_path_buf(),
+                            pid: holder,
+                        }
+                        .into());
+                    }
+                    remove_stale_lock(&path, holder)?;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("unable to create lock file '{}'", path.display())
+                    })
+                }
+            }
+        }
+        bail!("unable to acquire lock file '{}'", path.display())
+    }
+}
+
+impl Drop for InstallLock {
+    fn drop(&mut self) {
+        // The lock might have been taken over by force, which is no longer ours to remove.
+        // And the installation directory might be removed already, i.e. after uninstalling.
+        if read_holder(&self.path) == Some(std::process::id()) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Read the PID of the process holding the lock file at `path`.
+fn read_holder(path: &Path) -> Option<u32> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| content.trim().parse().ok())
+}
+
+/// Remove the stale lock file at `path` held by `holder`, so that it can be created again.
+///
+/// The file is renamed (atomically) before removing it, in case another process took it over
+/// and created a new lock in the meantime, which is put back instead of being removed.
+fn remove_stale_lock(path: &Path, holder: Option<u32>) -> Result<()> {
+    tracing::warn!("removing the stale lock file '{}'", path.display());
+    let stale = path.with_extension(format!("stale-{}", std::process::id()));
+    match fs::rename(path, &stale) {
+        Ok(()) => (),
+        // Another process removed it first.
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("unable to remove lock file '{}'", path.display()))
+        }
+    }
+    if read_holder(&stale) != holder {
+        // Put back the new lock, unless yet another one has been created.
+        let _ = fs::hard_link(&stale, path);
+    }
+    fs::remove_file(&stale)
+        .with_context(|| format!("unable to remove lock file '{}'", stale.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_install_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = InstallLock::acquire(dir.path(), false).unwrap();
+        let content = fs::read_to_string(dir.path().join(FILENAME)).unwrap();
+        assert_eq!(content, std::process::id().to_string());
+
+        let err = InstallLock::acquire(dir.path(), false).unwrap_err();
+        assert_eq!(crate::error_code(&err), Some("locked"));
+
+        drop(lock);
+        assert!(!dir.path().join(FILENAME).exists());
+    }
+
+    #[test]
+    fn take_over_stale_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILENAME);
+        fs::write(&path, u32::MAX.to_string()).unwrap();
+        let _lock = InstallLock::acquire(dir.path(), false).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+
+        // Locks held by running processes can only be taken over by force.
+        let forced = InstallLock::acquire(dir.path(), true).unwrap();
+        assert!(!dir
+            .path()
+            .join(format!("{FILENAME}.stale-{}", std::process::id()))
+            .exists());
+
+        // The lock taken over is not removed by its previous holder.
+        fs::write(&path, u32::MAX.to_string()).unwrap();
+        drop(forced);
+        assert_eq!(fs::read_to_string(&path).unwrap(), u32::MAX.to_string());
+    }
+
+    #[test]
+    fn unreadable_lock_is_held() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILENAME);
+        // Such as the one being written by another process.
+        fs::write(&path, "").unwrap();
+        let err = InstallLock::acquire(dir.path(), false).unwrap_err();
+        assert_eq!(crate::error_code(&err), Some("locked"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        let _lock = InstallLock::acquire(dir.path(), true).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+        // Nothing but the lock is left.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn lock_new_install_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let install_dir = dir.path().join("rust");
+        let _lock = InstallLock::acquire(&install_dir, false).unwrap();
+        assert!(install_dir.join(FILENAME).is_file());
+    }
+}
@@ -2,14 +2,30 @@
 //!
 //! Including configuration, toolchain, toolset management.
 
-mod custom_instructions;
+pub(crate) mod custom_instructions;
+pub(crate) mod doctor;
+pub mod env;
+pub mod error;
 pub mod install;
-mod os;
+pub mod lock;
+pub(crate) mod offline;
+pub(crate) mod os;
 pub(crate) mod parser;
+pub mod preflight;
+pub(crate) mod project;
+pub mod report;
+mod rollback;
 pub(crate) mod rustup;
+pub mod session;
+pub(crate) mod shims;
+pub(crate) mod snapshot;
+pub(crate) mod store;
+pub(crate) mod tool_versions;
 pub(crate) mod tools;
 pub mod try_it;
 pub(crate) mod uninstall;
+pub(crate) mod update;
+pub(crate) mod verify;
 
 macro_rules! declare_env_vars {
     ($($key:ident),+) => {
@@ -25,3 +41,7 @@ declare_env_vars!(
     RUSTUP_DIST_SERVER,
     RUSTUP_UPDATE_ROOT
 );
+
+/// Environment variable pointing to the installation directory of a tool,
+/// which is available when running the tool's post-install commands.
+pub(crate) const TOOL_INSTALL_DIR: &str = "TOOL_INSTALL_DIR";
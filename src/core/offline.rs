@@ -0,0 +1,304 @@
+//! Installing from a local bundle directory, without accessing the network.
+//!
+//! A bundle has the following layout:
+//!
+//! ```text
+//! <bundle>/
+//! ├── toolset_manifest.toml       (optional, the built-in manifest is used if missing)
+//! ├── rustup/                     (a mirror of the rustup dist server)
+//! │   └── dist/
+//! │       ├── <target>/rustup-init    (unless `rust.rustup-init.<target>` is given)
+//! │       ├── channel-rust-<version>.toml
+//! │       ├── <date>/rust-std-<version>-<target>.tar.xz   (for each of `rust.targets`)
+//! │       └── ...
+//! └── packages/
+//!     └── <name>/<version>/<file>
+//! ```
+//!
+//! Where `<file>` is the file name in the `url` of a tool, and `<version>` is
+//! the version of that tool, or `latest` if it doesn't have one.
+//! The relative paths of tools in the bundled manifest are relative to the bundle directory.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use url::Url;
+
+use super::parser::manifest::{ToolInfo, ToolsetManifest};
+use super::parser::ManifestParser;
+use super::rustup::RUSTUP_INIT;
+use crate::utils;
+
+/// The file name of the toolset manifest in a bundle.
+pub(crate) const MANIFEST_FILENAME: &str = "toolset_manifest.toml";
+
+/// A local directory containing everything needed for an installation.
+#[derive(Debug)]
+pub(crate) struct Bundle {
+    root: PathBuf,
+}
+
+impl Bundle {
+    pub(crate) fn new(root: &Path) -> Result<Self> {
+        let root = utils::to_nomalized_abspath(root, None)?;
+        if !root.is_dir() {
+            bail!("the bundle directory '{}' does not exist", root.display());
+        }
+        Ok(Self { root })
+    }
+
+    /// Load the manifest in this bundle, or return `None` if there isn't one.
+    pub(crate) fn manifest(&self) -> Result<Option<ToolsetManifest>> {
+        let path = self.root.join(MANIFEST_FILENAME);
+        if path.is_file() {
+            ToolsetManifest::load(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The url of the `rustup` mirror in this bundle, which is used as
+    /// both `RUSTUP_DIST_SERVER` and `RUSTUP_UPDATE_ROOT`.
+    pub(crate) fn rustup_server(&self) -> Result<Url> {
+        let dir = self.root.join("rustup");
+        Url::from_directory_path(&dir)
+            .map_err(|_| anyhow!("unable to convert '{}' to url", dir.display()))
+    }
+
+    /// Get the path to the bundled file of a tool that should be downloaded from `url`.
+    fn package_path(&self, name: &str, version: Option<&str>, url: &Url) -> Option<PathBuf> {
+        let file_name = url
+            .path_segments()?
+            .next_back()
+            .filter(|seg| !seg.is_empty())?;
+        let mut path = self.root.join("packages");
+        path.push(name);
+        path.push(version.unwrap_or("latest"));
+        path.push(file_name);
+        Some(path)
+    }
+
+    /// Make the given manifest use the files in this bundle instead of downloading them,
+    /// after making sure every artifact needed by current target is present.
+    ///
+    /// All the missing artifacts are reported at once.
+    pub(crate) fn apply_to(&self, manifest: &mut ToolsetManifest) -> Result<()> {
+        let mut problems = self.check_rust(manifest);
+
+        if let Some(tools) = manifest.current_target_tools_mut() {
+            for (name, tool) in tools.iter_mut() {
+                match tool {
+                    ToolInfo::Url {
+                        url,
+                        version,
+                        sha256,
+                        ..
+                    } => {
+                        let Some(path) = self.package_path(name, version.as_deref(), url) else {
+                            problems.push(format!("'{name}': '{url}' is not a downloadable file"));
+                            continue;
+                        };
+                        if !path.is_file() {
+                            problems.push(format!("'{name}': missing '{}'", path.display()));
+                            continue;
+                        }
+                        if let Some(checksum) = sha256 {
+                            let actual = utils::sha256_file(&path)?;
+                            if !actual.eq_ignore_ascii_case(checksum) {
+                                problems.push(format!(
+                                    "'{name}': checksum mismatch of '{}', expected '{checksum}' but got '{actual}'",
+                                    path.display()
+                                ));
+                                continue;
+                            }
+                        }
+                        tool.convert_to_path(path);
+                    }
+                    ToolInfo::Path { path, .. } => {
+                        if !path.exists() {
+                            problems.push(format!("'{name}': missing '{}'", path.display()));
+                        }
+                    }
+                    // Installed from the package repositories configured on the system.
+                    ToolInfo::System { .. } => (),
+                    _ => problems.push(format!(
+                        "'{name}': tools installed using `cargo install` require network access"
+                    )),
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            bail!(
+                "the bundle '{}' cannot be used for offline installation:\n  {}",
+                self.root.display(),
+                problems.join("\n  ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Check if the files needed to install Rust toolchain are present.
+    fn check_rust(&self, manifest: &ToolsetManifest) -> Vec<String> {
+        let dist = self.root.join("rustup").join("dist");
+        let rustup_init = match manifest.local_rustup_init() {
+            Some(local) => local.path.clone(),
+            None => dist.join(utils::native_triple()).join(RUSTUP_INIT),
+        };
+        let channel = dist.join(format!("channel-rust-{}.toml", manifest.rust.version));
+
+        let mut problems = vec![];
+        for path in [&rustup_init, &channel] {
+            if !path.is_file() {
+                problems.push(format!("'rust': missing '{}'", path.display()));
+            }
+        }
+        if channel.is_file() && !manifest.rust.targets.is_empty() {
+            if let Err(e) = check_targets(&dist, &channel, &manifest.rust.targets, &mut problems) {
+                problems.push(format!(
+                    "'rust': unable to read '{}': {e}",
+                    channel.display()
+                ));
+            }
+        }
+        for (name, toolchain) in &manifest.rust.toolchains {
+            if let Some(channel) = toolchain.channel.as_ref().filter(|c| c.scheme() != "file") {
+                problems.push(format!(
+                    "'rust': the channel manifest of toolchain '{name}' is not a local file: '{channel}'"
+                ));
+            }
+        }
+        if let Some(sig) = &manifest.rust.rustup_init_signature {
+            if sig.url.scheme() != "file" {
+                problems.push(format!(
+                    "'rust': the signature of '{RUSTUP_INIT}' is not a local file: '{}'",
+                    sig.url
+                ));
+            }
+        }
+        problems
+    }
+}
+
+/// Check if the standard libraries of the cross compilation `targets` are present under `dist`,
+/// according to the packages listed in the `channel` manifest.
+///
+/// The url of a package, such as `https://static.rust-lang.org/dist/<date>/<file>`,
+/// is served as `<dist>/<date>/<file>` by the bundled mirror.
+fn check_targets(
+    dist: &Path,
+    channel: &Path,
+    targets: &[String],
+    problems: &mut Vec<String>,
+) -> Result<()> {
+    let channel: toml::Table = toml::from_str(&utils::read_to_string(channel)?)?;
+    let rust_std = channel
+        .get("pkg")
+        .and_then(|pkg| pkg.get("rust-std"))
+        .and_then(|pkg| pkg.get("target"));
+    for target in targets {
+        let Some(package) = rust_std.and_then(|pkgs| pkgs.get(target)) else {
+            problems.push(format!("'rust': target '{target}' is not available"));
+            continue;
+        };
+        let paths = ["xz_url", "url"]
+            .into_iter()
+            .filter_map(|key| package.get(key)?.as_str())
+            .filter_map(|url| Some(dist.join(url.split_once("/dist/")?.1)))
+            .collect::<Vec<_>>();
+        if paths.iter().any(|path| path.is_file()) {
+            continue;
+        }
+        match paths.first() {
+            Some(path) => problems.push(format!("'rust': missing '{}'", path.display())),
+            None => problems.push(format!("'rust': target '{target}' is not available")),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_paths() {
+        let bundle = Bundle {
+            root: PathBuf::from("/bundle"),
+        };
+        let url = Url::parse("https://example.com/path/to/foo-1.0.tar.gz").unwrap();
+        assert_eq!(
+            bundle.package_path("foo", Some("1.0"), &url).unwrap(),
+            Path::new("/bundle/packages/foo/1.0/foo-1.0.tar.gz")
+        );
+        assert_eq!(
+            bundle.package_path("foo", None, &url).unwrap(),
+            Path::new("/bundle/packages/foo/latest/foo-1.0.tar.gz")
+        );
+        let url = Url::parse("https://example.com/path/to/").unwrap();
+        assert!(bundle.package_path("foo", None, &url).is_none());
+    }
+
+    #[test]
+    fn report_missing_artifacts() {
+        let bundle_dir = tempfile::tempdir().unwrap();
+        let bundle = Bundle::new(bundle_dir.path()).unwrap();
+        let mut manifest = ToolsetManifest::from_str(&format!(
+            r#"
+[rust]
+version = "1.80.0"
+
+[tools.target.{}]
+foo = {{ url = "https://example.com/foo.tar.gz", version = "1.0" }}
+bar = "0.1.0"
+"#,
+            utils::host_triple()
+        ))
+        .unwrap();
+
+        let err = bundle.apply_to(&mut manifest).unwrap_err().to_string();
+        assert!(err.contains("channel-rust-1.80.0.toml"));
+        assert!(err.contains("'foo': missing"));
+        assert!(err.contains("'bar': tools installed using `cargo install`"));
+    }
+
+    #[test]
+    fn report_missing_targets() {
+        let bundle_dir = tempfile::tempdir().unwrap();
+        let dist = bundle_dir.path().join("rustup").join("dist");
+        std::fs::create_dir_all(dist.join("2024-07-25")).unwrap();
+        std::fs::write(
+            dist.join("channel-rust-1.80.0.toml"),
+            r#"
+[pkg.rust-std.target.wasm32-unknown-unknown]
+available = true
+url = "https://static.rust-lang.org/dist/2024-07-25/rust-std-1.80.0-wasm32-unknown-unknown.tar.gz"
+xz_url = "https://static.rust-lang.org/dist/2024-07-25/rust-std-1.80.0-wasm32-unknown-unknown.tar.xz"
+
+[pkg.rust-std.target.aarch64-unknown-linux-musl]
+available = true
+xz_url = "https://static.rust-lang.org/dist/2024-07-25/rust-std-1.80.0-aarch64-unknown-linux-musl.tar.xz"
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dist.join("2024-07-25/rust-std-1.80.0-wasm32-unknown-unknown.tar.xz"),
+            "",
+        )
+        .unwrap();
+
+        let bundle = Bundle::new(bundle_dir.path()).unwrap();
+        let manifest = ToolsetManifest::from_str(
+            r#"
+[rust]
+version = "1.80.0"
+targets = ["wasm32-unknown-unknown", "aarch64-unknown-linux-musl", "riscv64-unknown-none"]
+"#,
+        )
+        .unwrap();
+        let problems = bundle.check_rust(&manifest).join("\n");
+        assert!(!problems.contains("'wasm32-unknown-unknown'"));
+        assert!(problems.contains("rust-std-1.80.0-aarch64-unknown-linux-musl.tar.xz"));
+        assert!(problems.contains("target 'riscv64-unknown-none' is not available"));
+    }
+}
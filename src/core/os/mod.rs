@@ -10,19 +10,22 @@ pub(crate) mod unix;
 #[cfg(windows)]
 pub(crate) mod windows;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{Context, Result};
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
+use crate::core::error::InstallError;
+use crate::core::parser::fingerprint::InstallationRecord;
+use crate::manifest::Proxy;
 use crate::utils;
 
 /// Try getting the installation root judging be current executable path.
 //
 // This program should be installed under `{install_dir}/.cargo/bin/`,
 // we should be able to track the installation dir by going up three parents.
-// We should also make sure it is indeed the installation dir by checking if
+// We should also make sure it is indeed the installation dir by looking for the
+// installation record, or, for older installations without one, checking if
 // the folder fits the characteristic.
-// FIXME: There might be risks involved, resulting unintended directory being removed
-// after uninstallation.
 pub(crate) fn install_dir_from_exe_path() -> Result<PathBuf> {
     let exe_path = std::env::current_exe().context("cannot locate current executable")?;
     let comp_count = exe_path.components().count();
@@ -32,32 +35,91 @@ pub(crate) fn install_dir_from_exe_path() -> Result<PathBuf> {
         .collect();
 
     if maybe_install_dir.parent().is_none() {
-        bail!("unable to uninstall as it appears that this program was mistakenly installed in root directory.");
+        return Err(InstallError::RootInstallDir).context("unable to uninstall");
     }
-    // Make sure this directory match some signature by checking its content.
-    // This is still unsafe, we're desparetely in need of an install manifest.
+    // The installation record is the most reliable signature of an installation directory.
+    if InstallationRecord::path_under(&maybe_install_dir).is_file() {
+        return Ok(maybe_install_dir);
+    }
+    // Otherwise, make sure this directory match some signature by checking its content,
+    // this might be the case when the installation was done by an older version of this program.
     let install_dir_contents = utils::walk_dir(&maybe_install_dir, false)
         .context("unable to uninstall as the installation directory cannot be determined.")?;
     if !install_dir_contents.contains(&maybe_install_dir.join(".cargo"))
         || !install_dir_contents.contains(&maybe_install_dir.join(".rustup"))
     {
-        bail!(
-            "unable to uninstall as the installation directory appears to be corrupted, \
-            try manually remove the following directory:\n\n\
-            {}\n",
-            maybe_install_dir.display()
-        );
+        return Err(InstallError::CorruptedInstallDir(maybe_install_dir))
+            .context("unable to uninstall");
     }
 
     Ok(maybe_install_dir)
 }
 
-pub(crate) fn add_to_path(path: &Path) -> Result<()> {
+static SYSTEM_WIDE: OnceLock<bool> = OnceLock::new();
+
+/// Install for (or manage the installation shared by) all users of this machine,
+/// this can only be set once, any subsequent calls will be ignored.
+///
+/// This changes where the installation goes by default (check [`default_install_root`]),
+/// and where the environment variables are written to, which are
+/// `HKEY_LOCAL_MACHINE` on Windows, or `/etc/profile.d` on unix.
+pub(crate) fn set_system_wide(yes: bool) {
+    let _ = SYSTEM_WIDE.set(yes);
+}
+
+/// Check if this is a system-wide installation, which is set by [`set_system_wide`].
+pub(crate) fn is_system_wide() -> bool {
+    SYSTEM_WIDE.get().copied().unwrap_or_default()
+}
+
+/// Get the directory to put the installation directory in if not specified,
+/// which is the home directory of current user, or the system-wide location for
+/// programs (`Program Files` on Windows, `/opt` on unix) for [system-wide](is_system_wide)
+/// installations.
+pub(crate) fn default_install_root() -> PathBuf {
+    if !is_system_wide() {
+        return utils::home_dir();
+    }
+    cfg_if::cfg_if! {
+        if #[cfg(windows)] {
+            std::env::var_os("ProgramFiles")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(r"C:\Program Files"))
+        } else {
+            PathBuf::from("/opt")
+        }
+    }
+}
+
+/// Make sure this program has the privileges to make system-wide changes,
+/// which is always the case if this is not a [system-wide](is_system_wide) installation.
+pub(crate) fn ensure_privileges() -> Result<()> {
+    if !is_system_wide() {
+        return Ok(());
+    }
+    cfg_if::cfg_if! {
+        if #[cfg(windows)] {
+            let elevated = windows::can_write_system_env();
+        } else {
+            // SAFETY: `geteuid` is always successful.
+            let elevated = unsafe { libc::geteuid() } == 0;
+        }
+    }
+    if !elevated {
+        anyhow::bail!(InstallError::InsufficientPrivileges);
+    }
+    Ok(())
+}
+
+/// Add a path to `PATH` persistently, `install_dir` is where the env scripts
+/// (such as `<install_dir>/env`) are kept on unix, or where the previous value of `PATH`
+/// is backed up on Windows.
+pub(crate) fn add_to_path(path: &Path, install_dir: &Path) -> Result<()> {
     #[cfg(windows)]
-    windows::add_to_path(path)?;
+    windows::add_to_path(path, install_dir)?;
 
     #[cfg(unix)]
-    unix::add_to_path(path)?;
+    unix::add_to_path(path, install_dir)?;
 
     Ok(())
 }
@@ -71,3 +133,101 @@ pub(crate) fn remove_from_path(path: &Path) -> Result<()> {
 
     Ok(())
 }
+
+/// Set (or remove if `val` is `None`) a persistent environment variable of the installation
+/// under `install_dir`, and keep its name in the installation record so that it gets removed
+/// when uninstalling.
+pub(crate) fn set_env_var(install_dir: &Path, key: &'static str, val: Option<&str>) -> Result<()> {
+    #[cfg(windows)]
+    windows::set_persistent_env_var(install_dir, key, val)?;
+
+    #[cfg(unix)]
+    unix::set_env_var(install_dir, key, val)?;
+
+    InstallationRecord::update(install_dir, |record| {
+        record.add_env_vars([key]);
+        record.set_env_value(key, val);
+    })
+}
+
+/// Get the persisted value of an environment variable of the installation under `install_dir`,
+/// which is read from the registry on Windows, or the config sections of shell profiles
+/// and env scripts on unix, return `None` if it isn't set.
+#[cfg_attr(windows, allow(unused_variables))]
+pub(crate) fn persistent_env_var(install_dir: &Path, key: &'static str) -> Option<String> {
+    cfg_if::cfg_if! {
+        if #[cfg(windows)] {
+            windows::persistent_env_var(key)
+        } else {
+            unix::persistent_env_var(install_dir, key)
+        }
+    }
+}
+
+/// Remove the persistent environment variables configured by this program,
+/// which are listed in the installation `record`.
+#[cfg_attr(not(windows), allow(unused_variables))]
+pub(crate) fn remove_env_vars(record: &InstallationRecord) -> Result<()> {
+    #[cfg(windows)]
+    windows::remove_env_vars(record)?;
+
+    #[cfg(not(windows))]
+    unix::remove_env_vars()?;
+
+    Ok(())
+}
+
+/// Detect the proxy settings of the system, return `None` if there isn't one or it cannot be detected.
+pub(crate) fn system_proxy() -> Option<Proxy> {
+    cfg_if::cfg_if! {
+        if #[cfg(windows)] {
+            windows::system_proxy()
+        } else {
+            unix::system_proxy()
+        }
+    }
+}
+
+/// Get the version of the running operating system, such as `10.0.19045` on Windows,
+/// `14.5` on macOS, or the kernel release on other unix systems,
+/// return `None` if it cannot be determined.
+pub(crate) fn os_version() -> Option<&'static str> {
+    static OS_VERSION: OnceLock<Option<String>> = OnceLock::new();
+    OS_VERSION
+        .get_or_init(|| {
+            cfg_if::cfg_if! {
+                if #[cfg(windows)] {
+                    windows::os_version()
+                } else {
+                    unix::os_version()
+                }
+            }
+        })
+        .as_deref()
+}
+
+/// Check whether a process with the given `pid` is still running.
+pub(crate) fn is_process_alive(pid: u32) -> bool {
+    cfg_if::cfg_if! {
+        if #[cfg(windows)] {
+            windows::is_process_alive(pid)
+        } else {
+            unix::is_process_alive(pid)
+        }
+    }
+}
+
+/// Get the available disk space in bytes of the volume that `path` is on,
+/// return `None` if it cannot be determined.
+///
+/// `path` doesn't need to exist, the closest existing ancestor of it is checked instead.
+pub(crate) fn free_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    cfg_if::cfg_if! {
+        if #[cfg(windows)] {
+            windows::free_space(existing)
+        } else {
+            unix::free_space(existing)
+        }
+    }
+}
@@ -1,11 +1,11 @@
 use std::{env, path::Path};
 
-use super::install_dir_from_exe_path;
-use crate::core::install::{EnvConfig, InstallConfiguration};
+use crate::core::install::{print_plan, EnvConfig, InstallConfiguration};
 use crate::core::uninstall::{UninstallConfiguration, Uninstallation};
-use crate::manifest::ToolsetManifest;
+use crate::manifest::{Proxy, ToolsetManifest};
 use crate::utils;
 use anyhow::{Context, Result};
+use tracing::warn;
 
 impl EnvConfig for InstallConfiguration {
     // On linux, persistent env vars needs to be written in `.profile`, `.bash_profile`, etc.
@@ -14,8 +14,44 @@ impl EnvConfig for InstallConfiguration {
     // because rustup will not write those file if a user has choose to pass `--no-modify-path`.
     // Which is not ideal for env vars such as `RUSTUP_DIST_SERVER`.
     fn config_env_vars(&self, manifest: &ToolsetManifest) -> Result<()> {
-        let vars_raw = self.env_vars(manifest)?;
-        for sh in shell::get_available_shells() {
+        if self.is_dry_run() {
+            self.print_env_vars_plan(manifest);
+            for (_, rcs) in shell::files_to_update(&self.install_dir) {
+                for rc in rcs {
+                    print_plan(&format!(
+                        "write environment variables into shell profile '{}'",
+                        rc.display()
+                    ));
+                }
+            }
+            return Ok(());
+        }
+        let vars_os = self.env_vars(manifest);
+        // Update vars for current process, so that the child processes
+        // will get the exact values even if they are not valid unicode.
+        for (key, val) in &vars_os {
+            env::set_var(key, val);
+        }
+
+        // Unlike the current process, shell profiles are texts, which can only contain unicode.
+        let vars_raw = vars_os
+            .iter()
+            .map(|(key, val)| {
+                let val = val.to_str().with_context(|| {
+                    format!(
+                        "unable to write `{key}` into shell profiles, \
+                        because its value '{}' contains invalid unicode characters",
+                        Path::new(val).display()
+                    )
+                })?;
+                Ok((*key, val))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if super::is_system_wide() {
+            utils::ensure_parent_dir(shell::system_profile())?;
+        }
+        let mut modified = false;
+        for (sh, rcs) in shell::files_to_update(&self.install_dir) {
             // Shell commands to set env var, such as `export KEY='val'`
             let vars_shell_lines = vars_raw
                 .iter()
@@ -24,7 +60,7 @@ impl EnvConfig for InstallConfiguration {
                 .join("\n");
             // This string will be wrapped in a certain identifier comments.
             let vars_shell_string = sh.script_content(&vars_shell_lines);
-            for rc in sh.update_rcs() {
+            for rc in rcs {
                 let vars_to_write = match utils::read_to_string(&rc) {
                     // Assume env configuration exist if the section label presents.
                     Ok(content) if content.contains(shell::RC_FILE_SECTION_END) => continue,
@@ -39,13 +75,14 @@ impl EnvConfig for InstallConfiguration {
                         rc.display()
                     )
                 })?;
+                modified = true;
             }
         }
-
-        // Update vars for current process
-        for (key, val) in vars_raw {
-            env::set_var(key, val);
+        // Only the newly written sections should be removed if the installation failed.
+        if modified {
+            self.record_env_vars(&vars_os)?;
         }
+        self.set_process_proxy_vars(manifest);
 
         Ok(())
     }
@@ -54,13 +91,12 @@ impl EnvConfig for InstallConfiguration {
 impl Uninstallation for UninstallConfiguration {
     // This is basically removing the section marked with `rustup config section` in shell profiles.
     fn remove_rustup_env_vars(&self) -> Result<()> {
-        remove_shell_profile_content()
+        remove_env_vars()
     }
 
     fn remove_self(&self) -> Result<()> {
         // Remove the installer dir.
-        let installed_dir = install_dir_from_exe_path()?;
-        std::fs::remove_dir_all(installed_dir)?;
+        std::fs::remove_dir_all(&self.install_dir)?;
         Ok(())
     }
 }
@@ -74,8 +110,8 @@ where
         .and_then(|s| utils::write_file(path, &s, false).ok())
         .is_none()
     {
-        println!(
-            "warning: unable to remove the desired content from file: '{}'. \
+        warn!(
+            "unable to remove the desired content from file: '{}'. \
             This could mean it was already removed, \
             please try manually removing the following content from that file if there's any: \n\n\
             {to_remove_sum}\n",
@@ -85,13 +121,23 @@ where
     Ok(())
 }
 
+/// Remove the persistent environment variables that were set by [`EnvConfig::config_env_vars`].
+pub(crate) fn remove_env_vars() -> Result<()> {
+    remove_shell_profile_content()
+}
+
 fn remove_shell_profile_content() -> Result<()> {
     // Remove the profiles content wrapped between `RC_FILE_SECTION_START` to `RC_FILE_SECTION_END`,
     // which is our dedicated configuration sections.
     let start = shell::RC_FILE_SECTION_START;
     let end = shell::RC_FILE_SECTION_END;
-    for sh in shell::get_available_shells() {
-        for rc in sh.rcfiles().iter().filter(|rc| rc.is_file()) {
+    // The system profile only contains our configurations.
+    let system_profile = shell::system_profile();
+    if super::is_system_wide() && system_profile.is_file() {
+        return utils::remove(&system_profile);
+    }
+    for (_, rcs) in shell::profiles(false) {
+        for rc in rcs.iter().filter(|rc| rc.is_file()) {
             let to_remove_summary = format!("{start}\n...\n{end}");
             remove_section_or_warn_(rc, &to_remove_summary, |cont| {
                 remove_sub_string_between(cont, start, end)
@@ -102,31 +148,41 @@ fn remove_shell_profile_content() -> Result<()> {
     Ok(())
 }
 
-fn remove_sub_string_between(input: String, start: &str, end: &str) -> Option<String> {
-    // TODO: this might not be an optimized solution.
-    let start_pos = input.lines().position(|line| line == start)?;
-    let end_pos = input.lines().position(|line| line == end)?;
-    assert!(
-        end_pos >= start_pos,
-        "Interal Error: Failed deleting sub string, the start pos is larger than end pos"
-    );
-    let result = input
+/// Find the first section between a pair of marker **lines**, return the line indexes of them.
+///
+/// The markers are matched ignoring the surrounding whitespaces, as the user might have
+/// edited the file, and the end marker must come after the start marker.
+fn find_section(input: &str, start: &str, end: &str) -> Option<(usize, usize)> {
+    let start_pos = input.lines().position(|line| line.trim() == start)?;
+    let end_pos = input
         .lines()
-        .take(start_pos)
-        .chain(input.lines().skip(end_pos + 1))
-        .collect::<Vec<_>>()
-        .join("\n");
-    Some(result)
+        .skip(start_pos + 1)
+        .position(|line| line.trim() == end)?
+        + start_pos
+        + 1;
+    Some((start_pos, end_pos))
+}
+
+/// Remove every section between the marker lines (including the markers),
+/// return `None` if there isn't one.
+fn remove_sub_string_between(input: String, start: &str, end: &str) -> Option<String> {
+    let mut result = input;
+    let mut removed = false;
+    while let Some((start_pos, end_pos)) = find_section(&result, start, end) {
+        result = result
+            .lines()
+            .take(start_pos)
+            .chain(result.lines().skip(end_pos + 1))
+            .collect::<Vec<_>>()
+            .join("\n");
+        removed = true;
+    }
+    removed.then_some(result)
 }
 
 /// Get the enclosing string between two desired **lines**.
 fn get_sub_string_between(input: &str, start: &str, end: &str) -> Option<String> {
-    let start_pos = input.lines().position(|line| line == start)?;
-    let end_pos = input.lines().position(|line| line == end)?;
-    assert!(
-        end_pos >= start_pos,
-        "Interal Error: Failed extracting sub string, the start pos is larger than end pos"
-    );
+    let (start_pos, end_pos) = find_section(input, start, end)?;
     let result = input
         .lines()
         .skip(start_pos + 1)
@@ -136,31 +192,60 @@ fn get_sub_string_between(input: &str, start: &str, end: &str) -> Option<String>
     Some(result)
 }
 
-pub(super) fn add_to_path(path: &Path) -> Result<()> {
+/// Replace the enclosing string between two desired **lines** with `content`,
+/// leaving everything else (including the markers) untouched.
+fn replace_sub_string_between(
+    input: &str,
+    start: &str,
+    end: &str,
+    content: &str,
+) -> Option<String> {
+    let (start_pos, end_pos) = find_section(input, start, end)?;
+    let mut result = input
+        .lines()
+        .take(start_pos + 1)
+        .chain(content.lines())
+        .chain(input.lines().skip(end_pos))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if input.ends_with('\n') {
+        result.push('\n');
+    }
+    Some(result)
+}
+
+pub(super) fn add_to_path(path: &Path, install_dir: &Path) -> Result<()> {
     let old_path = env::var_os("PATH").unwrap_or_default();
     let pathbuf = path.to_path_buf();
-    let path_str = utils::path_to_str(path)?;
 
     let splited = env::split_paths(&old_path).collect::<Vec<_>>();
-    let should_update_current_env = !splited.contains(&pathbuf);
-    let mut new_path = splited;
-    new_path.insert(0, pathbuf);
-
-    // Add the new path to bash profiles
-    for sh in shell::get_available_shells() {
-        for rc in sh.update_rcs() {
-            let rc_content = utils::read_to_string(&rc)?;
+    // Apply the new path to current process, this works even if the path is not valid unicode.
+    if !splited.contains(&pathbuf) {
+        let mut new_path = splited;
+        new_path.insert(0, pathbuf);
+        env::set_var("PATH", env::join_paths(new_path)?);
+    }
+
+    // But shell profiles can only contain unicode.
+    let path_str = utils::path_to_str(path)?;
+
+    // Add the new path to shell profiles and env scripts
+    for (sh, rcs) in shell::files_to_update(install_dir) {
+        for rc in rcs {
+            let rc_content = if rc.is_file() {
+                utils::read_to_string(&rc)?
+            } else {
+                String::new()
+            };
             let Some(new_content) =
                 config_section_with_updated_path(sh.as_ref(), path_str, &rc_content)
             else {
-                println!(
-                    "warning: unable to add path '{}' to rc file '{}' as it might already exists.",
-                    path.display(),
-                    rc.display(),
-                );
+                // Already exported in the config section, nothing to do.
                 continue;
             };
-            utils::write_file(&rc, &new_content, false).with_context(|| {
+            // `write_file` appends a line ending by itself.
+            let new_content = new_content.strip_suffix('\n').unwrap_or(&new_content);
+            utils::write_file(&rc, new_content, false).with_context(|| {
                 format!(
                     "failed to append PATH variable to shell profile: '{}'",
                     rc.display()
@@ -169,11 +254,6 @@ pub(super) fn add_to_path(path: &Path) -> Result<()> {
         }
     }
 
-    // Apply the new path to current process
-    if should_update_current_env {
-        env::set_var("PATH", env::join_paths(new_path)?);
-    }
-
     Ok(())
 }
 
@@ -201,26 +281,284 @@ fn config_section_with_updated_path(
 
         let new_content = sh.command_to_update_path(maybe_setting_path, path_str, false)?;
 
-        let mut new_configs = existing_configs.clone();
-        if let Some(setting_path) = maybe_setting_path {
-            new_configs = existing_configs.replace(setting_path, &new_content);
-        } else {
-            new_configs.push('\n');
-            new_configs.push_str(&new_content);
-        }
+        let new_configs = match maybe_setting_path {
+            Some(setting_path) => existing_configs.replace(setting_path, &new_content),
+            None if existing_configs.is_empty() => new_content,
+            None => format!("{existing_configs}\n{new_content}"),
+        };
 
-        Some(old_content.replace(&existing_configs, &new_configs))
+        replace_sub_string_between(
+            old_content,
+            shell::RC_FILE_SECTION_START,
+            shell::RC_FILE_SECTION_END,
+            &new_configs,
+        )
     } else {
-        // No previous configuration (this might never happed tho)
+        // No previous configuration, append a new section to the end of the file.
         let path_configs = sh.command_to_update_path(None, path_str, false)?;
-        Some(sh.script_content(&path_configs))
+        let section = sh.script_content(&path_configs);
+        Some(match old_content {
+            "" => format!("{section}\n"),
+            _ if old_content.ends_with('\n') => format!("{old_content}{section}\n"),
+            _ => format!("{old_content}\n{section}\n"),
+        })
+    }
+}
+
+/// Attempt to return a new config section without `path_str` in its `PATH` export.
+/// Return `None` if it doesn't need to be updated or it cannot be updated.
+fn config_section_without_path(
+    sh: &dyn shell::UnixShell,
+    path_str: &str,
+    old_content: &str,
+) -> Option<String> {
+    let existing_configs = get_sub_string_between(
+        old_content,
+        shell::RC_FILE_SECTION_START,
+        shell::RC_FILE_SECTION_END,
+    )?;
+    let setting_path = existing_configs
+        .lines()
+        .find(|line| line.contains("PATH") && line.contains(path_str))?;
+    let new_content = sh.command_to_update_path(Some(setting_path), path_str, true)?;
+    replace_sub_string_between(
+        old_content,
+        shell::RC_FILE_SECTION_START,
+        shell::RC_FILE_SECTION_END,
+        &existing_configs.replace(setting_path, &new_content),
+    )
+}
+
+/// Check whether a process is running by sending the null signal to it,
+/// which fails with `EPERM` if it's running as another user.
+pub(super) fn is_process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: the null signal only performs error checking, nothing is actually sent.
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Get the available disk space in bytes of the file system that `path` is on, using `statvfs`.
+// The types of `statvfs` fields vary across platforms, which need to be casted on some of them.
+#[allow(clippy::unnecessary_cast)]
+pub(super) fn free_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `c_path` is a valid nul-terminated string, and `stat` is only read
+    // after `statvfs` returned successfully, meaning it was initialized.
+    let stat = unsafe {
+        if libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    (stat.f_bavail as u64).checked_mul(stat.f_frsize as u64)
+}
+
+/// Get the product version of macOS using `sw_vers`, or the kernel release using `uname`
+/// on other unix systems.
+pub(super) fn os_version() -> Option<String> {
+    if cfg!(target_os = "macos") {
+        return utils::execute_for_output("sw_vers", &["-productVersion"])
+            .ok()
+            .map(|out| out.trim().to_string());
+    }
+
+    let mut name = std::mem::MaybeUninit::<libc::utsname>::uninit();
+    // SAFETY: `name` is only read after `uname` returned successfully, meaning it was initialized,
+    // and its `release` field is a nul-terminated string.
+    let release = unsafe {
+        if libc::uname(name.as_mut_ptr()) != 0 {
+            return None;
+        }
+        std::ffi::CStr::from_ptr(name.assume_init_ref().release.as_ptr())
+    };
+    Some(release.to_string_lossy().into_owned())
+}
+
+/// Read the proxy settings of GNOME using `gsettings`, which are only used in `manual` mode.
+pub(super) fn system_proxy() -> Option<Proxy> {
+    if !utils::cmd_exist("gsettings") {
+        return None;
+    }
+    let get = |schema: &str, key: &str| {
+        utils::execute_for_output("gsettings", &["get", schema, key])
+            .ok()
+            .map(|out| out.trim().trim_matches('\'').to_string())
+    };
+    if get("org.gnome.system.proxy", "mode")? != "manual" {
+        return None;
+    }
+
+    let mut servers = vec![];
+    for scheme in ["http", "https"] {
+        let schema = format!("org.gnome.system.proxy.{scheme}");
+        let Some(host) = get(&schema, "host").filter(|host| !host.is_empty()) else {
+            continue;
+        };
+        match get(&schema, "port").filter(|port| port != "0") {
+            Some(port) => servers.push(format!("{scheme}={host}:{port}")),
+            None => servers.push(format!("{scheme}={host}")),
+        }
+    }
+    let ignore_hosts = get("org.gnome.system.proxy", "ignore-hosts")
+        .map(|list| parse_gsettings_list(&list).join(";"));
+    Proxy::from_server_list(&servers.join(";"), ignore_hosts.as_deref())
+}
+
+/// Parse a string list printed by `gsettings`, such as `['localhost', '127.0.0.0/8']`,
+/// or `@as []` for an empty list.
+fn parse_gsettings_list(list: &str) -> Vec<String> {
+    list.trim()
+        .trim_start_matches("@as")
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(|item| item.trim().trim_matches('\'').to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Set (or remove if `val` is `None`) a persistent environment variable in the config sections
+/// of shell profiles and the env scripts under `install_dir`.
+///
+/// The files without a config section are left untouched.
+pub(super) fn set_env_var(install_dir: &Path, key: &'static str, val: Option<&str>) -> Result<()> {
+    match val {
+        Some(val) => env::set_var(key, val),
+        None => env::remove_var(key),
+    }
+    for (sh, rcs) in shell::files_to_update(install_dir) {
+        for rc in rcs.iter().filter(|rc| rc.is_file()) {
+            let content = utils::read_to_string(rc)?;
+            let Some(new_content) = config_section_with_env_var(sh.as_ref(), key, val, &content)
+            else {
+                continue;
+            };
+            // `write_file` appends a line ending by itself.
+            let new_content = new_content.strip_suffix('\n').unwrap_or(&new_content);
+            utils::write_file(rc, new_content, false).with_context(|| {
+                format!(
+                    "failed to update environment variable in shell profile: '{}'",
+                    rc.display()
+                )
+            })?;
+        }
     }
+    Ok(())
 }
 
-pub(super) fn remove_from_path(_path: &Path) -> Result<()> {
-    // FIXME: Remove the given `path` from those rc files we added in `add_to_path`.
-    // This is currently not needed because we don't have the functionality to uninstall
-    // individual tool yet.
+/// Return a new config with the line setting `key` replaced (or removed if `val` is `None`),
+/// or `None` if there isn't a config section.
+fn config_section_with_env_var(
+    sh: &dyn shell::UnixShell,
+    key: &'static str,
+    val: Option<&str>,
+    old_content: &str,
+) -> Option<String> {
+    let existing_configs = get_sub_string_between(
+        old_content,
+        shell::RC_FILE_SECTION_START,
+        shell::RC_FILE_SECTION_END,
+    )?;
+    let prefix = sh.to_env_var_string(key, "");
+    let new_line = val.map(|val| sh.to_env_var_string(key, &format!("'{val}'")));
+    let mut lines = existing_configs
+        .lines()
+        .filter(|line| !line.trim_start().starts_with(&prefix))
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    if let Some(new_line) = new_line {
+        lines.insert(0, new_line);
+    }
+    replace_sub_string_between(
+        old_content,
+        shell::RC_FILE_SECTION_START,
+        shell::RC_FILE_SECTION_END,
+        &lines.join("\n"),
+    )
+}
+
+/// Get the value of a persistent environment variable from the config sections of shell
+/// profiles and the env scripts under `install_dir`, `None` if none of them sets it.
+pub(super) fn persistent_env_var(install_dir: &Path, key: &'static str) -> Option<String> {
+    shell::files_to_update(install_dir)
+        .into_iter()
+        .flat_map(|(sh, rcs)| {
+            rcs.into_iter()
+                .map(move |rc| (sh.to_env_var_string(key, ""), rc))
+        })
+        .filter(|(_, rc)| rc.is_file())
+        .find_map(|(prefix, rc)| {
+            let content = utils::read_to_string(&rc).ok()?;
+            env_var_in_config_section(&prefix, &content)
+        })
+}
+
+/// Find the value set by the line starting with `prefix` in the config section of a file.
+fn env_var_in_config_section(prefix: &str, content: &str) -> Option<String> {
+    let configs = get_sub_string_between(
+        content,
+        shell::RC_FILE_SECTION_START,
+        shell::RC_FILE_SECTION_END,
+    )?;
+    let val = configs
+        .lines()
+        .find_map(|line| line.trim_start().strip_prefix(prefix))?
+        .trim();
+    let val = val
+        .strip_prefix('\'')
+        .and_then(|v| v.strip_suffix('\''))
+        .unwrap_or(val);
+    Some(val.to_string())
+}
+
+/// Remove a path from the `PATH` exports in the config sections of shell profiles,
+/// and the env scripts of current installation (if it can be found).
+///
+/// Only the config sections are touched, so a path added by the user elsewhere is kept.
+pub(super) fn remove_from_path(path: &Path) -> Result<()> {
+    let old_path = env::var_os("PATH").unwrap_or_default();
+    let new_path = env::split_paths(&old_path)
+        .filter(|p| p != path)
+        .collect::<Vec<_>>();
+    env::set_var("PATH", env::join_paths(new_path)?);
+
+    let Some(path_str) = path.to_str() else {
+        // Never written into shell profiles.
+        return Ok(());
+    };
+    let env_scripts = super::install_dir_from_exe_path()
+        .map(|dir| shell::env_scripts(&dir))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(sh, script)| (sh, vec![script]));
+    for (sh, rcs) in shell::profiles(false).into_iter().chain(env_scripts) {
+        for rc in rcs.iter().filter(|rc| rc.is_file()) {
+            let content = utils::read_to_string(rc)?;
+            let Some(new_content) = config_section_without_path(sh.as_ref(), path_str, &content)
+            else {
+                continue;
+            };
+            // `write_file` appends a line ending by itself.
+            let new_content = new_content.strip_suffix('\n').unwrap_or(&new_content);
+            utils::write_file(rc, new_content, false).with_context(|| {
+                format!(
+                    "failed to remove PATH variable from shell profile: '{}'",
+                    rc.display()
+                )
+            })?;
+        }
+    }
     Ok(())
 }
 
@@ -234,7 +572,8 @@ mod shell {
 
     use crate::utils;
     use anyhow::{bail, Result};
-    use std::{env, path::PathBuf};
+    use std::env;
+    use std::path::{Path, PathBuf};
 
     type Shell = Box<dyn UnixShell>;
 
@@ -270,7 +609,7 @@ mod shell {
         }
 
         /// Update the PATH export command, which should be `export PATH="..."` on bash like shells,
-        /// and `set -Ux PATH ...` on fish shell.
+        /// `set -gx PATH ...` on fish shell, and `$env.PATH = (...)` on nushell.
         ///
         /// If the remove flag is set to `true`, this will attempt to return the `old_command` but without `path_str`.
         fn command_to_update_path(
@@ -282,7 +621,15 @@ mod shell {
             if let Some(cmd) = old_command {
                 let path_str_with_spliter = format!("{path_str}:");
                 if remove {
-                    Some(cmd.replace(&path_str_with_spliter, ""))
+                    // Only remove the whole entries, not the ones that merely contain `path_str`.
+                    let start = cmd.find('\"')? + 1;
+                    let end = start + cmd[start..].find('\"')?;
+                    let entries = cmd[start..end]
+                        .split(':')
+                        .filter(|entry| *entry != path_str)
+                        .collect::<Vec<_>>()
+                        .join(":");
+                    Some(format!("{}{entries}{}", &cmd[..start], &cmd[end..]))
                 } else {
                     let where_to_insert = cmd.find('\"')? + 1;
                     let mut new_cmd = cmd.to_string();
@@ -303,6 +650,7 @@ mod shell {
     pub(super) struct Bash;
     pub(super) struct Zsh;
     pub(super) struct Fish;
+    pub(super) struct Nushell;
 
     impl UnixShell for Posix {
         fn does_exist(&self) -> bool {
@@ -414,7 +762,9 @@ mod shell {
         }
 
         fn to_env_var_string(&self, key: &'static str, val: &str) -> String {
-            format!("set -Ux {key} {val}")
+            // Global instead of universal variables, so that nothing is left
+            // once the file is removed.
+            format!("set -gx {key} {val}")
         }
 
         fn update_rcs(&self) -> Vec<PathBuf> {
@@ -432,9 +782,16 @@ mod shell {
             remove: bool,
         ) -> Option<String> {
             if let Some(cmd) = old_command {
-                let path_str_with_spliter = format!("{path_str} ");
                 if remove {
-                    Some(cmd.replace(&path_str_with_spliter, ""))
+                    // Only drop whole entries, the command could be either the `set -gx`
+                    // we write now, or the `set -Ux` that older versions wrote.
+                    let (before_path, after_path) = cmd.split_once(" PATH ")?;
+                    let entries = after_path
+                        .split_whitespace()
+                        .filter(|entry| *entry != path_str)
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    Some(format!("{before_path} PATH {entries}"))
                 } else {
                     let (before_path, after_path) = cmd.split_once("PATH")?;
                     Some(format!("{before_path}PATH {path_str}{after_path}"))
@@ -449,16 +806,128 @@ mod shell {
         }
     }
 
+    impl UnixShell for Nushell {
+        fn does_exist(&self) -> bool {
+            // nu has to either be the shell or be callable for nushell setup.
+            matches!(env::var("SHELL"), Ok(sh) if sh.ends_with("/nu")) || utils::cmd_exist("nu")
+        }
+
+        // `$nu.env-path`, which is under `$XDG_CONFIG_HOME` if it's set,
+        // otherwise the config directory of the platform.
+        fn rcfiles(&self) -> Vec<PathBuf> {
+            env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .filter(|dir| dir.is_absolute())
+                .or_else(dirs::config_dir)
+                .map(|dir| vec![dir.join("nushell").join("env.nu")])
+                .unwrap_or_default()
+        }
+
+        fn update_rcs(&self) -> Vec<PathBuf> {
+            self.rcfiles()
+        }
+
+        fn to_env_var_string(&self, key: &'static str, val: &str) -> String {
+            format!("$env.{key} = {val}")
+        }
+
+        fn command_to_update_path(
+            &self,
+            old_command: Option<&str>,
+            path_str: &str,
+            remove: bool,
+        ) -> Option<String> {
+            const PREPEND: &str = "prepend [";
+            let quoted = format!("'{path_str}'");
+            if let Some(cmd) = old_command {
+                if remove {
+                    Some(
+                        cmd.replace(&format!("{quoted} "), "")
+                            .replace(&format!(" {quoted}"), "")
+                            .replace(&quoted, ""),
+                    )
+                } else {
+                    let where_to_insert = cmd.find(PREPEND)? + PREPEND.len();
+                    let mut new_cmd = cmd.to_string();
+                    new_cmd.insert_str(where_to_insert, &format!("{quoted} "));
+                    Some(new_cmd)
+                }
+            } else {
+                if remove {
+                    None
+                } else {
+                    // `uniq` keeps the paths from being added again when sourced twice.
+                    Some(format!(
+                        "$env.PATH = ($env.PATH | split row (char esep) | {PREPEND}{quoted}] | uniq)"
+                    ))
+                }
+            }
+        }
+    }
+
     pub(super) fn get_available_shells() -> impl Iterator<Item = Shell> {
         let supported_shells: Vec<Shell> = vec![
             Box::new(Posix),
             Box::new(Bash),
             Box::new(Zsh),
             Box::new(Fish),
+            Box::new(Nushell),
         ];
 
         supported_shells.into_iter().filter(|sh| sh.does_exist())
     }
+
+    /// Get the scripts that set the same environment variables as the shell profiles
+    /// (like rustup's `$CARGO_HOME/env`), which users can source manually, such as
+    /// `. <install_dir>/env` in POSIX shells, or `source <install_dir>/env.fish` in fish.
+    ///
+    /// These are written regardless of the available shells.
+    pub(super) fn env_scripts(install_dir: &Path) -> Vec<(Shell, PathBuf)> {
+        vec![
+            (Box::new(Posix), install_dir.join("env")),
+            (Box::new(Fish), install_dir.join("env.fish")),
+            (Box::new(Nushell), install_dir.join("env.nu")),
+        ]
+    }
+
+    /// The profile script of [system-wide](crate::core::os::is_system_wide) installations,
+    /// which is sourced by the login shells of every user.
+    pub(super) fn system_profile() -> PathBuf {
+        PathBuf::from("/etc/profile.d").join(format!("{}.sh", env!("CARGO_PKG_NAME")))
+    }
+
+    /// Get the shell profiles that might contain our config section, grouped by their shells.
+    ///
+    /// This is the [system profile](system_profile) for system-wide installations,
+    /// otherwise the rc files of the [available shells](get_available_shells).
+    pub(super) fn profiles(to_update: bool) -> Vec<(Shell, Vec<PathBuf>)> {
+        if crate::core::os::is_system_wide() {
+            return vec![(Box::new(Posix), vec![system_profile()])];
+        }
+        get_available_shells()
+            .map(|sh| {
+                let rcs = if to_update {
+                    sh.update_rcs()
+                } else {
+                    sh.rcfiles()
+                };
+                (sh, rcs)
+            })
+            .collect()
+    }
+
+    /// Get the files to write environment variables into, grouped by their shells,
+    /// which are the [profiles] followed by the [env scripts](env_scripts).
+    pub(super) fn files_to_update(install_dir: &Path) -> Vec<(Shell, Vec<PathBuf>)> {
+        profiles(true)
+            .into_iter()
+            .chain(
+                env_scripts(install_dir)
+                    .into_iter()
+                    .map(|(sh, script)| (sh, vec![script])),
+            )
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -467,10 +936,57 @@ mod tests {
     use std::path::PathBuf;
 
     use super::{
-        config_section_with_updated_path,
+        config_section_with_env_var, config_section_with_updated_path, config_section_without_path,
+        env_var_in_config_section, free_space, parse_gsettings_list,
         shell::{self, UnixShell},
     };
 
+    #[test]
+    fn free_space_of_temp_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(free_space(dir.path()).is_some_and(|space| space > 0));
+        assert!(free_space(&dir.path().join("missing")).is_none());
+        // The closest existing ancestor is checked instead.
+        assert!(crate::core::os::free_space(&dir.path().join("missing")).is_some());
+    }
+
+    #[test]
+    fn read_env_var_from_config_section() {
+        let profile = "\
+# ===== rustup config section START =====
+export PATH=\"/path/to/bin:$PATH\"
+# ===== rustup config section END =====
+export http_proxy='http://user-set.example'";
+        let sh = shell::Posix;
+        let prefix = sh.to_env_var_string("http_proxy", "");
+        assert_eq!(env_var_in_config_section(&prefix, profile), None);
+
+        let profile =
+            config_section_with_env_var(&sh, "http_proxy", Some("http://proxy.example"), profile)
+                .unwrap();
+        assert_eq!(
+            env_var_in_config_section(&prefix, &profile).as_deref(),
+            Some("http://proxy.example")
+        );
+
+        let fish = shell::Fish;
+        let prefix = fish.to_env_var_string("no_proxy", "");
+        let script = fish.script_content(&fish.to_env_var_string("no_proxy", "'localhost'"));
+        assert_eq!(
+            env_var_in_config_section(&prefix, &script).as_deref(),
+            Some("localhost")
+        );
+    }
+
+    #[test]
+    fn gsettings_list() {
+        assert_eq!(
+            parse_gsettings_list("['localhost', '127.0.0.0/8', '::1']\n"),
+            ["localhost", "127.0.0.0/8", "::1"]
+        );
+        assert!(parse_gsettings_list("@as []").is_empty());
+    }
+
     #[test]
     fn remove_labeled_section() {
         let mock_profile = "\
@@ -533,6 +1049,42 @@ alias autoremove='sudo pacman -Rcns $(pacman -Qdtq)'
         );
     }
 
+    #[test]
+    fn remove_duplicated_and_edited_sections() {
+        let mocked_profile = r#"
+# ===== rustup config section START =====
+export CARGO_HOME='/home/.cargo'
+# ===== rustup config section END =====
+alias ll='ls -l'
+  # ===== rustup config section START =====
+export CARGO_HOME='/home/.cargo'
+export EDITOR=vim
+# ===== rustup config section END =====
+"#;
+        let new = super::remove_sub_string_between(
+            mocked_profile.to_string(),
+            shell::RC_FILE_SECTION_START,
+            shell::RC_FILE_SECTION_END,
+        )
+        .unwrap();
+        assert_eq!(new, "\nalias ll='ls -l'");
+    }
+
+    #[test]
+    fn unterminated_section_is_kept() {
+        let mocked_profile = r#"
+# ===== rustup config section END =====
+# ===== rustup config section START =====
+export CARGO_HOME='/home/.cargo'
+"#;
+        let new = super::remove_sub_string_between(
+            mocked_profile.to_string(),
+            shell::RC_FILE_SECTION_START,
+            shell::RC_FILE_SECTION_END,
+        );
+        assert!(new.is_none());
+    }
+
     // TODO: Move this test to `utils`
     #[test]
     fn path_ambiguity() {
@@ -633,6 +1185,14 @@ export PATH="/path/to/bin:$PATH""#
             cmd,
             Some("export PATH=\"/path/to/tool/bin:$PATH\"".to_string())
         );
+
+        // Entries that merely contain the path are kept.
+        let old_cmd = r#"export PATH="/opt/path/to/bin:/path/to/bin/sub:/path/to/bin:$PATH""#;
+        let cmd = shell.command_to_update_path(Some(old_cmd), path_str, true);
+        assert_eq!(
+            cmd,
+            Some("export PATH=\"/opt/path/to/bin:/path/to/bin/sub:$PATH\"".to_string())
+        );
     }
 
     #[test]
@@ -641,7 +1201,7 @@ export PATH="/path/to/bin:$PATH""#
         let path_str = "/path/to/bin";
         let cmd = shell.command_to_update_path(None, path_str, false);
 
-        assert_eq!(cmd, Some("set -Ux PATH /path/to/bin $PATH".to_string()));
+        assert_eq!(cmd, Some("set -gx PATH /path/to/bin $PATH".to_string()));
     }
 
     #[test]
@@ -657,6 +1217,19 @@ export PATH="/path/to/bin:$PATH""#
         );
     }
 
+    #[test]
+    fn insert_path_with_old_global_cmd_fish() {
+        let shell = shell::Fish;
+        let path_str = "/path/to/bin";
+        let old_cmd = "set -gx PATH /path/to/tool/bin $PATH";
+        let cmd = shell.command_to_update_path(Some(old_cmd), path_str, false);
+
+        assert_eq!(
+            cmd,
+            Some("set -gx PATH /path/to/bin /path/to/tool/bin $PATH".to_string())
+        );
+    }
+
     #[test]
     fn remove_path_with_no_old_cmd_fish() {
         let shell = shell::Fish;
@@ -679,6 +1252,87 @@ export PATH="/path/to/bin:$PATH""#
         );
     }
 
+    #[test]
+    fn remove_path_with_old_global_cmd_fish() {
+        let shell = shell::Fish;
+        let path_str = "/path/to/bin";
+        let old_cmd = "set -gx PATH /path/to/tool/bin /path/to/bin $PATH";
+        let cmd = shell.command_to_update_path(Some(old_cmd), path_str, true);
+
+        assert_eq!(
+            cmd,
+            Some("set -gx PATH /path/to/tool/bin $PATH".to_string())
+        );
+    }
+
+    #[test]
+    fn remove_path_keeps_similar_entries_fish() {
+        let shell = shell::Fish;
+        let path_str = "/path/to/bin";
+        let old_cmd = "set -gx PATH /path/to/bin2 /other/path/to/bin /path/to/bin $PATH";
+        let cmd = shell.command_to_update_path(Some(old_cmd), path_str, true);
+
+        assert_eq!(
+            cmd,
+            Some("set -gx PATH /path/to/bin2 /other/path/to/bin $PATH".to_string())
+        );
+    }
+
+    #[test]
+    fn insert_path_nushell() {
+        let shell = shell::Nushell;
+        let cmd = shell.command_to_update_path(None, "/path/to/bin", false);
+        assert_eq!(
+            cmd.as_deref(),
+            Some(
+                "$env.PATH = ($env.PATH | split row (char esep) | prepend ['/path/to/bin'] | uniq)"
+            )
+        );
+
+        let cmd = shell.command_to_update_path(cmd.as_deref(), "/path/to/tool/bin", false);
+        assert_eq!(
+            cmd.as_deref(),
+            Some("$env.PATH = ($env.PATH | split row (char esep) | prepend ['/path/to/tool/bin' '/path/to/bin'] | uniq)")
+        );
+    }
+
+    #[test]
+    fn remove_path_nushell() {
+        let shell = shell::Nushell;
+        let old_cmd = "$env.PATH = ($env.PATH | split row (char esep) | prepend ['/path/to/tool/bin' '/path/to/bin'] | uniq)";
+        assert_eq!(
+            shell
+                .command_to_update_path(Some(old_cmd), "/path/to/bin", true)
+                .as_deref(),
+            Some("$env.PATH = ($env.PATH | split row (char esep) | prepend ['/path/to/tool/bin'] | uniq)")
+        );
+        assert_eq!(
+            shell
+                .command_to_update_path(Some(old_cmd), "/path/to/tool/bin", true)
+                .as_deref(),
+            Some(
+                "$env.PATH = ($env.PATH | split row (char esep) | prepend ['/path/to/bin'] | uniq)"
+            )
+        );
+        assert_eq!(
+            shell.to_env_var_string("CARGO_HOME", "'/path/to/cargo'"),
+            "$env.CARGO_HOME = '/path/to/cargo'"
+        );
+    }
+
+    #[test]
+    fn env_scripts_in_install_dir() {
+        let install_dir = PathBuf::from("/path/to/install");
+        let scripts = shell::env_scripts(&install_dir)
+            .into_iter()
+            .map(|(_, script)| script)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            scripts,
+            ["env", "env.fish", "env.nu"].map(|name| install_dir.join(name))
+        );
+    }
+
     #[test]
     fn add_new_path_to_config_section() {
         let existing_rc = r#"\
@@ -754,4 +1408,110 @@ export PATH=/some/user/defined/bin:$PATH
 "#
         );
     }
+
+    #[test]
+    fn append_config_section_to_existing_rc() {
+        let existing_rc = "alias ll='ls -l'";
+        let shell = shell::Bash;
+        let new_content =
+            config_section_with_updated_path(&shell, "/path/to/rust/bin", existing_rc).unwrap();
+        assert_eq!(
+            new_content,
+            r#"alias ll='ls -l'
+# ===== rustup config section START =====
+export PATH="/path/to/rust/bin:$PATH"
+# ===== rustup config section END =====
+"#
+        );
+
+        // Adding the same path again changes nothing.
+        let again = config_section_with_updated_path(&shell, "/path/to/rust/bin", &new_content);
+        assert!(again.is_none());
+    }
+
+    #[test]
+    fn add_path_to_empty_config_section() {
+        let existing_rc = r#"alias ll='ls -l'
+# ===== rustup config section START =====
+# ===== rustup config section END =====
+"#;
+        let shell = shell::Bash;
+        let new_content =
+            config_section_with_updated_path(&shell, "/path/to/rust/bin", existing_rc).unwrap();
+        assert_eq!(
+            new_content,
+            r#"alias ll='ls -l'
+# ===== rustup config section START =====
+export PATH="/path/to/rust/bin:$PATH"
+# ===== rustup config section END =====
+"#
+        );
+    }
+
+    #[test]
+    fn remove_path_from_config_section() {
+        let existing_rc = r#"export PATH="/path/to/python/bin:$PATH"
+
+# ===== rustup config section START =====
+export CARGO_HOME='/path/to/cargo'
+export PATH="/path/to/python/bin:/path/to/rust/bin:$PATH"
+# ===== rustup config section END =====
+"#;
+        let shell = shell::Bash;
+        let new_content =
+            config_section_without_path(&shell, "/path/to/python/bin", existing_rc).unwrap();
+        assert_eq!(
+            new_content,
+            r#"export PATH="/path/to/python/bin:$PATH"
+
+# ===== rustup config section START =====
+export CARGO_HOME='/path/to/cargo'
+export PATH="/path/to/rust/bin:$PATH"
+# ===== rustup config section END =====
+"#
+        );
+        assert!(config_section_without_path(&shell, "/path/to/ruby/bin", &new_content).is_none());
+    }
+
+    #[test]
+    fn set_env_var_in_config_section() {
+        let existing_rc = r#"export RUSTUP_DIST_SERVER='https://example.com'
+
+# ===== rustup config section START =====
+export CARGO_HOME='/path/to/cargo'
+export RUSTUP_DIST_SERVER='https://old.example.com'
+export PATH="/path/to/rust/bin:$PATH"
+# ===== rustup config section END =====
+"#;
+        let shell = shell::Bash;
+        let new_content = super::config_section_with_env_var(
+            &shell,
+            "RUSTUP_DIST_SERVER",
+            Some("https://new.example.com"),
+            existing_rc,
+        )
+        .unwrap();
+        assert_eq!(
+            new_content,
+            r#"export RUSTUP_DIST_SERVER='https://example.com'
+
+# ===== rustup config section START =====
+export RUSTUP_DIST_SERVER='https://new.example.com'
+export CARGO_HOME='/path/to/cargo'
+export PATH="/path/to/rust/bin:$PATH"
+# ===== rustup config section END =====
+"#
+        );
+
+        let new_content =
+            super::config_section_with_env_var(&shell, "RUSTUP_DIST_SERVER", None, &new_content)
+                .unwrap();
+        assert!(!new_content.contains("new.example.com"));
+        assert!(new_content.starts_with("export RUSTUP_DIST_SERVER='https://example.com'"));
+
+        assert!(
+            super::config_section_with_env_var(&shell, "CARGO_HOME", None, "alias ll='ls -l'")
+                .is_none()
+        );
+    }
 }
@@ -1,19 +1,33 @@
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
 use std::process::Command;
 
-use super::install_dir_from_exe_path;
 use crate::core::install::{EnvConfig, InstallConfiguration};
+use crate::core::parser::fingerprint::InstallationRecord;
 use crate::core::uninstall::{UninstallConfiguration, Uninstallation};
-use crate::manifest::ToolsetManifest;
+use crate::manifest::{Proxy, ToolsetManifest};
 use anyhow::Result;
+use tracing::warn;
+use winreg::enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+use winreg::RegKey;
 
 pub(crate) use rustup::*;
 
 impl EnvConfig for InstallConfiguration {
     fn config_env_vars(&self, manifest: &ToolsetManifest) -> Result<()> {
-        let vars_raw = self.env_vars(manifest)?;
-        for (key, val) in vars_raw {
-            set_env_var(key, val.encode_utf16().collect())?;
+        if self.is_dry_run() {
+            self.print_env_vars_plan(manifest);
+            return Ok(());
+        }
+        let vars_raw = self.env_vars(manifest);
+        for (key, val) in &vars_raw {
+            let new_val: Vec<u16> = val.encode_wide().collect();
+            let old_val = get_env_var(key)?;
+            set_env_var(key, new_val.clone())?;
+            backup_env_var(&self.install_dir, key, old_val.as_deref(), &new_val)?;
         }
+        self.record_env_vars(&vars_raw)?;
+        self.set_process_proxy_vars(manifest);
 
         update_env();
 
@@ -23,19 +37,16 @@ impl EnvConfig for InstallConfiguration {
 
 impl Uninstallation for UninstallConfiguration {
     fn remove_rustup_env_vars(&self) -> Result<()> {
-        // Remove the `<InstallDir>/.cargo/bin` which is added by rustup
-        let mut cargo_bin_dir = install_dir_from_exe_path()?;
-        cargo_bin_dir.push(".cargo");
-        cargo_bin_dir.push("bin");
-        remove_from_path(&cargo_bin_dir)?;
-
-        for var_to_remove in crate::core::ALL_VARS {
-            set_env_var(var_to_remove, vec![])?;
+        if !restore_env_var(&self.record, "PATH")? {
+            for path in &self.record.paths {
+                remove_from_path(path)?;
+            }
+            // Remove the `<InstallDir>/.cargo/bin` which is added by rustup,
+            // in case it wasn't recorded by an older version of this program.
+            remove_from_path(&self.install_dir.join(".cargo").join("bin"))?;
         }
 
-        update_env();
-
-        Ok(())
+        remove_env_vars(&self.record)
     }
 
     fn remove_self(&self) -> Result<()> {
@@ -44,32 +55,240 @@ impl Uninstallation for UninstallConfiguration {
         // `rustup` did something like this, it creates a "self-destructable" clone called `rustup-gc`,
         // and it is far more safe than this primitive way of hack, if this method has problem,
         // use the rustup way.
-        remove_self_()?;
+        remove_self_(&self.install_dir, &self.record)?;
         Ok(())
     }
 }
 
+/// Set (or remove if `val` is `None`) a persistent environment variable,
+/// its previous value is backed up in the installation record under `install_dir`.
+pub(crate) fn set_persistent_env_var(
+    install_dir: &Path,
+    key: &str,
+    val: Option<&str>,
+) -> Result<()> {
+    let new_val: Vec<u16> = val
+        .map(|val| std::ffi::OsStr::new(val).encode_wide().collect())
+        .unwrap_or_default();
+    let old_val = get_env_var(key)?;
+    set_env_var(key, new_val.clone())?;
+    backup_env_var(install_dir, key, old_val.as_deref(), &new_val)?;
+    update_env();
+    Ok(())
+}
+
+/// Remove the persistent environment variables that were set by [`EnvConfig::config_env_vars`],
+/// or restore their original values if they were backed up, check [`restore_env_var`].
+///
+/// Older versions of this program did not record the names of the variables,
+/// in which case all of the known variables are removed, except for `RUSTUP_HOME`
+/// when a pre-existing `rustup` was reused.
+pub(crate) fn remove_env_vars(record: &InstallationRecord) -> Result<()> {
+    let vars_to_remove: Vec<&str> = if record.env_vars.is_empty() {
+        crate::core::ALL_VARS
+            .iter()
+            .copied()
+            .filter(|var| record.external_rustup.is_none() || *var != crate::core::RUSTUP_HOME)
+            .collect()
+    } else {
+        record.env_vars.iter().map(String::as_str).collect()
+    };
+    for var_to_remove in vars_to_remove {
+        if !restore_env_var(record, var_to_remove)? {
+            set_env_var(var_to_remove, vec![])?;
+        }
+    }
+
+    update_env();
+
+    Ok(())
+}
+
+/// Restore a persistent environment variable to the value before it was first modified,
+/// which was backed up in the installation `record`.
+///
+/// Return `false` if it cannot be restored, which is when there is no backup of it,
+/// or it was modified by something else after the last modification of this program,
+/// in which case only the changes made by this program should be removed.
+fn restore_env_var(record: &InstallationRecord, key: &str) -> Result<bool> {
+    let Some(backup) = record.env_backup.get(key) else {
+        return Ok(false);
+    };
+    let current = get_env_var(key)?.map(|val| String::from_utf16_lossy(&val));
+    if current != backup.modified {
+        warn!(
+            "`{key}` was modified after the installation, \
+            only the changes made by this program will be removed"
+        );
+        return Ok(false);
+    }
+    let original = backup
+        .original
+        .as_deref()
+        .map(|val| std::ffi::OsStr::new(val).encode_wide().collect())
+        .unwrap_or_default();
+    set_env_var(key, original)?;
+    if key != "PATH" {
+        return Ok(true);
+    }
+    // Make sure the paths of this installation are also removed from current process.
+    for path in &record.paths {
+        update_path_for_current_process(path, true)?;
+    }
+    Ok(true)
+}
+
 /// Remove the installation directory, including the binary of this program.
 // FIXME: This is such a mess, but it works. However, when uninstall from `control panel`,
 // a window flashs, the env vars are removed but nothing has been removed.
-fn remove_self_() -> Result<()> {
+fn remove_self_(installed_dir: &Path, record: &InstallationRecord) -> Result<()> {
     /// Execute a command then heads out.
     fn yolo(cmd: &mut Command) -> ! {
         let _yolo = cmd.spawn();
         std::process::exit(0)
     }
 
-    let installed_dir = install_dir_from_exe_path()?;
     let mut rmdir_cmd = Command::new("cmd.exe");
     let cmd = rmdir_cmd
         .args(["/C", "rmdir", "/s", "/q"])
-        .arg(&installed_dir);
+        .arg(installed_dir);
 
-    do_remove_from_programs(uninstall_entry())?;
+    remove_registry_keys(record)?;
 
     yolo(cmd);
 }
 
+/// Remove the registry keys listed in the installation `record`, such as the uninstall entry
+/// of this program in the "installed programs" list.
+///
+/// The uninstall entry is removed regardless, as older versions of this program didn't record it.
+pub(crate) fn remove_registry_keys(record: &InstallationRecord) -> Result<()> {
+    for key in &record.registry_keys {
+        do_remove_from_programs(key)?;
+    }
+    do_remove_from_programs(uninstall_entry())
+}
+
+/// Get the value of a persistent environment variable from the registry,
+/// `None` if it doesn't exist or isn't valid unicode.
+pub(super) fn persistent_env_var(key: &str) -> Option<String> {
+    let raw = get_env_var(key).ok()??;
+    String::from_utf16(&raw).ok().filter(|val| !val.is_empty())
+}
+
+/// Get the available disk space in bytes of the volume that `path` is on,
+/// which takes the disk quota of current user into account.
+pub(super) fn free_space(path: &Path) -> Option<u64> {
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0_u64;
+    // SAFETY: `wide_path` is a valid nul-terminated wide string,
+    // and the unused out parameters are allowed to be null.
+    let succeed = unsafe {
+        GetDiskFreeSpaceExW(
+            wide_path.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    (succeed != 0).then_some(available)
+}
+
+/// Check whether a process is running by querying its exit code.
+pub(super) fn is_process_alive(pid: u32) -> bool {
+    use windows_sys::Win32::Foundation::{CloseHandle, STILL_ACTIVE};
+    use windows_sys::Win32::System::Threading::{
+        GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    // SAFETY: the handle is checked before use, and closed afterwards.
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle == 0 {
+            return false;
+        }
+        let mut code = 0_u32;
+        let succeed = GetExitCodeProcess(handle, &mut code) != 0;
+        CloseHandle(handle);
+        succeed && code == STILL_ACTIVE as u32
+    }
+}
+
+/// Read the version of Windows from registry, such as `10.0.19045`,
+/// which is not affected by the compatibility settings of this program unlike `GetVersionExW`.
+pub(super) fn os_version() -> Option<String> {
+    let key = RegKey::predef(HKEY_LOCAL_MACHINE)
+        .open_subkey(r"SOFTWARE\Microsoft\Windows NT\CurrentVersion")
+        .ok()?;
+    let major: u32 = key.get_value("CurrentMajorVersionNumber").ok()?;
+    let minor: u32 = key.get_value("CurrentMinorVersionNumber").ok()?;
+    let build: String = key.get_value("CurrentBuildNumber").ok()?;
+    Some(format!("{major}.{minor}.{build}"))
+}
+
+/// Check if the environment variables of all users can be modified,
+/// which requires administrator privileges.
+pub(super) fn can_write_system_env() -> bool {
+    use winreg::enums::KEY_WRITE;
+
+    RegKey::predef(HKEY_LOCAL_MACHINE)
+        .open_subkey_with_flags(rustup::SYSTEM_ENVIRONMENT, KEY_WRITE)
+        .is_ok()
+}
+
+/// Read the proxy settings of Internet Options, or the WinHTTP ones if not enabled.
+pub(super) fn system_proxy() -> Option<Proxy> {
+    internet_options_proxy().or_else(winhttp_proxy)
+}
+
+fn internet_options_proxy() -> Option<Proxy> {
+    let settings = RegKey::predef(HKEY_CURRENT_USER)
+        .open_subkey(r"Software\Microsoft\Windows\CurrentVersion\Internet Settings")
+        .ok()?;
+    let enabled: u32 = settings.get_value("ProxyEnable").ok()?;
+    if enabled == 0 {
+        return None;
+    }
+    let servers: String = settings.get_value("ProxyServer").ok()?;
+    let bypass: Option<String> = settings.get_value("ProxyOverride").ok();
+    Proxy::from_server_list(&servers, bypass.as_deref())
+}
+
+/// Read the WinHTTP proxy settings, which are the ones set by `netsh winhttp set proxy`.
+fn winhttp_proxy() -> Option<Proxy> {
+    let connections = RegKey::predef(HKEY_LOCAL_MACHINE)
+        .open_subkey(r"SOFTWARE\Microsoft\Windows\CurrentVersion\Internet Settings\Connections")
+        .ok()?;
+    let raw = connections.get_raw_value("WinHttpSettings").ok()?;
+    let (servers, bypass) = parse_winhttp_settings(&raw.bytes)?;
+    Proxy::from_server_list(&servers, Some(&bypass))
+}
+
+/// Parse the binary `WinHttpSettings` value, which is laid out as:
+/// a 4-byte header, a 4-byte counter, 4-byte flags (`0x2` means proxy is enabled),
+/// then the proxy server list and the bypass list, each prefixed by its 4-byte length.
+fn parse_winhttp_settings(bytes: &[u8]) -> Option<(String, String)> {
+    fn read_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+        let raw = bytes.get(pos..pos + 4)?;
+        Some(u32::from_le_bytes(raw.try_into().ok()?))
+    }
+    fn read_str(bytes: &[u8], pos: usize) -> Option<(String, usize)> {
+        let len = read_u32(bytes, pos)? as usize;
+        let raw = bytes.get(pos + 4..pos + 4 + len)?;
+        Some((String::from_utf8_lossy(raw).into_owned(), pos + 4 + len))
+    }
+
+    let flags = read_u32(bytes, 8)?;
+    if flags & 0x2 == 0 {
+        return None;
+    }
+    let (servers, next) = read_str(bytes, 12)?;
+    let (bypass, _) = read_str(bytes, next)?;
+    Some((servers, bypass))
+}
+
 /// Module containing functions that are modified from `rustup`.
 pub(crate) mod rustup {
     use std::env;
@@ -80,13 +299,40 @@ pub(crate) mod rustup {
 
     use anyhow::{anyhow, Context, Result};
     use winapi::shared::minwindef;
+
+    use crate::core::error::InstallError;
+    use crate::core::parser::fingerprint::InstallationRecord;
     use winapi::um::winuser;
-    use winreg::enums::{RegType, HKEY_CURRENT_USER, KEY_READ, KEY_WRITE};
+    use winreg::enums::{RegType, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, KEY_READ, KEY_WRITE};
     use winreg::{RegKey, RegValue};
 
+    /// The key of the environment variables of all users.
+    pub(crate) const SYSTEM_ENVIRONMENT: &str =
+        r"SYSTEM\CurrentControlSet\Control\Session Manager\Environment";
+
+    /// Get the predefined key to write into, which is `HKEY_LOCAL_MACHINE` for
+    /// [system-wide](crate::core::os::is_system_wide) installations,
+    /// otherwise `HKEY_CURRENT_USER`.
+    pub(crate) fn root_key() -> RegKey {
+        if crate::core::os::is_system_wide() {
+            RegKey::predef(HKEY_LOCAL_MACHINE)
+        } else {
+            RegKey::predef(HKEY_CURRENT_USER)
+        }
+    }
+
+    /// Get the name of the [root key](root_key), which is used in messages.
+    pub(crate) fn root_key_name() -> &'static str {
+        if crate::core::os::is_system_wide() {
+            "HKEY_LOCAL_MACHINE"
+        } else {
+            "HKEY_CURRENT_USER"
+        }
+    }
+
     static UNINSTALL_ENTRY: OnceLock<String> = OnceLock::new();
 
-    pub(super) fn uninstall_entry() -> &'static str {
+    pub(crate) fn uninstall_entry() -> &'static str {
         UNINSTALL_ENTRY.get_or_init(|| {
             format!(
                 "Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\{}",
@@ -95,49 +341,87 @@ pub(crate) mod rustup {
         })
     }
 
-    pub(crate) fn do_add_to_programs(program_bin: &Path) -> Result<()> {
+    /// Add this program into the "Apps & Features" (or "Programs and Features") list,
+    /// so that it can be uninstalled from there, or by enterprise deployment tools
+    /// using the silent `QuietUninstallString`.
+    ///
+    /// The entry is left untouched if it belongs to another existing installation.
+    pub(crate) fn do_add_to_programs(program_bin: &Path, install_dir: &Path) -> Result<()> {
         use std::path::PathBuf;
 
-        let key = RegKey::predef(HKEY_CURRENT_USER)
+        let key = root_key()
             .create_subkey(uninstall_entry())
             .context("Failed creating uninstall key")?
             .0;
 
-        // Don't overwrite registry if Rustup is already installed
+        // Don't overwrite registry if this program is already installed elsewhere
         let prev = key
-            .get_raw_value("UninstallString")
+            .get_raw_value("InstallLocation")
             .map(|val| from_winreg_value(&val));
         if let Ok(Some(s)) = prev {
-            let mut path = PathBuf::from(OsString::from_wide(&s));
-            path.pop();
-            if path.exists() {
+            let path = PathBuf::from(OsString::from_wide(&s));
+            if path != install_dir && path.exists() {
                 return Ok(());
             }
         }
 
-        let mut uninstall_cmd = OsString::from("\"");
-        uninstall_cmd.push(program_bin);
-        uninstall_cmd.push("\"");
-
+        let mut program = OsString::from("\"");
+        program.push(program_bin);
+        program.push("\"");
         // FIXME: Remove this if the GUI app supports uninstallation with ui.
         #[cfg(feature = "gui")]
-        uninstall_cmd.push(" --no-gui");
+        program.push(" --no-gui");
 
+        let mut uninstall_cmd = program.clone();
         uninstall_cmd.push(" uninstall all");
+        let mut quiet_uninstall_cmd = program;
+        quiet_uninstall_cmd.push(" --quiet --yes uninstall all");
 
-        let reg_value = RegValue {
-            bytes: to_winreg_bytes(uninstall_cmd.encode_wide().collect()),
-            vtype: RegType::REG_SZ,
-        };
-
-        key.set_raw_value("UninstallString", &reg_value)
-            .context("Failed to set `UninstallString`")?;
+        set_string_value(&key, "UninstallString", &uninstall_cmd)?;
+        set_string_value(&key, "QuietUninstallString", &quiet_uninstall_cmd)?;
+        set_string_value(&key, "InstallLocation", install_dir.as_os_str())?;
+        set_string_value(&key, "DisplayIcon", program_bin.as_os_str())?;
         key.set_value("DisplayName", &"XuanWu Rust Installation Manager")
             .context("Failed to set `DisplayName`")?;
+        key.set_value("DisplayVersion", &env!("CARGO_PKG_VERSION"))
+            .context("Failed to set `DisplayVersion`")?;
+        key.set_value("Publisher", &"XuanWu")
+            .context("Failed to set `Publisher`")?;
+        // Modifying and repairing are not supported, only show the "Uninstall" button.
+        key.set_value("NoModify", &1_u32)
+            .context("Failed to set `NoModify`")?;
+        key.set_value("NoRepair", &1_u32)
+            .context("Failed to set `NoRepair`")?;
+
+        update_programs_entry_size(install_dir)
+    }
 
+    /// Update the estimated size (in KiB) shown in the "Apps & Features" list
+    /// to the size of `install_dir`, which should be called once the tools are installed.
+    ///
+    /// Nothing is done if the entry doesn't exist.
+    pub(crate) fn update_programs_entry_size(install_dir: &Path) -> Result<()> {
+        let key = match root_key().open_subkey_with_flags(uninstall_entry(), KEY_WRITE) {
+            Ok(key) => key,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(anyhow!(e)),
+        };
+        let size_kib =
+            u32::try_from(crate::utils::dir_size(install_dir) / 1024).unwrap_or(u32::MAX);
+        key.set_value("EstimatedSize", &size_kib)
+            .context("Failed to set `EstimatedSize`")?;
         Ok(())
     }
 
+    fn set_string_value(key: &RegKey, name: &str, val: &std::ffi::OsStr) -> Result<()> {
+        let reg_value = RegValue {
+            bytes: to_winreg_bytes(val.encode_wide().collect()),
+            vtype: RegType::REG_SZ,
+        };
+        key.set_raw_value(name, &reg_value)
+            .with_context(|| format!("Failed to set `{name}`"))
+    }
+
     /// This is used to decode the value of HKCU\Environment\PATH. If that key is
     /// not REG_SZ | REG_EXPAND_SZ then this returns None. The winreg library itself
     /// does a lossy unicode conversion.
@@ -167,7 +451,7 @@ pub(crate) mod rustup {
     }
 
     pub(crate) fn do_remove_from_programs(entry: &str) -> Result<()> {
-        match RegKey::predef(HKEY_CURRENT_USER).delete_subkey_all(entry) {
+        match root_key().delete_subkey_all(entry) {
             Ok(()) => Ok(()),
             Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
             Err(e) => Err(anyhow!(e)),
@@ -175,9 +459,14 @@ pub(crate) mod rustup {
     }
 
     fn environment() -> Result<RegKey> {
-        RegKey::predef(HKEY_CURRENT_USER)
-            .open_subkey_with_flags("Environment", KEY_READ | KEY_WRITE)
-            .context("Failed opening Environment key")
+        let subkey = if crate::core::os::is_system_wide() {
+            SYSTEM_ENVIRONMENT
+        } else {
+            "Environment"
+        };
+        root_key()
+            .open_subkey_with_flags(subkey, KEY_READ | KEY_WRITE)
+            .with_context(|| format!("Failed opening {}\\{subkey} key", root_key_name()))
     }
 
     // Get the windows PATH variable out of the registry as a String. If
@@ -192,9 +481,10 @@ pub(crate) mod rustup {
                 if let Some(s) = from_winreg_value(&val) {
                     Ok(Some(s))
                 } else {
-                    println!(
-                        "the registry key HKEY_CURRENT_USER\\Environment\\PATH is not a string. \
-                        Not modifying the PATH variable"
+                    warn!(
+                        "the registry value of PATH in {} is not a string. \
+                        Not modifying the PATH variable",
+                        root_key_name()
                     );
                     Ok(None)
                 }
@@ -204,16 +494,50 @@ pub(crate) mod rustup {
         }
     }
 
+    /// Get the raw value of a persistent environment variable, `None` if it doesn't exist.
+    pub(super) fn get_env_var(key: &str) -> Result<Option<Vec<u16>>> {
+        match environment()?.get_raw_value(key) {
+            Ok(val) => Ok(Some(from_winreg_value(&val).unwrap_or_default())),
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow!(e)),
+        }
+    }
+
+    /// Keep the values of a persistent environment variable before and after a modification
+    /// in the installation record under `install_dir`, an empty `new_val` means it was removed.
+    ///
+    /// Values that are not valid unicode are not backed up, in which case the changes are
+    /// removed string-wise when uninstalling.
+    pub(super) fn backup_env_var(
+        install_dir: &Path,
+        key: &str,
+        old_val: Option<&[u16]>,
+        new_val: &[u16],
+    ) -> Result<()> {
+        let Ok(old_val) = old_val.map(String::from_utf16).transpose() else {
+            return Ok(());
+        };
+        let Ok(new_val) = String::from_utf16(new_val) else {
+            return Ok(());
+        };
+        let new_val = (!new_val.is_empty()).then_some(new_val);
+        InstallationRecord::update(install_dir, |record| {
+            record.backup_env_var(key, old_val, new_val)
+        })
+    }
+
     pub(super) fn set_env_var(key: &str, val: Vec<u16>) -> Result<()> {
         let env = environment()?;
 
         if val.is_empty() {
+            // Delete for current process, even if it's not a persistent one
+            if key != "PATH" {
+                env::remove_var(key);
+            }
             // Don't do anything if the variable doesn't exist
             if env.get_raw_value(key).is_err() {
                 return Ok(());
             }
-            // Delete for current process
-            env::remove_var(key);
             // Delete for user environment
             env.delete_value(key)?;
         } else {
@@ -238,10 +562,13 @@ pub(crate) mod rustup {
         Ok(())
     }
 
-    /// Broadcast environment changes to other processes,
-    /// required after making env changes.
+    /// Broadcast environment changes to other processes (such as Explorer),
+    /// required after making env changes, so that the newly opened terminals get them
+    /// without logging in again.
     pub(super) fn update_env() {
-        unsafe {
+        // SAFETY: the message parameter is a nul-terminated string that outlives the call,
+        // and the result pointer is allowed to be null.
+        let sent = unsafe {
             winuser::SendMessageTimeoutA(
                 winuser::HWND_BROADCAST,
                 winuser::WM_SETTINGCHANGE,
@@ -250,10 +577,95 @@ pub(crate) mod rustup {
                 winuser::SMTO_ABORTIFHUNG,
                 5000,
                 std::ptr::null_mut(),
+            )
+        };
+        if sent == 0 {
+            warn!(
+                "unable to notify other programs about the environment changes, \
+                the changes will take effect after logging in again"
             );
         }
     }
 
+    /// Expand the `%VAR%` references in a `REG_EXPAND_SZ` value using the variables of
+    /// current process, the value is returned as is if it cannot be expanded.
+    fn expand_env_strings(val: &[u16]) -> Vec<u16> {
+        use windows_sys::Win32::System::Environment::ExpandEnvironmentStringsW;
+
+        let src: Vec<u16> = val.iter().copied().chain(Some(0)).collect();
+        // SAFETY: `src` is nul-terminated, and a null buffer is allowed when its size is zero,
+        // in which case the required size (including the nul) is returned.
+        let len = unsafe { ExpandEnvironmentStringsW(src.as_ptr(), std::ptr::null_mut(), 0) };
+        if len == 0 {
+            return val.to_vec();
+        }
+        let mut buf = vec![0_u16; len as usize];
+        // SAFETY: `buf` has the required size.
+        let written = unsafe { ExpandEnvironmentStringsW(src.as_ptr(), buf.as_mut_ptr(), len) };
+        if written == 0 || written > len {
+            return val.to_vec();
+        }
+        buf.truncate(written as usize - 1);
+        buf
+    }
+
+    /// Read the persistent environment variables under `subkey` of `root`,
+    /// with the `REG_EXPAND_SZ` ones expanded.
+    fn persistent_env_vars(root: &RegKey, subkey: &str) -> Vec<(String, OsString)> {
+        let Ok(key) = root.open_subkey(subkey) else {
+            return vec![];
+        };
+        key.enum_values()
+            .flatten()
+            .filter_map(|(name, val)| {
+                let words = from_winreg_value(&val)?;
+                let words = match val.vtype {
+                    RegType::REG_EXPAND_SZ => expand_env_strings(&words),
+                    _ => words,
+                };
+                Some((name, OsString::from_wide(&words)))
+            })
+            .collect()
+    }
+
+    /// Bring the persistent environment variables into current process, like the ones
+    /// a newly started process would get, so that the following installation steps see the
+    /// changes made by other programs (such as the installers run for tools) without restarting.
+    ///
+    /// Variables of current process are never removed or overridden, except that the
+    /// missing entries are appended to `PATH`.
+    pub(crate) fn refresh_process_env() {
+        let system = persistent_env_vars(&RegKey::predef(HKEY_LOCAL_MACHINE), SYSTEM_ENVIRONMENT);
+        let user = persistent_env_vars(&RegKey::predef(HKEY_CURRENT_USER), "Environment");
+        let (paths, vars): (Vec<_>, Vec<_>) = system
+            .into_iter()
+            .chain(user)
+            .partition(|(name, _)| name.eq_ignore_ascii_case("PATH"));
+
+        // User variables take precedence over the system ones.
+        for (name, val) in vars.into_iter().rev() {
+            if env::var_os(&name).is_none() {
+                env::set_var(&name, val);
+            }
+        }
+
+        let mut entries = env::var_os("PATH")
+            .map(|path| env::split_paths(&path).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let old_len = entries.len();
+        // The `PATH` of new processes is the system one followed by the user one.
+        for entry in paths.iter().flat_map(|(_, val)| env::split_paths(val)) {
+            if !entry.as_os_str().is_empty() && !entries.contains(&entry) {
+                entries.push(entry);
+            }
+        }
+        if entries.len() != old_len {
+            if let Ok(new_path) = env::join_paths(entries) {
+                env::set_var("PATH", new_path);
+            }
+        }
+    }
+
     /// Attempt to find the position of given path in the `PATH` environment variable.
     fn find_path_in_env(paths: &[u16], path_bytes: &[u16]) -> Option<usize> {
         paths
@@ -285,7 +697,92 @@ pub(crate) mod rustup {
         Ok(())
     }
 
-    pub(crate) fn add_to_path(path: &Path) -> Result<()> {
+    /// The maximum length of an environment variable, in UTF-16 code units.
+    const MAX_ENV_VAR_LEN: usize = 32767;
+    /// The length that some tools (such as `setx` and the environment variables editor
+    /// of older Windows versions) truncate environment variables to.
+    const LEGACY_ENV_VAR_LEN: usize = 2047;
+
+    /// Get the `PATH` of the other scope, which is the system one for per-user installations
+    /// and vice versa, it is combined with the one we modify to form the `PATH` of new processes.
+    fn other_path_var() -> Vec<u16> {
+        let (root, subkey) = if crate::core::os::is_system_wide() {
+            (HKEY_CURRENT_USER, "Environment")
+        } else {
+            (HKEY_LOCAL_MACHINE, SYSTEM_ENVIRONMENT)
+        };
+        RegKey::predef(root)
+            .open_subkey(subkey)
+            .and_then(|key| key.get_raw_value("PATH"))
+            .ok()
+            .and_then(|val| from_winreg_value(&val))
+            .unwrap_or_default()
+    }
+
+    /// Make sure the `PATH` won't be truncated after adding `path` into it, which is done
+    /// by refusing to add it if the combined `PATH` gets too long, along with the hints of
+    /// the entries that might be removed from `old_path`.
+    ///
+    /// A warning is shown if `new_path` gets too long for some tools to edit.
+    fn check_path_len(path: &Path, old_path: &[u16], new_path: &[u16]) -> Result<()> {
+        let other_len = other_path_var().len();
+        let len = new_path.len() + other_len + usize::from(other_len > 0);
+        if len > MAX_ENV_VAR_LEN {
+            let hints = path_diagnostics(old_path);
+            let hints = if hints.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    ", the following entries might be removed:\n  {}",
+                    hints.join("\n  ")
+                )
+            };
+            return Err(InstallError::PathTooLong {
+                path: path.to_path_buf(),
+                len,
+                max: MAX_ENV_VAR_LEN,
+                hints,
+            }
+            .into());
+        }
+        if new_path.len() > LEGACY_ENV_VAR_LEN {
+            warn!(
+                "PATH is {} characters long, which is longer than {LEGACY_ENV_VAR_LEN}, \
+                editing it using `setx` (or the environment variables editor of older Windows) \
+                might truncate it",
+                new_path.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Find the entries of `PATH` that can be removed to make it shorter,
+    /// which are the duplicated ones and the ones that don't exist.
+    pub(super) fn path_diagnostics(paths: &[u16]) -> Vec<String> {
+        use std::collections::HashSet;
+
+        let paths = OsString::from_wide(paths);
+        let mut seen = HashSet::new();
+        let mut hints = vec![];
+        for entry in env::split_paths(&paths) {
+            let raw = entry.to_string_lossy();
+            if raw.is_empty() {
+                continue;
+            }
+            // Paths are case insensitive on Windows.
+            let duplicated = !seen.insert(raw.to_lowercase());
+            // Variables such as `%SystemRoot%` are not expanded here.
+            let missing = !raw.contains('%') && !entry.exists();
+            if duplicated {
+                hints.push(format!("'{raw}' (duplicated)"));
+            } else if missing {
+                hints.push(format!("'{raw}' (does not exist)"));
+            }
+        }
+        hints
+    }
+
+    pub(crate) fn add_to_path(path: &Path, install_dir: &Path) -> Result<()> {
         let Some(old_path) = get_windows_path_var()? else {
             return Ok(());
         };
@@ -299,9 +796,11 @@ pub(crate) mod rustup {
         let mut new_path = path_bytes;
         new_path.push(b';' as u16);
         new_path.extend_from_slice(&old_path);
+        check_path_len(path, &old_path, &new_path)?;
 
         // Apply the new path
-        set_env_var("PATH", new_path)?;
+        set_env_var("PATH", new_path.clone())?;
+        backup_env_var(install_dir, "PATH", Some(&old_path), &new_path)?;
         update_path_for_current_process(path, false)?;
         // Sync changes
         update_env();
@@ -337,7 +836,15 @@ pub(crate) mod rustup {
         }
 
         // Apply the new path
-        set_env_var("PATH", new_path)?;
+        set_env_var("PATH", new_path.clone())?;
+        // Keep the backup in sync (if there is one), so that it can still be restored verbatim.
+        if let Ok(install_dir) = crate::core::os::install_dir_from_exe_path() {
+            if InstallationRecord::load_or_new(&install_dir)
+                .is_ok_and(|record| record.env_backup.contains_key("PATH"))
+            {
+                backup_env_var(&install_dir, "PATH", Some(&old_path), &new_path)?;
+            }
+        }
         update_path_for_current_process(path, true)?;
         // Sync changes
         update_env();
@@ -352,6 +859,31 @@ mod tests {
 
     use super::rustup;
 
+    #[test]
+    fn path_diagnostics() {
+        use std::os::windows::ffi::OsStrExt;
+
+        let existing = std::env::temp_dir();
+        let paths = std::env::join_paths([
+            existing.clone(),
+            PathBuf::from(r"C:\path\to\non_exist\bin"),
+            PathBuf::from(existing.to_string_lossy().to_uppercase()),
+            PathBuf::from(r"%SystemRoot%\system32"),
+        ])
+        .unwrap();
+        let hints = rustup::path_diagnostics(&paths.encode_wide().collect::<Vec<_>>());
+        assert_eq!(
+            hints,
+            [
+                r"'C:\path\to\non_exist\bin' (does not exist)".to_string(),
+                format!(
+                    "'{}' (duplicated)",
+                    existing.to_string_lossy().to_uppercase()
+                ),
+            ]
+        );
+    }
+
     #[test]
     fn update_path() {
         let dummy_path = PathBuf::from("/path/to/non_exist/bin");
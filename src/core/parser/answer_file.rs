@@ -0,0 +1,137 @@
+//! Module defining the answer file used by unattended installation.
+//!
+//! An answer file predefines everything that would otherwise be asked during installation,
+//! so that the installer can be deployed silently, for example:
+//!
+//! ```toml
+//! prefix = "D:\\rust"
+//! profile = "minimal"
+//! groups = ["basic"]
+//! components = ["rust-src"]
+//! reuse-existing-rustup = false
+//! keep-on-failure = false
+//!
+//! [registry]
+//! name = "mirror"
+//! url = "sparse+https://mirrors.example.com/crates.io-index/"
+//!
+//! [proxy]
+//! https = "http://proxy.example.com:8080"
+//! no-proxy = "localhost,127.0.0.1"
+//!
+//! [existing]
+//! vscode = "skip"
+//! ```
+//!
+//! Every field is optional, and the command line arguments take precedence over it.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use url::Url;
+
+use super::manifest::Proxy;
+use super::ManifestParser;
+use crate::core::report::ExistingToolChoice;
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct AnswerFile {
+    /// The directory to install Rust into (`--prefix`).
+    pub(crate) prefix: Option<PathBuf>,
+    /// Install for all users of this machine (`--system`).
+    #[serde(default)]
+    pub(crate) system: bool,
+    /// The profile of Rust toolchain (`--profile`).
+    pub(crate) profile: Option<String>,
+    /// The groups of tools to install (`--group`), default to every group.
+    #[serde(default)]
+    pub(crate) groups: Vec<String>,
+    /// The optional toolchain components to install, none of them by default.
+    pub(crate) components: Option<Vec<String>>,
+    /// The cargo registry to replace `crates.io`.
+    pub(crate) registry: Option<Registry>,
+    pub(crate) rustup_dist_server: Option<Url>,
+    pub(crate) rustup_update_root: Option<Url>,
+    /// The proxy for downloading, overriding the one in toolset manifest.
+    pub(crate) proxy: Option<Proxy>,
+    /// Whether to install Rust toolchain using the `rustup` found in `PATH`, default to `true`.
+    pub(crate) reuse_existing_rustup: Option<bool>,
+    /// Whether to include the git configurations in the global git configuration,
+    /// default to `true`.
+    pub(crate) include_gitconfig: Option<bool>,
+    /// What to do with each tool that was already installed elsewhere (`--existing`).
+    #[serde(default)]
+    pub(crate) existing: HashMap<String, ExistingToolChoice>,
+    /// Keep the installed files when the installation fails, instead of rolling it back.
+    #[serde(default)]
+    pub(crate) keep_on_failure: bool,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct Registry {
+    #[serde(default = "default_registry_name")]
+    pub(crate) name: String,
+    pub(crate) url: String,
+}
+
+fn default_registry_name() -> String {
+    "mirror".into()
+}
+
+impl ManifestParser for AnswerFile {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_answer_file() {
+        let input = r#"
+prefix = "/opt/rust"
+profile = "minimal"
+components = ["rust-src"]
+reuse-existing-rustup = false
+include-gitconfig = false
+
+[registry]
+url = "sparse+https://example.com/index/"
+
+[proxy]
+https = "http://proxy.example.com:8080"
+no-proxy = "localhost"
+
+[existing]
+vscode = "skip"
+"#;
+        let answers = AnswerFile::from_str(input).unwrap();
+        assert_eq!(answers.prefix, Some(PathBuf::from("/opt/rust")));
+        assert_eq!(answers.profile.as_deref(), Some("minimal"));
+        assert!(answers.groups.is_empty());
+        assert_eq!(answers.components, Some(vec!["rust-src".to_string()]));
+        assert_eq!(answers.reuse_existing_rustup, Some(false));
+        assert_eq!(answers.include_gitconfig, Some(false));
+        assert_eq!(
+            answers.registry,
+            Some(Registry {
+                name: "mirror".into(),
+                url: "sparse+https://example.com/index/".into()
+            })
+        );
+        let proxy = answers.proxy.unwrap();
+        assert_eq!(
+            proxy.https.unwrap().as_str(),
+            "http://proxy.example.com:8080/"
+        );
+        assert_eq!(proxy.no_proxy.as_deref(), Some("localhost"));
+        assert_eq!(answers.existing["vscode"], ExistingToolChoice::Skip);
+        assert!(!answers.keep_on_failure);
+    }
+
+    #[test]
+    fn reject_unknown_answers() {
+        assert!(AnswerFile::from_str("install-dir = \"/opt/rust\"").is_err());
+        assert_eq!(AnswerFile::from_str("").unwrap(), AnswerFile::default());
+    }
+}
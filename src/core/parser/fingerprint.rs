@@ -0,0 +1,527 @@
+//! Module defining the installation record (a.k.a. the `fingerprint` file),
+//! which is written under the installation directory, tracking what was installed.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::manifest::CustomInstruction;
+use crate::utils;
+
+use super::manifest::RustToolchain;
+use super::ManifestParser;
+
+/// The file name of installation record, located under `install_dir`.
+pub(crate) const FILENAME: &str = ".fingerprint.toml";
+
+/// Prevent the record file being updated by multiple threads at the same time.
+static RECORD_LOCK: Mutex<()> = Mutex::new(());
+
+/// A record of an installation, containing information of the installed
+/// toolchain, tools, and the environment that was modified, such as the paths that were
+/// added to `PATH` environment variable, which is used by uninstallation to know what to remove.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct InstallationRecord {
+    /// Path to the installation directory.
+    pub(crate) install_dir: PathBuf,
+    /// Paths that were added to `PATH` environment variable.
+    #[serde(default)]
+    pub(crate) paths: Vec<PathBuf>,
+    /// Names of the persistent environment variables that were set.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) env_vars: Vec<String>,
+    /// Values of the persistent environment variables that were set, keyed by their names,
+    /// which never include the credentials of proxies.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub(crate) env_values: IndexMap<String, String>,
+    /// Values of the persistent environment variables before they were modified,
+    /// keyed by their names, Windows only.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub(crate) env_backup: IndexMap<String, EnvVarBackup>,
+    /// Whether this installation is shared by all users of the machine.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) system: bool,
+    /// Registry keys (under `HKEY_CURRENT_USER`, or `HKEY_LOCAL_MACHINE` for system-wide
+    /// installations) that were created, Windows only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) registry_keys: Vec<String>,
+    /// Dotted keys (such as `source.crates-io.replace-with`) added to `config.toml`
+    /// under `CARGO_HOME`, which didn't exist in the user's configuration.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) cargo_config_keys: Vec<String>,
+    /// Original values (in TOML) of the dotted keys in `config.toml` under `CARGO_HOME`
+    /// that were overwritten, which are restored when uninstalling.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub(crate) cargo_config_backup: IndexMap<String, String>,
+    /// The git configuration file added to the `include.path` of the global git configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) git_include: Option<PathBuf>,
+    pub(crate) rust: Option<RustRecord>,
+    #[serde(default)]
+    pub(crate) tools: IndexMap<String, ToolRecord>,
+    /// Path to the pre-existing `rustup` that was reused instead of installing a new one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) external_rustup: Option<PathBuf>,
+    /// Steps done by the current (unfinished) installation, which will be
+    /// cleared once that installation is finished, check [`InstallStep`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) pending: Vec<InstallStep>,
+    /// Names of the [`SessionStep`](crate::SessionStep)s completed by the current (unfinished)
+    /// installation, which are skipped when it is resumed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) completed_steps: Vec<String>,
+    /// Whether the current (unfinished) installation was suspended,
+    /// so that it will be resumed by the next installation, instead of starting over.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) suspended: bool,
+    /// Identifies what the current (unfinished) installation installs, which is compared
+    /// with the one resuming it, check [`InstallSession`](crate::InstallSession).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) session_fingerprint: Option<String>,
+}
+
+impl ManifestParser for InstallationRecord {}
+
+/// The value of a persistent environment variable before it was first modified by this program,
+/// which is restored verbatim when uninstalling, as long as the variable wasn't modified by
+/// anything else since then (i.e. it still has the `modified` value).
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct EnvVarBackup {
+    /// The original value, `None` if the variable didn't exist.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) original: Option<String>,
+    /// The value after the last modification by this program, `None` if it was removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) modified: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct RustRecord {
+    pub(crate) version: String,
+    #[serde(default)]
+    pub(crate) components: Vec<String>,
+    /// Cross compilation targets added to the toolchain of `version`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) targets: Vec<String>,
+    /// Names of the other toolchains installed along with the one of `version`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) toolchains: Vec<String>,
+    /// The toolchain used by default if it's not the one of `version`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) default: Option<String>,
+}
+
+impl RustRecord {
+    /// Get the name of the toolchain used by default.
+    pub(crate) fn default_toolchain(&self) -> &str {
+        self.default.as_deref().unwrap_or(&self.version)
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ToolRecord {
+    pub(crate) version: Option<String>,
+    /// Where this tool was installed from, missing in the records of older installations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) source: Option<ToolSource>,
+    /// Files or directories of this tool, including its binaries.
+    #[serde(default)]
+    pub(crate) paths: Vec<PathBuf>,
+    /// When this tool was installed, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) installed_at: Option<u64>,
+    /// The instruction declared in the manifest, which is needed to uninstall this tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) instruction: Option<CustomInstruction>,
+    /// The versions kept under `tools/<name>/<version>`, in the order they were installed,
+    /// one of which is the default `version`. Empty if this tool was not installed that way,
+    /// check [`tool_versions`](crate::core::tool_versions).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) versions: Vec<String>,
+}
+
+/// The source of an installed tool.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub(crate) enum ToolSource {
+    /// Installed using `cargo install` from the registry.
+    Cargo,
+    /// Installed using `cargo install --git`.
+    Git { url: Url },
+    /// Installed from a local file or directory.
+    Path { path: PathBuf },
+    /// Installed from a downloaded file.
+    Url { url: Url },
+    /// Installed using the package manager of the system.
+    System,
+}
+
+impl ToolSource {
+    /// A short name of this kind of source, which is the same as [`ToolInfo::source_kind`].
+    ///
+    /// [`ToolInfo::source_kind`]: crate::manifest::ToolInfo::source_kind
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Self::Cargo => "cargo",
+            Self::Git { .. } => "git",
+            Self::Path { .. } => "path",
+            Self::Url { .. } => "url",
+            Self::System => "system",
+        }
+    }
+}
+
+/// A mutating step done by an installation, which can be reverted if that installation failed.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub(crate) enum InstallStep {
+    /// A directory was created, including everything inside of it.
+    CreateDir { path: PathBuf },
+    /// A path was added to `PATH` environment variable.
+    AddPath { path: PathBuf },
+    /// Persistent environment variables were configured.
+    EnvVars,
+    /// A tool was installed, its files are listed in the [`ToolRecord`].
+    Tool { name: String },
+    /// Rust toolchain was installed.
+    Rust,
+    /// A toolchain that didn't exist before was installed (or linked) using `rustup`,
+    /// which is the only way to remove it when reusing an existing `rustup`.
+    Toolchain { name: String },
+    /// An uninstall entry was written to the Windows "installed programs" list.
+    ProgramsEntry,
+    /// A git configuration file was included in the global git configuration.
+    GitInclude,
+}
+
+impl InstallationRecord {
+    /// Get the path to the record file under a certain installation directory.
+    pub(crate) fn path_under(install_dir: &Path) -> PathBuf {
+        install_dir.join(FILENAME)
+    }
+
+    /// Load the installation record under `install_dir`,
+    /// or return a new record if the file does not exist.
+    pub(crate) fn load_or_new(install_dir: &Path) -> Result<Self> {
+        let path = Self::path_under(install_dir);
+        if path.is_file() {
+            Self::load(path)
+        } else {
+            Ok(Self {
+                install_dir: install_dir.to_path_buf(),
+                ..Default::default()
+            })
+        }
+    }
+
+    /// Load the installation record under `install_dir`, apply changes to it with `f`,
+    /// then write it back.
+    ///
+    /// This is guarded by a lock, so it is safe to be called from multiple threads.
+    pub(crate) fn update<F>(install_dir: &Path, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Self),
+    {
+        let _guard = RECORD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut record = Self::load_or_new(install_dir)?;
+        f(&mut record);
+        record.write()
+    }
+
+    /// Write this record to the `install_dir`.
+    pub(crate) fn write(&self) -> Result<()> {
+        self.save(Self::path_under(&self.install_dir))
+    }
+
+    /// Record a path that was added to `PATH` environment variable, duplicated paths are ignored.
+    pub(crate) fn add_path(&mut self, path: &Path) {
+        if !self.paths.iter().any(|p| p == path) {
+            self.paths.push(path.to_path_buf());
+        }
+    }
+
+    /// Record the names of persistent environment variables that were set, duplicates are ignored.
+    pub(crate) fn add_env_vars<'a, I: IntoIterator<Item = &'a str>>(&mut self, names: I) {
+        for name in names {
+            if !self.env_vars.iter().any(|n| n == name) {
+                self.env_vars.push(name.to_string());
+            }
+        }
+    }
+
+    /// Record the value of a persistent environment variable, `None` if it was removed.
+    pub(crate) fn set_env_value(&mut self, name: &str, value: Option<&str>) {
+        match value {
+            Some(val) => {
+                self.env_values.insert(name.to_string(), val.to_string());
+            }
+            None => {
+                self.env_values.shift_remove(name);
+            }
+        }
+    }
+
+    /// Record the keys added to cargo's `config.toml`, duplicates are ignored.
+    pub(crate) fn add_cargo_config_keys<I: IntoIterator<Item = String>>(&mut self, keys: I) {
+        for key in keys {
+            if !self.cargo_config_keys.contains(&key) {
+                self.cargo_config_keys.push(key);
+            }
+        }
+    }
+
+    /// Record the original values of the keys overwritten in cargo's `config.toml`.
+    ///
+    /// Only the values before the first modification are kept, and the keys added by
+    /// this program are ignored, since they are removed instead.
+    pub(crate) fn backup_cargo_config_values<I>(&mut self, values: I)
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (key, val) in values {
+            if !self.cargo_config_keys.contains(&key) {
+                self.cargo_config_backup.entry(key).or_insert(val);
+            }
+        }
+    }
+
+    /// Record a modification of a persistent environment variable from `old` to `new`,
+    /// the value before the first modification is kept as the [original](EnvVarBackup::original).
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub(crate) fn backup_env_var(&mut self, name: &str, old: Option<String>, new: Option<String>) {
+        self.env_backup
+            .entry(name.to_string())
+            .or_insert_with(|| EnvVarBackup {
+                original: old,
+                modified: None,
+            })
+            .modified = new;
+    }
+
+    /// Record a registry key that was created, duplicated keys are ignored.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub(crate) fn add_registry_key(&mut self, key: &str) {
+        if !self.registry_keys.iter().any(|k| k == key) {
+            self.registry_keys.push(key.to_string());
+        }
+    }
+
+    /// Keep track of a mutating step done by the current installation.
+    pub(crate) fn add_step(&mut self, step: InstallStep) {
+        self.pending.push(step);
+    }
+
+    /// Record a [`SessionStep`](crate::SessionStep) completed by the current installation,
+    /// duplicates are ignored.
+    pub(crate) fn complete_step(&mut self, name: &str) {
+        if !self.completed_steps.iter().any(|s| s == name) {
+            self.completed_steps.push(name.to_string());
+        }
+    }
+
+    /// Get the names of the tools installed by the current (unfinished) installation.
+    pub(crate) fn pending_tools(&self) -> impl Iterator<Item = &str> {
+        self.pending.iter().filter_map(|step| match step {
+            InstallStep::Tool { name } => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    pub(crate) fn add_tool_record(&mut self, name: &str, record: ToolRecord) {
+        self.tools.insert(name.to_string(), record);
+    }
+
+    /// Check if a tool was recorded as installed with the given version, and is still present.
+    ///
+    /// A tool is considered present if all of its recorded paths exist, or, when there are
+    /// no recorded paths (such as the ones installed by `cargo install`),
+    /// a binary with the same name can be found in `PATH`.
+    /// Note that version mismatches are treated as "not installed", so that it can be reinstalled.
+    pub(crate) fn is_tool_installed(&self, name: &str, version: Option<&str>) -> bool {
+        let Some(record) = self.tools.get(name) else {
+            return false;
+        };
+        if record.version.as_deref() != version {
+            return false;
+        }
+        if record.paths.is_empty() {
+            utils::cmd_exist(&format!("{name}{}", utils::EXE_EXT))
+        } else {
+            record.paths.iter().all(|p| p.exists())
+        }
+    }
+
+    /// Record the toolchain installed according to `rust` section of the manifest,
+    /// along with all the `components` that were added.
+    pub(crate) fn add_rust_record(&mut self, rust: &RustToolchain, components: &[String]) {
+        self.rust = Some(RustRecord {
+            version: rust.version.clone(),
+            components: components.to_vec(),
+            targets: rust.targets.clone(),
+            toolchains: rust.toolchains.keys().cloned().collect(),
+            default: rust.default.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_roundtrip() {
+        let mut record = InstallationRecord {
+            install_dir: PathBuf::from("/path/to/install"),
+            ..Default::default()
+        };
+        let rust = RustToolchain {
+            targets: vec!["wasm32-unknown-unknown".into()],
+            toolchains: [("vendor".to_string(), Default::default())].into(),
+            default: Some("vendor".into()),
+            ..RustToolchain::new("stable")
+        };
+        record.add_rust_record(&rust, &["rustfmt".to_string()]);
+        record.add_path(Path::new("/path/to/install/.cargo/bin"));
+        record.add_path(Path::new("/path/to/install/.cargo/bin"));
+        record.add_env_vars(["CARGO_HOME", "RUSTUP_HOME"]);
+        record.add_env_vars(["CARGO_HOME"]);
+        record.set_env_value("RUSTUP_DIST_SERVER", Some("https://a.example.com"));
+        record.set_env_value("RUSTUP_DIST_SERVER", Some("https://b.example.com"));
+        record.set_env_value("http_proxy", Some("http://proxy:8080"));
+        record.set_env_value("http_proxy", None);
+        record.add_cargo_config_keys(["source.mirror.registry".to_string()]);
+        record.add_cargo_config_keys(["source.mirror.registry".to_string()]);
+        record.backup_cargo_config_values([
+            ("source.mirror.registry".to_string(), "\"a\"".to_string()),
+            ("build.jobs".to_string(), "4".to_string()),
+            ("build.jobs".to_string(), "8".to_string()),
+        ]);
+        record.add_registry_key("Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\foo");
+        record.add_step(InstallStep::AddPath {
+            path: PathBuf::from("/path/to/install/.cargo/bin"),
+        });
+        record.add_step(InstallStep::Tool {
+            name: "vscode".into(),
+        });
+        record.complete_step("install-tools");
+        record.complete_step("install-tools");
+        record.suspended = true;
+        record.add_tool_record(
+            "vscode",
+            ToolRecord {
+                version: Some("1.91.1".into()),
+                source: Some(ToolSource::Path {
+                    path: PathBuf::from("/path/to/VSCode-win32-x64-1.91.1.zip"),
+                }),
+                paths: vec![PathBuf::from("/path/to/install/tools/vscode")],
+                installed_at: Some(1_720_000_000),
+                instruction: None,
+                versions: vec!["1.91.1".into()],
+            },
+        );
+
+        assert_eq!(record.paths.len(), 1);
+        assert_eq!(record.env_vars, ["CARGO_HOME", "RUSTUP_HOME"]);
+        assert_eq!(
+            record.env_values,
+            [(
+                "RUSTUP_DIST_SERVER".to_string(),
+                "https://b.example.com".to_string()
+            )]
+            .into()
+        );
+        assert_eq!(record.cargo_config_keys, ["source.mirror.registry"]);
+        assert_eq!(
+            record.cargo_config_backup,
+            [("build.jobs".to_string(), "4".to_string())].into()
+        );
+        assert_eq!(record.completed_steps, ["install-tools"]);
+        assert_eq!(record.rust.as_ref().unwrap().default_toolchain(), "vendor");
+        assert_eq!(record.pending_tools().collect::<Vec<_>>(), ["vscode"]);
+
+        let toml = record.to_toml().unwrap();
+        assert_eq!(InstallationRecord::from_str(&toml).unwrap(), record);
+    }
+
+    #[test]
+    fn tool_installation_state() {
+        let tools_dir = tempfile::tempdir().unwrap();
+        let mut record = InstallationRecord::default();
+        record.add_tool_record(
+            "foo",
+            ToolRecord {
+                version: Some("1.0.0".into()),
+                paths: vec![tools_dir.path().to_path_buf()],
+                ..Default::default()
+            },
+        );
+        record.add_tool_record(
+            "bar",
+            ToolRecord {
+                version: None,
+                paths: vec![tools_dir.path().join("bar")],
+                ..Default::default()
+            },
+        );
+
+        assert!(record.is_tool_installed("foo", Some("1.0.0")));
+        // version mismatch
+        assert!(!record.is_tool_installed("foo", Some("1.0.1")));
+        assert!(!record.is_tool_installed("foo", None));
+        // missing paths
+        assert!(!record.is_tool_installed("bar", None));
+        // not recorded
+        assert!(!record.is_tool_installed("baz", None));
+    }
+
+    #[test]
+    fn env_var_backup() {
+        let mut record = InstallationRecord::default();
+        record.backup_env_var(
+            "PATH",
+            Some("C:\\foo".into()),
+            Some("C:\\bar;C:\\foo".into()),
+        );
+        record.backup_env_var(
+            "PATH",
+            Some("C:\\bar;C:\\foo".into()),
+            Some("C:\\baz;C:\\bar;C:\\foo".into()),
+        );
+        record.backup_env_var("CARGO_HOME", None, Some("C:\\cargo".into()));
+        assert_eq!(
+            record.env_backup["PATH"],
+            EnvVarBackup {
+                original: Some("C:\\foo".into()),
+                modified: Some("C:\\baz;C:\\bar;C:\\foo".into()),
+            }
+        );
+        assert_eq!(record.env_backup["CARGO_HOME"].original, None);
+
+        let toml = record.to_toml().unwrap();
+        assert_eq!(InstallationRecord::from_str(&toml).unwrap(), record);
+    }
+
+    #[test]
+    fn system_wide_record() {
+        let record = InstallationRecord::from_str("install-dir = '/opt/foo'").unwrap();
+        assert!(!record.system);
+        assert!(!record.to_toml().unwrap().contains("system"));
+
+        let record = InstallationRecord {
+            install_dir: PathBuf::from("/opt/foo"),
+            system: true,
+            ..Default::default()
+        };
+        let toml = record.to_toml().unwrap();
+        assert!(toml.contains("system = true"));
+        assert_eq!(InstallationRecord::from_str(&toml).unwrap(), record);
+    }
+}
@@ -0,0 +1,183 @@
+//! Module generating a git configuration file from the [`GitSettings`] in manifest.
+//!
+//! Instead of modifying the user's global git configuration key by key, the settings are
+//! written into a separated file, which is then added to the global configuration as
+//! an `include.path`, so that it can be reverted by removing that single entry.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Result;
+
+use super::manifest::GitSettings;
+use crate::utils;
+
+/// Render the settings into the content of a git configuration file.
+pub(crate) fn to_gitconfig(settings: &GitSettings) -> String {
+    let mut content = String::new();
+    if let Some(proxy) = &settings.proxy {
+        let _ = writeln!(content, "[http]\n\tproxy = {}", quote(proxy));
+    }
+    for (base, prefixes) in &settings.instead_of {
+        let _ = writeln!(content, "[url {}]", quote(base));
+        for prefix in prefixes {
+            let _ = writeln!(content, "\tinsteadOf = {}", quote(prefix));
+        }
+    }
+    for (url, helper) in &settings.credential_helper {
+        let _ = writeln!(content, "[credential {}]", quote(url));
+        let _ = writeln!(content, "\thelper = {}", quote(helper));
+    }
+    content
+}
+
+/// The arguments of `git config` to use the global git configuration.
+const GLOBAL: &[&str] = &["--global"];
+
+/// Add `path` to the `include.path` of the global git configuration,
+/// nothing happens if it was already included.
+pub(crate) fn include(path: &Path) -> Result<()> {
+    include_in(GLOBAL, path)
+}
+
+/// Remove `path` from the `include.path` of the global git configuration,
+/// which was added by [`include`].
+pub(crate) fn remove_include(path: &Path) -> Result<()> {
+    remove_include_in(GLOBAL, path)
+}
+
+/// Add `path` to the `include.path` of the configuration given by `scope`,
+/// which are the arguments of `git config` to choose the configuration file.
+fn include_in(scope: &[&str], path: &Path) -> Result<()> {
+    let path = path.to_string_lossy();
+    if is_included(scope, &path) {
+        return Ok(());
+    }
+    let args = [&["config"], scope, &["--add", "include.path", &*path]].concat();
+    utils::execute("git", &args)
+}
+
+fn remove_include_in(scope: &[&str], path: &Path) -> Result<()> {
+    let path = path.to_string_lossy();
+    if !is_included(scope, &path) {
+        return Ok(());
+    }
+    // Match the exact value with a regex, since `--fixed-value` requires git 2.30 or newer.
+    let value_regex = format!("^{}$", escape_regex(&path));
+    let args = [
+        &["config"],
+        scope,
+        &["--unset-all", "include.path", &value_regex],
+    ]
+    .concat();
+    utils::execute("git", &args)
+}
+
+fn is_included(scope: &[&str], path: &str) -> bool {
+    let args = [&["config"], scope, &["--get-all", "include.path"]].concat();
+    // `git config` fails if there's no such key at all.
+    utils::execute_for_output("git", &args)
+        .unwrap_or_default()
+        .lines()
+        .any(|line| line.trim() == path)
+}
+
+/// Escape the special characters of POSIX extended regular expressions,
+/// which is what `git config` uses to match values.
+fn escape_regex(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\.^$|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Quote a value (or a subsection name), escaping the backslashes and double quotes in it.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    #[test]
+    fn render_gitconfig() {
+        let settings = GitSettings {
+            proxy: Some("http://proxy.example.com:8080".into()),
+            instead_of: IndexMap::from_iter([(
+                "https://gitlab.example.com/".to_string(),
+                vec![
+                    "git@gitlab.example.com:".to_string(),
+                    "ssh://git@gitlab.example.com/".to_string(),
+                ],
+            )]),
+            credential_helper: IndexMap::from_iter([(
+                "https://gitlab.example.com".to_string(),
+                "store --file \"C:\\creds\"".to_string(),
+            )]),
+        };
+        assert_eq!(
+            to_gitconfig(&settings),
+            r#"[http]
+	proxy = "http://proxy.example.com:8080"
+[url "https://gitlab.example.com/"]
+	insteadOf = "git@gitlab.example.com:"
+	insteadOf = "ssh://git@gitlab.example.com/"
+[credential "https://gitlab.example.com"]
+	helper = "store --file \"C:\\creds\""
+"#
+        );
+        assert_eq!(to_gitconfig(&GitSettings::default()), "");
+    }
+
+    #[test]
+    fn include_then_remove() {
+        if !utils::cmd_exist(&format!("git{}", utils::EXE_EXT)) {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config");
+        let config = config.to_str().unwrap();
+        let scope = ["--file", config];
+        // The dot in it should not match other characters.
+        let ours = Path::new("/path/to/rust.dist/gitconfig");
+        let others = Path::new("/path/to/rust-dist/gitconfig");
+        let get_all = || {
+            utils::execute_for_output(
+                "git",
+                &["config", "--file", config, "--get-all", "include.path"],
+            )
+            .unwrap_or_default()
+        };
+
+        include_in(&scope, others).unwrap();
+        include_in(&scope, ours).unwrap();
+        include_in(&scope, ours).unwrap();
+        assert_eq!(
+            get_all(),
+            "/path/to/rust-dist/gitconfig\n/path/to/rust.dist/gitconfig\n"
+        );
+
+        remove_include_in(&scope, ours).unwrap();
+        assert_eq!(get_all(), "/path/to/rust-dist/gitconfig\n");
+        // Nothing happens if it's not included.
+        remove_include_in(&scope, ours).unwrap();
+        assert_eq!(get_all(), "/path/to/rust-dist/gitconfig\n");
+    }
+
+    #[test]
+    fn escape_regex_chars() {
+        assert_eq!(
+            escape_regex(r"C:\Program Files (x86)\rust.dist"),
+            r"C:\\Program Files \(x86\)\\rust\.dist"
+        );
+    }
+}
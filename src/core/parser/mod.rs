@@ -1,37 +1,176 @@
+pub(crate) mod answer_file;
 pub(crate) mod cargo_config;
+pub(crate) mod fingerprint;
+pub(crate) mod git_config;
 pub mod manifest;
 
 use anyhow::Result;
 use serde::{de::DeserializeOwned, Serialize};
 use std::path::Path;
-use toml::{de, ser};
 
 use crate::utils;
 
+/// The formats that manifests (and other configuration files) can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Format {
+    #[default]
+    Toml,
+    Json,
+}
+
+impl Format {
+    /// Get the format by the extension of `path`,
+    /// return `None` if the extension is neither `.toml` nor `.json`.
+    pub(crate) fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// Guess the format of a document by its content, which is JSON if it starts with `{`,
+    /// since a TOML document can never start with an inline table.
+    pub(crate) fn sniff(content: &str) -> Self {
+        if content
+            .trim_start_matches('\u{feff}')
+            .trim_start()
+            .starts_with('{')
+        {
+            Self::Json
+        } else {
+            Self::Toml
+        }
+    }
+}
+
+/// Load and serialize manifests (and other configuration files) in any of the [`Format`]s.
 #[allow(unused)]
-pub(crate) trait TomlParser {
-    /// Deserialize a certain type from [`str`] value.
+pub(crate) trait ManifestParser {
+    /// Deserialize a certain type from [`str`] value in the given format.
+    fn from_str_as(from: &str, format: Format) -> Result<Self>
+    where
+        Self: Sized + DeserializeOwned,
+    {
+        match format {
+            Format::Toml => Ok(toml::from_str(from)?),
+            Format::Json => Ok(serde_json::from_str(from)?),
+        }
+    }
+
+    /// Deserialize a certain type from [`str`] value, which format is [sniffed](Format::sniff).
     fn from_str(from: &str) -> Result<Self>
     where
         Self: Sized + DeserializeOwned,
     {
-        Ok(de::from_str(from)?)
+        Self::from_str_as(from, Format::sniff(from))
+    }
+
+    /// Serialize data of a type into [`String`] in the given format.
+    fn to_string_as(&self, format: Format) -> Result<String>
+    where
+        Self: Sized + Serialize,
+    {
+        match format {
+            Format::Toml => Ok(toml::to_string(self)?),
+            Format::Json => Ok(serde_json::to_string_pretty(self)?),
+        }
     }
 
-    /// Serialize data of a type into [`String`].
+    /// Serialize data of a type into TOML [`String`].
     fn to_toml(&self) -> Result<String>
     where
         Self: Sized + Serialize,
     {
-        Ok(ser::to_string(self)?)
+        self.to_string_as(Format::Toml)
     }
 
-    /// Load TOML data directly from a certain file path.
+    /// Serialize data of a type into JSON [`String`].
+    fn to_json(&self) -> Result<String>
+    where
+        Self: Sized + Serialize,
+    {
+        self.to_string_as(Format::Json)
+    }
+
+    /// Load data directly from a certain file path, the format is decided by the
+    /// file extension, or [sniffed](Format::sniff) from the content if it's unknown.
     fn load<P: AsRef<Path>>(path: P) -> Result<Self>
     where
         Self: Sized + DeserializeOwned,
     {
-        let raw = utils::read_to_string(path)?;
-        Self::from_str(&raw)
+        let raw = utils::read_to_string(&path)?;
+        let format = Format::from_path(path.as_ref()).unwrap_or_else(|| Format::sniff(&raw));
+        Self::from_str_as(&raw, format)
+    }
+
+    /// Save data directly to a certain file path, creating its parent directories if needed.
+    ///
+    /// The format is decided by the file extension, default to TOML.
+    fn save<P: AsRef<Path>>(&self, path: P) -> Result<()>
+    where
+        Self: Sized + Serialize,
+    {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            utils::ensure_dir(parent)?;
+        }
+        let format = Format::from_path(path).unwrap_or_default();
+        utils::write_file(path, &self.to_string_as(format)?, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Format, ManifestParser};
+    use crate::utils;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Foo {
+        name: String,
+        values: Vec<u32>,
+    }
+
+    impl ManifestParser for Foo {}
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sub").join("foo.toml");
+        let foo = Foo {
+            name: "foo".into(),
+            values: vec![1, 2, 3],
+        };
+
+        foo.save(&path).unwrap();
+        assert_eq!(Foo::load(&path).unwrap(), foo);
+    }
+
+    #[test]
+    fn save_and_load_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo.json");
+        let foo = Foo {
+            name: "foo".into(),
+            values: vec![1, 2, 3],
+        };
+
+        foo.save(&path).unwrap();
+        assert!(utils::read_to_string(&path).unwrap().starts_with('{'));
+        assert_eq!(Foo::load(&path).unwrap(), foo);
+
+        // Unknown extensions are sniffed from the content.
+        let no_ext = dir.path().join("foo");
+        std::fs::copy(&path, &no_ext).unwrap();
+        assert_eq!(Foo::load(&no_ext).unwrap(), foo);
+    }
+
+    #[test]
+    fn sniff_format() {
+        assert_eq!(Format::sniff("name = \"foo\""), Format::Toml);
+        assert_eq!(Format::sniff("[rust]\nversion = \"1\""), Format::Toml);
+        assert_eq!(Format::sniff("\n  {\"name\": \"foo\"}"), Format::Json);
+        assert_eq!(Format::sniff("\u{feff}{}"), Format::Json);
     }
 }
@@ -0,0 +1,183 @@
+//! Set up an existing project to be built with the installed toolchain and cargo registry,
+//! so that everyone in a team gets reproducible builds matching this distribution.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use super::parser::cargo_config::CargoConfig;
+use super::parser::fingerprint::{InstallationRecord, RustRecord};
+use super::parser::ManifestParser;
+use crate::utils::{self, OutputEvent};
+
+/// The file name of the toolchain file, check
+/// [the rustup book](https://rust-lang.github.io/rustup/overrides.html#the-toolchain-file).
+const TOOLCHAIN_FILE: &str = "rust-toolchain.toml";
+
+#[derive(Debug, Serialize)]
+struct ToolchainFile<'a> {
+    toolchain: ToolchainOverride<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct ToolchainOverride<'a> {
+    channel: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    components: &'a [String],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    targets: &'a [String],
+}
+
+/// Write a `rust-toolchain.toml` pinned to the default toolchain installed in `install_dir`,
+/// and a `.cargo/config.toml` with the cargo registry configured by the installation (if any)
+/// into the project directory `dir`.
+///
+/// Existing files are not touched unless `force` is `true`, in which case the toolchain file
+/// is overwritten, while the registry configuration is merged into the existing
+/// `.cargo/config.toml`, keeping the other settings of the project.
+pub(crate) fn init_project(install_dir: &Path, dir: &Path, force: bool) -> Result<()> {
+    let record = InstallationRecord::load_or_new(install_dir)?;
+    let Some(rust) = &record.rust else {
+        bail!(
+            "Rust toolchain is not installed in '{}'",
+            install_dir.display()
+        );
+    };
+    let toolchain_path = dir.join(TOOLCHAIN_FILE);
+    let config_path = dir.join(".cargo").join("config.toml");
+    let registry = registry_config(&install_dir.join(".cargo").join("config.toml"))?;
+
+    if !force {
+        let paths = [
+            Some(&toolchain_path),
+            registry.as_ref().map(|_| &config_path),
+        ];
+        if let Some(path) = paths.into_iter().flatten().find(|path| path.exists()) {
+            bail!(
+                "'{}' already exists, run again with `--force` to overwrite it",
+                path.display()
+            );
+        }
+    }
+
+    write_project_file(&toolchain_path, &toolchain_file(rust)?, "written")?;
+    if let Some(registry) = registry {
+        let (content, verb) = if config_path.is_file() {
+            let existing = utils::read_to_string(&config_path)?;
+            let (merged, _) = registry.merge_into(&existing).with_context(|| {
+                format!("invalid cargo configuration '{}'", config_path.display())
+            })?;
+            (merged, "updated")
+        } else {
+            (registry.to_toml()?, "written")
+        };
+        write_project_file(&config_path, &content, verb)?;
+    }
+    Ok(())
+}
+
+fn write_project_file(path: &Path, content: &str, verb: &str) -> Result<()> {
+    utils::ensure_parent_dir(path)?;
+    utils::write_file(path, content.trim_end(), false)?;
+    OutputEvent::Message {
+        text: &format!("{verb} '{}'", path.display()),
+    }
+    .emit();
+    Ok(())
+}
+
+/// Generate a toolchain file pinned to the default toolchain in the record.
+///
+/// The components and targets are only listed for the toolchain installed from a dist server,
+/// because they cannot be added to a linked vendor toolchain.
+fn toolchain_file(rust: &RustRecord) -> Result<String> {
+    let channel = rust.default_toolchain();
+    let (components, targets) = if channel == rust.version {
+        (rust.components.as_slice(), rust.targets.as_slice())
+    } else {
+        (&[][..], &[][..])
+    };
+    let file = ToolchainFile {
+        toolchain: ToolchainOverride {
+            channel,
+            components,
+            targets,
+        },
+    };
+    Ok(toml::to_string(&file)?)
+}
+
+/// Extract the registry related configurations (`source` and `registries`) from the cargo
+/// configuration at `path`, or return `None` if there isn't any.
+fn registry_config(path: &Path) -> Result<Option<CargoConfig>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let mut config: toml::Table = toml::from_str(&utils::read_to_string(path)?)
+        .with_context(|| format!("invalid cargo configuration '{}'", path.display()))?;
+    config.retain(|key, _| matches!(key, "source" | "registries"));
+    if config.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(config.try_into()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::parser::manifest::RustToolchain;
+
+    #[test]
+    fn init_project_files() {
+        let install_dir = tempfile::tempdir().unwrap();
+        let install_dir = install_dir.path();
+        let mut record = InstallationRecord::load_or_new(install_dir).unwrap();
+        let rust = RustToolchain {
+            targets: vec!["wasm32-unknown-unknown".into()],
+            ..RustToolchain::new("1.80.0")
+        };
+        record.add_rust_record(&rust, &["rustfmt".to_string()]);
+        record.write().unwrap();
+        let cargo_config = install_dir.join(".cargo").join("config.toml");
+        utils::ensure_parent_dir(&cargo_config).unwrap();
+        utils::write_file(
+            &cargo_config,
+            "[net]\ngit-fetch-with-cli = true\n\n\
+            [source.crates-io]\nreplace-with = \"mirror\"\n\n\
+            [source.mirror]\nregistry = \"sparse+https://mirror.example.com/index/\"",
+            false,
+        )
+        .unwrap();
+
+        let project = tempfile::tempdir().unwrap();
+        init_project(install_dir, project.path(), false).unwrap();
+        let toolchain = utils::read_to_string(project.path().join(TOOLCHAIN_FILE)).unwrap();
+        assert_eq!(
+            toolchain,
+            "[toolchain]\nchannel = \"1.80.0\"\ncomponents = [\"rustfmt\"]\n\
+            targets = [\"wasm32-unknown-unknown\"]\n"
+        );
+        let config =
+            utils::read_to_string(project.path().join(".cargo").join("config.toml")).unwrap();
+        assert!(config.contains("replace-with = \"mirror\""));
+        assert!(!config.contains("git-fetch-with-cli"));
+
+        assert!(init_project(install_dir, project.path(), false).is_err());
+        // The other settings of the project are kept when forced.
+        let project_config = project.path().join(".cargo").join("config.toml");
+        utils::write_file(
+            &project_config,
+            "# build settings\n[build]\ntarget-dir = \"out\"\n\n\
+            [source.crates-io]\nreplace-with = \"vendored\"",
+            false,
+        )
+        .unwrap();
+        init_project(install_dir, project.path(), true).unwrap();
+        let config = utils::read_to_string(&project_config).unwrap();
+        assert!(config.starts_with("# build settings\n[build]\ntarget-dir = \"out\"\n"));
+        assert!(config.contains("replace-with = \"mirror\""));
+        assert!(!config.contains("vendored"));
+        assert!(config.contains("registry = \"sparse+https://mirror.example.com/index/\""));
+    }
+}
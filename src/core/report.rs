@@ -0,0 +1,207 @@
+//! A report of this machine made before installing anything, so that the user can decide what to
+//! do with the tools that were already installed elsewhere, check [`PreInstallReport`].
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::custom_instructions;
+use super::parser::manifest::ToolMap;
+
+/// An existing installation of a tool, such as a VS Code installed by the user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExistingTool {
+    pub name: String,
+    /// Where the tool was found, such as the path to its main executable.
+    pub path: Option<PathBuf>,
+    pub version: Option<String>,
+}
+
+/// What to do with a tool that already exists on this machine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExistingToolChoice {
+    /// Use the existing installation instead of installing the bundled copy,
+    /// the tools that require it are still installed.
+    #[default]
+    Reuse,
+    /// Leave the tool out of this installation, as if it was not selected.
+    Skip,
+    /// Install the bundled copy anyway.
+    Install,
+}
+
+impl FromStr for ExistingToolChoice {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "reuse" => Ok(Self::Reuse),
+            "skip" => Ok(Self::Skip),
+            "install" => Ok(Self::Install),
+            _ => bail!("invalid choice '{s}', expecting one of 'reuse', 'skip' or 'install'"),
+        }
+    }
+}
+
+/// Parse a choice of an existing tool in the form of `<NAME>=<CHOICE>`, i.e. `vscode=install`.
+pub fn parse_existing_tool_choice(s: &str) -> Result<(String, ExistingToolChoice)> {
+    let (name, choice) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("invalid value '{s}', expecting `<NAME>=<CHOICE>`"))?;
+    Ok((name.trim().to_string(), choice.trim().parse()?))
+}
+
+/// The packages that a tool needs to be installed using the package manager of the system,
+/// such as the compilers and libraries of `linux-build-essentials`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissingPackages {
+    /// The name of the tool that needs these packages.
+    pub tool: String,
+    pub packages: Vec<String>,
+    /// The command to install these packages, which might need `sudo`.
+    pub command: String,
+}
+
+/// The things found on this machine before installing, which are presented to the user
+/// (by the CLI or GUI) to decide whether to reuse them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreInstallReport {
+    /// The tools that were already installed, such as by the user.
+    pub existing_tools: Vec<ExistingTool>,
+    /// The system packages that are not installed yet, which will be installed
+    /// (with administrator privileges) only if the user explicitly opted in.
+    #[serde(default)]
+    pub missing_packages: Vec<MissingPackages>,
+}
+
+impl PreInstallReport {
+    /// Look for the existing installations of the given `tools`.
+    ///
+    /// Only the tools with custom instructions can be detected, which are the built-in ones
+    /// (such as `vscode` and `buildtools`) and the ones with a `detect` command in the manifest.
+    /// The system packages needed by the selected tools are checked as well.
+    pub fn new(tools: &ToolMap) -> Self {
+        let existing_tools = tools
+            .iter()
+            .filter_map(|(name, tool)| {
+                let detection = match tool.instruction() {
+                    Some(instruction) => {
+                        custom_instructions::declared::already_installed(instruction)
+                            .then(custom_instructions::Detection::default)
+                    }
+                    None => custom_instructions::detect(name, Some(tool)),
+                }?;
+                Some(ExistingTool {
+                    name: name.clone(),
+                    path: detection.path,
+                    version: detection.version,
+                })
+            })
+            .collect();
+        let missing_packages = tools
+            .iter()
+            .filter_map(|(name, tool)| {
+                let (packages, command) = custom_instructions::missing_packages(name, tool)?;
+                Some(MissingPackages {
+                    tool: name.clone(),
+                    packages,
+                    command,
+                })
+            })
+            .collect();
+        Self {
+            existing_tools,
+            missing_packages,
+        }
+    }
+
+    /// Get the existing installation of a tool, if there is one.
+    pub fn existing_tool(&self, name: &str) -> Option<&ExistingTool> {
+        self.existing_tools.iter().find(|tool| tool.name == name)
+    }
+
+    /// Apply the `choices` of the existing tools to the set of `tools` to install,
+    /// which removes the skipped ones, then return the choice of each existing tool,
+    /// where the ones without a choice are [reused](ExistingToolChoice::Reuse).
+    ///
+    /// Tools that were not found can be skipped as well. The ones that need
+    /// [system packages](Self::missing_packages) are skipped unless they are chosen to be
+    /// [installed](ExistingToolChoice::Install), since that needs administrator privileges.
+    pub fn apply(
+        &self,
+        choices: &HashMap<String, ExistingToolChoice>,
+        tools: &mut ToolMap,
+    ) -> HashMap<String, ExistingToolChoice> {
+        let applied = self
+            .existing_tools
+            .iter()
+            .map(|tool| {
+                let choice = choices.get(&tool.name).copied().unwrap_or_default();
+                (tool.name.clone(), choice)
+            })
+            .collect::<HashMap<_, _>>();
+        tools.retain(|name, _| match choices.get(name) {
+            Some(choice) => *choice != ExistingToolChoice::Skip,
+            None => !self.missing_packages.iter().any(|m| m.tool == *name),
+        });
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::ToolInfo;
+
+    #[test]
+    fn parse_choices() {
+        assert_eq!(
+            parse_existing_tool_choice("vscode=install").unwrap(),
+            ("vscode".to_string(), ExistingToolChoice::Install)
+        );
+        assert_eq!(
+            parse_existing_tool_choice(" buildtools = skip ").unwrap(),
+            ("buildtools".to_string(), ExistingToolChoice::Skip)
+        );
+        assert!(parse_existing_tool_choice("vscode").is_err());
+        assert!(parse_existing_tool_choice("vscode=remove").is_err());
+    }
+
+    #[test]
+    fn apply_choices() {
+        let existing = |name: &str| ExistingTool {
+            name: name.to_string(),
+            path: None,
+            version: None,
+        };
+        let missing = |name: &str| MissingPackages {
+            tool: name.to_string(),
+            packages: vec!["gcc".into()],
+            command: "sudo apt-get install -y gcc".into(),
+        };
+        let report = PreInstallReport {
+            existing_tools: vec![existing("a"), existing("b"), existing("c")],
+            missing_packages: vec![missing("e"), missing("f")],
+        };
+        let mut tools = ToolMap::new();
+        for name in ["a", "b", "c", "d", "e", "f"] {
+            tools.insert(name.to_string(), ToolInfo::PlainVersion("1.0.0".into()));
+        }
+        let choices = HashMap::from([
+            ("a".to_string(), ExistingToolChoice::Skip),
+            ("b".to_string(), ExistingToolChoice::Install),
+            ("d".to_string(), ExistingToolChoice::Skip),
+            ("f".to_string(), ExistingToolChoice::Install),
+        ]);
+
+        // System packages are only installed if explicitly chosen to.
+        let applied = report.apply(&choices, &mut tools);
+        assert_eq!(tools.keys().collect::<Vec<_>>(), ["b", "c", "f"]);
+        assert_eq!(applied.len(), 3);
+        assert_eq!(applied["b"], ExistingToolChoice::Install);
+        assert_eq!(applied["c"], ExistingToolChoice::Reuse);
+    }
+}
@@ -0,0 +1,191 @@
+//! Reverting the steps done by an unfinished installation.
+//!
+//! Each mutating step of an installation is kept as [`InstallStep`] in the installation record,
+//! so when the installation fails, we can undo them in reverse order
+//! using the same removal functions as uninstallation.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use tracing::{info, warn};
+
+use super::os;
+use super::parser::fingerprint::{InstallStep, InstallationRecord};
+use super::parser::git_config;
+use super::rustup;
+use super::store::Store;
+use super::tool_versions;
+use super::uninstall;
+use super::verify;
+use crate::utils;
+
+/// Revert every pending step of the installation under `install_dir`.
+///
+/// This will try its best to revert all the steps even if some of them fail,
+/// then return an error listing how many of them failed.
+pub(crate) fn rollback(install_dir: &Path) -> Result<()> {
+    let mut record = InstallationRecord::load_or_new(install_dir)?;
+    let steps = std::mem::take(&mut record.pending);
+    let mut failures = 0;
+
+    for step in steps.iter().rev() {
+        if let Err(e) = revert_step(&mut record, step) {
+            warn!("unable to revert installation step {step:?}: {e:?}");
+            failures += 1;
+        }
+    }
+
+    // The installation directory might have already been removed.
+    if install_dir.is_dir() {
+        record.write()?;
+        if let Err(e) = Store::new(install_dir).gc(&record) {
+            warn!("unable to clean up the store: {e:?}");
+        }
+    }
+    if failures > 0 {
+        bail!("{failures} step(s) cannot be reverted, check the warnings above for details");
+    }
+    Ok(())
+}
+
+fn revert_step(record: &mut InstallationRecord, step: &InstallStep) -> Result<()> {
+    match step {
+        InstallStep::CreateDir { path } => {
+            info!("removing '{}'", path.display());
+            utils::remove(path)?;
+        }
+        InstallStep::AddPath { path } => {
+            os::remove_from_path(path)?;
+            record.paths.retain(|p| p != path);
+        }
+        InstallStep::EnvVars => os::remove_env_vars(record)?,
+        InstallStep::Tool { name } => {
+            let installed_version = record
+                .tools
+                .get(name)
+                .and_then(|tool| tool.version.clone().filter(|_| tool.versions.len() > 1));
+            if let Some(version) = installed_version {
+                // Switch back to the previous version, instead of removing all of them.
+                info!("removing version '{version}' of '{name}'");
+                tool_versions::remove_version(record, name, &version)?;
+            } else if let Some(tool) = record.tools.shift_remove(name) {
+                info!("removing '{name}'");
+                uninstall::remove_tool_paths(name, &tool)?;
+                verify::remove_checksums(&record.install_dir, name)?;
+            }
+        }
+        InstallStep::Rust => record.rust = None,
+        InstallStep::Toolchain { name } => {
+            info!("removing toolchain '{name}'");
+            rustup::uninstall_toolchain(record, name)?;
+        }
+        InstallStep::ProgramsEntry => {
+            #[cfg(windows)]
+            os::windows::remove_registry_keys(record)?;
+        }
+        InstallStep::GitInclude => {
+            if let Some(path) = record.git_include.take() {
+                git_config::remove_include(&path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::core::parser::fingerprint::{RustRecord, ToolRecord};
+
+    fn record_in(install_dir: &Path) -> InstallationRecord {
+        InstallationRecord {
+            install_dir: install_dir.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn revert_created_dirs_and_tools() {
+        let install_dir = tempfile::tempdir().unwrap();
+        let created = install_dir.path().join("toolchains").join("vendor");
+        fs::create_dir_all(&created).unwrap();
+        let tool = install_dir.path().join("tools").join("foo");
+        utils::ensure_parent_dir(&tool).unwrap();
+        fs::write(&tool, "foo").unwrap();
+        utils::create_executable_file(&tool).unwrap();
+        let checksums = verify::checksums_of(std::slice::from_ref(&tool));
+        verify::write_checksums(install_dir.path(), "foo", &checksums).unwrap();
+
+        let mut record = record_in(install_dir.path());
+        record.add_step(InstallStep::CreateDir {
+            path: created.clone(),
+        });
+        record.add_tool_record(
+            "foo",
+            ToolRecord {
+                paths: vec![tool.clone()],
+                ..Default::default()
+            },
+        );
+        record.add_step(InstallStep::Tool { name: "foo".into() });
+        record.write().unwrap();
+
+        rollback(install_dir.path()).unwrap();
+        assert!(!created.exists());
+        assert!(!tool.exists());
+        assert!(verify::read_checksums(install_dir.path(), "foo").is_none());
+        let record = InstallationRecord::load_or_new(install_dir.path()).unwrap();
+        assert!(record.tools.is_empty());
+        assert!(record.pending.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn revert_installed_toolchains() {
+        let install_dir = tempfile::tempdir().unwrap();
+        let log = install_dir.path().join("rustup.log");
+        // A fake `rustup` logging the `RUSTUP_HOME` and the arguments it runs with.
+        let rustup = install_dir
+            .path()
+            .join(".cargo")
+            .join("bin")
+            .join(rustup::RUSTUP);
+        utils::ensure_parent_dir(&rustup).unwrap();
+        let script = format!(
+            "#!/bin/sh\necho \"$RUSTUP_HOME $*\" >> '{}'\n",
+            log.display()
+        );
+        fs::write(&rustup, script).unwrap();
+        utils::create_executable_file(&rustup).unwrap();
+
+        let mut record = record_in(install_dir.path());
+        record.rust = Some(RustRecord {
+            version: "1.80.0".into(),
+            ..Default::default()
+        });
+        // The existing toolchains are not recorded, thus never removed.
+        record.add_step(InstallStep::Toolchain {
+            name: "1.80.0".into(),
+        });
+        record.add_step(InstallStep::Toolchain {
+            name: "vendor".into(),
+        });
+        record.add_step(InstallStep::Rust);
+        record.write().unwrap();
+
+        rollback(install_dir.path()).unwrap();
+        let rustup_home = install_dir.path().join(".rustup");
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            format!(
+                "{0} toolchain uninstall vendor\n{0} toolchain uninstall 1.80.0\n",
+                rustup_home.display()
+            )
+        );
+        let record = InstallationRecord::load_or_new(install_dir.path()).unwrap();
+        assert!(record.rust.is_none());
+        assert!(record.pending.is_empty());
+    }
+}
@@ -1,13 +1,20 @@
+use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use url::Url;
 
-use super::install::InstallConfiguration;
-use super::parser::manifest::ToolsetManifest;
+use super::error::InstallError;
+use super::install::{verify_local_checksum, verify_signature, InstallConfiguration};
+use super::parser::fingerprint::{InstallStep, InstallationRecord};
+use super::parser::manifest::{parse_toolchain_profile, ExtraToolchain, ToolsetManifest};
+use super::{CARGO_HOME, RUSTUP_HOME};
 use crate::manifest::Proxy;
-use crate::utils::{create_executable_file, download, execute, force_url_join};
+use crate::utils::{
+    self, create_executable_file, download, execute, force_url_join, Extractable, OutputEvent,
+    ProgressEvent,
+};
 
 #[cfg(windows)]
 pub(crate) const RUSTUP_INIT: &str = "rustup-init.exe";
@@ -15,9 +22,26 @@ pub(crate) const RUSTUP_INIT: &str = "rustup-init.exe";
 pub(crate) const RUSTUP_INIT: &str = "rustup-init";
 
 #[cfg(windows)]
-const RUSTUP: &str = "rustup.exe";
+pub(crate) const RUSTUP: &str = "rustup.exe";
 #[cfg(not(windows))]
-const RUSTUP: &str = "rustup";
+pub(crate) const RUSTUP: &str = "rustup";
+
+/// Select the first reachable server from the ranked rustup dist servers in manifest,
+/// falling back to the first one if none of them is reachable.
+///
+/// Return `None` if the manifest doesn't have any.
+pub(crate) fn select_dist_server(manifest: &ToolsetManifest) -> Option<Url> {
+    let servers = &manifest.rust.rustup_dist_servers;
+    let first = servers.first()?;
+    let probe = format!("dist/channel-rust-{}.toml", manifest.rust.version);
+    let selected =
+        utils::select_mirror(servers, &probe, manifest.proxy_or_env().as_ref()).unwrap_or(first);
+    OutputEvent::Message {
+        text: &format!("using rustup dist server '{selected}'"),
+    }
+    .emit();
+    Some(selected.clone())
+}
 
 pub struct Rustup;
 
@@ -33,10 +57,17 @@ impl Rustup {
         server: &Url,
         proxy: Option<&Proxy>,
     ) -> Result<()> {
-        let download_url =
-            force_url_join(server, &format!("dist/{}/{RUSTUP_INIT}", env!("TARGET")))
-                .context("Failed to init rustup download url.")?;
-        download(RUSTUP_INIT, &download_url, dest, proxy).context("Failed to download rustup.")
+        let download_url = force_url_join(
+            server,
+            &format!("dist/{}/{RUSTUP_INIT}", utils::native_triple()),
+        )
+        .context("Failed to init rustup download url.")?;
+        download(RUSTUP_INIT, &download_url, dest, proxy).with_context(|| {
+            InstallError::DownloadFailed {
+                name: RUSTUP_INIT.to_string(),
+                url: download_url.clone(),
+            }
+        })
     }
 
     pub(crate) fn generate_rustup(&self, rustup_init: &PathBuf) -> Result<()> {
@@ -46,53 +77,118 @@ impl Rustup {
             "--default-toolchain",
             "none",
             "--default-host",
-            env!("TARGET"),
+            default_host(),
             "-vy",
         ];
         execute(rustup_init, &args)
     }
 
-    fn download_rust_toolchain(&self, rustup: &Path, manifest: &ToolsetManifest) -> Result<()> {
+    /// Install the toolchain using the given `profile`,
+    /// or the one in the manifest if not provided.
+    fn download_rust_toolchain(
+        &self,
+        rustup: &Path,
+        manifest: &ToolsetManifest,
+        profile: Option<&str>,
+    ) -> Result<()> {
         // TODO: check local manifest.
         let version = manifest.rust.version.clone();
         let mut args = vec!["toolchain", "install", &version, "--no-self-update"];
-        if let Some(profile) = &manifest.rust.profile {
-            args.extend(["--profile", &profile.name]);
+        let profile = profile
+            .or(manifest.rust.profile.as_ref().map(|p| p.name.as_str()))
+            .map(parse_toolchain_profile)
+            .transpose()?;
+        if let Some(profile) = &profile {
+            args.extend(["--profile", profile]);
         }
         execute(rustup, &args)
     }
 
-    fn download_rust_components(&self, rustup: &Path, components: Vec<&str>) -> Result<()> {
+    fn download_rust_components(
+        &self,
+        rustup: &Path,
+        toolchain: &str,
+        components: Vec<&str>,
+    ) -> Result<()> {
         if components.is_empty() {
             return Ok(());
         }
-        let mut args = vec!["component", "add"];
+        let mut args = vec!["component", "add", "--toolchain", toolchain];
         args.extend(components);
         execute(rustup, &args)
     }
 
+    /// Add the cross compilation targets to `toolchain` one by one, reporting each of them.
+    fn download_rust_targets(
+        &self,
+        rustup: &Path,
+        toolchain: &str,
+        targets: &[String],
+    ) -> Result<()> {
+        for target in targets {
+            utils::check_cancelled()?;
+            utils::report_progress(ProgressEvent::Message {
+                text: format!("adding target '{target}'"),
+            })?;
+            execute(rustup, &["target", "add", "--toolchain", toolchain, target])?;
+        }
+        Ok(())
+    }
+
+    /// Install the [extra toolchains](ExtraToolchain) in the manifest,
+    /// along with their components and targets.
+    fn download_extra_toolchains(
+        &self,
+        rustup: &Path,
+        config: &InstallConfiguration,
+        manifest: &ToolsetManifest,
+    ) -> Result<()> {
+        for (name, toolchain) in &manifest.rust.toolchains {
+            utils::check_cancelled()?;
+            OutputEvent::Message {
+                text: &format!("installing toolchain '{name}'"),
+            }
+            .emit();
+            if toolchain.is_linked() {
+                self.link_toolchain(rustup, config, manifest, name, toolchain)?;
+                continue;
+            }
+            let mut args = vec!["toolchain", "install", name, "--no-self-update"];
+            let profile = toolchain
+                .profile
+                .as_deref()
+                .map(parse_toolchain_profile)
+                .transpose()?;
+            if let Some(profile) = &profile {
+                args.extend(["--profile", profile]);
+            }
+            for component in &toolchain.components {
+                args.extend(["--component", component]);
+            }
+            for target in &toolchain.targets {
+                args.extend(["--target", target]);
+            }
+            execute(rustup, &args)?;
+        }
+        Ok(())
+    }
+
     pub(crate) fn download_toolchain(
         &self,
         config: &InstallConfiguration,
         manifest: &ToolsetManifest,
+        profile: Option<&str>,
         optional_components: Option<&[String]>,
     ) -> Result<()> {
-        // We are putting the binary here so that it will be deleted automatically after done.
-        let temp_dir = config.create_temp_dir("rustup-init")?;
-        let rustup_init = temp_dir.path().join(RUSTUP_INIT);
-        // Download rustup-init.
-        self.download_rustup_init(
-            &rustup_init,
-            &config.rustup_update_root,
-            manifest.proxy.as_ref(),
-        )?;
-        // File permission
-        create_executable_file(&rustup_init)?;
-        // Install rustup.
-        self.generate_rustup(&rustup_init)?;
+        let rustup = if let Some(existing) = config.existing_rustup() {
+            // Reuse the existing rustup, and its toolchains directory.
+            existing.to_path_buf()
+        } else {
+            self.install_rustup(config, manifest)?;
+            config.cargo_bin().join(RUSTUP)
+        };
         // Install rust toolchain via rustup.
-        let rustup = config.cargo_bin().join(RUSTUP);
-        self.download_rust_toolchain(&rustup, manifest)?;
+        self.download_rust_toolchain(&rustup, manifest, profile)?;
 
         // Install extra rust components via rustup.
         // NOTE: that the `component` field in manifest is essential
@@ -112,7 +208,89 @@ impl Rustup {
                 .map(|s| s.as_str())
                 .collect()
         };
-        self.download_rust_components(&rustup, components_to_install)?;
+        self.download_rust_components(&rustup, &manifest.rust.version, components_to_install)?;
+        self.download_rust_targets(&rustup, &manifest.rust.version, &manifest.rust.targets)?;
+        self.download_extra_toolchains(&rustup, config, manifest)?;
+
+        // Leave the default toolchain of the existing rustup alone unless it was specified.
+        if config.existing_rustup().is_none() || manifest.rust.default.is_some() {
+            execute(&rustup, &["default", manifest.rust.default_toolchain()])?;
+        }
+
+        Ok(())
+    }
+
+    /// Install a vendor toolchain that isn't served by any dist server into
+    /// `<install_dir>/toolchains/<name>`, then link it using `rustup toolchain link`,
+    /// check [`ExtraToolchain`].
+    fn link_toolchain(
+        &self,
+        rustup: &Path,
+        config: &InstallConfiguration,
+        manifest: &ToolsetManifest,
+        name: &str,
+        toolchain: &ExtraToolchain,
+    ) -> Result<()> {
+        let temp_dir = config.create_temp_dir("toolchain")?;
+        let extracted = temp_dir.path().join("extracted");
+        utils::ensure_dir(&extracted)?;
+        let source = match (&toolchain.path, &toolchain.channel) {
+            (Some(path), _) if path.is_dir() => path.clone(),
+            (Some(path), _) => {
+                Extractable::try_from(path.as_path())?.extract_to(&extracted)?;
+                toolchain_root(&extracted)?
+            }
+            (None, Some(channel)) => {
+                let proxy = manifest.proxy_or_env();
+                let tarball =
+                    download_channel_package(name, channel, temp_dir.path(), proxy.as_ref())?;
+                Extractable::try_from(tarball.as_path())?.extract_to(&extracted)?;
+                toolchain_root(&extracted)?
+            }
+            (None, None) => bail!("toolchain '{name}' has neither `path` nor `channel` to link"),
+        };
+
+        let dest = config.install_dir.join("toolchains").join(name);
+        if dest.exists() {
+            utils::remove(&dest)?;
+        } else {
+            config.record_step(InstallStep::CreateDir { path: dest.clone() })?;
+        }
+        install_components(&source, &dest)?;
+        execute(
+            rustup,
+            &["toolchain", "link", name, &utils::stringify_path(&dest)?],
+        )
+    }
+
+    /// Download `rustup-init` and use it to install `rustup` into
+    /// [`cargo_bin`](InstallConfiguration::cargo_bin).
+    fn install_rustup(
+        &self,
+        config: &InstallConfiguration,
+        manifest: &ToolsetManifest,
+    ) -> Result<()> {
+        // We are putting the binary here so that it will be deleted automatically after done.
+        let temp_dir = config.create_temp_dir("rustup-init")?;
+        let rustup_init = temp_dir.path().join(RUSTUP_INIT);
+        let proxy = manifest.proxy_or_env();
+        if let Some(local) = manifest.local_rustup_init() {
+            // Use the local rustup-init, such as the one bundled for air-gapped machines.
+            let url = Url::from_file_path(&local.path)
+                .map_err(|_| anyhow!("invalid path '{}'", local.path.display()))?;
+            verify_local_checksum(RUSTUP_INIT, &url, &local.path, &local.sha256)?;
+            utils::copy_as(&local.path, &rustup_init)?;
+        } else {
+            // Download rustup-init.
+            self.download_rustup_init(&rustup_init, &config.rustup_update_root, proxy.as_ref())?;
+        }
+        if let Some(sig) = &manifest.rust.rustup_init_signature {
+            verify_signature(config, RUSTUP_INIT, &rustup_init, sig, proxy.as_ref())?;
+        }
+        // File permission
+        create_executable_file(&rustup_init)?;
+        // Install rustup.
+        self.generate_rustup(&rustup_init)?;
 
         // Remove the `rustup` uninstall entry on windows, because we don't want
         // uses to accidently uninstall `rustup` thus removing the installed binary of this program.
@@ -124,3 +302,238 @@ impl Rustup {
         Ok(())
     }
 }
+
+/// Get the names of the toolchains installed by `rustup`, such as `stable-x86_64-unknown-linux-gnu`,
+/// or an empty list if they cannot be listed.
+pub(crate) fn installed_toolchains(rustup: &Path) -> Vec<String> {
+    utils::execute_for_output(rustup, &["toolchain", "list"])
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| line.trim() != "no installed toolchains")
+        .filter_map(|line| line.split_whitespace().next())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Check if the toolchain `name` (such as `stable`) is one of the `installed` ones,
+/// whose names come with the host triple unless they are linked.
+pub(crate) fn is_toolchain_installed(installed: &[String], name: &str) -> bool {
+    installed.iter().any(|toolchain| {
+        toolchain == name
+            || toolchain
+                .strip_prefix(name)
+                .is_some_and(|rest| rest.starts_with('-'))
+    })
+}
+
+/// Uninstall the toolchain `name` from the installation of `record`, using the `rustup`
+/// it was installed with. Nothing happens if that `rustup` no longer exists.
+pub(crate) fn uninstall_toolchain(record: &InstallationRecord, name: &str) -> Result<()> {
+    let args = ["toolchain", "uninstall", name];
+    if let Some(rustup) = &record.external_rustup {
+        if rustup.is_file() {
+            execute(rustup, &args)?;
+        }
+        return Ok(());
+    }
+    let rustup = record.install_dir.join(".cargo").join("bin").join(RUSTUP);
+    if !rustup.is_file() {
+        return Ok(());
+    }
+    let cargo_home = record.install_dir.join(".cargo");
+    let rustup_home = record.install_dir.join(".rustup");
+    utils::execute_program_with_env(
+        &rustup,
+        &args,
+        [
+            (CARGO_HOME, cargo_home.as_os_str()),
+            (RUSTUP_HOME, rustup_home.as_os_str()),
+        ],
+    )
+}
+
+/// Get the default host triple of the installed `rustup`, which is the
+/// [host triple](utils::host_triple) if it runs natively on this machine,
+/// such as `x86_64-pc-windows-gnu` for the `gnu` [flavor](crate::manifest::ToolchainFlavor),
+/// otherwise the [native one](utils::native_triple).
+fn default_host() -> &'static str {
+    let host = utils::host_triple();
+    let native = utils::native_triple();
+    if utils::triple_arch(host) == utils::triple_arch(native)
+        && utils::triple_os(host) == utils::triple_os(native)
+    {
+        host
+    } else {
+        native
+    }
+}
+
+/// Download the `rust` package of the host target listed in the `channel` manifest into `dir`,
+/// and verify it with the checksum in that manifest.
+fn download_channel_package(
+    name: &str,
+    channel: &Url,
+    dir: &Path,
+    proxy: Option<&Proxy>,
+) -> Result<PathBuf> {
+    let channel_file = dir.join("channel.toml");
+    download(
+        format!("channel of '{name}'"),
+        channel,
+        &channel_file,
+        proxy,
+    )
+    .with_context(|| InstallError::DownloadFailed {
+        name: name.to_string(),
+        url: channel.clone(),
+    })?;
+    let manifest: toml::Table = toml::from_str(&utils::read_to_string(&channel_file)?)
+        .with_context(|| format!("invalid channel manifest '{channel}'"))?;
+
+    let host = default_host();
+    let package = manifest
+        .get("pkg")
+        .and_then(|pkg| pkg.get("rust"))
+        .and_then(|pkg| pkg.get("target"))
+        .and_then(|targets| targets.get(host))
+        .filter(|pkg| pkg.get("available").and_then(|a| a.as_bool()) != Some(false))
+        .ok_or_else(|| {
+            anyhow!("toolchain '{name}' is not available for '{host}' in '{channel}'")
+        })?;
+    let (url, hash) = [("xz_url", "xz_hash"), ("url", "hash")]
+        .into_iter()
+        .find_map(|(url, hash)| {
+            let url = package.get(url)?.as_str()?;
+            Some((url, package.get(hash).and_then(|h| h.as_str())))
+        })
+        .ok_or_else(|| anyhow!("toolchain '{name}' has no package url in '{channel}'"))?;
+    // The url might be relative to the channel manifest.
+    let url = channel.join(url)?;
+
+    let file_name = url
+        .path_segments()
+        .and_then(|mut segs| segs.next_back())
+        .filter(|seg| !seg.is_empty())
+        .ok_or_else(|| anyhow!("'{url}' is not a downloadable file"))?;
+    let tarball = dir.join(file_name);
+    download(name, &url, &tarball, proxy).with_context(|| InstallError::DownloadFailed {
+        name: name.to_string(),
+        url: url.clone(),
+    })?;
+    if let Some(hash) = hash {
+        verify_local_checksum(name, &url, &tarball, hash)?;
+    }
+    Ok(tarball)
+}
+
+/// Get the root directory of an extracted toolchain tarball, which is usually the only directory
+/// in it, such as `rust-1.80.0-x86_64-unknown-linux-gnu`.
+fn toolchain_root(extracted: &Path) -> Result<PathBuf> {
+    if extracted.join("components").is_file() || extracted.join("bin").is_dir() {
+        return Ok(extracted.to_path_buf());
+    }
+    match utils::walk_dir(extracted, false)?.as_slice() {
+        [dir] if dir.is_dir() => Ok(dir.clone()),
+        _ => bail!(
+            "'{}' doesn't look like a Rust toolchain",
+            extracted.display()
+        ),
+    }
+}
+
+/// Install the components of a toolchain tarball extracted in `root` into `dest`,
+/// the same as its `install.sh` does, which cannot be run on Windows.
+///
+/// A directory without the `components` file is treated as an installed toolchain,
+/// which is copied as is.
+fn install_components(root: &Path, dest: &Path) -> Result<()> {
+    let components = root.join("components");
+    if !components.is_file() {
+        return utils::copy_as(root, dest);
+    }
+    for component in utils::read_to_string(&components)?.lines() {
+        let component = component.trim();
+        if !component.is_empty() {
+            merge_dir(&root.join(component), dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy the files under `src` into `dest` recursively, except the `manifest.in` of components.
+fn merge_dir(src: &Path, dest: &Path) -> Result<()> {
+    utils::ensure_dir(dest)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            merge_dir(&entry.path(), &target)?;
+        } else if entry.file_name() != "manifest.in" {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Find a working `rustup` in `PATH` that was not installed under `install_dir`,
+/// such as the one installed by the official `rustup-init`.
+pub(crate) fn find_existing_rustup(install_dir: &Path) -> Option<PathBuf> {
+    let rustup = utils::find_in_path(RUSTUP)?;
+    if rustup.starts_with(install_dir) {
+        return None;
+    }
+    utils::execute_for_output(&rustup, &["--version"])
+        .is_ok()
+        .then_some(rustup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install_toolchain_components() {
+        let extracted = tempfile::tempdir().unwrap();
+        let root = extracted
+            .path()
+            .join("rust-1.80.0-vendor-x86_64-unknown-linux-gnu");
+        for (file, content) in [
+            ("components", "rustc\ncargo\n"),
+            ("rustc/manifest.in", "file:bin/rustc"),
+            ("rustc/bin/rustc", "rustc"),
+            ("cargo/manifest.in", "file:bin/cargo"),
+            ("cargo/bin/cargo", "cargo"),
+            ("cargo/share/doc/cargo/README.md", "readme"),
+        ] {
+            let path = root.join(file);
+            utils::ensure_parent_dir(&path).unwrap();
+            utils::write_file(&path, content, false).unwrap();
+        }
+
+        assert_eq!(toolchain_root(extracted.path()).unwrap(), root);
+        let dest = tempfile::tempdir().unwrap();
+        let dest = dest.path().join("vendor");
+        install_components(&root, &dest).unwrap();
+        assert!(dest.join("bin").join("rustc").is_file());
+        assert!(dest.join("bin").join("cargo").is_file());
+        assert!(dest.join("share/doc/cargo/README.md").is_file());
+        assert!(!dest.join("manifest.in").exists());
+        assert!(!dest.join("components").exists());
+    }
+
+    #[test]
+    fn find_installed_toolchains() {
+        let installed = [
+            "stable-x86_64-unknown-linux-gnu".to_string(),
+            "1.80.0-x86_64-unknown-linux-gnu".to_string(),
+            "vendor".to_string(),
+        ];
+        assert!(is_toolchain_installed(&installed, "stable"));
+        assert!(is_toolchain_installed(&installed, "1.80.0"));
+        assert!(is_toolchain_installed(&installed, "vendor"));
+        assert!(!is_toolchain_installed(&installed, "1.80"));
+        assert!(!is_toolchain_installed(&installed, "1.81.0"));
+        assert!(!is_toolchain_installed(&installed, "vendor-2"));
+        assert!(!is_toolchain_installed(&[], "stable"));
+    }
+}
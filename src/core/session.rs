@@ -0,0 +1,296 @@
+//! An installation broken down into a list of steps, which can be run one by one,
+//! so that a frontend can show the real progress of each step, or retry a failed one.
+
+use std::borrow::Cow;
+
+use anyhow::Result;
+
+use super::install::{EnvConfig, InstallConfiguration};
+use super::parser::manifest::{ToolMap, ToolsetManifest};
+use crate::utils::{self, MultiThreadProgress, ProgressEvent};
+
+/// A step of an [`InstallSession`], listed in the order they should be run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionStep {
+    /// Configure the persistent environment variables, such as `CARGO_HOME`.
+    ConfigEnv,
+    /// Write the `config.toml` of `cargo`, such as the registries,
+    /// install the CA certificate in manifest if there is one, then configure `git`.
+    ConfigCargo,
+    /// Install the tools that don't require `cargo install`.
+    InstallTools,
+    /// Install `rustup` and the Rust toolchain.
+    InstallRust,
+    /// Install the tools using `cargo install`.
+    CargoInstall,
+}
+
+impl SessionStep {
+    /// All the steps in the order they should be run.
+    pub const ALL: &'static [SessionStep] = &[
+        Self::ConfigEnv,
+        Self::ConfigCargo,
+        Self::InstallTools,
+        Self::InstallRust,
+        Self::CargoInstall,
+    ];
+
+    /// A short name of this step, such as `install-rust`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ConfigEnv => "config-env",
+            Self::ConfigCargo => "config-cargo",
+            Self::InstallTools => "install-tools",
+            Self::InstallRust => "install-rust",
+            Self::CargoInstall => "cargo-install",
+        }
+    }
+
+    /// Whether this step can be skipped once it was completed before the installation was
+    /// [suspended](InstallConfiguration::suspend).
+    ///
+    /// The configuration steps are cheap, and they also set up the environment of this process,
+    /// thus they are always run again.
+    pub fn is_resumable(&self) -> bool {
+        !matches!(self, Self::ConfigEnv | Self::ConfigCargo)
+    }
+
+    /// The percentage this step takes in the whole installation,
+    /// the weights of all steps add up to `100`.
+    pub fn weight(&self) -> usize {
+        match self {
+            Self::ConfigEnv | Self::ConfigCargo => 5,
+            Self::InstallTools | Self::InstallRust | Self::CargoInstall => 30,
+        }
+    }
+}
+
+/// An installation that runs the [`SessionStep`]s using an [`InstallConfiguration`].
+///
+/// The configuration is borrowed, so it can still be used to
+/// [roll back](InstallConfiguration::rollback) or finish the installation afterward.
+pub struct InstallSession<'a> {
+    config: &'a mut InstallConfiguration,
+    manifest: &'a ToolsetManifest,
+    /// The tools to install, default to the [applicable ones](ToolsetManifest::tools_of_groups)
+    /// for current target in the manifest, which are resolved only once before the first step.
+    tools: Option<Cow<'a, ToolMap>>,
+    /// The toolchain profile to use instead of the one in manifest.
+    profile: Option<String>,
+    /// The optional toolchain components to install.
+    optional_components: Option<Vec<String>>,
+    /// Whether the [fingerprint](Self::fingerprint) was checked against the suspended
+    /// installation being resumed, which happens before running the first step.
+    fingerprint_checked: bool,
+}
+
+impl<'a> InstallSession<'a> {
+    pub fn new(config: &'a mut InstallConfiguration, manifest: &'a ToolsetManifest) -> Self {
+        Self {
+            config,
+            manifest,
+            tools: None,
+            profile: None,
+            optional_components: None,
+            fingerprint_checked: false,
+        }
+    }
+
+    /// Install the given set of tools instead of every tool of current target.
+    pub fn tools(mut self, tools: &'a ToolMap) -> Self {
+        self.tools = Some(Cow::Borrowed(tools));
+        self
+    }
+
+    /// Install the Rust toolchain with the given profile instead of the one in manifest.
+    pub fn profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Install the given optional components along with the Rust toolchain.
+    pub fn optional_components(mut self, components: Option<Vec<String>>) -> Self {
+        self.optional_components = components;
+        self
+    }
+
+    pub fn config(&self) -> &InstallConfiguration {
+        self.config
+    }
+
+    /// Identify what this session installs, which are the toolset manifest, the version of
+    /// this program, and the selected tools and components.
+    ///
+    /// It must be the same for the same installation resumed in another process,
+    /// thus only built from the values that are always printed in the same order.
+    fn fingerprint(&self) -> String {
+        let mut tools = self
+            .tools
+            .iter()
+            .flat_map(|tools| tools.keys())
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        tools.sort_unstable();
+        let components = self.optional_components.as_ref().map(|components| {
+            let mut components = components.clone();
+            components.sort_unstable();
+            components
+        });
+        utils::sha256_str(&format!(
+            "{}\n{:?}\n{tools:?}\n{:?}\n{components:?}",
+            env!("CARGO_PKG_VERSION"),
+            self.manifest,
+            self.profile,
+        ))
+    }
+
+    /// Get the list of steps to run, check [`SessionStep::ALL`].
+    pub fn steps(&self) -> &'static [SessionStep] {
+        SessionStep::ALL
+    }
+
+    /// Run a single step, which reports a [`ProgressEvent::PhaseStarted`] with its
+    /// [name](SessionStep::name) first, then the progress of its [weight](SessionStep::weight)
+    /// through `progress` once it's done.
+    ///
+    /// Note that the steps depend on each other, such as [`SessionStep::CargoInstall`]
+    /// requires [`SessionStep::InstallRust`] to be done first.
+    ///
+    /// When [resuming](InstallConfiguration::is_resuming) a suspended installation,
+    /// the [resumable](SessionStep::is_resumable) steps that were completed are skipped,
+    /// unless that installation was started with another toolset or selection of components,
+    /// in which case it starts over.
+    pub fn run_step(
+        &mut self,
+        step: SessionStep,
+        progress: &mut MultiThreadProgress,
+    ) -> Result<()> {
+        if self.tools.is_none() {
+            self.tools = Some(Cow::Owned(self.manifest.tools_of_groups(&[])?));
+        }
+        if !self.fingerprint_checked {
+            let fingerprint = self.fingerprint();
+            self.config.check_session_fingerprint(&fingerprint)?;
+            self.fingerprint_checked = true;
+        }
+        progress.report(ProgressEvent::PhaseStarted {
+            name: step.name().to_string(),
+        })?;
+        progress.val = step.weight();
+        if step.is_resumable() && self.config.is_step_completed(step.name()) {
+            progress.report(ProgressEvent::Message {
+                text: format!("skipping '{}', which was done before", step.name()),
+            })?;
+            if step == SessionStep::InstallRust {
+                self.config.reuse_installed_rust()?;
+            }
+            return progress.send_progress();
+        }
+        self.run_step_(step, progress)?;
+        self.config.complete_step(step.name())
+    }
+
+    fn run_step_(&mut self, step: SessionStep, progress: &mut MultiThreadProgress) -> Result<()> {
+        match step {
+            SessionStep::ConfigEnv => {
+                self.config.config_env_vars(self.manifest)?;
+                progress.send_progress()
+            }
+            SessionStep::ConfigCargo => {
+                self.config.config_cargo(self.manifest)?;
+                self.config.config_git(self.manifest)?;
+                progress.send_progress()
+            }
+            SessionStep::InstallTools => self.config.install_set_of_tools(
+                self.tools.iter().flat_map(|tools| tools.iter()),
+                progress,
+                self.manifest.proxy_or_env().as_ref(),
+            ),
+            SessionStep::InstallRust => self.config.install_rust_with_optional_components(
+                self.manifest,
+                self.profile.as_deref(),
+                self.optional_components.as_deref(),
+                progress,
+            ),
+            SessionStep::CargoInstall => self.config.cargo_install_set_of_tools(
+                self.tools.iter().flat_map(|tools| tools.iter()),
+                progress,
+            ),
+        }
+    }
+
+    /// Run all the steps in order, stop at the first failure,
+    /// then report [`ProgressEvent::Finished`] if every step succeeded.
+    pub fn run_all(&mut self, progress: &mut MultiThreadProgress) -> Result<()> {
+        for step in self.steps() {
+            self.run_step(*step, progress)?;
+        }
+        progress.report(ProgressEvent::Finished)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InstallSession, SessionStep};
+    use crate::core::install::InstallConfiguration;
+    use crate::core::parser::ManifestParser;
+    use crate::manifest::{ToolMap, ToolsetManifest};
+    use crate::utils::MultiThreadProgress;
+
+    #[test]
+    fn suspend_then_resume() {
+        // Each run parses the manifest again, like another process does when resuming,
+        // the tool groups shouldn't make it a different installation.
+        let input = r#"
+[rust]
+version = "1.0.0"
+
+[tools.group]
+"Some Group" = ["t1", "t2", "t3"]
+Others = ["t4", "t5", "t6"]
+"#;
+        let parse = || ToolsetManifest::from_str(input).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = parse();
+        let tools = ToolMap::new();
+        let mut progress = MultiThreadProgress::default();
+
+        let mut config = InstallConfiguration::open(dir.path()).unwrap();
+        assert!(!config.is_resuming());
+        InstallSession::new(&mut config, &manifest)
+            .tools(&tools)
+            .run_step(SessionStep::InstallTools, &mut progress)
+            .unwrap();
+        config.suspend().unwrap();
+
+        // The same installation skips the steps done before it was suspended.
+        let manifest = parse();
+        let mut config = InstallConfiguration::open(dir.path()).unwrap();
+        assert!(config.is_resuming());
+        let mut session = InstallSession::new(&mut config, &manifest).tools(&tools);
+        session
+            .run_step(SessionStep::InstallTools, &mut progress)
+            .unwrap();
+        assert!(session.config().is_step_completed("install-tools"));
+        config.suspend().unwrap();
+
+        // Another selection of components starts over.
+        let manifest = parse();
+        let mut config = InstallConfiguration::open(dir.path()).unwrap();
+        assert!(config.is_resuming());
+        let mut session = InstallSession::new(&mut config, &manifest)
+            .tools(&tools)
+            .profile(Some("minimal".into()));
+        session
+            .run_step(SessionStep::InstallTools, &mut progress)
+            .unwrap();
+        assert!(!session.config().is_resuming());
+        assert!(!session.config().is_step_completed("install-tools"));
+    }
+
+    #[test]
+    fn step_weights_add_up() {
+        let total: usize = SessionStep::ALL.iter().map(SessionStep::weight).sum();
+        assert_eq!(total, 100);
+    }
+}
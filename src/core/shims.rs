@@ -0,0 +1,231 @@
+//! Shims in `cargo`'s `bin` directory that run the real executables of the tools under
+//! `tools` directory, so that the tools don't need their own `PATH` entries,
+//! and switching the version of a tool only needs to replace its shims.
+//!
+//! On Unix, a shim is simply a symbolic link to the executable. On Windows, where creating
+//! symbolic links requires privilege, and an executable copied elsewhere can't find its DLLs,
+//! a shim is a copy (or hard link) of this program named after the executable,
+//! along with a `<name>.shim` file next to it containing the path to the executable.
+//! Once such copy is run, it runs the executable with the same arguments instead,
+//! check [`run_if_shim`].
+//!
+//! Set `MANAGER_SHIM_LOG` environment variable to a file path to log every run of the
+//! Windows shims into it, as tab separated seconds since the Unix epoch, executable path
+//! and arguments, which helps finding out which tools are actually used.
+
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::utils;
+
+/// The extension of the file containing the path to the executable of a shim.
+pub(crate) const EXTENSION: &str = "shim";
+
+/// Environment variable of the file to log the runs of shims into.
+const SHIM_LOG: &str = "MANAGER_SHIM_LOG";
+
+/// Create a shim of the `exe` into `cargo_bin`, replacing the existing one,
+/// then return the path of the shim.
+#[cfg(unix)]
+pub(crate) fn create(exe: &Path, cargo_bin: &Path) -> Result<PathBuf> {
+    utils::symlink_or_copy_file_to(exe, cargo_bin)
+}
+
+/// Create a shim of the `exe` into `cargo_bin`, replacing the existing one,
+/// then return the path of the shim.
+#[cfg(windows)]
+pub(crate) fn create(exe: &Path, cargo_bin: &Path) -> Result<PathBuf> {
+    let name = exe
+        .file_name()
+        .ok_or_else(|| anyhow!("path '{}' does not have a file name", exe.display()))?;
+    let shim = cargo_bin.join(name);
+    remove(&shim)?;
+    // Shims are copies of the manager installed into `cargo_bin`, which stays after installation,
+    // unlike this program, which might be an installer that is removed later.
+    let manager = super::install::manager_exe(cargo_bin)?;
+    if !manager.is_file() {
+        anyhow::bail!(
+            "unable to create shim of '{}', because the manager is not installed in '{}'",
+            exe.display(),
+            cargo_bin.display()
+        );
+    }
+    if fs::hard_link(&manager, &shim).is_err() {
+        utils::copy_as(&manager, &shim)?;
+    }
+    utils::write_file(
+        shim.with_extension(EXTENSION),
+        &exe.to_string_lossy(),
+        false,
+    )?;
+    Ok(shim)
+}
+
+fn executables(bin_dir: &Path) -> Vec<PathBuf> {
+    utils::walk_dir(bin_dir, false)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|path| utils::is_executable(path))
+        .collect()
+}
+
+/// Create the shims of the executables in `bin_dir` into `cargo_bin`,
+/// then return the paths of the shims.
+pub(crate) fn create_all(bin_dir: &Path, cargo_bin: &Path) -> Result<Vec<PathBuf>> {
+    executables(bin_dir)
+        .iter()
+        .map(|exe| create(exe, cargo_bin))
+        .collect()
+}
+
+/// Get the paths of the shims in `cargo_bin` of the executables in `bin_dir`,
+/// which are created by [`create_all`].
+pub(crate) fn paths_of(bin_dir: &Path, cargo_bin: &Path) -> Vec<PathBuf> {
+    executables(bin_dir)
+        .iter()
+        .filter_map(|exe| exe.file_name())
+        .map(|name| cargo_bin.join(name))
+        .collect()
+}
+
+/// Remove the shims among the `previous` paths of a tool that are not in its current `paths`,
+/// such as the ones of the executables that no longer exist in the new version.
+pub(crate) fn remove_stale(previous: &[PathBuf], paths: &[PathBuf], cargo_bin: &Path) {
+    for shim in previous
+        .iter()
+        .filter(|path| path.parent() == Some(cargo_bin) && !paths.contains(*path))
+    {
+        let _ = remove(shim);
+    }
+}
+
+/// Remove a shim created by [`create`], do nothing if it does not exist.
+pub(crate) fn remove(shim: &Path) -> Result<()> {
+    utils::remove(shim)?;
+    let config = shim.with_extension(EXTENSION);
+    if config.is_file() {
+        utils::remove(config)?;
+    }
+    Ok(())
+}
+
+/// Check if `path` is the file containing the path to the executable of a shim.
+pub(crate) fn is_shim_config(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == EXTENSION) && path.is_file()
+}
+
+/// Get the path to the executable run by a shim.
+pub(crate) fn target_of(shim: &Path) -> Option<PathBuf> {
+    let config = shim.with_extension(EXTENSION);
+    if config.is_file() {
+        let target = utils::read_to_string(config).ok()?;
+        Some(PathBuf::from(target.trim()))
+    } else {
+        fs::read_link(shim).ok()
+    }
+}
+
+/// If the current executable is a shim, run its executable with the same arguments,
+/// then exit with the exit code of it, otherwise do nothing.
+///
+/// This needs to be called before anything else, since the shim is a copy of this program.
+pub fn run_if_shim() {
+    let Ok(this) = std::env::current_exe() else {
+        return;
+    };
+    if !this.with_extension(EXTENSION).is_file() {
+        return;
+    }
+    match run_shim(&this) {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("error: {e:?}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_shim(shim: &Path) -> Result<i32> {
+    let exe = target_of(shim)
+        .ok_or_else(|| anyhow!("unable to read the shim file of '{}'", shim.display()))?;
+    let args = std::env::args_os().skip(1).collect::<Vec<_>>();
+    if let Some(log) = std::env::var_os(SHIM_LOG) {
+        // Failing to log should not stop the tool from running.
+        let _ = log_run(Path::new(&log), &exe, &args);
+    }
+    ignore_ctrl_c();
+
+    let status = Command::new(&exe)
+        .args(&args)
+        .status()
+        .with_context(|| format!("unable to run '{}'", exe.display()))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+fn log_run(log: &Path, exe: &Path, args: &[OsString]) -> Result<()> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let args = args
+        .iter()
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut file = OpenOptions::new().create(true).append(true).open(log)?;
+    writeln!(file, "{now}\t{}\t{args}", exe.display())?;
+    Ok(())
+}
+
+/// Let the executable handle `Ctrl-C` by itself, while the shim keeps waiting for it to exit.
+fn ignore_ctrl_c() {
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::Foundation::{BOOL, FALSE, TRUE};
+        use windows_sys::Win32::System::Console::{
+            SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT,
+        };
+
+        unsafe extern "system" fn handler(ctrl_type: u32) -> BOOL {
+            if ctrl_type == CTRL_C_EVENT || ctrl_type == CTRL_BREAK_EVENT {
+                TRUE
+            } else {
+                FALSE
+            }
+        }
+        // NB: Unlike passing no handler, ignoring it in a handler is not inherited by the child.
+        // SAFETY: the handler does nothing but returning.
+        unsafe { SetConsoleCtrlHandler(Some(handler), TRUE) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_and_remove_shim() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin_dir = dir.path().join("tools").join("foo").join("bin");
+        let cargo_bin = dir.path().join(".cargo").join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        fs::create_dir_all(&cargo_bin).unwrap();
+        let exe = bin_dir.join(format!("foo{}", utils::EXE_EXT));
+        fs::write(&exe, "").unwrap();
+
+        let shim = create(&exe, &cargo_bin).unwrap();
+        assert_eq!(shim, cargo_bin.join(format!("foo{}", utils::EXE_EXT)));
+        assert_eq!(target_of(&shim).unwrap(), exe);
+        assert!(!is_shim_config(&shim));
+
+        remove(&shim).unwrap();
+        assert!(utils::walk_dir(&cargo_bin, false).unwrap().is_empty());
+        assert!(exe.is_file());
+    }
+}
@@ -0,0 +1,455 @@
+//! Packing an existing installation into a single archive (a.k.a. an exported bundle),
+//! which can then be installed with `--from-bundle` on other machines of the same target,
+//! so that one machine can provision many air-gapped ones.
+//!
+//! The archive is a `.tar.gz` of the installation directory, with the following layout:
+//!
+//! ```text
+//! <archive>
+//! ├── toolset_manifest.toml   (generated, describing the toolchain and tools in this bundle)
+//! ├── .fingerprint.toml       (the installation record of the exporting machine)
+//! ├── .rustup/
+//! ├── .cargo/
+//! │   ├── bin/
+//! │   ├── registry/           (the index and the downloaded crates, without the extracted sources)
+//! │   └── config.toml
+//! ├── toolchains/             (the linked toolchains, if there are any)
+//! ├── checksums/              (the checksum manifests of tools)
+//! └── tools/
+//! ```
+//!
+//! The temporary files, logs, download cache and cargo credentials are not packaged.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use tracing::warn;
+
+use super::install::{print_plan, EnvConfig, InstallConfiguration};
+use super::lock;
+use super::offline::MANIFEST_FILENAME;
+use super::parser::fingerprint::{self, InstallStep, InstallationRecord, ToolRecord, ToolSource};
+use super::parser::manifest::ToolsetManifest;
+use super::parser::ManifestParser;
+use super::rustup::RUSTUP;
+use super::shims;
+use super::store;
+use crate::utils::{self, Extractable, OutputEvent};
+
+/// Paths under the installation directory that are not packaged, relative to it.
+const EXCLUDED: &[&str] = &[
+    "temp",
+    "logs",
+    "downloads",
+    "cache",
+    "gitconfig",
+    store::STORE_DIR,
+    lock::FILENAME,
+    ".cargo/registry/src",
+    ".cargo/git/checkouts",
+    ".cargo/credentials",
+    ".cargo/credentials.toml",
+];
+
+/// Pack the installation under `install_dir` into a `.tar.gz` archive at `dest`.
+///
+/// The tools installed by the system package manager, or outside of `install_dir`,
+/// cannot be packaged, they are reported as warnings and left out of the bundle.
+pub(crate) fn export_bundle(install_dir: &Path, dest: &Path) -> Result<()> {
+    if !dest.to_string_lossy().ends_with(".tar.gz") {
+        bail!("the bundle '{}' must be a `.tar.gz` file", dest.display());
+    }
+    let dest = utils::to_nomalized_abspath(dest, None)?;
+    if dest.starts_with(install_dir) {
+        bail!(
+            "the bundle cannot be written into the installation directory '{}'",
+            install_dir.display()
+        );
+    }
+    let record = InstallationRecord::load_or_new(install_dir)?;
+    if record.rust.is_none() {
+        bail!(
+            "there's no Rust toolchain installed in '{}'",
+            install_dir.display()
+        );
+    }
+    if let Some(rustup) = &record.external_rustup {
+        bail!(
+            "the Rust toolchain was installed by the existing rustup at '{}', \
+            which cannot be exported",
+            rustup.display()
+        );
+    }
+    for (name, tool) in &record.tools {
+        if !is_packaged(install_dir, tool) {
+            warn!("'{name}' was not installed under the installation directory, skipping");
+        }
+    }
+
+    OutputEvent::Message {
+        text: &format!(
+            "exporting '{}' to '{}'",
+            install_dir.display(),
+            dest.display()
+        ),
+    }
+    .emit();
+    let manifest = offline_manifest(install_dir, &record)?;
+    utils::ensure_parent_dir(&dest)?;
+    let file = std::fs::File::create(&dest)
+        .with_context(|| format!("unable to create '{}'", dest.display()))?;
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        file,
+        flate2::Compression::default(),
+    ));
+    // Keep the links (such as the ones of `rustup` proxies) as is.
+    builder.follow_symlinks(false);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_FILENAME, manifest.as_bytes())?;
+    append_dir(&mut builder, install_dir, install_dir)?;
+    builder.into_inner()?.finish()?;
+
+    OutputEvent::Message {
+        text: &format!("bundle exported to '{}'", dest.display()),
+    }
+    .emit();
+    Ok(())
+}
+
+/// Add the entries under `dir` into the archive recursively,
+/// naming them by their paths relative to `root`, except the [`EXCLUDED`] ones.
+fn append_dir<W: Write>(builder: &mut tar::Builder<W>, root: &Path, dir: &Path) -> Result<()> {
+    for path in utils::walk_dir(dir, false)? {
+        utils::check_cancelled()?;
+        let rel = path.strip_prefix(root)?;
+        if EXCLUDED.iter().any(|excluded| rel == Path::new(excluded)) {
+            continue;
+        }
+        if path.is_dir() && !utils::is_symlink(&path) {
+            builder.append_dir(rel, &path)?;
+            append_dir(builder, root, &path)?;
+        } else {
+            builder
+                .append_path_with_name(&path, rel)
+                .with_context(|| format!("unable to pack '{}'", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Check whether the files of a tool are all under `install_dir`, thus can be packaged.
+fn is_packaged(install_dir: &Path, tool: &ToolRecord) -> bool {
+    tool.source != Some(ToolSource::System)
+        && tool.paths.iter().all(|path| path.starts_with(install_dir))
+}
+
+/// Generate a toolset manifest describing the toolchain and tools in the bundle,
+/// the tools are listed as `path` entries relative to the bundle.
+///
+/// The tools installed using `cargo install` are not listed,
+/// because they are packaged along with `cargo`'s `bin` directory.
+fn offline_manifest(install_dir: &Path, record: &InstallationRecord) -> Result<String> {
+    let mut manifest = toml::Table::new();
+    if let Some(rust) = &record.rust {
+        let mut table = toml::Table::new();
+        table.insert("version".into(), rust.version.clone().into());
+        table.insert(
+            "components".into(),
+            toml::Value::try_from(&rust.components)?,
+        );
+        if !rust.targets.is_empty() {
+            table.insert("targets".into(), toml::Value::try_from(&rust.targets)?);
+        }
+        manifest.insert("rust".into(), table.into());
+    }
+
+    let mut tools = toml::Table::new();
+    for (name, tool) in &record.tools {
+        if !is_packaged(install_dir, tool) {
+            continue;
+        }
+        let Some(path) = tool.paths.first() else {
+            continue;
+        };
+        let mut info = toml::Table::new();
+        let rel = path.strip_prefix(install_dir)?;
+        // Use `/` as separator, so that the manifest is the same on every platform.
+        info.insert(
+            "path".into(),
+            rel.to_string_lossy().replace('\\', "/").into(),
+        );
+        if let Some(version) = &tool.version {
+            info.insert("version".into(), version.clone().into());
+        }
+        tools.insert(name.clone(), info.into());
+    }
+    if !tools.is_empty() {
+        let target = toml::Table::from_iter([(utils::host_triple().to_string(), tools.into())]);
+        let tools = toml::Table::from_iter([("target".to_string(), target.into())]);
+        manifest.insert("tools".into(), tools.into());
+    }
+    Ok(toml::to_string(&manifest)?)
+}
+
+/// Install everything in a bundle created by [`export_bundle`] into the installation directory
+/// of `config`, then configure the environment for it.
+///
+/// Nothing is downloaded, the files are moved into place, and the paths in the installation
+/// record (and the cargo configuration) are rewritten from the ones of the exporting machine.
+pub(crate) fn install_from_bundle(config: &mut InstallConfiguration, archive: &Path) -> Result<()> {
+    if !archive.is_file() {
+        bail!("the bundle '{}' does not exist", archive.display());
+    }
+    if config.is_dry_run() {
+        print_plan(&format!(
+            "extract '{}' into '{}'",
+            archive.display(),
+            config.install_dir.display()
+        ));
+        config.print_env_vars_plan(&ToolsetManifest::default());
+        return config.reuse_installed_rust();
+    }
+
+    let temp_dir = config.create_temp_dir("bundle")?;
+    OutputEvent::Message {
+        text: &format!("extracting '{}'", archive.display()),
+    }
+    .emit();
+    Extractable::try_from(archive)?.extract_to(temp_dir.path())?;
+    let extracted = temp_dir.path();
+    let manifest_path = extracted.join(MANIFEST_FILENAME);
+    let record_path = InstallationRecord::path_under(extracted);
+    if !manifest_path.is_file() || !record_path.is_file() {
+        bail!(
+            "'{}' is not a bundle exported by `manager export-bundle`",
+            archive.display()
+        );
+    }
+    check_host(extracted)?;
+    let manifest = ToolsetManifest::from_str(&utils::read_to_string(&manifest_path)?)?;
+    let bundled = InstallationRecord::load(&record_path)?;
+
+    for entry in utils::walk_dir(extracted, false)? {
+        let name = entry.file_name().unwrap_or_default();
+        if name != MANIFEST_FILENAME && name != fingerprint::FILENAME {
+            move_into(config, &entry, &config.install_dir.join(name))?;
+        }
+    }
+
+    let from = bundled.install_dir.as_path();
+    let to = config.install_dir.clone();
+    InstallationRecord::update(&to, |record| {
+        if let Some(rust) = bundled.rust {
+            record.rust = Some(rust);
+            record.add_step(InstallStep::Rust);
+        }
+        for (name, mut tool) in bundled.tools {
+            let paths = tool
+                .paths
+                .iter()
+                .map(|path| relocate(path, from, &to))
+                .collect::<Option<Vec<_>>>();
+            let Some(paths) = paths.filter(|_| tool.source != Some(ToolSource::System)) else {
+                warn!("'{name}' was not installed under the installation directory, skipping");
+                continue;
+            };
+            tool.paths = paths;
+            record.add_tool_record(&name, tool);
+            record.add_step(InstallStep::Tool { name });
+        }
+        record.add_cargo_config_keys(bundled.cargo_config_keys);
+        record.backup_cargo_config_values(bundled.cargo_config_backup);
+    })?;
+    relocate_shims(config, from)?;
+    relocate_cargo_config(config, from)?;
+
+    config.config_env_vars(&manifest)?;
+    config.reuse_installed_rust()?;
+    for path in bundled.paths.iter().filter_map(|p| relocate(p, from, &to)) {
+        config.add_to_path(&path)?;
+    }
+    relink_toolchains(config)
+}
+
+/// Check if the toolchain in the extracted bundle can be run on this machine,
+/// according to the `default_host_triple` in its `rustup` settings.
+fn check_host(extracted: &Path) -> Result<()> {
+    let settings = extracted.join(".rustup").join("settings.toml");
+    if !settings.is_file() {
+        return Ok(());
+    }
+    let settings: toml::Table = toml::from_str(&utils::read_to_string(&settings)?)?;
+    let Some(host) = settings.get("default_host_triple").and_then(|v| v.as_str()) else {
+        return Ok(());
+    };
+    if host != utils::host_triple() && host != utils::native_triple() {
+        bail!(
+            "the bundle was exported for '{host}', which cannot be installed on '{}'",
+            utils::native_triple()
+        );
+    }
+    Ok(())
+}
+
+/// Move the entry at `src` to `dest`, recording it so that it can be rolled back.
+///
+/// If `dest` is an existing directory, the entries of `src` are moved into it recursively,
+/// and the existing files are kept, such as the manager copied by this installer.
+fn move_into(config: &InstallConfiguration, src: &Path, dest: &Path) -> Result<()> {
+    if !dest.exists() && !utils::is_symlink(dest) {
+        config.record_step(InstallStep::CreateDir {
+            path: dest.to_path_buf(),
+        })?;
+        return utils::move_to(src, dest, false);
+    }
+    if dest.is_dir() && src.is_dir() && !utils::is_symlink(src) {
+        for entry in utils::walk_dir(src, false)? {
+            let name = entry.file_name().unwrap_or_default();
+            move_into(config, &entry, &dest.join(name))?;
+        }
+    } else {
+        tracing::debug!("keeping the existing '{}'", dest.display());
+    }
+    Ok(())
+}
+
+/// Replace the `from` prefix of `path` with `to`,
+/// return `None` if `path` is not under `from`.
+fn relocate(path: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+    path.strip_prefix(from).ok().map(|rel| to.join(rel))
+}
+
+/// Re-create the shims of the tools, which still run the executables
+/// under the installation directory of the exporting machine.
+fn relocate_shims(config: &InstallConfiguration, from: &Path) -> Result<()> {
+    let record = InstallationRecord::load_or_new(&config.install_dir)?;
+    let cargo_bin = config.cargo_bin();
+    for path in record
+        .tools
+        .values()
+        .flat_map(|tool| &tool.paths)
+        .filter(|path| path.parent() == Some(cargo_bin))
+    {
+        let target = shims::target_of(path).and_then(|t| relocate(&t, from, &config.install_dir));
+        if let Some(target) = target {
+            shims::create(&target, cargo_bin)?;
+        }
+    }
+    Ok(())
+}
+
+/// Rewrite the paths under the installation directory of the exporting machine
+/// in the cargo configuration, such as `http.cainfo`.
+fn relocate_cargo_config(config: &InstallConfiguration, from: &Path) -> Result<()> {
+    let path = config.cargo_home().join("config.toml");
+    if !path.is_file() {
+        return Ok(());
+    }
+    let from = from.to_string_lossy();
+    let to = config.install_dir.to_string_lossy();
+    let content = utils::read_to_string(&path)?;
+    // The backslashes of Windows paths are escaped in basic strings.
+    let relocated = content
+        .replace(&*from, &to)
+        .replace(&from.replace('\\', r"\\"), &to.replace('\\', r"\\"));
+    if relocated != content {
+        utils::write_file(&path, &relocated, false)?;
+    }
+    Ok(())
+}
+
+/// Link the toolchains under `<install_dir>/toolchains` again,
+/// because the links in the bundle point to the exporting machine.
+fn relink_toolchains(config: &InstallConfiguration) -> Result<()> {
+    let dir = config.install_dir.join("toolchains");
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let rustup = config.cargo_bin().join(RUSTUP);
+    for toolchain in utils::walk_dir(&dir, false)? {
+        let Some(name) = toolchain.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let path = utils::stringify_path(&toolchain)?;
+        utils::remove(config.rustup_home().join("toolchains").join(name))?;
+        utils::execute(&rustup, &["toolchain", "link", name, path.as_str()])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::parser::fingerprint::RustRecord;
+    use crate::core::parser::manifest::ToolInfo;
+
+    #[test]
+    fn generate_offline_manifest() {
+        let install_dir = Path::new("/opt/rust");
+        let mut record = InstallationRecord {
+            install_dir: install_dir.to_path_buf(),
+            rust: Some(RustRecord {
+                version: "1.80.0".into(),
+                components: vec!["clippy".into()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        record.add_tool_record(
+            "mingw64",
+            ToolRecord {
+                version: Some("14.2.0".into()),
+                paths: vec![install_dir.join("tools").join("mingw64")],
+                ..Default::default()
+            },
+        );
+        record.add_tool_record(
+            "cargo-nextest",
+            ToolRecord {
+                source: Some(ToolSource::Cargo),
+                ..Default::default()
+            },
+        );
+        record.add_tool_record(
+            "gcc",
+            ToolRecord {
+                source: Some(ToolSource::System),
+                ..Default::default()
+            },
+        );
+        record.add_tool_record(
+            "elsewhere",
+            ToolRecord {
+                paths: vec![PathBuf::from("/usr/local/elsewhere")],
+                ..Default::default()
+            },
+        );
+
+        let manifest = offline_manifest(install_dir, &record).unwrap();
+        let parsed = ToolsetManifest::from_str(&manifest).unwrap();
+        assert_eq!(parsed.rust.version, "1.80.0");
+        assert_eq!(parsed.rust.components, ["clippy"]);
+        let tools = parsed.current_target_tools().unwrap();
+        assert_eq!(tools.keys().collect::<Vec<_>>(), ["mingw64"]);
+        assert!(matches!(
+            &tools["mingw64"],
+            ToolInfo::Path { path, version: Some(ver), .. }
+                if path == Path::new("tools/mingw64") && ver == "14.2.0"
+        ));
+    }
+
+    #[test]
+    fn relocate_paths() {
+        let from = Path::new("/opt/rust");
+        let to = Path::new("/home/user/rust");
+        assert_eq!(
+            relocate(&from.join("tools").join("foo"), from, to),
+            Some(to.join("tools").join("foo"))
+        );
+        assert_eq!(relocate(Path::new("/usr/bin/foo"), from, to), None);
+    }
+}
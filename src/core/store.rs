@@ -0,0 +1,216 @@
+//! A content-addressed store of the installed files of tools, located at `<install_dir>/store`.
+//!
+//! Each file under the `tools` directory is kept in the store as `objects/<xx>/<sha256>`,
+//! where `<xx>` is the first two characters of its SHA-256 checksum, and the installed file
+//! is replaced with a hard link to that object. So the identical files shared by different tools,
+//! or by the old and new versions of a tool during an update, only take up the disk space once.
+//!
+//! On file systems without hard link support, the objects are copies of the installed files,
+//! which are left as they are. Note that a tool modifying its installed files in place modifies
+//! the shared objects as well, which can be found by `manager verify`, and such an object is
+//! replaced once another file with its original content is added.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use tracing::debug;
+
+use super::parser::fingerprint::InstallationRecord;
+use super::verify::read_checksums;
+use crate::utils;
+
+/// The name of the store directory under `install_dir`.
+pub(crate) const STORE_DIR: &str = "store";
+
+#[derive(Debug)]
+pub(crate) struct Store {
+    root: PathBuf,
+}
+
+impl Store {
+    /// Get the store of the installation under `install_dir`,
+    /// which is created once the first file is added into it.
+    pub(crate) fn new(install_dir: &Path) -> Self {
+        Self {
+            root: install_dir.join(STORE_DIR),
+        }
+    }
+
+    fn objects_dir(&self) -> PathBuf {
+        self.root.join("objects")
+    }
+
+    fn object_path(&self, checksum: &str) -> PathBuf {
+        let checksum = checksum.to_lowercase();
+        self.objects_dir()
+            .join(checksum.get(..2).unwrap_or_default())
+            .join(checksum)
+    }
+
+    /// Deduplicate the given files, keyed by their SHA-256 checksums, against the store.
+    ///
+    /// A file is added into the store if there isn't an identical one yet,
+    /// otherwise it's replaced with a hard link to the stored one.
+    /// Failures are not fatal, since the files are still usable as they are.
+    pub(crate) fn dedup<'a, I>(&self, files: I)
+    where
+        I: IntoIterator<Item = (&'a PathBuf, &'a String)>,
+    {
+        for (path, checksum) in files {
+            if let Err(e) = self.link(path, checksum) {
+                debug!("unable to deduplicate '{}': {e}", path.display());
+            }
+        }
+    }
+
+    /// Replace the file at `path` with a hard link to the object of `checksum`,
+    /// which is the checksum of `path` calculated when it was installed, thus not hashed again.
+    fn link(&self, path: &Path, checksum: &str) -> Result<()> {
+        let object = self.object_path(checksum);
+        // The object might have been modified in place through another link of it,
+        // which is replaced instead, so that the broken content won't be shared further.
+        let is_intact = object.symlink_metadata().is_ok_and(|meta| meta.is_file())
+            && utils::sha256_file(&object)
+                .is_ok_and(|actual| actual.eq_ignore_ascii_case(checksum));
+        if !is_intact {
+            utils::remove(&object)?;
+            utils::ensure_parent_dir(&object)?;
+            // The installed file becomes the object, which is then shared with the next ones,
+            // or a copy of it if hard links are not supported.
+            if fs::hard_link(path, &object).is_err() {
+                let temp = object.with_extension("part");
+                fs::copy(path, &temp)?;
+                fs::rename(&temp, &object)?;
+            }
+            return Ok(());
+        }
+        // Hard links share the permissions as well, such as whether they are executable.
+        if fs::metadata(path)?.permissions() != fs::metadata(&object)?.permissions() {
+            return Ok(());
+        }
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temp = path.with_file_name(format!(".{file_name}.store"));
+        if let Err(e) = fs::hard_link(&object, &temp) {
+            // The installed file is already a copy of the object, which is fine to keep as is.
+            debug!("unable to link '{}' to the store: {e}", path.display());
+            return Ok(());
+        }
+        if let Err(e) = fs::rename(&temp, path) {
+            let _ = fs::remove_file(&temp);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Remove the objects that are no longer used by any tool in the installation `record`,
+    /// according to their checksum manifests.
+    pub(crate) fn gc(&self, record: &InstallationRecord) -> Result<()> {
+        let objects_dir = self.objects_dir();
+        if !objects_dir.is_dir() {
+            return Ok(());
+        }
+        let referenced = record
+            .tools
+            .keys()
+            .filter_map(|name| read_checksums(&record.install_dir, name))
+            .flat_map(BTreeMap::into_values)
+            .map(|checksum| checksum.to_lowercase())
+            .collect::<HashSet<_>>();
+        for dir in utils::walk_dir(&objects_dir, false)? {
+            for object in utils::walk_dir(&dir, false)? {
+                let is_referenced = object
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| referenced.contains(name));
+                if !is_referenced {
+                    utils::remove(&object)?;
+                }
+            }
+            // Only succeeds if it's empty.
+            let _ = fs::remove_dir(&dir);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::core::parser::fingerprint::ToolRecord;
+    use crate::core::verify::{checksums_of, write_checksums};
+
+    #[test]
+    fn dedup_and_gc() {
+        let install_dir = tempfile::tempdir().unwrap();
+        let store = Store::new(install_dir.path());
+        let mut record = InstallationRecord {
+            install_dir: install_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        for name in ["foo", "bar"] {
+            let dir = install_dir.path().join("tools").join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("shared"), "same content").unwrap();
+            fs::write(dir.join("own"), name).unwrap();
+            let paths = vec![dir];
+            let checksums = checksums_of(&paths);
+            store.dedup(&checksums);
+            write_checksums(install_dir.path(), name, &checksums).unwrap();
+            record.add_tool_record(
+                name,
+                ToolRecord {
+                    paths,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let shared = utils::sha256_str("same content");
+        let object = store.object_path(&shared);
+        assert!(object.is_file());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(fs::metadata(&object).unwrap().nlink(), 3);
+        }
+        let foo = install_dir.path().join("tools").join("foo");
+        assert_eq!(
+            fs::read_to_string(foo.join("shared")).unwrap(),
+            "same content"
+        );
+
+        // The shared object is kept as long as one of the tools is still installed.
+        fs::remove_dir_all(&foo).unwrap();
+        record.tools.shift_remove("foo");
+        store.gc(&record).unwrap();
+        assert!(object.is_file());
+        assert!(!store.object_path(&utils::sha256_str("foo")).exists());
+        assert!(store.object_path(&utils::sha256_str("bar")).is_file());
+
+        // An object modified in place is not shared with the next identical file.
+        let bar = install_dir.path().join("tools").join("bar");
+        fs::write(bar.join("shared"), "SAME CONTENT").unwrap();
+        let baz = install_dir.path().join("tools").join("baz");
+        fs::create_dir_all(&baz).unwrap();
+        fs::write(baz.join("shared"), "same content").unwrap();
+        store.dedup(&checksums_of(std::slice::from_ref(&baz)));
+        assert_eq!(fs::read_to_string(&object).unwrap(), "same content");
+        assert_eq!(
+            fs::read_to_string(baz.join("shared")).unwrap(),
+            "same content"
+        );
+        fs::remove_dir_all(&baz).unwrap();
+
+        record.tools.clear();
+        store.gc(&record).unwrap();
+        assert!(!object.exists());
+        assert!(utils::walk_dir(&store.objects_dir(), false)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+//! Keeping multiple versions of a tool side by side, so that an update that broke something
+//! can be rolled back by switching the default version of the tool.
+//!
+//! The versions are installed with the following layout:
+//!
+//! ```text
+//! <install_dir>/tools/<name>/
+//! ├── <version>/
+//! │   ├── bin/
+//! │   └── ...
+//! └── <another version>/
+//! ```
+//!
+//! The executables in the `bin` directory of the default version have their [shims](super::shims)
+//! in `cargo`'s `bin` directory. Only the tools made of a directory with `bin` subdirectory,
+//! that have a version in the toolset manifest, are installed this way.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+use super::parser::fingerprint::{InstallationRecord, ToolRecord};
+use super::shims;
+use super::verify::{checksums_of, write_checksums};
+use crate::utils;
+
+/// Check if `version` can be used as the name of a directory.
+///
+/// A version named `bin` is not allowed, since `tools/<name>/bin` is the `bin` directory
+/// of the tools installed without their versions, which would be mistaken for a version.
+pub(crate) fn is_valid_version(version: &str) -> bool {
+    !version.is_empty()
+        && version != "."
+        && version != ".."
+        && !version.eq_ignore_ascii_case("bin")
+        && !version.contains(['/', '\\', ':'])
+}
+
+/// Get the directory of the given version of a tool.
+pub(crate) fn version_dir(tools_dir: &Path, name: &str, version: &str) -> PathBuf {
+    tools_dir.join(name).join(version)
+}
+
+/// Get the versions of a tool after installing its `version`, which are the `previous` ones
+/// that still exist, followed by the installed one, so the last one is the latest installed.
+pub(crate) fn installed_versions(
+    tools_dir: &Path,
+    name: &str,
+    version: &str,
+    previous: Option<&ToolRecord>,
+) -> Vec<String> {
+    let mut versions = previous
+        .map(|tool| tool.versions.clone())
+        .unwrap_or_default();
+    versions.retain(|v| v != version && version_dir(tools_dir, name, v).is_dir());
+    versions.push(version.to_string());
+    versions
+}
+
+/// Make `version` the default version of the tool `name`, by replacing the shims of its executables
+/// in `cargo`'s `bin` directory, then update the `record` accordingly.
+pub(crate) fn set_default(
+    record: &mut InstallationRecord,
+    name: &str,
+    version: &str,
+) -> Result<()> {
+    let tools_dir = record.install_dir.join("tools");
+    let cargo_bin = record.install_dir.join(".cargo").join("bin");
+    let Some(tool) = record.tools.get_mut(name) else {
+        bail!("'{name}' is not installed");
+    };
+    if tool.versions.is_empty() {
+        bail!("'{name}' was not installed with multiple versions support");
+    }
+    if !tool.versions.iter().any(|v| v == version) {
+        bail!(
+            "version '{version}' of '{name}' is not installed, the installed versions are: {}",
+            tool.versions.join(", ")
+        );
+    }
+    let bin_dir = version_dir(&tools_dir, name, version).join("bin");
+    if !bin_dir.is_dir() {
+        bail!(
+            "'{}' does not exist, try reinstalling '{name}'",
+            bin_dir.display()
+        );
+    }
+
+    for shim in tool
+        .paths
+        .iter()
+        .filter(|path| path.parent() == Some(cargo_bin.as_path()))
+    {
+        shims::remove(shim)?;
+    }
+    let mut paths = shims::create_all(&bin_dir, &cargo_bin)?;
+    paths.push(tools_dir.join(name));
+    write_checksums(&record.install_dir, name, &checksums_of(&paths))?;
+    tool.paths = paths;
+    tool.version = Some(version.to_string());
+    Ok(())
+}
+
+/// Remove a version of the tool `name`, if it's the default version,
+/// the latest installed one of the remaining versions becomes the default.
+pub(crate) fn remove_version(
+    record: &mut InstallationRecord,
+    name: &str,
+    version: &str,
+) -> Result<()> {
+    let tools_dir = record.install_dir.join("tools");
+    let Some(tool) = record.tools.get_mut(name) else {
+        bail!("'{name}' is not installed");
+    };
+    if !tool.versions.iter().any(|v| v == version) {
+        bail!("version '{version}' of '{name}' is not installed");
+    }
+    if tool.versions.len() < 2 {
+        bail!("unable to remove the only version of '{name}', uninstall it instead");
+    }
+    utils::remove(version_dir(&tools_dir, name, version))?;
+    tool.versions.retain(|v| v != version);
+    if tool.version.as_deref() == Some(version) {
+        let Some(latest) = tool.versions.last().cloned() else {
+            bail!("there's no other version of '{name}' to use as the default");
+        };
+        set_default(record, name, &latest)
+    } else {
+        write_checksums(&record.install_dir, name, &checksums_of(&tool.paths))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const EXE: &str = if cfg!(windows) { "foo.exe" } else { "foo" };
+
+    #[test]
+    fn switch_default_version() {
+        let install_dir = tempfile::tempdir().unwrap();
+        let tools_dir = install_dir.path().join("tools");
+        let cargo_bin = install_dir.path().join(".cargo").join("bin");
+        fs::create_dir_all(&cargo_bin).unwrap();
+        for version in ["1.0.0", "1.1.0"] {
+            let bin_dir = version_dir(&tools_dir, "foo", version).join("bin");
+            fs::create_dir_all(&bin_dir).unwrap();
+            fs::write(bin_dir.join(EXE), version).unwrap();
+        }
+
+        let mut record = InstallationRecord {
+            install_dir: install_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        record.add_tool_record(
+            "foo",
+            ToolRecord {
+                version: Some("1.1.0".into()),
+                versions: vec!["1.0.0".into(), "1.1.0".into()],
+                ..Default::default()
+            },
+        );
+        set_default(&mut record, "foo", "1.1.0").unwrap();
+        assert_eq!(
+            record.tools["foo"].paths,
+            [cargo_bin.join(EXE), tools_dir.join("foo")]
+        );
+        assert_eq!(
+            shims::target_of(&cargo_bin.join(EXE)).unwrap(),
+            version_dir(&tools_dir, "foo", "1.1.0")
+                .join("bin")
+                .join(EXE)
+        );
+
+        set_default(&mut record, "foo", "1.0.0").unwrap();
+        assert_eq!(record.tools["foo"].version.as_deref(), Some("1.0.0"));
+        assert_eq!(
+            shims::target_of(&cargo_bin.join(EXE)).unwrap(),
+            version_dir(&tools_dir, "foo", "1.0.0")
+                .join("bin")
+                .join(EXE)
+        );
+        assert!(set_default(&mut record, "foo", "2.0.0").is_err());
+        assert!(set_default(&mut record, "bar", "1.0.0").is_err());
+
+        // Removing the default version switches to the latest remaining one.
+        remove_version(&mut record, "foo", "1.0.0").unwrap();
+        assert!(!version_dir(&tools_dir, "foo", "1.0.0").exists());
+        assert_eq!(record.tools["foo"].versions, ["1.1.0"]);
+        assert_eq!(record.tools["foo"].version.as_deref(), Some("1.1.0"));
+        assert_eq!(
+            shims::target_of(&cargo_bin.join(EXE)).unwrap(),
+            version_dir(&tools_dir, "foo", "1.1.0")
+                .join("bin")
+                .join(EXE)
+        );
+        assert!(remove_version(&mut record, "foo", "1.1.0").is_err());
+        assert!(remove_version(&mut record, "foo", "2.0.0").is_err());
+        assert!(version_dir(&tools_dir, "foo", "1.1.0").is_dir());
+    }
+
+    #[test]
+    fn valid_versions() {
+        assert!(is_valid_version("1.2.3-beta.1"));
+        assert!(!is_valid_version(""));
+        assert!(!is_valid_version(".."));
+        assert!(!is_valid_version("1.0/../.."));
+        assert!(!is_valid_version("bin"));
+        assert!(!is_valid_version("BIN"));
+    }
+}
@@ -5,8 +5,12 @@ use std::{
 };
 
 use anyhow::{bail, Result};
+use tracing::{info, warn};
 
-use crate::{core::custom_instructions, utils, InstallConfiguration};
+use crate::core::parser::fingerprint::InstallationRecord;
+use crate::core::{custom_instructions, shims, tool_versions};
+use crate::manifest::{CustomInstruction, ToolInfo};
+use crate::{utils, InstallConfiguration};
 
 #[derive(Debug)]
 /// Representing the structure of an (extracted) tool's directory.
@@ -30,9 +34,36 @@ pub(crate) enum Tool<'a> {
     /// ├─── bin/
     /// ├─── ...
     /// ```
-    DirWithBin { name: String, bin_dir: PathBuf },
-    /// We have a custom "script" for how to deal with such directory.
-    Custom { name: String, path: &'a Path },
+    ///
+    /// `version` is the version in the manifest, which is only available when installing,
+    /// check [`tool_versions`](super::tool_versions) for how it's installed with a version.
+    DirWithBin {
+        name: String,
+        bin_dir: PathBuf,
+        version: Option<&'a str>,
+    },
+    /// We have a custom "script" for how to deal with such directory,
+    /// `info` is the manifest entry of this tool, which is only available when installing.
+    Custom {
+        name: String,
+        path: &'a Path,
+        info: Option<&'a ToolInfo>,
+    },
+    /// Installed by following the [`CustomInstruction`] declared in the manifest,
+    /// `path` is the (extracted) installer, or the installation directory when uninstalling.
+    Declared {
+        name: String,
+        path: &'a Path,
+        instruction: &'a CustomInstruction,
+    },
+    /// Windows installer, such as `.msi` packages, or `.exe` installers made by NSIS,
+    /// Inno Setup, etc., which will be executed silently with `args`.
+    Installer {
+        name: String,
+        kind: InstallerType,
+        path: PathBuf,
+        args: Vec<String>,
+    },
 }
 
 impl<'a> Tool<'a> {
@@ -41,7 +72,9 @@ impl<'a> Tool<'a> {
             Self::DirWithBin { name, .. }
             | Self::Executables(name, _)
             | Self::Plugin { name, .. }
-            | Self::Custom { name, .. } => name,
+            | Self::Custom { name, .. }
+            | Self::Declared { name, .. }
+            | Self::Installer { name, .. } => name,
         }
     }
     pub(crate) fn from_path(name: &str, path: &'a Path) -> Result<Self> {
@@ -55,7 +88,11 @@ impl<'a> Tool<'a> {
 
         // Step 1: Looking for custom instruction
         if custom_instructions::is_supported(&name) {
-            return Ok(Self::Custom { name, path });
+            return Ok(Self::Custom {
+                name,
+                path,
+                info: None,
+            });
         }
 
         // Step 2: Identify from file extension (if it's a file ofc).
@@ -65,6 +102,14 @@ impl<'a> Tool<'a> {
             let maybe_extension = path.extension();
             if let Some(ext) = maybe_extension.and_then(|ext| ext.to_str()) {
                 match ext {
+                    "msi" => {
+                        return Ok(Self::Installer {
+                            name,
+                            kind: InstallerType::Msi,
+                            path: path.to_path_buf(),
+                            args: vec![],
+                        });
+                    }
                     "vsix" => {
                         // TODO: When installing, invoke `vscode` plugin install command,
                         // this must be handled after `VS-Code` has been installed,
@@ -95,6 +140,7 @@ impl<'a> Tool<'a> {
                 return Ok(Self::DirWithBin {
                     name,
                     bin_dir: bin_dir.to_owned(),
+                    version: None,
                 });
             }
             // If no sub folder exists, and there are binaries lays directly in the folder
@@ -109,6 +155,54 @@ impl<'a> Tool<'a> {
         bail!("unable to process tool '{name}' as it is not supported")
     }
 
+    /// Set the arguments to run the installer with,
+    /// check [`ToolInfo::installer_args`](crate::manifest::ToolInfo::installer_args).
+    ///
+    /// A single executable will be treated as an `.exe` installer if `args` is not `None`.
+    pub(crate) fn with_installer_args(self, args: Option<&[String]>) -> Self {
+        let Some(args) = args else {
+            return self;
+        };
+        match self {
+            Self::Installer {
+                name, kind, path, ..
+            } => Self::Installer {
+                name,
+                kind,
+                path,
+                args: args.to_vec(),
+            },
+            Self::Executables(name, mut exes) if exes.len() == 1 => Self::Installer {
+                name,
+                kind: InstallerType::Exe,
+                path: exes.remove(0),
+                args: args.to_vec(),
+            },
+            _ => self,
+        }
+    }
+
+    /// Attach the manifest entry of this tool, which is needed by some custom instructions,
+    /// such as the [components](ToolInfo::components) to install with `buildtools`,
+    /// and by the tools with `bin` directory to be installed with their versions.
+    pub(crate) fn with_tool_info(self, info: &'a ToolInfo) -> Self {
+        match self {
+            Self::Custom { name, path, .. } => Self::Custom {
+                name,
+                path,
+                info: Some(info),
+            },
+            Self::DirWithBin { name, bin_dir, .. } => Self::DirWithBin {
+                name,
+                bin_dir,
+                version: info
+                    .version()
+                    .filter(|version| tool_versions::is_valid_version(version)),
+            },
+            _ => self,
+        }
+    }
+
     pub(crate) fn install(&self, config: &InstallConfiguration) -> Result<()> {
         match self {
             Self::Executables(_, exes) => {
@@ -116,11 +210,22 @@ impl<'a> Tool<'a> {
                     utils::copy_file_to(exe, config.cargo_bin())?;
                 }
             }
-            Self::Custom { name, path } => {
-                custom_instructions::install(name, path, config)?;
+            Self::Custom { name, path, info } => {
+                custom_instructions::install(name, path, *info, config)?;
+            }
+            Self::Declared {
+                name,
+                path,
+                instruction,
+            } => {
+                custom_instructions::declared::install(name, path, instruction, config)?;
             }
-            Self::DirWithBin { name, bin_dir } => {
-                install_dir_with_bin_(config, name, bin_dir)?;
+            Self::DirWithBin {
+                name,
+                bin_dir,
+                version,
+            } => {
+                install_dir_with_bin_(config, name, bin_dir, *version)?;
             }
             Self::Plugin { kind, path, .. } => {
                 // First, we need to "cache" to installer, so that we could uninstall with it.
@@ -128,10 +233,62 @@ impl<'a> Tool<'a> {
                 // Then, run the installation command.
                 kind.install_plugin(path)?;
             }
+            Self::Installer {
+                kind, path, args, ..
+            } => {
+                // Keep a copy of the installer, so that we could uninstall with it.
+                utils::copy_file_to(path, config.tools_dir())?;
+                kind.install(path, args)?;
+            }
         }
         Ok(())
     }
 
+    /// Get the directory where this tool will be installed into.
+    pub(crate) fn install_dir(&self, config: &InstallConfiguration) -> PathBuf {
+        match self {
+            Self::Executables(..) => config.cargo_bin().to_path_buf(),
+            Self::Plugin { .. } | Self::Installer { .. } => config.tools_dir().to_path_buf(),
+            Self::DirWithBin {
+                name,
+                version: Some(version),
+                ..
+            } => tool_versions::version_dir(config.tools_dir(), name, version),
+            Self::DirWithBin { name, .. }
+            | Self::Custom { name, .. }
+            | Self::Declared { name, .. } => config.tools_dir().join(name),
+        }
+    }
+
+    /// Get a list of files or directories that will be created after installing this tool.
+    pub(crate) fn installed_paths(&self, config: &InstallConfiguration) -> Vec<PathBuf> {
+        match self {
+            Self::Executables(_, exes) => exes
+                .iter()
+                .filter_map(|exe| exe.file_name())
+                .map(|name| config.cargo_bin().join(name))
+                .collect(),
+            Self::Plugin { path, .. } => path
+                .file_name()
+                .map(|name| vec![config.tools_dir().join(name)])
+                .unwrap_or_default(),
+            Self::Installer { path, .. } => path
+                .file_name()
+                .map(|name| vec![config.tools_dir().join(name)])
+                .unwrap_or_default(),
+            // The shims come first, so that they are removed before their executables.
+            Self::DirWithBin { name, .. } => {
+                let bin_dir = self.install_dir(config).join("bin");
+                let mut paths = shims::paths_of(&bin_dir, config.cargo_bin());
+                paths.push(config.tools_dir().join(name));
+                paths
+            }
+            Self::Custom { .. } | Self::Declared { .. } => {
+                vec![self.install_dir(config)]
+            }
+        }
+    }
+
     pub(crate) fn uninstall(&self) -> Result<()> {
         match self {
             Self::Executables(_, binaries) => {
@@ -140,34 +297,75 @@ impl<'a> Tool<'a> {
                 }
             }
             Self::Custom { name, .. } => custom_instructions::uninstall(name)?,
+            Self::Declared {
+                name,
+                path,
+                instruction,
+            } => custom_instructions::declared::uninstall(name, path, instruction)?,
             Self::DirWithBin { bin_dir, .. } => uninstall_dir_with_bin_(bin_dir)?,
             Self::Plugin { kind, path, .. } => kind.uninstall_plugin(path)?,
+            Self::Installer { kind, path, .. } => {
+                kind.uninstall(path)?;
+                fs::remove_file(path)?;
+            }
         }
         Ok(())
     }
 }
 
 /// Installing [`ToolInstaller::DirWithBin`], with a couple steps:
-/// - Move the `tool_dir` to [`tools_dir`](InstallConfiguration::tools_dir).
-/// - Add the `bin_dir` to PATH
-fn install_dir_with_bin_(config: &InstallConfiguration, name: &str, bin_dir: &Path) -> Result<()> {
-    let dir = config.tools_dir().join(name);
+/// - Move the `tool_dir` to [`tools_dir`](InstallConfiguration::tools_dir),
+///   or the directory of its `version` if there is one.
+/// - Create the [shims](super::shims) of the executables in `bin_dir`.
+fn install_dir_with_bin_(
+    config: &InstallConfiguration,
+    name: &str,
+    bin_dir: &Path,
+    version: Option<&str>,
+) -> Result<()> {
     // Safe to unwrap, because we already checked the `bin` dir is inside `tool_dir`
     let tool_dir = bin_dir.parent().unwrap();
+    let dir = config.tools_dir().join(name);
 
-    utils::move_to(tool_dir, &dir, true)?;
+    // The previous installation might have added its `bin` dir to PATH instead of using shims.
+    let old_bin_dir = dir.join("bin");
+    if InstallationRecord::load_or_new(&config.install_dir)?
+        .paths
+        .contains(&old_bin_dir)
+    {
+        super::os::remove_from_path(&old_bin_dir)?;
+        InstallationRecord::update(&config.install_dir, |record| {
+            record.paths.retain(|path| path != &old_bin_dir);
+        })?;
+    }
 
-    let bin_dir_after_move = dir.join("bin");
-    super::os::add_to_path(&bin_dir_after_move)
+    let dest = match version {
+        Some(version) => {
+            // Replace the previous installation without version.
+            if old_bin_dir.is_dir() {
+                utils::remove(&dir)?;
+            }
+            let dest = tool_versions::version_dir(config.tools_dir(), name, version);
+            utils::ensure_parent_dir(&dest)?;
+            dest
+        }
+        None => dir,
+    };
+    utils::move_to(tool_dir, &dest, true)?;
+    shims::create_all(&dest.join("bin"), config.cargo_bin())?;
+    Ok(())
 }
 
 /// Uninstalling a tool with bin folder is as simple as removing the directory,
 /// and removing the `bin` dir from `PATH`.
-fn uninstall_dir_with_bin_(tool_path: &Path) -> Result<()> {
+/// Uninstalling [`ToolInstaller::DirWithBin`], which removes the `bin_dir` from PATH,
+/// then removes the whole tool directory containing it.
+fn uninstall_dir_with_bin_(bin_dir: &Path) -> Result<()> {
     // Remove from `PATH` at first.
-    let bin_dir = tool_path.join("bin");
-    super::os::remove_from_path(&bin_dir)?;
+    super::os::remove_from_path(bin_dir)?;
 
+    // Safe to unwrap, because the `bin` dir is always inside the tool directory.
+    let tool_path = bin_dir.parent().unwrap();
     fs::remove_dir_all(tool_path)?;
 
     Ok(())
@@ -206,7 +404,7 @@ impl PluginType {
                     if utils::cmd_exist(program) {
                         let op = if uninstall { "uninstall" } else { "install" };
                         let arg_opt = format!("--{op}-extension");
-                        println!(
+                        info!(
                             "{op}ing extension '{}' for program '{program}'",
                             plugin_path.display()
                         );
@@ -217,8 +415,8 @@ impl PluginType {
                             Ok(()) => continue,
                             // Ignore error when uninstalling.
                             Err(_) if uninstall => {
-                                println!(
-                                    "warning: plugin '{}' for '{program}' is not installed, skipping...",
+                                warn!(
+                                    "plugin '{}' for '{program}' is not installed, skipping...",
                                     plugin_path.display()
                                 );
                                 continue;
@@ -240,3 +438,44 @@ impl PluginType {
         self.install_or_uninstall_(plugin_path, true)
     }
 }
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum InstallerType {
+    /// Windows installer package, installed using `msiexec`.
+    Msi,
+    /// Executable installer that can be run silently with some arguments.
+    Exe,
+}
+
+impl InstallerType {
+    fn install(&self, path: &Path, args: &[String]) -> Result<()> {
+        info!("running installer '{}'", path.display());
+        match self {
+            Self::Msi => msiexec_("/i", path, args),
+            Self::Exe => utils::execute(path, args),
+        }
+    }
+
+    /// Uninstall the product installed by the given installer.
+    ///
+    /// Only `.msi` packages can be uninstalled this way, because there are no common
+    /// ways to find and run the uninstaller of an `.exe` installer.
+    fn uninstall(&self, path: &Path) -> Result<()> {
+        match self {
+            Self::Msi => msiexec_("/x", path, &[]),
+            Self::Exe => Ok(()),
+        }
+    }
+}
+
+fn msiexec_(op: &str, package: &Path, extra_args: &[String]) -> Result<()> {
+    if !cfg!(windows) {
+        bail!(
+            "unable to process '{}': msi packages are only supported on Windows",
+            package.display()
+        );
+    }
+    let mut args = vec![op, utils::path_to_str(package)?, "/qn", "/norestart"];
+    args.extend(extra_args.iter().map(|s| s.as_str()));
+    utils::execute("msiexec", &args)
+}
@@ -4,6 +4,7 @@ use std::{
     env,
     path::{Path, PathBuf},
 };
+use tracing::info;
 
 /// Export an example `cargo` project, then open it with `VSCode` editor or `file explorer`.
 pub fn try_it(path: Option<&Path>) -> Result<()> {
@@ -39,7 +40,7 @@ pub fn try_it(path: Option<&Path>) -> Result<()> {
             return Ok(());
         }
 
-        println!(
+        info!(
             "unable to open example directory with `VSCode` or `file explorer`, \
             try open it manually: {}",
             example_dir.display()
@@ -1,44 +1,317 @@
-use std::{cmp::Ordering, path::PathBuf};
+use std::collections::{BTreeMap, HashSet};
+use std::{
+    cmp::Ordering,
+    path::{Path, PathBuf},
+};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use tracing::warn;
 
-use crate::{core::tools::Tool, utils};
+use crate::{
+    core::tools::Tool,
+    utils::{self, OutputEvent},
+};
 
-use super::os::install_dir_from_exe_path;
+use super::custom_instructions;
+use super::os::{install_dir_from_exe_path, remove_from_path};
+use super::parser::cargo_config;
+use super::parser::fingerprint::{InstallationRecord, ToolRecord, ToolSource};
+use super::parser::git_config;
+use super::parser::ManifestParser;
+use super::shims;
+use super::store::Store;
+use super::verify;
+
+/// Names of the binaries in `cargo`'s `bin` directory that are managed by `rustup`.
+const RUSTUP_PROXIES: &[&str] = &[
+    "cargo",
+    "cargo-clippy",
+    "cargo-fmt",
+    "cargo-miri",
+    "clippy-driver",
+    "rls",
+    "rust-analyzer",
+    "rust-gdb",
+    "rust-gdbgui",
+    "rust-lldb",
+    "rustc",
+    "rustdoc",
+    "rustfmt",
+    "rustup",
+];
+
+/// The `.crates.toml` file written by `cargo install` under `CARGO_HOME`,
+/// mapping the installed packages to their binaries.
+#[derive(Debug, Default, Deserialize)]
+struct CratesToml {
+    #[serde(default)]
+    v1: BTreeMap<String, Vec<String>>,
+}
+
+impl ManifestParser for CratesToml {}
 
 /// Contains definition of uninstallation steps.
 pub(crate) trait Uninstallation {
     /// Remove persistent environment variables for `rustup`.
     ///
-    /// This will remove persistent environment variables including
-    /// `RUSTUP_DIST_SERVER`, `RUSTUP_UPDATE_ROOT`, `CARGO_HOME`, `RUSTUP_HOME`.
+    /// This will remove the persistent environment variables listed in the installation record,
+    /// such as `RUSTUP_DIST_SERVER`, `RUSTUP_UPDATE_ROOT`, `CARGO_HOME`, `RUSTUP_HOME`,
+    /// along with the paths that were added to `PATH`.
     fn remove_rustup_env_vars(&self) -> Result<()>;
     /// The last step of uninstallation, this will remove the binary itself, along with
     /// the folder it's in.
     fn remove_self(&self) -> Result<()>;
 }
 
-/// Configurations to use when installing.
-// NB: Currently, there's no uninstall configurations, this struct is only
-// used for abstract purpose.
-pub(crate) struct UninstallConfiguration;
+/// Configurations to use when uninstalling.
+pub(crate) struct UninstallConfiguration {
+    pub(crate) install_dir: PathBuf,
+    /// The record written by the installation, listing what to remove.
+    pub(crate) record: InstallationRecord,
+}
 
 impl UninstallConfiguration {
-    pub(crate) fn install_dir(&self) -> Result<PathBuf> {
-        install_dir_from_exe_path()
+    /// Initialize the configuration of the installation that this program belongs to.
+    pub(crate) fn init() -> Result<Self> {
+        Self::init_at(&install_dir_from_exe_path()?)
+    }
+
+    /// Initialize the configuration of the installation under `install_dir`.
+    pub(crate) fn init_at(install_dir: &Path) -> Result<Self> {
+        let record = InstallationRecord::load_or_new(install_dir)?;
+        super::os::set_system_wide(record.system);
+        super::os::ensure_privileges()?;
+        Ok(Self {
+            install_dir: install_dir.to_path_buf(),
+            record,
+        })
+    }
+
+    /// Uninstall everything, including the installation directory itself,
+    /// after copying the user data into `keep_user_data` if it's given.
+    pub(crate) fn uninstall_all(&self, keep_user_data: Option<&Path>) -> Result<()> {
+        if let Some(dest) = keep_user_data {
+            self.keep_user_data(dest)?;
+        }
+        self.remove_rustup_env_vars()?;
+        self.remove_git_config()?;
+        self.remove_tools()?;
+        // Keep the log file after the installation directory was removed.
+        utils::move_log_to_temp_dir();
+        self.remove_self()
     }
 
-    pub(crate) fn tools_dir(&self) -> Result<PathBuf> {
-        self.install_dir()
-            .map(|install_dir| install_dir.join("tools"))
+    pub(crate) fn tools_dir(&self) -> PathBuf {
+        self.install_dir.join("tools")
     }
 
-    /// Uninstall any tools that may or may not installed with custom instructions.
+    /// Uninstall the tools listed in the installation record, in reverse installation order.
+    ///
+    /// If there are no tools recorded, which is the case for the installations done by
+    /// older versions of this program, the tools in `tools` directory will be removed instead.
     pub(crate) fn remove_tools(&self) -> Result<()> {
-        // TODO: Read a list of tools to remove, this require a manifest file to be written after installation.
-        // But right now we only remove those in `tools` directory
+        if self.record.tools.is_empty() {
+            return self.remove_tools_in_tools_dir();
+        }
+        for (name, tool) in self.record.tools.iter().rev() {
+            OutputEvent::Message {
+                text: &format!("uninstalling '{name}'"),
+            }
+            .emit();
+            remove_tool_paths(name, tool)?;
+        }
+        Ok(())
+    }
+
+    /// Uninstall a list of tools listed in the installation record,
+    /// nothing is uninstalled if any of them is not installed.
+    pub(crate) fn uninstall_tools(&mut self, names: &[String]) -> Result<()> {
+        let unknown = names
+            .iter()
+            .filter(|name| !self.record.tools.contains_key(name.as_str()))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            bail!(
+                "the following tool(s) are not installed: {}",
+                unknown.join(", ")
+            );
+        }
+        for name in names {
+            self.uninstall_tool(name)?;
+        }
+        Ok(())
+    }
+
+    /// Uninstall a single tool listed in the installation record.
+    ///
+    /// This removes the recorded files of the tool (or runs `cargo uninstall` for the ones
+    /// installed using cargo), runs its custom uninstall instruction if there is one,
+    /// and removes the paths under it from `PATH`, then updates the record accordingly.
+    pub(crate) fn uninstall_tool(&mut self, name: &str) -> Result<()> {
+        let Some(tool) = self.record.tools.get(name) else {
+            bail!("'{name}' is not installed");
+        };
+        OutputEvent::Message {
+            text: &format!("uninstalling '{name}'"),
+        }
+        .emit();
+
+        let in_path = self
+            .record
+            .paths
+            .iter()
+            .filter(|path| tool.paths.iter().any(|p| path.starts_with(p)))
+            .cloned()
+            .collect::<Vec<_>>();
+        for path in &in_path {
+            remove_from_path(path)?;
+        }
+
+        // Older records don't have the source, but only cargo tools have no recorded paths.
+        let installed_by_cargo = match &tool.source {
+            Some(source) => matches!(source, ToolSource::Cargo | ToolSource::Git { .. }),
+            None => tool.paths.is_empty() && !custom_instructions::is_supported(name),
+        };
+        if installed_by_cargo {
+            utils::execute("cargo", &["uninstall", name])?;
+        } else if custom_instructions::is_supported(name) && !tool.paths.iter().any(|p| p.exists())
+        {
+            // The custom uninstall instruction is otherwise run by `remove_tool_paths`.
+            if custom_instructions::already_installed(name) {
+                custom_instructions::uninstall(name)?;
+            }
+        }
+        remove_tool_paths(name, tool)?;
+
+        self.record.tools.shift_remove(name);
+        self.record.paths.retain(|path| !in_path.contains(path));
+        self.record.write()?;
+        verify::remove_checksums(&self.install_dir, name)?;
+        if let Err(e) = Store::new(&self.install_dir).gc(&self.record) {
+            warn!("unable to clean up the store: {e:?}");
+        }
+        Ok(())
+    }
+
+    /// Remove the git configuration file included by the installation
+    /// from the global git configuration.
+    pub(crate) fn remove_git_config(&self) -> Result<()> {
+        match &self.record.git_include {
+            Some(path) => git_config::remove_include(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Move the user data under `CARGO_HOME` into `dest` before everything gets removed,
+    /// which are the registry cache, `config.toml` and the binaries installed by the user.
+    pub(crate) fn keep_user_data(&self, dest: &Path) -> Result<()> {
+        let dest = utils::to_nomalized_abspath(dest, None)?;
+        if dest.starts_with(&self.install_dir) {
+            bail!(
+                "unable to keep user data in '{}', as it will be removed along with the installation",
+                dest.display()
+            );
+        }
+        let cargo_home = self.install_dir.join(".cargo");
+        utils::ensure_dir(&dest)?;
+
+        for name in ["registry", "config.toml"] {
+            let src = cargo_home.join(name);
+            if src.exists() {
+                OutputEvent::Message {
+                    text: &format!("moving '{}' to '{}'", src.display(), dest.display()),
+                }
+                .emit();
+                utils::move_to(&src, &dest.join(name), false)?;
+            }
+        }
+        self.remove_cargo_config_keys(&dest.join("config.toml"))?;
+
+        let user_binaries = self.user_binaries(&cargo_home)?;
+        if !user_binaries.is_empty() {
+            let bin_dir = dest.join("bin");
+            utils::ensure_dir(&bin_dir)?;
+            for bin in user_binaries {
+                // Safe to unwrap, the binaries are files in `bin` directory.
+                let bin_name = bin.file_name().unwrap();
+                OutputEvent::Message {
+                    text: &format!("moving '{}' to '{}'", bin.display(), bin_dir.display()),
+                }
+                .emit();
+                utils::move_to(&bin, &bin_dir.join(bin_name), false)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the keys added by the installation from the kept cargo configuration at `path`,
+    /// and restore the overwritten ones, the file is removed if nothing is left.
+    fn remove_cargo_config_keys(&self, path: &Path) -> Result<()> {
+        let record = &self.record;
+        if !path.is_file()
+            || (record.cargo_config_keys.is_empty() && record.cargo_config_backup.is_empty())
+        {
+            return Ok(());
+        }
+        let content =
+            cargo_config::remove_keys(&utils::read_to_string(path)?, &record.cargo_config_keys)?;
+        let content = cargo_config::restore_values(&content, &record.cargo_config_backup)?;
+        if content.trim().is_empty() {
+            utils::remove(path)
+        } else {
+            utils::write_file(path, content.trim_end(), false)
+        }
+    }
+
+    /// Get the binaries in `cargo`'s `bin` directory that were not installed by this program,
+    /// nor are they managed by `rustup`.
+    pub(crate) fn user_binaries(&self, cargo_home: &Path) -> Result<Vec<PathBuf>> {
+        let bin_dir = cargo_home.join("bin");
+        if !bin_dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let crates_toml = cargo_home.join(".crates.toml");
+        let installed_by_cargo = if crates_toml.is_file() {
+            CratesToml::load(&crates_toml)?.v1
+        } else {
+            BTreeMap::new()
+        };
+        // The binaries of the tools installed using `cargo install` by this program.
+        let tool_bins = installed_by_cargo
+            .iter()
+            .filter(|(pkg, _)| {
+                pkg.split_whitespace()
+                    .next()
+                    .is_some_and(|name| self.record.tools.contains_key(name))
+            })
+            .flat_map(|(_, bins)| bins.iter().map(String::as_str))
+            .collect::<HashSet<_>>();
+
+        let is_user_binary = |path: &PathBuf| {
+            let (Some(name), Some(stem)) = (
+                path.file_name().and_then(|n| n.to_str()),
+                path.file_stem().and_then(|n| n.to_str()),
+            ) else {
+                return false;
+            };
+            path.is_file()
+                && !RUSTUP_PROXIES.contains(&stem)
+                && !stem.starts_with("manager")
+                && !shims::is_shim_config(path)
+                && !tool_bins.contains(name)
+                && !self.record.tools.values().any(|t| t.paths.contains(path))
+        };
+        Ok(utils::walk_dir(&bin_dir, false)?
+            .into_iter()
+            .filter(is_user_binary)
+            .collect())
+    }
+
+    fn remove_tools_in_tools_dir(&self) -> Result<()> {
         // If there's nothing to remove, do nothing
-        let tools_dir = self.tools_dir()?;
+        let tools_dir = self.tools_dir();
         if !tools_dir.exists() {
             return Ok(());
         }
@@ -58,7 +331,10 @@ impl UninstallConfiguration {
         });
 
         for tool in tools_to_remove {
-            println!("uninstalling '{}'", tool.name());
+            OutputEvent::Message {
+                text: &format!("uninstalling '{}'", tool.name()),
+            }
+            .emit();
             tool.uninstall()?;
         }
 
@@ -66,7 +342,37 @@ impl UninstallConfiguration {
     }
 }
 
-fn tool_from_path(path: &PathBuf) -> Option<Tool> {
+/// Remove the recorded files or directories of a tool.
+///
+/// The paths that can be recognized as a [`Tool`] are uninstalled as such,
+/// (such as `.msi` packages or `vscode` plugins), then whatever left is simply removed.
+/// For the tools installed with a [declared instruction](Tool::Declared),
+/// the uninstall steps are executed under its installation directory instead.
+pub(crate) fn remove_tool_paths(name: &str, tool: &ToolRecord) -> Result<()> {
+    for path in tool.paths.iter().filter(|p| p.exists()) {
+        if path.with_extension(shims::EXTENSION).is_file() {
+            shims::remove(path)?;
+            continue;
+        }
+        let installed = match &tool.instruction {
+            Some(instruction) if path.is_dir() => Ok(Tool::Declared {
+                name: name.to_string(),
+                path,
+                instruction,
+            }),
+            _ => Tool::from_path(name, path),
+        };
+        if let Ok(installed) = installed {
+            installed.uninstall()?;
+        }
+        if path.exists() {
+            utils::remove(path)?;
+        }
+    }
+    Ok(())
+}
+
+fn tool_from_path(path: &PathBuf) -> Option<Tool<'_>> {
     // TODO: This name should be read from manifest anyway, but right now we get the name
     // by the `folder`'s name, which technically does the same thing, but for those tools
     // that were installed without folder, things could get a little bit ugly.
@@ -77,3 +383,106 @@ fn tool_from_path(path: &PathBuf) -> Option<Tool> {
         .to_string();
     Tool::from_path(&name, path).ok()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn uninstall_single_tool() {
+        let install_dir = tempfile::tempdir().unwrap();
+        let tool_dir = install_dir.path().join("tools").join("foo");
+        fs::create_dir_all(&tool_dir).unwrap();
+        fs::write(tool_dir.join("foo.txt"), "").unwrap();
+
+        let mut record = InstallationRecord {
+            install_dir: install_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        for name in ["foo", "bar"] {
+            record.add_tool_record(
+                name,
+                ToolRecord {
+                    source: Some(ToolSource::Path {
+                        path: PathBuf::from("/path/to/archive.zip"),
+                    }),
+                    paths: vec![install_dir.path().join("tools").join(name)],
+                    ..Default::default()
+                },
+            );
+        }
+        let mut config = UninstallConfiguration {
+            install_dir: install_dir.path().to_path_buf(),
+            record,
+        };
+
+        config.uninstall_tool("foo").unwrap();
+        assert!(!tool_dir.exists());
+        assert!(config.uninstall_tool("baz").is_err());
+
+        let record = InstallationRecord::load_or_new(install_dir.path()).unwrap();
+        assert_eq!(record.tools.keys().collect::<Vec<_>>(), ["bar"]);
+    }
+
+    #[test]
+    fn keep_user_data() {
+        let install_dir = tempfile::tempdir().unwrap();
+        let cargo_home = install_dir.path().join(".cargo");
+        let bin_dir = cargo_home.join("bin");
+        fs::create_dir_all(bin_dir.join("nested")).unwrap();
+        fs::create_dir_all(cargo_home.join("registry").join("cache")).unwrap();
+        fs::write(
+            cargo_home.join("config.toml"),
+            "[build]\njobs = 8\n\n[source.mirror]\nregistry = \"https://example.com/index\"\n",
+        )
+        .unwrap();
+        fs::write(
+            cargo_home.join(".crates.toml"),
+            r#"[v1]
+"foo 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["foo"]
+"mine 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)" = ["mine"]
+"#,
+        )
+        .unwrap();
+        for bin in ["cargo", "rustup", "manager", "foo", "mine", "linked"] {
+            fs::write(bin_dir.join(bin), "").unwrap();
+        }
+
+        let mut record = InstallationRecord::default();
+        record.add_cargo_config_keys(["source.mirror.registry".to_string()]);
+        record.backup_cargo_config_values([("build.jobs".to_string(), "4".to_string())]);
+        record.add_tool_record(
+            "foo",
+            ToolRecord {
+                source: Some(ToolSource::Cargo),
+                ..Default::default()
+            },
+        );
+        record.add_tool_record(
+            "linked",
+            ToolRecord {
+                paths: vec![bin_dir.join("linked")],
+                ..Default::default()
+            },
+        );
+        let config = UninstallConfiguration {
+            install_dir: install_dir.path().to_path_buf(),
+            record,
+        };
+
+        assert!(config.keep_user_data(&cargo_home.join("kept")).is_err());
+
+        let dest = tempfile::tempdir().unwrap();
+        config.keep_user_data(dest.path()).unwrap();
+        assert!(dest.path().join("registry").join("cache").is_dir());
+        assert_eq!(
+            fs::read_to_string(dest.path().join("config.toml")).unwrap(),
+            "[build]\njobs = 4\n"
+        );
+        let kept_bins = utils::walk_dir(&dest.path().join("bin"), false).unwrap();
+        assert_eq!(kept_bins, [dest.path().join("bin").join("mine")]);
+        assert!(bin_dir.join("foo").exists());
+    }
+}
@@ -0,0 +1,462 @@
+//! Updating the installed toolchain and tools, as well as the `manager` program itself.
+//!
+//! For updating `manager`, the update root has the following layout, which is similar to rustup's:
+//!
+//! ```text
+//! <update root>/
+//! ├── release-stable.toml         (contains `version = "<latest version>"`)
+//! └── archive/
+//!     └── <version>/<target>/
+//!         ├── manager[.exe]
+//!         ├── manager[.exe].sha256
+//!         └── manager[.exe].minisig (required if there are trusted public keys)
+//! ```
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use url::Url;
+
+use super::install::InstallConfiguration;
+use super::parser::fingerprint::InstallationRecord;
+use super::parser::manifest::{trusted_public_keys, Proxy, ToolMap, ToolsetManifest};
+use super::parser::ManifestParser;
+use super::session::{InstallSession, SessionStep};
+use crate::utils::{self, download, force_url_join, MultiThreadProgress, OutputEvent};
+
+/// The toolchain or a tool whose installed version differs from the one in toolset manifest.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Outdated<'a> {
+    Rust {
+        installed: &'a str,
+        latest: &'a str,
+    },
+    Tool {
+        name: &'a str,
+        installed: Option<&'a str>,
+        latest: Option<&'a str>,
+    },
+}
+
+impl<'a> Outdated<'a> {
+    /// Return the name, installed version and latest version of this.
+    fn parts(&self) -> (&'a str, Option<&'a str>, Option<&'a str>) {
+        match self {
+            Self::Rust { installed, latest } => ("rust", Some(*installed), Some(*latest)),
+            Self::Tool {
+                name,
+                installed,
+                latest,
+            } => (*name, *installed, *latest),
+        }
+    }
+
+    /// Convert this into an [`OutputEvent::Outdated`] to be reported.
+    pub(crate) fn to_event(&self) -> OutputEvent<'a> {
+        let (name, installed, latest) = self.parts();
+        OutputEvent::Outdated {
+            name,
+            installed,
+            latest,
+        }
+    }
+}
+
+impl fmt::Display for Outdated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, installed, latest) = self.parts();
+        write!(
+            f,
+            "{name}: {} -> {}",
+            installed.unwrap_or("unknown"),
+            latest.unwrap_or("unknown")
+        )
+    }
+}
+
+/// Compare the versions in the installation record against the given manifest,
+/// and return the installed toolchain and tools that are outdated.
+///
+/// Tools that are not installed are not considered outdated,
+/// neither are the ones without a version in both the record and the manifest.
+pub(crate) fn find_outdated<'a>(
+    record: &'a InstallationRecord,
+    manifest: &'a ToolsetManifest,
+) -> Vec<Outdated<'a>> {
+    let mut outdated = vec![];
+    if let Some(rust) = &record.rust {
+        if rust.version != manifest.rust.version {
+            outdated.push(Outdated::Rust {
+                installed: &rust.version,
+                latest: &manifest.rust.version,
+            });
+        }
+    }
+    for (name, tool) in manifest.current_target_tools().into_iter().flatten() {
+        let Some(tool_record) = record.tools.get(name) else {
+            continue;
+        };
+        if tool_record.version.as_deref() != tool.version() {
+            outdated.push(Outdated::Tool {
+                name,
+                installed: tool_record.version.as_deref(),
+                latest: tool.version(),
+            });
+        }
+    }
+    outdated
+}
+
+/// Update the given outdated toolchain and tools to the versions in the manifest.
+///
+/// The Rust toolchain is updated along with the optional components that were installed.
+pub(crate) fn update_toolkit(
+    config: &mut InstallConfiguration,
+    manifest: &ToolsetManifest,
+    record: &InstallationRecord,
+    outdated: &[Outdated],
+) -> Result<()> {
+    let tools: ToolMap = manifest
+        .current_target_tools()
+        .into_iter()
+        .flatten()
+        .filter(|(name, _)| {
+            outdated
+                .iter()
+                .any(|o| matches!(o, Outdated::Tool { name: n, .. } if n == name))
+        })
+        .map(|(name, tool)| (name.clone(), tool.clone()))
+        .collect();
+    let optional_components = record.rust.as_ref().map(|rust| {
+        rust.components
+            .iter()
+            .filter(|c| !manifest.rust.components.contains(c))
+            .cloned()
+            .collect::<Vec<_>>()
+    });
+
+    let mut progress = MultiThreadProgress::default();
+    let mut session = InstallSession::new(config, manifest)
+        .tools(&tools)
+        .optional_components(optional_components);
+    session.run_step(SessionStep::InstallTools, &mut progress)?;
+    if outdated.iter().any(|o| matches!(o, Outdated::Rust { .. })) {
+        session.run_step(SessionStep::InstallRust, &mut progress)?;
+    }
+    session.run_step(SessionStep::CargoInstall, &mut progress)?;
+
+    config.finish_installation()
+}
+
+/// The update root embedded at build time, by setting the `MANAGER_UPDATE_ROOT`
+/// environment variable.
+const EMBEDDED_UPDATE_ROOT: Option<&str> = option_env!("MANAGER_UPDATE_ROOT");
+
+const RELEASE_FILENAME: &str = "release-stable.toml";
+
+/// Information of the latest release under an update root.
+#[derive(Debug, Deserialize)]
+struct Release {
+    version: String,
+}
+
+impl ManifestParser for Release {}
+
+/// Get the update root to use, which is either the given one or the embedded one.
+pub(crate) fn update_root(root: Option<&Url>) -> Result<Url> {
+    if let Some(root) = root {
+        return Ok(root.clone());
+    }
+    let Some(embedded) = EMBEDDED_UPDATE_ROOT.filter(|s| !s.trim().is_empty()) else {
+        bail!("no update root was configured, try specifying one with `--update-root`");
+    };
+    Url::parse(embedded.trim())
+        .with_context(|| format!("invalid embedded update root '{embedded}'"))
+}
+
+/// Check the update root for a newer version of `manager`.
+///
+/// Return the latest version if it is newer than the current one, or `None` otherwise.
+pub(crate) fn check_update(root: &Url, proxy: Option<&Proxy>) -> Result<Option<String>> {
+    let temp_dir = tempfile::tempdir()?;
+    let release_path = temp_dir.path().join(RELEASE_FILENAME);
+    download(
+        RELEASE_FILENAME,
+        &force_url_join(root, RELEASE_FILENAME)?,
+        &release_path,
+        proxy,
+    )
+    .context("unable to check for updates")?;
+    let release = Release::load(&release_path)?;
+
+    let latest = Version::parse(&release.version)
+        .ok_or_else(|| anyhow!("invalid version '{}' in release info", release.version))?;
+    let current = Version::parse(env!("CARGO_PKG_VERSION"))
+        .expect("Internal Error: the package version should be a valid version");
+    Ok((latest > current).then_some(release.version))
+}
+
+/// Download the given version of `manager` from the update root, verify it,
+/// then replace the running executable with it.
+pub(crate) fn update_self(root: &Url, version: &str, proxy: Option<&Proxy>) -> Result<()> {
+    let current_exe = std::env::current_exe().context("cannot locate current executable")?;
+    let exe_dir = current_exe
+        .parent()
+        .ok_or_else(|| anyhow!("invalid executable path '{}'", current_exe.display()))?;
+    // Download into the same directory as the current executable,
+    // so it can be moved into place without copying across file systems.
+    let temp_dir = tempfile::Builder::new()
+        .prefix(".manager-update")
+        .tempdir_in(exe_dir)
+        .with_context(|| {
+            format!(
+                "unable to create temporary directory in '{}'",
+                exe_dir.display()
+            )
+        })?;
+
+    let exe_name = format!("manager{}", utils::EXE_EXT);
+    let base_url = force_url_join(root, &format!("archive/{version}/{}/", env!("TARGET")))?;
+    let fetch = |file_name: &str| -> Result<PathBuf> {
+        let dest = temp_dir.path().join(file_name);
+        download(
+            file_name,
+            &force_url_join(&base_url, file_name)?,
+            &dest,
+            proxy,
+        )?;
+        Ok(dest)
+    };
+
+    let new_exe = fetch(&exe_name)?;
+    let checksum_path = fetch(&format!("{exe_name}.sha256"))?;
+    verify_checksum(&new_exe, &utils::read_to_string(checksum_path)?)?;
+
+    let public_keys = trusted_public_keys();
+    if !public_keys.is_empty() {
+        let sig_path = fetch(&format!("{exe_name}.minisig"))?;
+        if !public_keys
+            .iter()
+            .any(|key| utils::verify_minisign(&new_exe, &sig_path, key).is_ok())
+        {
+            bail!("the signature of the downloaded '{exe_name}' cannot be verified");
+        }
+    }
+
+    utils::create_executable_file(&new_exe)?;
+    replace_exe(&new_exe, &current_exe)
+}
+
+/// Verify the sha256 checksum of `path`, the `expected` content is in the format of
+/// `sha256sum`'s output, which is the hex checksum optionally followed by a file name.
+fn verify_checksum(path: &Path, expected: &str) -> Result<()> {
+    let expected = expected.split_whitespace().next().unwrap_or_default();
+    let actual = utils::sha256_file(path)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        bail!(
+            "checksum mismatch of '{}', expected '{expected}' but got '{actual}'",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Get the path of the replaced executable, which is `<exe>.old`.
+fn old_exe_path(exe: &Path) -> PathBuf {
+    let mut old = exe.as_os_str().to_os_string();
+    old.push(".old");
+    PathBuf::from(old)
+}
+
+/// Atomically replace `current` executable with `new`, which must be on the same file system.
+#[cfg(unix)]
+fn replace_exe(new: &Path, current: &Path) -> Result<()> {
+    fs::rename(new, current)
+        .with_context(|| format!("unable to replace executable '{}'", current.display()))
+}
+
+/// Replace `current` executable with `new`, which must be on the same file system.
+///
+/// A running executable cannot be overwritten or deleted on Windows, but it can be renamed,
+/// so it is moved aside as `<exe>.old` first, which is then removed by
+/// [`remove_old_exe`] the next time this program runs.
+#[cfg(windows)]
+fn replace_exe(new: &Path, current: &Path) -> Result<()> {
+    let old = old_exe_path(current);
+    if old.exists() {
+        fs::remove_file(&old)
+            .with_context(|| format!("unable to remove old executable '{}'", old.display()))?;
+    }
+    fs::rename(current, &old)
+        .with_context(|| format!("unable to move executable '{}' aside", current.display()))?;
+    if let Err(e) = fs::rename(new, current) {
+        // Put the original one back, so the user won't end up without a manager.
+        let _ = fs::rename(&old, current);
+        return Err(e)
+            .with_context(|| format!("unable to replace executable '{}'", current.display()));
+    }
+    Ok(())
+}
+
+/// Remove the executable left over by a previous update, if there is one.
+pub(crate) fn remove_old_exe() {
+    if let Ok(exe) = std::env::current_exe() {
+        let old = old_exe_path(&exe);
+        if old.is_file() {
+            let _ = fs::remove_file(old);
+        }
+    }
+}
+
+/// A simplified semantic version, which only consider the numeric
+/// `major.minor.patch` and the optional pre-release part.
+#[derive(Debug, PartialEq, Eq)]
+struct Version<'a> {
+    numbers: [u64; 3],
+    pre: Option<&'a str>,
+}
+
+impl<'a> Version<'a> {
+    fn parse(s: &'a str) -> Option<Self> {
+        let s = s.trim().trim_start_matches('v');
+        // build metadata does not affect precedence
+        let s = s.split_once('+').map_or(s, |(ver, _)| ver);
+        let (nums, pre) = match s.split_once('-') {
+            Some((nums, pre)) => (nums, Some(pre)),
+            None => (s, None),
+        };
+        let mut numbers = [0; 3];
+        let mut parts = nums.split('.');
+        for num in &mut numbers {
+            *num = parts.next()?.parse().ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self { numbers, pre })
+    }
+}
+
+impl Ord for Version<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.numbers
+            .cmp(&other.numbers)
+            .then_with(|| match (self.pre, other.pre) {
+                (None, None) => Ordering::Equal,
+                // a pre-release version has lower precedence than a normal version
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => cmp_pre_release(a, b),
+            })
+    }
+}
+
+impl PartialOrd for Version<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compare pre-release identifiers, numeric identifiers are compared numerically
+/// and have lower precedence than alphanumeric ones.
+fn cmp_pre_release(a: &str, b: &str) -> Ordering {
+    let mut a_ids = a.split('.');
+    let mut b_ids = b.split('.');
+    loop {
+        let ord = match (a_ids.next(), b_ids.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => x.cmp(y),
+            },
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_versions() {
+        let v = |s| Version::parse(s).unwrap();
+        assert!(v("0.1.0") > v("0.1.0-alpha.4"));
+        assert!(v("0.1.0-alpha.10") > v("0.1.0-alpha.4"));
+        assert!(v("0.1.0-beta") > v("0.1.0-alpha.4"));
+        assert!(v("0.1.0-alpha") < v("0.1.0-alpha.1"));
+        assert!(v("1.0.0") > v("0.10.0"));
+        assert_eq!(v("v0.2.0+build.1"), v("0.2.0"));
+        assert!(Version::parse("1.0").is_none());
+        assert!(Version::parse("1.0.0.0").is_none());
+    }
+
+    #[test]
+    fn find_outdated_toolkit() {
+        let manifest = ToolsetManifest::from_str(&format!(
+            r#"
+[rust]
+version = "1.81.0"
+
+[tools.target.{}]
+foo = "1.1.0"
+bar = {{ url = "https://example.com/bar.zip", version = "2.0" }}
+baz = "0.1.0"
+"#,
+            utils::host_triple()
+        ))
+        .unwrap();
+        let record = InstallationRecord::from_str(
+            r#"
+install-dir = "/path/to/install"
+
+[rust]
+version = "1.80.0"
+
+[tools.foo]
+version = "1.0.0"
+
+[tools.bar]
+version = "2.0"
+"#,
+        )
+        .unwrap();
+
+        let outdated = find_outdated(&record, &manifest);
+        assert_eq!(
+            outdated,
+            [
+                Outdated::Rust {
+                    installed: "1.80.0",
+                    latest: "1.81.0"
+                },
+                Outdated::Tool {
+                    name: "foo",
+                    installed: Some("1.0.0"),
+                    latest: Some("1.1.0")
+                },
+            ]
+        );
+        assert_eq!(outdated[1].to_string(), "foo: 1.0.0 -> 1.1.0");
+    }
+
+    #[test]
+    fn verify_sha256_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("manager");
+        fs::write(&path, "hello").unwrap();
+        let checksum = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        verify_checksum(&path, &format!("{checksum}  manager\n")).unwrap();
+        assert!(verify_checksum(&path, "0000").is_err());
+    }
+}
@@ -0,0 +1,353 @@
+//! Integrity verification of an existing installation, by comparing the installed files
+//! against the checksum manifests of the tools.
+//!
+//! The checksums of the installed files of each tool are kept in its own manifest at
+//! `<install_dir>/checksums/<name>.sha256`, in the format of `sha256sum`, i.e. one
+//! `<checksum>  <path>` per line, where the paths are relative to the installation directory
+//! if they are under it, so that the manifests stay valid after the installation is moved.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use super::parser::fingerprint::InstallationRecord;
+use super::uninstall::UninstallConfiguration;
+use crate::utils;
+
+/// A problem found by [`verify_installation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Problem {
+    /// A recorded file (or directory) of a tool no longer exists.
+    Missing(PathBuf),
+    /// The content of a recorded file of a tool was changed.
+    Modified(PathBuf),
+}
+
+/// The result of [`verify_installation`].
+#[derive(Debug, Default)]
+pub(crate) struct VerifyReport {
+    /// The problems of each tool, the intact tools are not listed.
+    pub(crate) tools: BTreeMap<String, Vec<Problem>>,
+    /// The files in `tools` directory and `cargo`'s `bin` directory that
+    /// don't belong to any tool, nor are they managed by `rustup` or `cargo install`.
+    pub(crate) foreign: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    pub(crate) fn is_ok(&self) -> bool {
+        self.tools.is_empty() && self.foreign.is_empty()
+    }
+}
+
+/// Calculate the SHA-256 checksums of the files under `paths`, keyed by their paths.
+///
+/// Directories are walked recursively, symbolic links are not followed,
+/// and the files that cannot be read are ignored.
+pub(crate) fn checksums_of(paths: &[PathBuf]) -> BTreeMap<PathBuf, String> {
+    paths
+        .iter()
+        .flat_map(|path| {
+            if path.is_dir() && !path.is_symlink() {
+                utils::walk_dir(path, true).unwrap_or_default()
+            } else {
+                vec![path.clone()]
+            }
+        })
+        .filter(|path| path.symlink_metadata().is_ok_and(|meta| meta.is_file()))
+        .filter_map(|path| {
+            let checksum = utils::sha256_file(&path).ok()?;
+            Some((path, checksum))
+        })
+        .collect()
+}
+
+/// The name of the directory under `install_dir` to keep the checksum manifests of tools.
+pub(crate) const CHECKSUMS_DIR: &str = "checksums";
+
+fn checksums_path(install_dir: &Path, name: &str) -> PathBuf {
+    install_dir
+        .join(CHECKSUMS_DIR)
+        .join(format!("{name}.sha256"))
+}
+
+/// Write the `checksums` of the installed files of tool `name` into its checksum manifest,
+/// replacing the existing one.
+pub(crate) fn write_checksums(
+    install_dir: &Path,
+    name: &str,
+    checksums: &BTreeMap<PathBuf, String>,
+) -> Result<()> {
+    let content = checksums
+        .iter()
+        .filter_map(|(path, checksum)| {
+            let path = path.strip_prefix(install_dir).unwrap_or(path).to_str()?;
+            // Such paths cannot be written in one line, which are rather unlikely anyway.
+            (!path.contains('\n')).then(|| format!("{checksum}  {path}"))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let path = checksums_path(install_dir, name);
+    utils::ensure_parent_dir(&path)?;
+    utils::write_file(path, &content, false)
+}
+
+/// Read the checksum manifest of tool `name`, keyed by the absolute paths of the files.
+///
+/// Returns `None` if there isn't one, such as for the tools installed by older versions
+/// of this program.
+pub(crate) fn read_checksums(install_dir: &Path, name: &str) -> Option<BTreeMap<PathBuf, String>> {
+    let content = utils::read_to_string(checksums_path(install_dir, name)).ok()?;
+    let checksums = content
+        .lines()
+        .filter_map(|line| line.split_once("  "))
+        .map(|(checksum, path)| (install_dir.join(path), checksum.to_string()))
+        .collect();
+    Some(checksums)
+}
+
+/// Remove the checksum manifest of tool `name` once it's uninstalled.
+pub(crate) fn remove_checksums(install_dir: &Path, name: &str) -> Result<()> {
+    utils::remove(checksums_path(install_dir, name))
+}
+
+/// The files and directories that belong to the installed tools.
+#[derive(Default)]
+struct Owned<'a> {
+    /// The files in the checksum manifests.
+    files: HashSet<&'a Path>,
+    /// The paths of the tools without checksum manifests, everything under them belongs to them.
+    roots: Vec<&'a Path>,
+    /// The paths of all tools, under which directories and links are not foreign by themselves.
+    tool_paths: Vec<&'a Path>,
+    /// The directories containing any of the above.
+    ancestors: HashSet<&'a Path>,
+}
+
+impl<'a> Owned<'a> {
+    fn new(
+        record: &'a InstallationRecord,
+        checksums: &'a BTreeMap<&str, Option<BTreeMap<PathBuf, String>>>,
+    ) -> Self {
+        let mut owned = Self::default();
+        for (name, tool) in &record.tools {
+            owned
+                .tool_paths
+                .extend(tool.paths.iter().map(PathBuf::as_path));
+            match &checksums[name.as_str()] {
+                Some(checksums) => owned.files.extend(checksums.keys().map(PathBuf::as_path)),
+                None => owned.roots.extend(tool.paths.iter().map(PathBuf::as_path)),
+            }
+        }
+        let ancestors = owned
+            .files
+            .iter()
+            .chain(&owned.roots)
+            .chain(&owned.tool_paths)
+            .flat_map(|path| path.ancestors().skip(1))
+            .collect();
+        owned.ancestors = ancestors;
+        owned
+    }
+
+    /// Find the entries under `dir` that don't belong to any tool, a directory is reported
+    /// as a whole if there's nothing owned in it, or ignored if there's nothing in it at all.
+    fn find_foreign(&self, dir: &Path, foreign: &mut Vec<PathBuf>) -> Result<()> {
+        for path in utils::walk_dir(dir, false)? {
+            if self.files.contains(path.as_path())
+                || self.roots.iter().any(|root| path.starts_with(root))
+            {
+                continue;
+            }
+            let in_tool = self.tool_paths.iter().any(|p| path.starts_with(p));
+            if path.is_symlink() {
+                // Links are not hashed, thus cannot be told apart from the tool's own ones.
+                if !in_tool {
+                    foreign.push(path);
+                }
+            } else if path.is_dir() && self.ancestors.contains(path.as_path()) {
+                self.find_foreign(&path, foreign)?;
+            } else if path.is_dir() && in_tool {
+                // Empty directories of a tool, such as the ones extracted from its archive.
+                let has_files = utils::walk_dir(&path, true)?
+                    .iter()
+                    .any(|p| p.is_symlink() || !p.is_dir());
+                if has_files {
+                    foreign.push(path);
+                }
+            } else {
+                foreign.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Re-hash the installed files of every recorded tool under `install_dir`, to find the ones
+/// that were removed or modified, then look for the foreign files in `tools` directory
+/// and `cargo`'s `bin` directory.
+///
+/// Tools installed by older versions of this program have no checksum manifests,
+/// thus only the existence of their paths are checked.
+pub(crate) fn verify_installation(install_dir: &Path) -> Result<VerifyReport> {
+    let record = InstallationRecord::load_or_new(install_dir)?;
+    let mut report = VerifyReport::default();
+    let checksums = record
+        .tools
+        .keys()
+        .map(|name| (name.as_str(), read_checksums(install_dir, name)))
+        .collect::<BTreeMap<_, _>>();
+
+    for (name, tool) in &record.tools {
+        let mut problems = tool
+            .paths
+            .iter()
+            .filter(|path| !path.exists() && !path.is_symlink())
+            .cloned()
+            .map(Problem::Missing)
+            .collect::<Vec<_>>();
+        for (path, expected) in checksums[name.as_str()].iter().flatten() {
+            if problems
+                .iter()
+                .any(|p| matches!(p, Problem::Missing(missing) if path.starts_with(missing)))
+            {
+                continue;
+            }
+            match utils::sha256_file(path) {
+                Ok(actual) if actual.eq_ignore_ascii_case(expected) => (),
+                Ok(_) => problems.push(Problem::Modified(path.clone())),
+                Err(_) => problems.push(Problem::Missing(path.clone())),
+            }
+        }
+        if !problems.is_empty() {
+            report.tools.insert(name.clone(), problems);
+        }
+    }
+
+    let tools_dir = install_dir.join("tools");
+    if tools_dir.is_dir() {
+        Owned::new(&record, &checksums).find_foreign(&tools_dir, &mut report.foreign)?;
+    }
+    let config = UninstallConfiguration {
+        install_dir: install_dir.to_path_buf(),
+        record,
+    };
+    report
+        .foreign
+        .extend(config.user_binaries(&install_dir.join(".cargo"))?);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::core::parser::fingerprint::ToolRecord;
+
+    #[test]
+    fn verify_tools() {
+        let install_dir = tempfile::tempdir().unwrap();
+        let tools_dir = install_dir.path().join("tools");
+        for name in ["foo", "bar", "baz", "unknown"] {
+            fs::create_dir_all(tools_dir.join(name)).unwrap();
+            fs::write(tools_dir.join(name).join("main"), name).unwrap();
+        }
+        fs::create_dir_all(install_dir.path().join(".cargo").join("bin")).unwrap();
+
+        let mut record = InstallationRecord {
+            install_dir: install_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        for name in ["foo", "bar", "baz"] {
+            let paths = vec![tools_dir.join(name)];
+            write_checksums(install_dir.path(), name, &checksums_of(&paths)).unwrap();
+            record.add_tool_record(
+                name,
+                ToolRecord {
+                    paths,
+                    ..Default::default()
+                },
+            );
+        }
+        assert_eq!(
+            utils::read_to_string(install_dir.path().join("checksums").join("foo.sha256"))
+                .unwrap()
+                .trim(),
+            format!("{}  tools/foo/main", utils::sha256_str("foo"))
+        );
+        record.write().unwrap();
+        assert!(verify_installation(install_dir.path())
+            .unwrap()
+            .tools
+            .is_empty());
+
+        fs::write(tools_dir.join("bar").join("main"), "modified").unwrap();
+        fs::remove_dir_all(tools_dir.join("baz")).unwrap();
+        let report = verify_installation(install_dir.path()).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.tools["bar"],
+            [Problem::Modified(tools_dir.join("bar").join("main"))]
+        );
+        assert_eq!(
+            report.tools["baz"],
+            [Problem::Missing(tools_dir.join("baz"))]
+        );
+        assert!(!report.tools.contains_key("foo"));
+        assert_eq!(report.foreign, [tools_dir.join("unknown")]);
+    }
+
+    #[test]
+    fn find_foreign_files_inside_tools() {
+        let install_dir = tempfile::tempdir().unwrap();
+        let tools_dir = install_dir.path().join("tools");
+        for name in ["foo", "old"] {
+            fs::create_dir_all(tools_dir.join(name).join("bin")).unwrap();
+            fs::write(tools_dir.join(name).join("bin").join(name), name).unwrap();
+        }
+        fs::create_dir_all(tools_dir.join("foo").join("empty")).unwrap();
+        fs::create_dir_all(install_dir.path().join(".cargo").join("bin")).unwrap();
+
+        let mut record = InstallationRecord {
+            install_dir: install_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        for name in ["foo", "old"] {
+            record.add_tool_record(
+                name,
+                ToolRecord {
+                    paths: vec![tools_dir.join(name)],
+                    ..Default::default()
+                },
+            );
+        }
+        // `old` was installed by an older version, which has no checksum manifest.
+        write_checksums(
+            install_dir.path(),
+            "foo",
+            &checksums_of(&record.tools["foo"].paths),
+        )
+        .unwrap();
+        record.write().unwrap();
+        assert!(verify_installation(install_dir.path()).unwrap().is_ok());
+
+        fs::write(tools_dir.join("foo").join("bin").join("extra"), "").unwrap();
+        fs::create_dir_all(tools_dir.join("foo").join("plugins").join("a")).unwrap();
+        fs::write(
+            tools_dir.join("foo").join("plugins").join("a").join("b"),
+            "",
+        )
+        .unwrap();
+        fs::write(tools_dir.join("old").join("bin").join("extra"), "").unwrap();
+        let mut foreign = verify_installation(install_dir.path()).unwrap().foreign;
+        foreign.sort();
+        assert_eq!(
+            foreign,
+            [
+                tools_dir.join("foo").join("bin").join("extra"),
+                tools_dir.join("foo").join("plugins"),
+            ]
+        );
+    }
+}
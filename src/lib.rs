@@ -1,11 +1,27 @@
 #![deny(unused_must_use)]
 #![allow(clippy::ptr_arg)]
 
+pub mod api;
 pub mod cli;
 mod core;
 pub mod utils;
 
 // Exports
-pub use core::install::{default_install_dir, EnvConfig, InstallConfiguration};
+pub use api::{
+    install, list_components, uninstall, Component, ComponentSelection, InstallOutcome,
+    InstallRequest, UninstallRequest,
+};
+pub use core::error::{error_code, InstallError};
+pub use core::install::{
+    default_install_dir, estimate_required_space, EnvConfig, InstallConfiguration,
+};
+pub use core::lock::InstallLock;
 pub use core::parser::manifest;
+pub use core::preflight::{collect_preflight_report, DetectedProxy, PreflightReport, ProxySource};
+pub use core::report::{
+    parse_existing_tool_choice, ExistingTool, ExistingToolChoice, PreInstallReport,
+};
+pub use core::session::{InstallSession, SessionStep};
+pub use core::shims::run_if_shim;
 pub use core::try_it::try_it;
+pub use utils::{ProgressEvent, ProgressReporter};
@@ -0,0 +1,94 @@
+//! Cancellation of the in-flight installation, which could be requested by the GUI,
+//! or by pressing `Ctrl-C` once [`cancel_on_ctrl_c`] is called.
+//!
+//! Long running operations, such as downloading, extracting and running child processes,
+//! [check](check_cancelled) this periodically, then stop with [`InstallError::Cancelled`],
+//! so that the installation could be rolled back as if it failed,
+//! or kept to be resumed later if it was [suspended](suspend).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+
+use crate::core::error::InstallError;
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+/// Request to cancel the in-flight installation.
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::SeqCst);
+}
+
+/// Check whether the cancellation was requested.
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Request to suspend the in-flight installation, which stops it like [`cancel`],
+/// but what was done should be kept instead of rolled back, so that it could be resumed later.
+pub fn suspend() {
+    SUSPENDED.store(true, Ordering::SeqCst);
+    cancel();
+}
+
+/// Check whether the suspension was requested.
+pub fn is_suspended() -> bool {
+    SUSPENDED.load(Ordering::SeqCst)
+}
+
+/// Return [`InstallError::Cancelled`] if the cancellation was requested.
+pub fn check_cancelled() -> Result<()> {
+    if is_cancelled() {
+        return Err(InstallError::Cancelled.into());
+    }
+    Ok(())
+}
+
+/// Clear the cancellation (and suspension) request, so that another installation could be
+/// started, i.e. after the cancelled one was rolled back.
+pub fn reset_cancellation() {
+    CANCELLED.store(false, Ordering::SeqCst);
+    SUSPENDED.store(false, Ordering::SeqCst);
+}
+
+/// Request the cancellation when `Ctrl-C` is pressed, instead of terminating this process,
+/// pressing it again terminates this process immediately.
+pub fn cancel_on_ctrl_c() {
+    #[cfg(unix)]
+    {
+        extern "C" fn handler(_: libc::c_int) {
+            if CANCELLED.swap(true, Ordering::SeqCst) {
+                // SAFETY: `_exit` is async-signal-safe.
+                unsafe { libc::_exit(130) };
+            }
+        }
+        let handler: extern "C" fn(libc::c_int) = handler;
+        // SAFETY: the handler only touches an atomic, which is async-signal-safe.
+        unsafe {
+            libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+            libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+        }
+    }
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::Foundation::{BOOL, FALSE, TRUE};
+        use windows_sys::Win32::System::Console::{
+            SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT,
+        };
+
+        unsafe extern "system" fn handler(ctrl_type: u32) -> BOOL {
+            if ctrl_type != CTRL_C_EVENT && ctrl_type != CTRL_BREAK_EVENT {
+                return FALSE;
+            }
+            // Let the default handler terminate this process when it's pressed again.
+            if CANCELLED.swap(true, Ordering::SeqCst) {
+                FALSE
+            } else {
+                TRUE
+            }
+        }
+        // SAFETY: the handler only touches an atomic.
+        unsafe { SetConsoleCtrlHandler(Some(handler), TRUE) };
+    }
+}
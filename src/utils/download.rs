@@ -1,23 +1,228 @@
 use std::cmp::min;
 use std::fs::{self, OpenOptions};
-use std::io::{self, Write};
-use std::path::Path;
-use std::time::Duration;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
 use reqwest::blocking::{Client, ClientBuilder};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE};
+use reqwest::Certificate;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use url::Url;
 
 use crate::manifest::Proxy;
 
-use super::progress_bar::{ProgressIndicator, Style};
+use super::cancel::{check_cancelled, is_cancelled};
+use super::extraction::ExtractableKind;
+use super::output::OutputEvent;
+use super::progress::{report_progress, ProgressEvent};
+use super::progress_bar::{ProgressIndicator, Style, TransferRate};
+
+/// The default timeout of each download request.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+static RATE_LIMIT: OnceLock<u64> = OnceLock::new();
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+static RETRY: OnceLock<RetryPolicy> = OnceLock::new();
+static CA_CERTS: OnceLock<Vec<Certificate>> = OnceLock::new();
+
+/// How to retry a download on transient failures, such as timeouts,
+/// connection resets and server errors.
+///
+/// Permanent failures, such as `404 Not Found`, are never retried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt, `0` means no retry.
+    pub retries: u32,
+    /// Time to wait before the first retry, which doubles after each retry.
+    pub backoff: Duration,
+    /// The maximum time to wait before each retry.
+    pub max_backoff: Duration,
+    /// The HTTP status codes that are considered as transient.
+    pub retry_on_status: Vec<StatusCode>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            retry_on_status: vec![
+                StatusCode::REQUEST_TIMEOUT,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Time to wait before the `nth` retry (starting from 1).
+    fn backoff_of(&self, nth: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(nth.saturating_sub(1));
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+
+    /// Check whether a download error is transient and worth retrying.
+    fn is_retryable(&self, err: &anyhow::Error) -> bool {
+        err.chain().any(|cause| {
+            if let Some(e) = cause.downcast_ref::<StatusError>() {
+                self.retry_on_status.contains(&e.status)
+            } else if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                is_transient_request_error(e)
+            } else if let Some(e) = cause.downcast_ref::<io::Error>() {
+                // Errors when reading the response body are wrapped in `io::Error`.
+                let inner = e.get_ref().and_then(|inner| inner.downcast_ref());
+                inner.is_some_and(is_transient_request_error)
+                    || matches!(
+                        e.kind(),
+                        io::ErrorKind::TimedOut
+                            | io::ErrorKind::ConnectionReset
+                            | io::ErrorKind::ConnectionAborted
+                            | io::ErrorKind::UnexpectedEof
+                            | io::ErrorKind::Interrupted
+                    )
+            } else {
+                false
+            }
+        })
+    }
+}
+
+fn is_transient_request_error(err: &reqwest::Error) -> bool {
+    err.is_timeout() || err.is_connect() || err.is_body()
+}
+
+/// The server responds with an unsuccessful status code.
+#[derive(Debug, thiserror::Error)]
+#[error("server returns error when attempting download from '{url}': {status}")]
+struct StatusError {
+    url: String,
+    status: StatusCode,
+}
+
+/// Set the global retry policy of downloads, default to [`RetryPolicy::default`].
+///
+/// This can only be set once, any subsequent calls will be ignored.
+pub fn set_download_retry(policy: RetryPolicy) {
+    let _ = RETRY.set(policy);
+}
+
+/// Set the global timeout of each download request in seconds, default to 30 seconds.
+///
+/// This can only be set once, any subsequent calls will be ignored.
+pub fn set_download_timeout(secs: u64) {
+    let _ = TIMEOUT.set(Duration::from_secs(secs));
+}
+
+/// Set the global download speed limit in bytes per second, `0` means unlimited.
+///
+/// This can only be set once, any subsequent calls will be ignored.
+pub fn set_download_rate_limit(bytes_per_sec: u64) {
+    let _ = RATE_LIMIT.set(bytes_per_sec);
+}
+
+/// Trust the certificates in a PEM bundle file as additional root certificates for downloads,
+/// such as the root CA of a corporate proxy which intercepts TLS connections.
+///
+/// This can only be set once, any subsequent calls will be ignored.
+pub fn set_download_ca_cert(path: &Path) -> Result<()> {
+    if CA_CERTS.get().is_some() {
+        return Ok(());
+    }
+    let pem = fs::read(path)
+        .with_context(|| format!("unable to read CA certificate '{}'", path.display()))?;
+    let certs = Certificate::from_pem_bundle(&pem)
+        .with_context(|| format!("invalid CA certificate '{}'", path.display()))?;
+    if certs.is_empty() {
+        bail!("no certificate found in '{}'", path.display());
+    }
+    let _ = CA_CERTS.set(certs);
+    Ok(())
+}
+
+/// Write a PEM bundle into `dest`, containing the certificates in `extra` followed by
+/// the root certificates trusted by this system.
+///
+/// This is needed by the programs that can only trust a single bundle file, such as `cargo`
+/// with `CARGO_HTTP_CAINFO`, which would otherwise stop trusting the public certificates
+/// once pointed to the certificate of a corporate proxy alone.
+pub(crate) fn write_ca_bundle(extra: &Path, dest: &Path) -> Result<()> {
+    let mut bundle = fs::read_to_string(extra)
+        .with_context(|| format!("unable to read CA certificate '{}'", extra.display()))?;
+    if !bundle.ends_with('\n') {
+        bundle.push('\n');
+    }
+    let native = rustls_native_certs::load_native_certs();
+    for err in &native.errors {
+        tracing::warn!("unable to load some of the system certificates: {err}");
+    }
+    for cert in &native.certs {
+        bundle.push_str(&pem_encode(cert));
+    }
+    super::write_file(dest, bundle.trim_end(), false)
+}
+
+fn pem_encode(der: &[u8]) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(der);
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    pem
+}
+
+/// Parse a download speed limit string, similar to the `--limit-rate` option of `curl`.
+///
+/// The value is in bytes per second, with an optional suffix `K`, `M` or `G`
+/// (case insensitive, 1K = 1024 bytes), i.e. `200K`, `1.5M`, `1048576`.
+pub fn parse_rate_limit(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let (num, unit) = match s.char_indices().last() {
+        Some((idx, c)) if c.is_ascii_alphabetic() => (&s[..idx], c.to_ascii_lowercase()),
+        _ => (s, 'b'),
+    };
+    let multiplier: u64 = match unit {
+        'b' => 1,
+        'k' => 1 << 10,
+        'm' => 1 << 20,
+        'g' => 1 << 30,
+        _ => bail!("invalid rate '{s}', expecting a number with optional suffix 'K', 'M' or 'G'"),
+    };
+    let num: f64 = num
+        .parse()
+        .with_context(|| format!("invalid rate '{s}', expecting a number"))?;
+    if num < 0.0 {
+        bail!("invalid rate '{s}', the value cannot be negative");
+    }
+    Ok((num * multiplier as f64) as u64)
+}
 
 fn client_builder() -> ClientBuilder {
     let user_agent = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
-    Client::builder()
+    let builder = Client::builder()
         .user_agent(user_agent)
-        .timeout(Duration::from_secs(30))
-        .connection_verbose(false)
+        .timeout(TIMEOUT.get().copied().unwrap_or(DEFAULT_TIMEOUT))
+        .connection_verbose(false);
+    CA_CERTS
+        .get()
+        .into_iter()
+        .flatten()
+        .fold(builder, |builder, cert| {
+            builder.add_root_certificate(cert.clone())
+        })
 }
 
 pub struct DownloadOpt<T: Sized> {
@@ -25,6 +230,9 @@ pub struct DownloadOpt<T: Sized> {
     pub name: String,
     client: Client,
     pub handler: Option<ProgressIndicator<T>>,
+    /// Maximum download speed in bytes per second, `None` means unlimited.
+    rate_limit: Option<u64>,
+    retry: RetryPolicy,
 }
 
 impl<T: Sized> DownloadOpt<T> {
@@ -40,10 +248,223 @@ impl<T: Sized> DownloadOpt<T> {
             name,
             client,
             handler,
+            rate_limit: RATE_LIMIT.get().copied().filter(|limit| *limit > 0),
+            retry: RETRY.get().cloned().unwrap_or_default(),
         })
     }
-    // TODO: make local file download fancier
+
+    /// Set how to retry on transient failures.
+    ///
+    /// Default to the value set by [`set_download_retry`].
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Download the file from `url` to `path`, retrying on transient failures
+    /// according to the [`RetryPolicy`].
+    ///
+    /// If `resume` is `true`, the file will be downloaded into a `<path>.part` file first,
+    /// which is kept when the download is interrupted, so that the next attempt can
+    /// continue from where it left off using an HTTP `Range` request.
+    /// The `.part` file is renamed to `path` once the download is completed.
     pub fn download_file(&self, url: &Url, path: &Path, resume: bool) -> Result<()> {
+        tracing::debug!("downloading '{url}' to '{}'", path.display());
+        self.retrying(|| self.download_file_once(url, path, resume))
+    }
+
+    /// Download a compressed tarball from `url` and extract it into `root` on the fly,
+    /// without storing the tarball itself (unless `copy_to` is given, such as a path in the
+    /// download cache), then return the SHA-256 checksum of the tarball.
+    ///
+    /// Each attempt extracts into a fresh directory next to `root`, which replaces `root`
+    /// only after `verify` accepted the checksum, so that neither the leftovers of a failed
+    /// attempt nor the content of a mismatched tarball could end up in `root`.
+    /// The same goes for `copy_to`, which is written to `<copy_to>.part` first.
+    ///
+    /// This is retried according to the [`RetryPolicy`] as well,
+    /// in which case the tarball is downloaded and extracted again from scratch.
+    pub fn download_and_extract(
+        &self,
+        url: &Url,
+        kind: ExtractableKind,
+        root: &Path,
+        copy_to: Option<&Path>,
+        verify: &dyn Fn(&str) -> Result<()>,
+    ) -> Result<String> {
+        self.retrying(|| {
+            let result = self.download_and_extract_once(url, kind, root, copy_to, verify);
+            if result.is_err() {
+                if let Some(copy_to) = copy_to {
+                    // Never resumed, as the content is unknown.
+                    let _ = fs::remove_file(part_file_path(copy_to));
+                }
+            }
+            result
+        })
+    }
+
+    /// Report the [`ProgressEvent::DownloadProgress`] of this file.
+    ///
+    /// Failures are ignored, as they shouldn't stop the download.
+    fn report_download(&self, bytes: u64, total: Option<u64>) {
+        let _ = report_progress(ProgressEvent::DownloadProgress {
+            name: self.name.clone(),
+            bytes,
+            total,
+        });
+    }
+
+    /// Run `f` until it succeeds, or it fails with a permanent error, or runs out of retries.
+    fn retrying<R>(&self, mut f: impl FnMut() -> Result<R>) -> Result<R> {
+        let mut nth_retry = 0;
+        loop {
+            match f() {
+                Ok(val) => return Ok(val),
+                Err(e)
+                    if !is_cancelled()
+                        && nth_retry < self.retry.retries
+                        && self.retry.is_retryable(&e) =>
+                {
+                    nth_retry += 1;
+                    let backoff = self.retry.backoff_of(nth_retry);
+                    OutputEvent::Message {
+                        text: &format!(
+                            "failed to download '{}': {e:#}, retrying in {}s ({nth_retry}/{})",
+                            self.name,
+                            backoff.as_secs_f32(),
+                            self.retry.retries
+                        ),
+                    }
+                    .emit();
+                    thread::sleep(backoff);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn download_and_extract_once(
+        &self,
+        url: &Url,
+        kind: ExtractableKind,
+        root: &Path,
+        copy_to: Option<&Path>,
+        verify: &dyn Fn(&str) -> Result<()>,
+    ) -> Result<String> {
+        let parent = root
+            .parent()
+            .ok_or_else(|| anyhow!("invalid extraction directory '{}'", root.display()))?;
+        super::ensure_dir(parent)?;
+        let staging = tempfile::Builder::new()
+            .prefix(".extracting_")
+            .tempdir_in(parent)
+            .with_context(|| {
+                format!(
+                    "unable to create temp directory under '{}'",
+                    parent.display()
+                )
+            })?;
+        let copy = copy_to
+            .map(|path| {
+                let part = part_file_path(path);
+                fs::File::create(&part)
+                    .with_context(|| format!("failed to create '{}'", part.display()))
+            })
+            .transpose()?;
+
+        let (source, total_size): (Box<dyn Read>, Option<u64>) = if url.scheme() == "file" {
+            let path = url.to_file_path().map_err(|_| {
+                anyhow!("unable to convert to file path for url '{}'", url.as_str())
+            })?;
+            let file = fs::File::open(&path)
+                .with_context(|| format!("failed to open '{}'", path.display()))?;
+            let len = file.metadata()?.len();
+            (Box::new(file), Some(len))
+        } else {
+            let resp = self.client.get(url.as_ref()).send().with_context(|| {
+                format!(
+                    "failed to receive surver response when downloading from '{}':",
+                    url.as_str()
+                )
+            })?;
+            let status = resp.status();
+            if !status.is_success() {
+                return Err(StatusError {
+                    url: url.to_string(),
+                    status,
+                }
+                .into());
+            }
+            let len = resp.content_length();
+            (Box::new(resp), len)
+        };
+
+        let maybe_indicator = self.handler.as_ref().and_then(|h| {
+            (h.start)(
+                total_size.unwrap_or_default(),
+                format!("downloading and extracting '{}'", &self.name),
+                Style::Bytes,
+            )
+            .ok()
+        });
+        let mut reader = StreamReader {
+            inner: source,
+            hasher: Sha256::new(),
+            throttle: self
+                .rate_limit
+                .map(|limit| TokenBucket::new(limit, Instant::now())),
+            progress: self.handler.as_ref().zip(maybe_indicator.as_ref()),
+            read_len: 0,
+            transfer_rate: TransferRate::new(0, Instant::now()),
+            opt: self,
+            total_size,
+            copy,
+        };
+        kind.extract_stream(&mut reader, staging.path())
+            .with_context(|| format!("failed to extract '{}' from '{url}'", self.name))?;
+        // Drain the rest of the stream (the padding after the tarball, if there is any),
+        // so that the checksum covers the whole file.
+        io::copy(&mut reader, &mut io::sink())?;
+        self.report_download(reader.read_len, total_size);
+        let checksum: String = reader
+            .hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        if let Some(mut copy) = reader.copy.take() {
+            copy.flush()?;
+        }
+        verify(&checksum)?;
+
+        if root.exists() {
+            super::remove(root)?;
+        }
+        fs::rename(staging.path(), root).with_context(|| {
+            format!(
+                "failed to move extracted files of '{}' to '{}'",
+                self.name,
+                root.display()
+            )
+        })?;
+        if let Some(path) = copy_to {
+            fs::rename(part_file_path(path), path)?;
+        }
+
+        if let Some(indicator) = &maybe_indicator {
+            // safe to unwrap, because indicator won't exist if self.handler is none
+            (self.handler.as_ref().unwrap().stop)(
+                indicator,
+                format!("'{}' successfully downloaded and extracted.", &self.name),
+            );
+        }
+
+        Ok(checksum)
+    }
+
+    // TODO: make local file download fancier
+    fn download_file_once(&self, url: &Url, path: &Path, resume: bool) -> Result<()> {
         if url.scheme() == "file" {
             fs::copy(
                 url.to_file_path().map_err(|_| {
@@ -54,22 +475,45 @@ impl<T: Sized> DownloadOpt<T> {
             return Ok(());
         }
 
-        let mut resp = self.client.get(url.as_ref()).send().with_context(|| {
+        let part_path = part_file_path(path);
+        let dest = if resume { part_path.as_path() } else { path };
+        let existing_len = if resume {
+            fs::metadata(dest).map(|m| m.len()).unwrap_or_default()
+        } else {
+            0
+        };
+
+        let mut req = self.client.get(url.as_ref());
+        if existing_len > 0 {
+            req = req.header(RANGE, format!("bytes={existing_len}-"));
+        }
+        let mut resp = req.send().with_context(|| {
             format!(
                 "failed to receive surver response when downloading from '{}':",
                 url.as_str()
             )
         })?;
         let status = resp.status();
+        if status == StatusCode::RANGE_NOT_SATISFIABLE && existing_len > 0 {
+            // The partial file is probably complete (or corrupted),
+            // discard it and download again from scratch.
+            fs::remove_file(dest)?;
+            return self.download_file_once(url, path, resume);
+        }
         if !status.is_success() {
-            bail!(
-                "server returns error when attempting download from '{}': {status}",
-                url.as_str()
-            );
+            return Err(StatusError {
+                url: url.to_string(),
+                status,
+            }
+            .into());
         }
+        // Servers that don't support range requests would send the whole file instead.
+        let is_resuming = existing_len > 0 && status == StatusCode::PARTIAL_CONTENT;
+        let resumed_len = if is_resuming { existing_len } else { 0 };
         let total_size = resp
             .content_length()
-            .ok_or_else(|| anyhow!("unable to get file length of '{}'", url.as_str()))?;
+            .ok_or_else(|| anyhow!("unable to get file length of '{}'", url.as_str()))?
+            + resumed_len;
 
         let maybe_indicator = self.handler.as_ref().and_then(|h| {
             (h.start)(
@@ -80,37 +524,57 @@ impl<T: Sized> DownloadOpt<T> {
             .ok()
         });
 
-        let (mut downloaded_len, mut file) = if resume {
-            let file = OpenOptions::new()
+        let mut downloaded_len = resumed_len;
+        let mut file = if is_resuming {
+            OpenOptions::new().append(true).open(dest)?
+        } else {
+            OpenOptions::new()
                 .create(true)
-                .truncate(false)
                 .write(true)
-                .open(path)?;
-            (file.metadata()?.len().saturating_sub(1), file)
-        } else {
-            (
-                0,
-                OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .truncate(true)
-                    .open(path)?,
-            )
+                .truncate(true)
+                .open(dest)?
         };
 
-        let mut buffer = vec![0u8; 65535];
+        // Use smaller chunks when the speed is limited, so that the speed could be more stable.
+        let buffer_size = self
+            .rate_limit
+            .map_or(65535, |limit| limit.clamp(1024, 65535) as usize);
+        let mut buffer = vec![0u8; buffer_size];
+        let mut throttle = self
+            .rate_limit
+            .map(|limit| TokenBucket::new(limit, Instant::now()));
+        let mut transfer_rate = TransferRate::new(downloaded_len, Instant::now());
 
         loop {
-            let bytes_read = io::Read::read(&mut resp, &mut buffer)?;
+            check_cancelled()?;
+            let bytes_read = resp.read(&mut buffer)?;
 
             if bytes_read != 0 {
+                if let Some(bucket) = &mut throttle {
+                    thread::sleep(bucket.take(bytes_read as u64, Instant::now()));
+                }
                 downloaded_len = min(downloaded_len + bytes_read as u64, total_size);
+                // safe to unwrap, because indicator won't exist if self.handler is none
                 if let Some(indicator) = &maybe_indicator {
-                    // safe to unwrap, because indicator won't exist if self.handler is none
                     (self.handler.as_ref().unwrap().update)(indicator, downloaded_len);
                 }
+                if transfer_rate.update(downloaded_len, Instant::now()) {
+                    if let Some(indicator) = &maybe_indicator {
+                        let remaining = total_size.saturating_sub(downloaded_len);
+                        (self.handler.as_ref().unwrap().status)(
+                            indicator,
+                            transfer_rate.summary(remaining),
+                        );
+                    }
+                    self.report_download(downloaded_len, Some(total_size));
+                }
                 file.write_all(&buffer[..bytes_read])?;
             } else {
+                if resume {
+                    drop(file);
+                    fs::rename(dest, path)?;
+                }
+                self.report_download(downloaded_len, Some(total_size));
                 if let Some(indicator) = &maybe_indicator {
                     // safe to unwrap, because indicator won't exist if self.handler is none
                     (self.handler.as_ref().unwrap().stop)(
@@ -125,6 +589,80 @@ impl<T: Sized> DownloadOpt<T> {
     }
 }
 
+/// A reader of the downloading stream, which throttles the speed, reports the progress,
+/// calculates the checksum of the content that has been read, and optionally writes a copy of it.
+struct StreamReader<'a, T> {
+    inner: Box<dyn Read>,
+    hasher: Sha256,
+    throttle: Option<TokenBucket>,
+    progress: Option<(&'a ProgressIndicator<T>, &'a T)>,
+    read_len: u64,
+    /// Only used to limit how often the progress is reported.
+    transfer_rate: TransferRate,
+    opt: &'a DownloadOpt<T>,
+    total_size: Option<u64>,
+    copy: Option<fs::File>,
+}
+
+impl<T> Read for StreamReader<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        check_cancelled().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let bytes_read = self.inner.read(buf)?;
+        if bytes_read != 0 {
+            if let Some(bucket) = &mut self.throttle {
+                thread::sleep(bucket.take(bytes_read as u64, Instant::now()));
+            }
+            self.hasher.update(&buf[..bytes_read]);
+            if let Some(copy) = &mut self.copy {
+                copy.write_all(&buf[..bytes_read])?;
+            }
+            self.read_len += bytes_read as u64;
+            if let Some((handler, indicator)) = self.progress {
+                (handler.update)(indicator, self.read_len);
+            }
+            if self.transfer_rate.update(self.read_len, Instant::now()) {
+                self.opt.report_download(self.read_len, self.total_size);
+            }
+        }
+        Ok(bytes_read)
+    }
+}
+
+/// A token bucket to throttle the download speed, which allows bursts of up to
+/// one second worth of bytes, and is refilled at the rate limit.
+struct TokenBucket {
+    /// Bytes per second.
+    rate: f64,
+    /// Available bytes, which is negative when the download is ahead of the rate limit.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(bytes_per_sec: u64, now: Instant) -> Self {
+        let rate = bytes_per_sec as f64;
+        Self {
+            rate,
+            tokens: rate,
+            last_refill: now,
+        }
+    }
+
+    /// Take `bytes` from the bucket, and return how long to wait
+    /// until the taken bytes conform to the rate limit.
+    fn take(&mut self, bytes: u64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate);
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
 /// Download a file without resuming, with proxy settings.
 pub fn download<S: ToString>(name: S, url: &Url, dest: &Path, proxy: Option<&Proxy>) -> Result<()> {
     let dl_opt = DownloadOpt::new(
@@ -134,3 +672,515 @@ pub fn download<S: ToString>(name: S, url: &Url, dest: &Path, proxy: Option<&Pro
     )?;
     dl_opt.download_file(url, dest, false)
 }
+
+/// Download a file with proxy settings, resuming the previously interrupted download if there is one.
+///
+/// Check [`DownloadOpt::download_file`] for more details.
+pub fn download_resumable<S: ToString>(
+    name: S,
+    url: &Url,
+    dest: &Path,
+    proxy: Option<&Proxy>,
+) -> Result<()> {
+    let dl_opt = DownloadOpt::new(
+        name.to_string(),
+        proxy.cloned(),
+        Some(ProgressIndicator::new()),
+    )?;
+    dl_opt.download_file(url, dest, true)
+}
+
+/// Download a file from the first of the ranked mirrors that works, trying them in order,
+/// then return the url that was actually used.
+///
+/// Check [`download`] and [`download_resumable`] for the meaning of `resume`.
+pub fn download_from_mirrors<S: ToString>(
+    name: S,
+    urls: &[&Url],
+    dest: &Path,
+    proxy: Option<&Proxy>,
+    resume: bool,
+) -> Result<Url> {
+    let name = name.to_string();
+    let (url, ()) = try_mirrors(&name, urls, |url| {
+        if resume {
+            download_resumable(&name, url, dest, proxy)
+        } else {
+            download(&name, url, dest, proxy)
+        }
+    })?;
+    Ok(url)
+}
+
+/// Download a compressed tarball from the first of the ranked mirrors that works,
+/// and extract it into `root` on the fly, without storing the tarball itself.
+///
+/// `verify` is called with the url and the SHA-256 checksum of the tarball before the
+/// extracted files are moved into `root`, the next mirror is tried if it returns an error.
+/// Return the url that was actually used, and the checksum.
+/// Check [`DownloadOpt::download_and_extract`] for more details.
+pub fn download_and_extract_from_mirrors<S: ToString>(
+    name: S,
+    urls: &[&Url],
+    kind: ExtractableKind,
+    root: &Path,
+    copy_to: Option<&Path>,
+    proxy: Option<&Proxy>,
+    verify: &dyn Fn(&Url, &str) -> Result<()>,
+) -> Result<(Url, String)> {
+    let name = name.to_string();
+    try_mirrors(&name, urls, |url| {
+        DownloadOpt::new(name.clone(), proxy.cloned(), Some(ProgressIndicator::new()))?
+            .download_and_extract(url, kind, root, copy_to, &|checksum| verify(url, checksum))
+    })
+}
+
+/// Run `f` with each of the ranked mirrors in order, until one of them succeeds,
+/// then return the url of that mirror along with the result.
+fn try_mirrors<R>(
+    name: &str,
+    urls: &[&Url],
+    mut f: impl FnMut(&Url) -> Result<R>,
+) -> Result<(Url, R)> {
+    let mut errors = vec![];
+    for url in urls {
+        match f(url) {
+            Ok(val) => {
+                if urls.len() > 1 {
+                    OutputEvent::Message {
+                        text: &format!("'{name}' was downloaded from '{url}'"),
+                    }
+                    .emit();
+                }
+                return Ok(((*url).clone(), val));
+            }
+            Err(e) => {
+                if urls.len() > 1 {
+                    OutputEvent::Message {
+                        text: &format!(
+                            "failed to download '{name}' from '{url}', trying next mirror"
+                        ),
+                    }
+                    .emit();
+                }
+                errors.push(format!("{url}: {e:#}"));
+            }
+        }
+    }
+    bail!(
+        "unable to download '{name}' from any of the mirrors:\n  {}",
+        errors.join("\n  ")
+    )
+}
+
+/// The validators of a cached download, which are stored next to it as `<path>.cache.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheValidators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// Download a (small) file from `url` into `dest` with proxy settings, unless the previously
+/// downloaded `dest` is still up to date, which is revalidated with the server using the
+/// `ETag` and `Last-Modified` headers of the previous response.
+///
+/// If the server cannot be reached, the previously downloaded file is used as is.
+/// Return `true` if the file was (re)downloaded.
+pub fn download_revalidated<S: ToString>(
+    name: S,
+    url: &Url,
+    dest: &Path,
+    proxy: Option<&Proxy>,
+) -> Result<bool> {
+    let name = name.to_string();
+    if url.scheme() == "file" {
+        super::ensure_parent_dir(dest)?;
+        download(&name, url, dest, proxy)?;
+        return Ok(true);
+    }
+
+    let mut meta_path = dest.as_os_str().to_os_string();
+    meta_path.push(".cache.json");
+    let meta_path = PathBuf::from(meta_path);
+    let cached = dest
+        .is_file()
+        .then(|| fs::read_to_string(&meta_path).ok())
+        .flatten()
+        .and_then(|raw| serde_json::from_str::<CacheValidators>(&raw).ok());
+
+    let client = client_builder()
+        .proxy(proxy.cloned().unwrap_or_default().try_into()?)
+        .build()?;
+    let mut req = client.get(url.as_ref());
+    if let Some(validators) = &cached {
+        if let Some(etag) = &validators.etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            req = req.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let resp = match req.send() {
+        Ok(resp) => resp,
+        Err(e) if cached.is_some() => {
+            OutputEvent::Message {
+                text: &format!(
+                    "unable to check for updates of '{name}', using the cached one: {e}"
+                ),
+            }
+            .emit();
+            return Ok(false);
+        }
+        Err(e) => {
+            return Err(e).with_context(|| format!("failed to download '{name}' from '{url}'"))
+        }
+    };
+
+    let status = resp.status();
+    if status == StatusCode::NOT_MODIFIED && cached.is_some() {
+        return Ok(false);
+    }
+    if !status.is_success() {
+        return Err(StatusError {
+            url: url.to_string(),
+            status,
+        }
+        .into());
+    }
+    let header = |key| {
+        resp.headers()
+            .get(key)
+            .and_then(|val| val.to_str().ok())
+            .map(ToOwned::to_owned)
+    };
+    let validators = CacheValidators {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+    let content = resp.bytes()?;
+
+    super::ensure_parent_dir(dest)?;
+    let part_path = part_file_path(dest);
+    fs::write(&part_path, &content)?;
+    fs::rename(&part_path, dest)?;
+    fs::write(&meta_path, serde_json::to_string(&validators)?)?;
+    Ok(true)
+}
+
+/// Return the first of the ranked mirrors that is reachable, which is checked by
+/// requesting the `probe` path under each mirror.
+pub fn select_mirror<'a>(
+    mirrors: &'a [Url],
+    probe: &str,
+    proxy: Option<&Proxy>,
+) -> Option<&'a Url> {
+    let client = client_builder()
+        .proxy(proxy.cloned().unwrap_or_default().try_into().ok()?)
+        .build()
+        .ok()?;
+    mirrors.iter().find(|mirror| {
+        super::force_url_join(mirror, probe)
+            .ok()
+            .and_then(|url| client.head(url).send().ok())
+            .is_some_and(|resp| resp.status().is_success())
+    })
+}
+
+/// Check whether the server of `url` can be reached, by sending a `GET` request to it.
+///
+/// Any response other than a server error counts as reachable, since some servers
+/// do not serve their root path.
+pub fn check_reachable(url: &Url, proxy: Option<&Proxy>) -> Result<()> {
+    let client = client_builder()
+        .proxy(proxy.cloned().unwrap_or_default().try_into()?)
+        .build()?;
+    let resp = client
+        .get(url.as_ref())
+        .send()
+        .with_context(|| format!("unable to connect to '{url}'"))?;
+    if resp.status().is_server_error() {
+        bail!("'{url}' responded with '{}'", resp.status());
+    }
+    Ok(())
+}
+
+/// Get the path to the partially downloaded file of `path`, which is `<path>.part`.
+fn part_file_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_os_string();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    use url::Url;
+
+    use anyhow::anyhow;
+    use reqwest::StatusCode;
+
+    use super::{
+        download_and_extract_from_mirrors, download_from_mirrors, download_resumable,
+        parse_rate_limit, part_file_path, pem_encode, write_ca_bundle, ExtractableKind,
+        RetryPolicy, StatusError, TokenBucket,
+    };
+
+    /// Serve `content` over HTTP on a local port, honoring `Range` requests if `ranged`,
+    /// and send the `Range` header (if any) of every request through the returned receiver.
+    fn serve(
+        content: &'static [u8],
+        ranged: bool,
+    ) -> (Url, std::sync::mpsc::Receiver<Option<String>>) {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/foo.bin",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((key, value)) = line.split_once(':') {
+                        if key.eq_ignore_ascii_case("range") {
+                            range = Some(value.trim().to_string());
+                        }
+                    }
+                }
+                let start = range
+                    .as_deref()
+                    .filter(|_| ranged)
+                    .and_then(|r| r.strip_prefix("bytes="))
+                    .and_then(|r| r.strip_suffix('-'))
+                    .map(|r| r.parse::<usize>().unwrap());
+                let head = match start {
+                    Some(start) => format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {start}-{}/{}\r\n",
+                        content.len() - 1,
+                        content.len()
+                    ),
+                    None => "HTTP/1.1 200 OK\r\n".to_string(),
+                };
+                let body = &content[start.unwrap_or_default()..];
+                write!(
+                    stream,
+                    "{head}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+                tx.send(range).unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    #[test]
+    fn ca_bundle_starts_with_extra_cert() {
+        let dir = tempfile::tempdir().unwrap();
+        let extra = dir.path().join("corp.pem");
+        let pem = pem_encode(b"not really a certificate");
+        std::fs::write(&extra, pem.trim_end()).unwrap();
+        let dest = dir.path().join("bundle.pem");
+        write_ca_bundle(&extra, &dest).unwrap();
+
+        let bundle = std::fs::read_to_string(&dest).unwrap();
+        assert!(bundle.starts_with(&pem));
+        assert!(bundle.ends_with("-----END CERTIFICATE-----\n"));
+    }
+
+    #[test]
+    fn parse_rate_limits() {
+        assert_eq!(parse_rate_limit("0").unwrap(), 0);
+        assert_eq!(parse_rate_limit("1000").unwrap(), 1000);
+        assert_eq!(parse_rate_limit("200K").unwrap(), 200 * 1024);
+        assert_eq!(parse_rate_limit("1.5m").unwrap(), 1024 * 1024 * 3 / 2);
+        assert_eq!(parse_rate_limit("2G").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert!(parse_rate_limit("10X").is_err());
+        assert!(parse_rate_limit("K").is_err());
+        assert!(parse_rate_limit("-1").is_err());
+    }
+
+    #[test]
+    fn part_file_name() {
+        assert_eq!(
+            part_file_path(Path::new("/path/to/foo.tar.gz")),
+            Path::new("/path/to/foo.tar.gz.part")
+        );
+    }
+
+    #[test]
+    fn fallback_to_next_mirror() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("foo.txt");
+        std::fs::write(&src, "foo").unwrap();
+        let missing = Url::from_file_path(dir.path().join("missing.txt")).unwrap();
+        let existing = Url::from_file_path(&src).unwrap();
+        let dest = dir.path().join("downloaded.txt");
+
+        let used =
+            download_from_mirrors("foo", &[&missing, &existing], &dest, None, false).unwrap();
+        assert_eq!(used, existing);
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "foo");
+
+        let err = download_from_mirrors("foo", &[&missing], &dest, None, false).unwrap_err();
+        assert!(err.to_string().contains("missing.txt"));
+    }
+
+    #[test]
+    fn resume_partial_download() {
+        const CONTENT: &[u8] = b"0123456789abcdef";
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("foo.bin");
+        let part = part_file_path(&dest);
+
+        // Only the missing bytes are requested and appended to the partial file.
+        let (url, requests) = serve(CONTENT, true);
+        std::fs::write(&part, &CONTENT[..6]).unwrap();
+        download_resumable("foo", &url, &dest, None).unwrap();
+        assert_eq!(requests.recv().unwrap().as_deref(), Some("bytes=6-"));
+        assert_eq!(std::fs::read(&dest).unwrap(), CONTENT);
+        assert!(!part.exists());
+
+        // The partial file is overwritten if the server sends the whole file instead.
+        let (url, requests) = serve(CONTENT, false);
+        std::fs::remove_file(&dest).unwrap();
+        std::fs::write(&part, &CONTENT[..6]).unwrap();
+        download_resumable("foo", &url, &dest, None).unwrap();
+        assert_eq!(requests.recv().unwrap().as_deref(), Some("bytes=6-"));
+        assert_eq!(std::fs::read(&dest).unwrap(), CONTENT);
+        assert!(!part.exists());
+    }
+
+    #[test]
+    fn token_bucket_throttling() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1000, start);
+        // A burst of one second worth of bytes is allowed.
+        assert_eq!(bucket.take(1000, start), Duration::ZERO);
+        assert_eq!(bucket.take(500, start), Duration::from_millis(500));
+        // Waited as required.
+        assert_eq!(
+            bucket.take(0, start + Duration::from_millis(500)),
+            Duration::ZERO
+        );
+        // The bucket won't hold more than one second worth of bytes after being idle.
+        let later = start + Duration::from_secs(10);
+        assert_eq!(bucket.take(1000, later), Duration::ZERO);
+        assert_eq!(bucket.take(100, later), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn retry_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff_of(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff_of(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff_of(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff_of(10), Duration::from_secs(30));
+        assert_eq!(policy.backoff_of(u32::MAX), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn retryable_errors() {
+        let policy = RetryPolicy::default();
+        let status_err = |status| {
+            anyhow::Error::from(StatusError {
+                url: "https://example.com/foo".into(),
+                status,
+            })
+        };
+        assert!(policy.is_retryable(&status_err(StatusCode::SERVICE_UNAVAILABLE)));
+        assert!(policy.is_retryable(&status_err(StatusCode::TOO_MANY_REQUESTS)));
+        assert!(!policy.is_retryable(&status_err(StatusCode::NOT_FOUND)));
+        assert!(!policy.is_retryable(&status_err(StatusCode::FORBIDDEN)));
+
+        let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        assert!(policy.is_retryable(&anyhow::Error::from(reset).context("reading body")));
+        let not_found = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert!(!policy.is_retryable(&anyhow::Error::from(not_found)));
+        assert!(!policy.is_retryable(&anyhow!("checksum mismatch")));
+    }
+
+    #[test]
+    fn stream_extract_from_mirrors() {
+        let dir = tempfile::tempdir().unwrap();
+        let tarball = dir.path().join("foo.tar.gz");
+        {
+            let encoder = flate2::write::GzEncoder::new(
+                std::fs::File::create(&tarball).unwrap(),
+                flate2::Compression::fast(),
+            );
+            let mut builder = tar::Builder::new(encoder);
+            for name in ["foo/bin/foo", "foo/README"] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(3);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder
+                    .append_data(&mut header, name, b"foo".as_slice())
+                    .unwrap();
+            }
+            builder.into_inner().unwrap().finish().unwrap();
+        }
+        let missing = Url::from_file_path(dir.path().join("missing.tar.gz")).unwrap();
+        let existing = Url::from_file_path(&tarball).unwrap();
+        let dest = dir.path().join("extracted");
+        let copy = dir.path().join("copy.tar.gz");
+        let expected = crate::utils::sha256_file(&tarball).unwrap();
+
+        // Nothing is left behind by a tarball with another checksum.
+        let err = download_and_extract_from_mirrors(
+            "foo",
+            &[&existing],
+            ExtractableKind::Gz,
+            &dest,
+            Some(&copy),
+            None,
+            &|_, _| Err(anyhow!("checksum mismatch")),
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("checksum mismatch"));
+        assert!(!dest.exists());
+        assert!(!copy.exists());
+        assert!(!part_file_path(&copy).exists());
+
+        let (used, checksum) = download_and_extract_from_mirrors(
+            "foo",
+            &[&missing, &existing],
+            ExtractableKind::Gz,
+            &dest,
+            Some(&copy),
+            None,
+            &|_, checksum| {
+                assert_eq!(checksum, expected);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(used, existing);
+        assert_eq!(checksum, expected);
+        assert_eq!(std::fs::read(dest.join("bin").join("foo")).unwrap(), b"foo");
+        assert!(dest.join("README").is_file());
+        assert_eq!(
+            std::fs::read(&copy).unwrap(),
+            std::fs::read(&tarball).unwrap()
+        );
+        // Only the extracted directory is left next to it.
+        let mut entries = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(entries, ["copy.tar.gz", "extracted", "foo.tar.gz"]);
+    }
+}
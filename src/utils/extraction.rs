@@ -1,9 +1,11 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use common_path::common_path_all;
-use std::io::{Read, Write};
+use std::io::{BufReader, Read, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use tracing::info;
 
+use crate::core::error::InstallError;
 use crate::utils::progress_bar::Style;
 
 use super::progress_bar::ProgressIndicator;
@@ -15,6 +17,8 @@ pub enum ExtractableKind {
     Gz,
     Xz,
     Zip,
+    /// `zstd` compressed files, ended with `.zst` (tarball or single file) or `.tzst`
+    Zst,
 }
 
 impl FromStr for ExtractableKind {
@@ -25,11 +29,91 @@ impl FromStr for ExtractableKind {
             "xz" => Ok(Self::Xz),
             "zip" => Ok(Self::Zip),
             "7z" => Ok(Self::SevenZ),
-            _ => Err(anyhow!("'{s}' is not a supported extrable file format")),
+            "zst" | "tzst" => Ok(Self::Zst),
+            _ => Err(InstallError::UnsupportedArchive(s.to_string()).into()),
         }
     }
 }
 
+impl ExtractableKind {
+    /// Get the kind of a compressed tarball by its file name, such as `foo.tar.gz`,
+    /// which can be extracted while being downloaded, check [`ExtractableKind::extract_stream`].
+    ///
+    /// Return `None` for other formats, because `zip` and `7z` archives need to be
+    /// read randomly, and `.zst` files might not be tarballs.
+    pub fn streamable(file_name: &str) -> Option<Self> {
+        let (stem, ext) = file_name.rsplit_once('.')?;
+        match ext {
+            "tzst" => Some(Self::Zst),
+            _ if !stem.ends_with(".tar") => None,
+            "gz" => Some(Self::Gz),
+            "xz" => Some(Self::Xz),
+            "zst" => Some(Self::Zst),
+            _ => None,
+        }
+    }
+
+    /// Extract a compressed tarball from a stream into `root`, the stream is read only once,
+    /// thus the archive doesn't need to be stored anywhere.
+    ///
+    /// Same as [`Extractable::extract_to`], the common prefix of the entries is skipped.
+    pub fn extract_stream<R: Read>(self, reader: R, root: &Path) -> Result<()> {
+        use flate2::read::GzDecoder;
+        use xz2::read::XzDecoder;
+        use zstd::stream::read::Decoder as ZstdDecoder;
+
+        let reader = BufReader::new(reader);
+        let decoder: Box<dyn Read + '_> = match self {
+            Self::Gz => Box::new(GzDecoder::new(reader)),
+            Self::Xz => Box::new(XzDecoder::new(reader)),
+            Self::Zst => Box::new(ZstdDecoder::with_buffer(reader)?),
+            _ => bail!("{self:?} archives cannot be extracted from a stream"),
+        };
+        let mut archive = tar::Archive::new(decoder);
+        #[cfg(unix)]
+        archive.set_preserve_permissions(true);
+        archive
+            .unpack(root)
+            .context("failed to extract tarball from stream")?;
+
+        // The entries are unknown until the whole stream was read,
+        // so the common prefix can only be skipped afterwards.
+        strip_common_prefix(root)
+    }
+
+    /// Guess the kind of an archive by its leading magic bytes.
+    fn from_magic(bytes: &[u8]) -> Option<Self> {
+        const MAGICS: &[(&[u8], ExtractableKind)] = &[
+            (&[0x1f, 0x8b], ExtractableKind::Gz),
+            (&[0xfd, b'7', b'z', b'X', b'Z', 0x00], ExtractableKind::Xz),
+            (b"PK\x03\x04", ExtractableKind::Zip),
+            (
+                &[b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c],
+                ExtractableKind::SevenZ,
+            ),
+            (&[0x28, 0xb5, 0x2f, 0xfd], ExtractableKind::Zst),
+        ];
+        MAGICS
+            .iter()
+            .find(|(magic, _)| bytes.starts_with(magic))
+            .map(|(_, kind)| *kind)
+    }
+
+    /// Guess the kind of an archive by reading the leading bytes of it.
+    fn sniff(path: &Path) -> Result<Self> {
+        let mut header = Vec::with_capacity(8);
+        std::fs::File::open(path)?
+            .take(8)
+            .read_to_end(&mut header)?;
+        Self::from_magic(&header).ok_or_else(|| {
+            anyhow!(
+                "path '{}' is not extractable because it has no file extension nor known archive signature",
+                path.display()
+            )
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Extractable<'a> {
     path: &'a Path,
@@ -39,9 +123,13 @@ pub struct Extractable<'a> {
 impl<'a> TryFrom<&'a Path> for Extractable<'a> {
     type Error = anyhow::Error;
     fn try_from(value: &'a Path) -> std::result::Result<Self, Self::Error> {
-        let ext = value
-            .extension()
-            .ok_or_else(|| anyhow!("path '{}' is not extractable because it appears to have no file extension", value.display()))?
+        let Some(ext) = value.extension() else {
+            // Files without extension might still be archives, such as the ones downloaded
+            // from an url like `https://example.com/download?id=foo`, check their content.
+            let kind = ExtractableKind::sniff(value)?;
+            return Ok(Self { path: value, kind });
+        };
+        let ext = ext
             .to_str()
             .ok_or_else(|| anyhow!("path '{}' is not extractable because it's path contains invalid unicode characters", value.display()))?;
 
@@ -60,32 +148,89 @@ impl Extractable<'_> {
         match self.kind {
             ExtractableKind::Zip => extract_zip(self.path, root, indicator),
             ExtractableKind::SevenZ => extract_7z(self.path, root, indicator),
-            ExtractableKind::Gz => {
-                use flate2::read::GzDecoder;
+            ExtractableKind::Gz | ExtractableKind::Xz => extract_tar(self, root, indicator),
+            ExtractableKind::Zst if self.is_tarball()? => extract_tar(self, root, indicator),
+            ExtractableKind::Zst => self.decompress_to(root),
+        }
+    }
 
-                let tar_file = std::fs::File::open(self.path)?;
-                let tar_gz = GzDecoder::new(tar_file);
-                let mut archive = tar::Archive::new(tar_gz);
-                extract_tar(&mut archive, self.path, root, indicator)
-            }
-            ExtractableKind::Xz => {
-                use xz2::read::XzDecoder;
+    /// Check whether the decompressed content is a tarball, by looking for the `ustar`
+    /// magic in the header of its first entry.
+    fn is_tarball(&self) -> Result<bool> {
+        let mut header = Vec::with_capacity(512);
+        self.decoder()?.take(512).read_to_end(&mut header)?;
+        Ok(header.get(257..262) == Some(b"ustar".as_slice()))
+    }
+
+    /// Decompress a single compressed file (not a tarball) into `root`,
+    /// the decompressed file is named after the file stem, i.e. `foo.exe.zst` -> `foo.exe`.
+    fn decompress_to(&self, root: &Path) -> Result<()> {
+        let name = self
+            .path
+            .file_stem()
+            .filter(|_| self.path.extension().is_some())
+            .or_else(|| self.path.file_name())
+            .ok_or_else(|| anyhow!("invalid file path '{}'", self.path.display()))?;
+        let out_path = root.join(name);
+        super::ensure_parent_dir(&out_path)?;
+        let mut out_file = std::fs::File::create(&out_path)?;
+        std::io::copy(&mut self.decoder()?, &mut out_file)
+            .with_context(|| format!("failed to decompress '{}'", self.path.display()))?;
+        Ok(())
+    }
+
+    /// Open the compressed file as a stream of its decompressed content.
+    fn decoder(&self) -> Result<Box<dyn Read>> {
+        use flate2::read::GzDecoder;
+        use xz2::read::XzDecoder;
+        use zstd::stream::read::Decoder as ZstdDecoder;
+
+        let file = BufReader::new(std::fs::File::open(self.path)?);
+        let decoder: Box<dyn Read> = match self.kind {
+            ExtractableKind::Gz => Box::new(GzDecoder::new(file)),
+            ExtractableKind::Xz => Box::new(XzDecoder::new(file)),
+            ExtractableKind::Zst => Box::new(ZstdDecoder::with_buffer(file)?),
+            _ => bail!("'{}' is not a compressed stream", self.path.display()),
+        };
+        Ok(decoder)
+    }
+
+    /// Open a compressed tarball as a stream, which decompresses the file while reading,
+    /// so that the whole archive never needs to be loaded into memory.
+    fn open_tar(&self) -> Result<tar::Archive<Box<dyn Read>>> {
+        Ok(tar::Archive::new(self.decoder()?))
+    }
+}
 
-                let tar_file = std::fs::File::open(self.path)?;
-                let tar_gz = XzDecoder::new(tar_file);
-                let mut archive = tar::Archive::new(tar_gz);
-                extract_tar(&mut archive, self.path, root, indicator)
+/// Move the content of the only directory in `root` into `root`, until there are
+/// more than one entries, or the only entry is a file.
+fn strip_common_prefix(root: &Path) -> Result<()> {
+    loop {
+        let entries = super::walk_dir(root, false)?;
+        let [only_entry] = entries.as_slice() else {
+            return Ok(());
+        };
+        if !only_entry.is_dir() {
+            return Ok(());
+        }
+        // Rename it first, in case it contains an entry with the same name.
+        let prefix_dir = root.join(".prefix");
+        std::fs::rename(only_entry, &prefix_dir)?;
+        for entry in super::walk_dir(&prefix_dir, false)? {
+            if let Some(name) = entry.file_name() {
+                std::fs::rename(&entry, root.join(name))?;
             }
         }
+        std::fs::remove_dir(&prefix_dir)?;
     }
 }
 
 fn extract_zip<T: Sized>(path: &Path, root: &Path, indicator: ProgressIndicator<T>) -> Result<()> {
     use zip::ZipArchive;
 
-    println!("loading '{}'", path.display());
+    info!("loading '{}'", path.display());
     // FIXME: this is too slow for large files, see if it can be optimized.
-    let file = std::fs::File::open(path)?;
+    let file = BufReader::new(std::fs::File::open(path)?);
     let mut zip_archive = ZipArchive::new(file)?;
     let zip_len = zip_archive.len();
 
@@ -97,6 +242,7 @@ fn extract_zip<T: Sized>(path: &Path, root: &Path, indicator: ProgressIndicator<
     )?;
 
     for i in 0..zip_len {
+        super::check_cancelled()?;
         let mut zip_file = zip_archive.by_index(i)?;
 
         let out_path = match zip_file.enclosed_name() {
@@ -165,6 +311,11 @@ fn extract_7z<T: Sized>(path: &Path, root: &Path, indicator: ProgressIndicator<T
     )?;
 
     sz_reader.for_each_entries(|entry, reader| {
+        if super::is_cancelled() {
+            return Err(sevenz_rust::Error::other(
+                InstallError::Cancelled.to_string(),
+            ));
+        }
         let mut buf = [0_u8; 1024];
         let mut entry_path = PathBuf::from(entry.name());
         if let Some(prefix) = &common_prefix {
@@ -215,42 +366,47 @@ fn extract_7z<T: Sized>(path: &Path, root: &Path, indicator: ProgressIndicator<T
     Ok(())
 }
 
-fn extract_tar<T: Sized, R: Read>(
-    archive: &mut tar::Archive<R>,
-    path: &Path,
+fn extract_tar<T: Sized>(
+    tarball: &Extractable,
     root: &Path,
     indicator: ProgressIndicator<T>,
 ) -> Result<()> {
-    #[cfg(unix)]
-    archive.set_preserve_permissions(true);
-
-    let entries = archive.entries()?.collect::<Vec<_>>();
-    // Find common prefix so we can skip them and reserve the only "important" parts.
-    // Fxxk this, wtf are these types!!!!!
-    let common_prefix = {
-        if entries.len() < 2 {
-            None
-        } else {
-            let all_paths = entries
-                .iter()
-                .filter_map(|entry| entry.as_ref().ok())
-                // Only get the files entry
-                .filter(|entry| entry.header().entry_type().is_file())
-                .filter_map(|f| f.path().map(|p| p.to_path_buf()).ok())
-                .collect::<Vec<_>>();
-            common_path_all(all_paths.iter().map(|pb| pb.as_path()))
+    let path = tarball.path;
+
+    // Since a compressed tarball can only be read sequentially, and keeping every entry
+    // in memory is not acceptable for large archives, this is done in two passes:
+    // the first one only reads the headers to find the common prefix and the number of entries,
+    // then the second one actually extract them.
+    let mut entries_count: u64 = 0;
+    let mut file_paths = vec![];
+    for maybe_entry in tarball.open_tar()?.entries()? {
+        let entry = maybe_entry?;
+        entries_count += 1;
+        // Only get the files entry
+        if entry.header().entry_type().is_file() {
+            file_paths.push(entry.path()?.to_path_buf());
         }
+    }
+    // Find common prefix so we can skip them and reserve the only "important" parts.
+    let common_prefix = if entries_count < 2 {
+        None
+    } else {
+        common_path_all(file_paths.iter().map(|pb| pb.as_path()))
     };
 
-    let total_len: u64 = entries.len().try_into()?;
     // Init progress bar
     let bar = (indicator.start)(
-        total_len,
+        entries_count,
         format!("extracting file '{}'", path.display()),
         Style::Len,
     )?;
 
-    for (idx, maybe_entry) in entries.into_iter().enumerate() {
+    let mut archive = tarball.open_tar()?;
+    #[cfg(unix)]
+    archive.set_preserve_permissions(true);
+
+    for (idx, maybe_entry) in archive.entries()?.enumerate() {
+        super::check_cancelled()?;
         let mut entry = maybe_entry?;
         let entry_path = if let Some(prefix) = &common_prefix {
             let Ok(stripped) = entry.path()?.strip_prefix(prefix).map(|p| p.to_path_buf()) else {
@@ -5,6 +5,7 @@ use std::fs;
 use std::io::Write;
 use std::path::{Component, Path, PathBuf};
 use std::time::Duration;
+use tracing::warn;
 
 /// Get a path to user's "home" directory.
 ///
@@ -30,7 +31,7 @@ pub fn stringify_path<P: AsRef<Path>>(path: P) -> Result<String> {
         .ok_or_else(|| {
             anyhow!(
                 "failed to stringify path '{}'",
-                path.as_ref().to_string_lossy().to_string()
+                path.as_ref().to_string_lossy()
             )
         })
 }
@@ -179,6 +180,10 @@ where
     }
 
     if from.as_ref().is_file() {
+        // Copying onto a symlink will overwrite its target instead, so remove the link first.
+        if is_symlink(&to) {
+            remove(&to)?;
+        }
         fs::copy(&from, &to).with_context(|| {
             format!(
                 "could not copy file '{}' to '{}'",
@@ -198,6 +203,45 @@ where
     Ok(())
 }
 
+/// Create a symbolic link of a file into an existing directory, and return the path to the link.
+///
+/// If a file or link with the same name already exists in `to`, it will be replaced.
+/// When symbolic links are not permitted (such as on Windows without privilege),
+/// this will fallback to [`copy_file_to`].
+pub fn symlink_or_copy_file_to<P, Q>(from: P, to: Q) -> Result<PathBuf>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let from = from.as_ref();
+    let dest = to.as_ref().join(
+        from.file_name()
+            .ok_or_else(|| anyhow!("path '{}' does not have a file name", from.display()))?,
+    );
+    remove(&dest)?;
+
+    #[cfg(unix)]
+    let linked = std::os::unix::fs::symlink(from, &dest);
+    #[cfg(windows)]
+    let linked = std::os::windows::fs::symlink_file(from, &dest);
+
+    match linked {
+        Ok(()) => Ok(dest),
+        Err(e) => {
+            warn!(
+                "unable to create symbolic link for '{}' ({e}), copying it instead",
+                from.display()
+            );
+            copy_file_to(from, to)
+        }
+    }
+}
+
+/// Returns `true` if the given path is a symbolic link, regardless if its target exists or not.
+pub fn is_symlink<P: AsRef<Path>>(path: P) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+}
+
 /// Set file permissions (executable)
 /// rwxr-xr-x: 0o755
 #[cfg(not(windows))]
@@ -233,6 +277,19 @@ pub fn walk_dir(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
     Ok(paths)
 }
 
+/// Get the total size in bytes of the files in a directory, recursively.
+///
+/// Symbolic links are not followed, and the entries that cannot be read are ignored.
+pub fn dir_size(dir: &Path) -> u64 {
+    walk_dir(dir, true)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|path| path.symlink_metadata().ok())
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len())
+        .sum()
+}
+
 pub fn is_executable<P: AsRef<Path>>(path: P) -> bool {
     #[cfg(windows)]
     let is_executable_ext = matches!(
@@ -245,8 +302,14 @@ pub fn is_executable<P: AsRef<Path>>(path: P) -> bool {
     path.as_ref().is_file() && is_executable_ext
 }
 
+/// Remove a file or directory, do nothing if it does not exist.
+///
+/// Symbolic links are removed without following them, so their targets are left untouched.
 pub fn remove<P: AsRef<Path>>(src: P) -> Result<()> {
-    if src.as_ref().is_file() {
+    if is_symlink(&src) {
+        remove_symlink_(src.as_ref())
+            .with_context(|| format!("unable to remove link '{}'", src.as_ref().display()))?;
+    } else if src.as_ref().is_file() {
         fs::remove_file(&src)
             .with_context(|| format!("unable to remove file '{}'", src.as_ref().display()))?;
     } else if src.as_ref().is_dir() {
@@ -256,6 +319,17 @@ pub fn remove<P: AsRef<Path>>(src: P) -> Result<()> {
     Ok(())
 }
 
+#[cfg(not(windows))]
+fn remove_symlink_(link: &Path) -> std::io::Result<()> {
+    fs::remove_file(link)
+}
+
+#[cfg(windows)]
+fn remove_symlink_(link: &Path) -> std::io::Result<()> {
+    // Directory links on Windows must be removed with `remove_dir`, which won't follow them.
+    fs::remove_file(link).or_else(|_| fs::remove_dir(link))
+}
+
 /// Move `src` path to `dest`.
 pub fn move_to(src: &Path, dest: &Path, force: bool) -> Result<()> {
     if force && dest.exists() {
@@ -277,11 +351,71 @@ pub fn move_to(src: &Path, dest: &Path, force: bool) -> Result<()> {
     // try copy and delete.
     copy_as(src, dest)?;
     if remove(src).is_err() {
-        println!(
-            "warning: unable to remove '{}', please try manually removing it",
+        warn!(
+            "unable to remove '{}', please try manually removing it",
             src.display()
         )
     }
 
     Ok(())
 }
+
+/// Calculate the SHA-256 checksum of a file, returned as a lowercase hex string.
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> Result<String> {
+    use sha2::{Digest, Sha256};
+
+    let mut file = fs::File::open(path.as_ref())
+        .with_context(|| format!("failed to open '{}'", path.as_ref().display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    let hash = hasher.finalize();
+
+    Ok(hash.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Get the SHA-256 checksum of a string, in lowercase hex.
+pub fn sha256_str(s: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let hash = Sha256::digest(s.as_bytes());
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Verify a file with a detached [`minisign`](https://jedisct1.github.io/minisign/) signature,
+/// using the given base64 encoded public key.
+///
+/// The file is hashed as it's read, instead of being loaded into memory as a whole,
+/// so only the (pre-hashed) signatures of the current `minisign` are supported.
+pub fn verify_minisign<P, Q>(path: P, signature_path: Q, public_key: &str) -> Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    use minisign_verify::{PublicKey, Signature};
+    use std::io::Read;
+
+    let public_key = PublicKey::from_base64(public_key.trim())
+        .map_err(|e| anyhow!("invalid minisign public key: {e}"))?;
+    let signature = Signature::decode(&read_to_string(&signature_path)?).map_err(|e| {
+        anyhow!(
+            "invalid minisign signature file '{}': {e}",
+            signature_path.as_ref().display()
+        )
+    })?;
+    let mut verifier = public_key
+        .verify_stream(&signature)
+        .map_err(|e| anyhow!("{e}"))?;
+    let mut file = fs::File::open(path.as_ref())
+        .with_context(|| format!("unable to read file '{}'", path.as_ref().display()))?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let len = file
+            .read(&mut buf)
+            .with_context(|| format!("unable to read file '{}'", path.as_ref().display()))?;
+        if len == 0 {
+            break;
+        }
+        verifier.update(&buf[..len]);
+    }
+    verifier.finalize().map_err(|e| anyhow!("{e}"))
+}
@@ -0,0 +1,268 @@
+//! The global [`tracing`] subscriber, which prints the events to console for human,
+//! and writes every one of them into a log file, such as `<install_dir>/logs/install.log`.
+//!
+//! The console output respects the global [`OutputFormat`](super::OutputFormat),
+//! in JSON mode the events are printed as [`OutputEvent`]s instead.
+
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context as _, Result};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::{Layer, Registry};
+
+use super::output::{is_json_output, OutputEvent};
+
+/// The target of the events emitted by [`OutputEvent::emit`], which are already printed,
+/// so they will only be written into the log file.
+pub(crate) const OUTPUT_TARGET: &str = "output";
+/// A log file is rotated once it grows larger than this, in bytes.
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+/// Number of rotated log files to keep, i.e. `install.log.1` to `install.log.3`.
+const MAX_ROTATED_LOGS: usize = 3;
+/// Number of lines to keep before the log file is set, which will be written into it once set.
+const MAX_BUFFERED_LINES: usize = 1000;
+
+static LOG_FILE: Mutex<LogFile> = Mutex::new(LogFile {
+    path: None,
+    file: None,
+    buffer: vec![],
+});
+static LOGGER_INIT: OnceLock<()> = OnceLock::new();
+
+struct LogFile {
+    path: Option<PathBuf>,
+    file: Option<RollingFileAppender>,
+    /// Lines logged before the log file is set.
+    buffer: Vec<Vec<u8>>,
+}
+
+/// Install the global logger, `verbosity` is the number of `-v` flags,
+/// the console prints `INFO` events by default, `DEBUG` with `-v` and `TRACE` with `-vv`,
+/// or only the warnings and errors if `quiet`.
+///
+/// The log file always contains the `DEBUG` events, or `TRACE` events with `-vv`.
+/// This can only be set once, any subsequent calls will be ignored.
+pub fn init_logger(verbosity: u8, quiet: bool) {
+    LOGGER_INIT.get_or_init(|| {
+        let console_level = match (quiet, verbosity) {
+            (true, _) => Level::WARN,
+            (false, 0) => Level::INFO,
+            (false, 1) => Level::DEBUG,
+            (false, _) => Level::TRACE,
+        };
+        let file_level = console_level.max(Level::DEBUG);
+        let subscriber = Registry::default()
+            .with(ConsoleLayer.with_filter(LevelFilter::from_level(console_level)))
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(|| LogWriter)
+                    .with_filter(LevelFilter::from_level(file_level)),
+            );
+        let _ = tracing::subscriber::set_global_default(subscriber);
+    });
+}
+
+/// Write the log into `path` from now on, along with the previously logged lines.
+///
+/// If there was another log file, its content is moved into the new one, so that it can be
+/// relocated before the directory containing it is removed (i.e. when rolling back).
+/// `path` is rotated first if it's larger than [`MAX_LOG_SIZE`].
+pub fn set_log_file(path: &Path) -> Result<()> {
+    let mut log = LOG_FILE.lock().unwrap_or_else(|e| e.into_inner());
+    if log.path.as_deref() == Some(path) {
+        return Ok(());
+    }
+    super::ensure_parent_dir(path)?;
+    rotate(path)?;
+
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name().and_then(|n| n.to_str())) else {
+        anyhow::bail!("invalid log file path '{}'", path.display());
+    };
+    let mut file = RollingFileAppender::builder()
+        .rotation(Rotation::NEVER)
+        .filename_prefix(name)
+        .build(dir)
+        .with_context(|| format!("unable to open log file '{}'", path.display()))?;
+    if let Some(old_path) = log.path.take() {
+        log.file = None;
+        if let Ok(content) = fs::read(&old_path) {
+            file.write_all(&content)?;
+            let _ = fs::remove_file(&old_path);
+        }
+    }
+    for line in std::mem::take(&mut log.buffer) {
+        file.write_all(&line)?;
+    }
+    log.path = Some(path.to_path_buf());
+    log.file = Some(file);
+    Ok(())
+}
+
+/// Move the log file into the temporary directory,
+/// so that it is kept after the installation directory was removed.
+pub fn move_log_to_temp_dir() {
+    let Some(name) = log_file().and_then(|path| path.file_name().map(ToOwned::to_owned)) else {
+        return;
+    };
+    let path = std::env::temp_dir().join(env!("CARGO_PKG_NAME")).join(name);
+    if let Err(e) = set_log_file(&path) {
+        tracing::warn!("unable to move the log file to '{}': {e}", path.display());
+    }
+}
+
+/// Get the path of the current log file, if it was set.
+pub fn log_file() -> Option<PathBuf> {
+    LOG_FILE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .path
+        .clone()
+}
+
+/// Rename `path` to `<path>.1` (and the existing `<path>.1` to `<path>.2`, etc.),
+/// if it's larger than [`MAX_LOG_SIZE`].
+fn rotate(path: &Path) -> Result<()> {
+    if !fs::metadata(path).is_ok_and(|meta| meta.len() > MAX_LOG_SIZE) {
+        return Ok(());
+    }
+    let rotated = |n: usize| {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    };
+    for n in (1..MAX_ROTATED_LOGS).rev() {
+        let from = rotated(n);
+        if from.is_file() {
+            fs::rename(&from, rotated(n + 1))?;
+        }
+    }
+    fs::rename(path, rotated(1))?;
+    Ok(())
+}
+
+/// Writes the formatted events into the current [log file](set_log_file),
+/// or keeps them until it is set.
+struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut log = LOG_FILE.lock().unwrap_or_else(|e| e.into_inner());
+        let LogFile { file, buffer, .. } = &mut *log;
+        match file {
+            Some(file) => file.write_all(buf)?,
+            None if buffer.len() < MAX_BUFFERED_LINES => buffer.push(buf.to_vec()),
+            None => (),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut log = LOG_FILE.lock().unwrap_or_else(|e| e.into_inner());
+        log.file.as_mut().map_or(Ok(()), Write::flush)
+    }
+}
+
+/// Prints the events to console, check [`init_logger`].
+struct ConsoleLayer;
+
+impl<S: Subscriber> Layer<S> for ConsoleLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        write_console(event.metadata(), &visitor.0);
+    }
+}
+
+fn write_console(metadata: &Metadata<'_>, message: &str) {
+    let level = *metadata.level();
+    if is_json_output() {
+        if metadata.target() == OUTPUT_TARGET {
+            return;
+        }
+        let text = match level {
+            Level::INFO | Level::ERROR => message.to_string(),
+            _ => format!("{}: {message}", level_name(level)),
+        };
+        let event = if level == Level::ERROR {
+            OutputEvent::Error {
+                text: &text,
+                code: None,
+            }
+        } else {
+            OutputEvent::Message { text: &text }
+        };
+        if let Ok(json) = serde_json::to_string(&event) {
+            println!("{json}");
+        }
+    } else if level == Level::INFO {
+        println!("{message}");
+    } else {
+        eprintln!("{}: {message}", level_name(level));
+    }
+}
+
+/// Collect the message of an event, followed by its other fields as `name=value`.
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{value}"));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{value:?}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::ERROR => "error",
+        Level::WARN => "warning",
+        Level::INFO => "info",
+        Level::DEBUG => "debug",
+        Level::TRACE => "trace",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rotate, MAX_LOG_SIZE};
+
+    #[test]
+    fn rotate_large_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("install.log");
+        std::fs::write(&log, "small").unwrap();
+        rotate(&log).unwrap();
+        assert!(log.is_file());
+
+        std::fs::write(dir.path().join("install.log.1"), "older").unwrap();
+        std::fs::write(&log, vec![b'a'; MAX_LOG_SIZE as usize + 1]).unwrap();
+        rotate(&log).unwrap();
+        assert!(!log.exists());
+        assert_eq!(
+            std::fs::metadata(dir.path().join("install.log.1"))
+                .unwrap()
+                .len(),
+            MAX_LOG_SIZE + 1
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("install.log.2")).unwrap(),
+            "older"
+        );
+    }
+}
@@ -3,19 +3,45 @@
 //! NOTE: Most of these are moved from the `experimental` branch,
 //! some of them might turns out to be unused, so remember to clean those after version `1.0`.
 
+mod cancel;
 mod download;
 mod extraction;
 mod file_system;
+mod log;
+mod output;
+mod patch;
 mod process;
+mod progress;
 mod progress_bar;
+mod triple;
 
 use std::path::{Path, PathBuf};
 
-pub use download::download;
+pub use cancel::{
+    cancel, cancel_on_ctrl_c, check_cancelled, is_cancelled, is_suspended, reset_cancellation,
+    suspend,
+};
+pub(crate) use download::write_ca_bundle;
+pub use download::{
+    check_reachable, download, download_and_extract_from_mirrors, download_from_mirrors,
+    download_resumable, download_revalidated, parse_rate_limit, select_mirror,
+    set_download_ca_cert, set_download_rate_limit, set_download_retry, set_download_timeout,
+    DownloadOpt, RetryPolicy,
+};
 pub use extraction::{Extractable, ExtractableKind};
 pub use file_system::*;
+pub use log::{init_logger, log_file, move_log_to_temp_dir, set_log_file};
+pub use output::{is_json_output, output_format, set_output_format, OutputEvent, OutputFormat};
+pub use patch::apply_patch;
 pub use process::*;
-pub use progress_bar::MultiThreadProgress;
+pub use progress::{
+    report_progress, scoped_progress_reporter, set_progress_reporter, MultiThreadProgress,
+    ProgressEvent, ProgressReporter, ProgressReporterGuard, TerminalReporter,
+};
+pub use triple::{
+    host_triple, host_triple_with_env, native_triple, parse_host_triple, set_host_triple,
+    triple_arch, triple_env, triple_os, SUPPORTED_TRIPLES,
+};
 
 use anyhow::Result;
 use url::Url;
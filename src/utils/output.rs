@@ -0,0 +1,284 @@
+//! Output events that are either printed as plain text for human, or emitted as
+//! line-delimited JSON for other programs (such as the GUI frontend) to consume.
+
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use indicatif::HumanBytes;
+use serde::Serialize;
+
+use super::log::OUTPUT_TARGET;
+use crate::{PreflightReport, ProxySource};
+
+static OUTPUT_FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text.
+    #[default]
+    Text,
+    /// Line-delimited JSON events, one event per line.
+    Json,
+}
+
+/// Set the global output format, this can only be set once,
+/// any subsequent calls will be ignored.
+pub fn set_output_format(format: OutputFormat) {
+    let _ = OUTPUT_FORMAT.set(format);
+}
+
+/// Get the global output format, defaulting to [`OutputFormat::Text`].
+pub fn output_format() -> OutputFormat {
+    OUTPUT_FORMAT.get().copied().unwrap_or_default()
+}
+
+/// Returns `true` if the output events should be emitted as JSON.
+pub fn is_json_output() -> bool {
+    output_format() == OutputFormat::Json
+}
+
+/// An event that should be reported to the user (or the frontend).
+///
+/// When serialized, each event will be a JSON object with a `type` field, i.e.:
+///
+/// ```json
+/// {"type":"progress","pct":42}
+/// {"type":"message","text":"installing 'ripgrep'"}
+/// {"type":"error","text":"unable to install 'ripgrep'","code":"tool-install-failed"}
+/// ```
+///
+/// Besides the progress of an operation, the results of the `manager` commands are also
+/// reported as events, such as [`OutputEvent::Tool`] for each line of `list`.
+/// The fields of existing events are considered stable, new fields might be added though.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OutputEvent<'a> {
+    Progress {
+        pct: usize,
+    },
+    Message {
+        text: &'a str,
+    },
+    Error {
+        text: &'a str,
+        /// The [code](crate::InstallError::code) of the error, if it's a known kind of error.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code: Option<&'a str>,
+    },
+    /// A toolchain or tool, listed by `list` command.
+    Tool {
+        name: &'a str,
+        /// The source of the tool, such as `crate`, `url` or `path`.
+        kind: &'a str,
+        version: Option<&'a str>,
+        installed: bool,
+        /// Whether this tool has a custom install instruction.
+        custom: bool,
+    },
+    /// The result of a check performed by `doctor` command.
+    Check {
+        desc: &'a str,
+        ok: bool,
+        /// The suggested fix of a failed check.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        fix: Option<&'a str>,
+    },
+    /// An outdated toolchain or tool, found by `update` command.
+    Outdated {
+        name: &'a str,
+        installed: Option<&'a str>,
+        latest: Option<&'a str>,
+    },
+    /// A configuration shown by `config` command, `value` is `None` if it was not set.
+    Config {
+        key: &'a str,
+        value: Option<&'a str>,
+    },
+    /// An environment variable exported by `env` command, the value of `PATH`
+    /// only contains the entries to prepend.
+    #[serde(rename = "env")]
+    EnvVar {
+        key: &'a str,
+        value: &'a str,
+    },
+    /// The system requirements and existing installations reported by `--preflight`.
+    Preflight(&'a PreflightReport),
+}
+
+impl OutputEvent<'_> {
+    /// Print this event to stdout base on the global [`OutputFormat`].
+    ///
+    /// Messages and errors are also logged, which are printed by the [logger](super::init_logger)
+    /// in text mode, if there is one.
+    ///
+    /// Note that [`OutputEvent::Progress`] will not be printed in text mode,
+    /// as there are progress bars for that instead.
+    pub fn emit(&self) {
+        let has_logger = tracing::dispatcher::has_been_set();
+        match self {
+            Self::Message { text } => tracing::info!(target: OUTPUT_TARGET, "{text}"),
+            Self::Error { text, .. } => tracing::error!(target: OUTPUT_TARGET, "{text}"),
+            _ => (),
+        }
+        match output_format() {
+            OutputFormat::Text => match self {
+                Self::Progress { .. } => (),
+                Self::Message { .. } | Self::Error { .. } if has_logger => (),
+                Self::Message { text } => println!("{text}"),
+                Self::Error { text, .. } => eprintln!("error: {text}"),
+                // Tab separated columns: name, kind, version, installation status, custom flag.
+                Self::Tool {
+                    name,
+                    kind,
+                    version,
+                    installed,
+                    custom,
+                } => println!(
+                    "{name}\t{kind}\t{}\t{}\t{}",
+                    version.unwrap_or("-"),
+                    if *installed {
+                        "installed"
+                    } else {
+                        "not-installed"
+                    },
+                    if *custom { "custom" } else { "-" },
+                ),
+                Self::Check { desc, ok, fix } => {
+                    if *ok {
+                        println!("[PASS] {desc}");
+                    } else {
+                        println!("[FAIL] {desc}");
+                    }
+                    if let Some(fix) = fix {
+                        println!("       fix: {fix}");
+                    }
+                }
+                Self::Outdated {
+                    name,
+                    installed,
+                    latest,
+                } => println!(
+                    "  {name}: {} -> {}",
+                    installed.unwrap_or("unknown"),
+                    latest.unwrap_or("unknown")
+                ),
+                Self::Config { key, value } => println!("{key}\t{}", value.unwrap_or("-")),
+                Self::EnvVar { key, value } => println!("{key}={value}"),
+                Self::Preflight(report) => print_preflight_report(report),
+            },
+            OutputFormat::Json => match serde_json::to_string(self) {
+                Ok(json) => println!("{json}"),
+                Err(e) => eprintln!("error: unable to serialize output event: {e}"),
+            },
+        }
+    }
+}
+
+fn print_preflight_report(report: &PreflightReport) {
+    println!(
+        "system: {} {} ({})",
+        report.os,
+        report.os_version.as_deref().unwrap_or("unknown version"),
+        report.arch
+    );
+    println!(
+        "install directory: {} ({})",
+        report.install_dir.display(),
+        if report.writable {
+            "writable"
+        } else {
+            "not writable"
+        }
+    );
+    match report.free_space {
+        Some(bytes) => println!("free space: {}", HumanBytes(bytes)),
+        None => println!("free space: unknown"),
+    }
+    for (kind, tools) in [
+        ("existing rust", &report.existing_rust),
+        ("existing tool", &report.existing_tools),
+    ] {
+        for tool in tools {
+            let path = tool.path.as_deref().map(|p| p.display().to_string());
+            println!(
+                "{kind}: {} {} ({})",
+                tool.name,
+                tool.version.as_deref().unwrap_or("-"),
+                path.as_deref().unwrap_or("-")
+            );
+        }
+    }
+    match &report.proxy {
+        Some(proxy) => {
+            let servers = [&proxy.http, &proxy.https]
+                .into_iter()
+                .flatten()
+                .map(|url| url.as_str())
+                .collect::<Vec<_>>();
+            let source = match proxy.source {
+                ProxySource::Env => "environment variables",
+                ProxySource::System => "system settings",
+            };
+            println!("proxy: {} (from {source})", servers.join(", "));
+        }
+        None => println!("proxy: none"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OutputEvent;
+
+    #[test]
+    fn serialize_output_events() {
+        let progress = serde_json::to_string(&OutputEvent::Progress { pct: 42 }).unwrap();
+        assert_eq!(progress, r#"{"type":"progress","pct":42}"#);
+
+        let msg = serde_json::to_string(&OutputEvent::Message {
+            text: "installing 'ripgrep'",
+        })
+        .unwrap();
+        assert_eq!(msg, r#"{"type":"message","text":"installing 'ripgrep'"}"#);
+
+        let err = serde_json::to_string(&OutputEvent::Error {
+            text: "oops",
+            code: None,
+        })
+        .unwrap();
+        assert_eq!(err, r#"{"type":"error","text":"oops"}"#);
+
+        let err = serde_json::to_string(&OutputEvent::Error {
+            text: "oops",
+            code: Some("download-failed"),
+        })
+        .unwrap();
+        assert_eq!(
+            err,
+            r#"{"type":"error","text":"oops","code":"download-failed"}"#
+        );
+
+        let tool = serde_json::to_string(&OutputEvent::Tool {
+            name: "ripgrep",
+            kind: "crate",
+            version: None,
+            installed: true,
+            custom: false,
+        })
+        .unwrap();
+        assert_eq!(
+            tool,
+            r#"{"type":"tool","name":"ripgrep","kind":"crate","version":null,"installed":true,"custom":false}"#
+        );
+
+        let check = serde_json::to_string(&OutputEvent::Check {
+            desc: "`cargo --version` runs successfully",
+            ok: false,
+            fix: Some("reinstall"),
+        })
+        .unwrap();
+        assert_eq!(
+            check,
+            r#"{"type":"check","desc":"`cargo --version` runs successfully","ok":false,"fix":"reinstall"}"#
+        );
+    }
+}
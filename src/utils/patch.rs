@@ -0,0 +1,84 @@
+//! Applying binary patches (a.k.a. deltas) to reconstruct a new version of a large file from
+//! an old one, so that updating it only needs to download the difference between them.
+//!
+//! The patches are the ones created by `zstd --patch-from=<OLD> <NEW> -o <PATCH>`, which are
+//! `zstd` frames compressed using the old file as a reference prefix.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// The maximum window size (as a power of 2) allowed when decoding a patch,
+/// which has to cover the old file, since the patch may refer to any part of it.
+#[cfg(target_pointer_width = "64")]
+const WINDOW_LOG_MAX: u32 = 31;
+#[cfg(not(target_pointer_width = "64"))]
+const WINDOW_LOG_MAX: u32 = 30;
+
+/// Apply `patch` to the `old` file, writing the reconstructed file to `dest`.
+///
+/// The old file is read into memory entirely. Note that the result is not verified here,
+/// make sure to compare its checksum against the expected one.
+pub fn apply_patch(old: &Path, patch: &Path, dest: &Path) -> Result<()> {
+    let old_content =
+        fs::read(old).with_context(|| format!("unable to read '{}'", old.display()))?;
+    let patch_file =
+        File::open(patch).with_context(|| format!("unable to open patch '{}'", patch.display()))?;
+    let mut decoder =
+        zstd::stream::read::Decoder::with_ref_prefix(BufReader::new(patch_file), &old_content)?;
+    decoder.window_log_max(WINDOW_LOG_MAX)?;
+
+    super::ensure_parent_dir(dest)?;
+    let mut writer = BufWriter::new(
+        File::create(dest).with_context(|| format!("unable to create '{}'", dest.display()))?,
+    );
+    io::copy(&mut decoder, &mut writer)
+        .and_then(|_| writer.flush())
+        .with_context(|| {
+            format!(
+                "unable to apply patch '{}' to '{}'",
+                patch.display(),
+                old.display()
+            )
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::apply_patch;
+
+    #[test]
+    fn apply_zstd_patch() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = b"the quick brown fox jumps over the lazy dog\n".repeat(100);
+        let mut new = old.clone();
+        new.extend_from_slice(b"and then runs away\n");
+
+        let mut encoder =
+            zstd::stream::write::Encoder::with_ref_prefix(Vec::new(), 19, &old).unwrap();
+        encoder.write_all(&new).unwrap();
+        let patch = encoder.finish().unwrap();
+        // The patch only contains the difference.
+        assert!(patch.len() < 100);
+
+        let old_path = dir.path().join("old");
+        let patch_path = dir.path().join("patch.zst");
+        let dest = dir.path().join("new");
+        std::fs::write(&old_path, &old).unwrap();
+        std::fs::write(&patch_path, &patch).unwrap();
+        apply_patch(&old_path, &patch_path, &dest).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), new);
+
+        // A patch applied to another file cannot be decoded, or results in different content.
+        std::fs::write(&old_path, b"something else").unwrap();
+        assert!(
+            apply_patch(&old_path, &patch_path, &dest).is_err()
+                || std::fs::read(&dest).unwrap() != new
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Typed progress events of an installation, which are sent to a [`ProgressReporter`],
+//! so that the command line interface and the GUI could consume the same event stream.
+//!
+//! The command line interface renders them using [`TerminalReporter`] by default,
+//! while the GUI could [set](set_progress_reporter) a channel [`Sender`] to receive them instead.
+
+use std::sync::mpsc::Sender;
+use std::sync::RwLock;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::output::OutputEvent;
+
+static REPORTER: RwLock<Option<Box<dyn ProgressReporter>>> = RwLock::new(None);
+
+/// An event of the installation progress.
+///
+/// When serialized, each event will be a JSON object with a `type` field, i.e.:
+///
+/// ```json
+/// {"type":"phase-started","name":"install-rust"}
+/// {"type":"download-progress","name":"rustup","bytes":1024,"total":4096}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ProgressEvent {
+    /// A phase of the installation was started, such as `install-rust`.
+    PhaseStarted { name: String },
+    /// A message of what is going on in the current phase.
+    Message { text: String },
+    /// The overall progress reached `pct` percent.
+    Progress { pct: usize },
+    /// `bytes` of the file `name` were downloaded, `total` is `None` if the size is unknown.
+    DownloadProgress {
+        name: String,
+        bytes: u64,
+        total: Option<u64>,
+    },
+    /// The tool `name` was installed.
+    ToolInstalled { name: String },
+    /// Something went wrong, but the installation could go on.
+    Warning { text: String },
+    /// Every phase was done.
+    Finished,
+}
+
+/// Something that consumes the [`ProgressEvent`]s, such as a channel [`Sender`].
+pub trait ProgressReporter: Send + Sync {
+    fn report(&self, event: ProgressEvent) -> Result<()>;
+}
+
+impl ProgressReporter for Sender<ProgressEvent> {
+    fn report(&self, event: ProgressEvent) -> Result<()> {
+        self.send(event)?;
+        Ok(())
+    }
+}
+
+/// The default reporter, which prints the events to console as [`OutputEvent`]s.
+///
+/// Note that [`ProgressEvent::DownloadProgress`] is not printed,
+/// because the downloads draw their own progress bars in console.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalReporter;
+
+impl ProgressReporter for TerminalReporter {
+    fn report(&self, event: ProgressEvent) -> Result<()> {
+        match event {
+            ProgressEvent::PhaseStarted { name } => tracing::debug!("running '{name}'"),
+            ProgressEvent::Message { text } => OutputEvent::Message { text: &text }.emit(),
+            ProgressEvent::Progress { pct } => OutputEvent::Progress { pct }.emit(),
+            ProgressEvent::ToolInstalled { name } => tracing::debug!("'{name}' installed"),
+            ProgressEvent::Warning { text } => tracing::warn!("{text}"),
+            ProgressEvent::DownloadProgress { .. } | ProgressEvent::Finished => (),
+        }
+        Ok(())
+    }
+}
+
+/// Send every [`ProgressEvent`] to `reporter` instead of [`TerminalReporter`] from now on.
+pub fn set_progress_reporter<R: ProgressReporter + 'static>(reporter: R) {
+    *REPORTER.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(reporter));
+}
+
+/// Send every [`ProgressEvent`] to `reporter` until the returned guard is dropped,
+/// after which the previous reporter is restored.
+#[must_use = "the reporter is restored once the guard is dropped"]
+pub fn scoped_progress_reporter<R: ProgressReporter + 'static>(
+    reporter: R,
+) -> ProgressReporterGuard {
+    let previous = REPORTER
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .replace(Box::new(reporter));
+    ProgressReporterGuard { previous }
+}
+
+/// Restores the previous reporter when dropped, check [`scoped_progress_reporter`].
+pub struct ProgressReporterGuard {
+    previous: Option<Box<dyn ProgressReporter>>,
+}
+
+impl Drop for ProgressReporterGuard {
+    fn drop(&mut self) {
+        *REPORTER.write().unwrap_or_else(|e| e.into_inner()) = self.previous.take();
+    }
+}
+
+/// Report an event to the reporter set by [`set_progress_reporter`],
+/// or [`TerminalReporter`] if there isn't one.
+pub fn report_progress(event: ProgressEvent) -> Result<()> {
+    match &*REPORTER.read().unwrap_or_else(|e| e.into_inner()) {
+        Some(reporter) => reporter.report(event),
+        None => TerminalReporter.report(event),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Help to report the overall install progress across threads,
+/// where each step takes up [`val`](Self::val) percent.
+pub struct MultiThreadProgress {
+    pub val: usize,
+    cur_progress: usize,
+}
+
+impl MultiThreadProgress {
+    pub fn new(initial_progress: usize) -> Self {
+        Self {
+            cur_progress: initial_progress,
+            ..Default::default()
+        }
+    }
+    /// Report an event, check [`report_progress`].
+    pub fn report(&self, event: ProgressEvent) -> Result<()> {
+        report_progress(event)
+    }
+    pub fn send_progress(&mut self) -> Result<()> {
+        self.cur_progress = (self.cur_progress + self.val).min(100);
+        self.report(ProgressEvent::Progress {
+            pct: self.cur_progress,
+        })
+    }
+    pub fn send_any_progress(&mut self, prog: usize) -> Result<()> {
+        self.val = prog;
+        self.send_progress()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{report_progress, scoped_progress_reporter, ProgressEvent, REPORTER};
+
+    #[test]
+    fn scoped_reporter_is_restored() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        {
+            let _guard = scoped_progress_reporter(tx);
+            report_progress(ProgressEvent::Finished).unwrap();
+        }
+        assert!(REPORTER.read().unwrap().is_none());
+        assert!(rx.iter().any(|event| event == ProgressEvent::Finished));
+    }
+
+    #[test]
+    fn serde_progress_events() {
+        let phase = ProgressEvent::PhaseStarted {
+            name: "install-rust".into(),
+        };
+        assert_eq!(
+            serde_json::to_string(&phase).unwrap(),
+            r#"{"type":"phase-started","name":"install-rust"}"#
+        );
+        let download = ProgressEvent::DownloadProgress {
+            name: "rustup".into(),
+            bytes: 1024,
+            total: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&download).unwrap(),
+            r#"{"type":"download-progress","name":"rustup","bytes":1024,"total":null}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&ProgressEvent::Finished).unwrap(),
+            r#"{"type":"finished"}"#
+        );
+        let parsed: ProgressEvent = serde_json::from_str(
+            r#"{"type":"download-progress","name":"rustup","bytes":1024,"total":null}"#,
+        )
+        .unwrap();
+        assert_eq!(parsed, download);
+    }
+}
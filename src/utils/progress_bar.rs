@@ -1,50 +1,12 @@
 //! Progress bar indicator for commandline user interface.
 
-use std::sync::mpsc::Sender;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use indicatif::{ProgressBar, ProgressState, ProgressStyle};
-
-#[derive(Debug, Clone, Copy, Default)]
-/// Help to send install progress across threads.
-pub struct MultiThreadProgress<'a> {
-    msg_sender: Option<&'a Sender<String>>,
-    prog_sender: Option<&'a Sender<usize>>,
-    pub val: usize,
-    cur_progress: usize,
-}
+use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
 
-impl<'a> MultiThreadProgress<'a> {
-    pub fn new(
-        msg_sender: &'a Sender<String>,
-        progress_sender: &'a Sender<usize>,
-        initial_progress: usize,
-    ) -> Self {
-        Self {
-            msg_sender: Some(msg_sender),
-            prog_sender: Some(progress_sender),
-            cur_progress: initial_progress,
-            ..Default::default()
-        }
-    }
-    pub fn send_msg(&self, msg: String) -> Result<()> {
-        if let Some(sender) = self.msg_sender {
-            sender.send(msg)?;
-        }
-        Ok(())
-    }
-    pub fn send_progress(&mut self) -> Result<()> {
-        if let Some(sender) = self.prog_sender {
-            self.cur_progress = (self.cur_progress + self.val).min(100);
-            sender.send(self.cur_progress)?;
-        }
-        Ok(())
-    }
-    pub fn send_any_progress(&mut self, prog: usize) -> Result<()> {
-        self.val = prog;
-        self.send_progress()
-    }
-}
+use super::output::is_json_output;
 
 /// Convinent struct with methods that are useful to indicate download progress.
 #[derive(Debug, Clone, Copy)]
@@ -53,6 +15,8 @@ pub struct ProgressIndicator<T: Sized> {
     pub start: fn(u64, String, Style) -> Result<T>,
     /// A update function that will be called after each downloaded chunk.
     pub update: fn(&T, u64),
+    /// A function to display extra status, such as the transfer rate and ETA.
+    pub status: fn(&T, String),
     /// A function that will be called once after a successful download.
     pub stop: fn(&T, String),
 }
@@ -69,12 +33,18 @@ pub enum Style {
 impl Style {
     fn template_str(&self) -> &str {
         match self {
-            Style::Bytes => "{bytes}/{total_bytes}",
-            Style::Len => "{pos}/{len}",
+            Style::Bytes => "{bytes}/{total_bytes} {prefix}",
+            Style::Len => "{pos}/{len} ({eta})",
         }
     }
 }
 
+/// The container of all progress bars, so that multiple bars can be shown at the same time.
+fn multi_progress() -> &'static MultiProgress {
+    static MULTI_PROGRESS: OnceLock<MultiProgress> = OnceLock::new();
+    MULTI_PROGRESS.get_or_init(MultiProgress::new)
+}
+
 // TODO: Mark this with cfg(feature = "cli")
 impl ProgressIndicator<ProgressBar> {
     /// Create a new progress bar for CLI to indicate download progress.
@@ -83,10 +53,16 @@ impl ProgressIndicator<ProgressBar> {
     /// i.e.: ("downloading", "download"), ("extracting", "extraction"), etc.
     pub fn new() -> Self {
         fn start(total: u64, msg: String, style: Style) -> Result<ProgressBar> {
-            let pb = ProgressBar::new(total);
+            // Progress bars would mess up the JSON output, so hide them in that case.
+            let pb = if is_json_output() {
+                ProgressBar::hidden()
+            } else {
+                // Files might be downloaded concurrently, the bars need to be drawn together.
+                multi_progress().add(ProgressBar::new(total))
+            };
             pb.set_style(
                 ProgressStyle::with_template(
-                    &format!("{{msg}}\n{{spinner:.green}}] [{{elapsed_precise}}] [{{wide_bar:.cyan/blue}}] {} ({{eta}})", style.template_str())
+                    &format!("{{msg}}\n{{spinner:.green}}] [{{elapsed_precise}}] [{{wide_bar:.cyan/blue}}] {}", style.template_str())
                 )?
                 .with_key("eta", |state: &ProgressState, w: &mut dyn std::fmt::Write| {
                     write!(w, "{:.1}s", state.eta().as_secs_f64()).expect("unable to display progress bar")
@@ -99,6 +75,9 @@ impl ProgressIndicator<ProgressBar> {
         fn update(pb: &ProgressBar, pos: u64) {
             pb.set_position(pos);
         }
+        fn status(pb: &ProgressBar, status: String) {
+            pb.set_prefix(status);
+        }
         fn stop(pb: &ProgressBar, msg: String) {
             pb.finish_with_message(msg);
         }
@@ -106,7 +85,104 @@ impl ProgressIndicator<ProgressBar> {
         ProgressIndicator {
             start,
             update,
+            status,
             stop,
         }
     }
 }
+
+/// Estimating the transfer rate using exponential moving average,
+/// which is updated at a fixed interval to keep it stable.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TransferRate {
+    last_update: Instant,
+    last_pos: u64,
+    /// Estimated bytes per second, `None` if not enough data yet.
+    rate: Option<f64>,
+}
+
+impl TransferRate {
+    const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+    /// The weight of the latest sample.
+    const SMOOTHING: f64 = 0.3;
+
+    pub(crate) fn new(start_pos: u64, now: Instant) -> Self {
+        Self {
+            last_update: now,
+            last_pos: start_pos,
+            rate: None,
+        }
+    }
+
+    /// Feed the current position at `now`, return `true` if the rate was updated.
+    pub(crate) fn update(&mut self, pos: u64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_update);
+        if elapsed < Self::UPDATE_INTERVAL {
+            return false;
+        }
+        let sample = pos.saturating_sub(self.last_pos) as f64 / elapsed.as_secs_f64();
+        self.rate = Some(match self.rate {
+            Some(rate) => Self::SMOOTHING * sample + (1.0 - Self::SMOOTHING) * rate,
+            None => sample,
+        });
+        self.last_update = now;
+        self.last_pos = pos;
+        true
+    }
+
+    /// Get the estimated time to transfer the remaining bytes,
+    /// `None` if the rate is unknown or zero.
+    pub(crate) fn eta(&self, remaining: u64) -> Option<Duration> {
+        self.rate
+            .filter(|rate| *rate > 0.0)
+            .map(|rate| Duration::from_secs_f64(remaining as f64 / rate))
+    }
+
+    /// Format the rate and ETA as `(X MB/s, ETA mm:ss)`.
+    pub(crate) fn summary(&self, remaining: u64) -> String {
+        let rate = self.rate.map_or_else(
+            || "-- MB/s".to_string(),
+            |rate| format!("{:.2} MB/s", rate / (1024.0 * 1024.0)),
+        );
+        let eta = self.eta(remaining).map_or_else(
+            || "--:--".to_string(),
+            |eta| {
+                let secs = eta.as_secs();
+                format!("{:02}:{:02}", secs / 60, secs % 60)
+            },
+        );
+        format!("({rate}, ETA {eta})")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::TransferRate;
+
+    #[test]
+    fn transfer_rate_summary() {
+        let start = Instant::now();
+        let mut rate = TransferRate::new(0, start);
+        // No data yet, make sure it doesn't divide by zero.
+        assert!(!rate.update(0, start));
+        assert_eq!(rate.eta(100), None);
+        assert_eq!(rate.summary(100), "(-- MB/s, ETA --:--)");
+
+        // Not updated until the interval has passed.
+        assert!(!rate.update(1024 * 1024, start + Duration::from_millis(100)));
+        assert!(rate.update(2 * 1024 * 1024, start + Duration::from_secs(1)));
+        assert_eq!(rate.eta(120 * 1024 * 1024), Some(Duration::from_secs(60)));
+        assert_eq!(rate.summary(120 * 1024 * 1024), "(2.00 MB/s, ETA 01:00)");
+
+        // The latest sample (4 MB/s) is smoothed with the previous rate.
+        assert!(rate.update(6 * 1024 * 1024, start + Duration::from_secs(2)));
+        assert_eq!(rate.summary(0), "(2.60 MB/s, ETA 00:00)");
+
+        // Stalled transfer shouldn't produce infinite ETA.
+        let mut stalled = TransferRate::new(0, start);
+        assert!(stalled.update(0, start + Duration::from_secs(1)));
+        assert_eq!(stalled.eta(100), None);
+    }
+}
@@ -0,0 +1,248 @@
+//! The target triple of the host machine, which decides the set of tools to install.
+
+use std::sync::OnceLock;
+
+use anyhow::{bail, Result};
+
+/// Target triples that are known to be supported by this program.
+pub const SUPPORTED_TRIPLES: &[&str] = &[
+    "aarch64-apple-darwin",
+    "aarch64-pc-windows-msvc",
+    "aarch64-unknown-linux-gnu",
+    "i686-pc-windows-gnu",
+    "i686-pc-windows-msvc",
+    "x86_64-apple-darwin",
+    "x86_64-pc-windows-gnu",
+    "x86_64-pc-windows-msvc",
+    "x86_64-unknown-linux-gnu",
+];
+
+static HOST_TRIPLE: OnceLock<String> = OnceLock::new();
+
+/// Override the detected host triple, this can only be set once,
+/// any subsequent calls will be ignored.
+pub fn set_host_triple(triple: String) {
+    let _ = HOST_TRIPLE.set(triple);
+}
+
+static NATIVE_TRIPLE: OnceLock<String> = OnceLock::new();
+
+/// Get the host triple set by [`set_host_triple`],
+/// defaulting to the [native one](native_triple) of this machine.
+pub fn host_triple() -> &'static str {
+    HOST_TRIPLE
+        .get()
+        .map(|s| s.as_str())
+        .unwrap_or_else(native_triple)
+}
+
+/// Get the target triple of this machine, which is the one this program was built for,
+/// except that the CPU architecture is the native one of the machine.
+///
+/// These could be different when a `x86_64` build of this program is emulated on an `aarch64`
+/// machine, such as on Windows ARM64, or by Rosetta 2 on Apple silicon.
+pub fn native_triple() -> &'static str {
+    NATIVE_TRIPLE.get_or_init(|| {
+        let built = env!("TARGET");
+        let native = native_arch()
+            .map(|arch| triple_with_arch(built, arch))
+            .filter(|triple| SUPPORTED_TRIPLES.contains(&triple.as_str()));
+        native.unwrap_or_else(|| built.to_string())
+    })
+}
+
+/// Get the CPU architecture of this machine, return `None` if unknown.
+#[cfg(windows)]
+fn native_arch() -> Option<&'static str> {
+    use windows_sys::Win32::System::SystemInformation::{
+        IMAGE_FILE_MACHINE_AMD64, IMAGE_FILE_MACHINE_ARM64, IMAGE_FILE_MACHINE_I386,
+    };
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, IsWow64Process2};
+
+    let (mut process, mut native) = (0, 0);
+    // SAFETY: The pseudo handle of current process is always valid,
+    // and the machine types are only read if the call succeeded.
+    if unsafe { IsWow64Process2(GetCurrentProcess(), &mut process, &mut native) } == 0 {
+        return None;
+    }
+    match native {
+        IMAGE_FILE_MACHINE_AMD64 => Some("x86_64"),
+        IMAGE_FILE_MACHINE_ARM64 => Some("aarch64"),
+        IMAGE_FILE_MACHINE_I386 => Some("i686"),
+        _ => None,
+    }
+}
+
+/// Get the CPU architecture of this machine, return `None` if unknown.
+///
+/// A `x86_64` program translated by Rosetta 2 is running on an `aarch64` machine.
+#[cfg(target_os = "macos")]
+fn native_arch() -> Option<&'static str> {
+    let mut translated: libc::c_int = 0;
+    let mut size = std::mem::size_of::<libc::c_int>();
+    // SAFETY: The name is a nul-terminated string, and `translated` is large enough
+    // for the value of it, which is only read if the call succeeded.
+    let ret = unsafe {
+        libc::sysctlbyname(
+            b"sysctl.proc_translated\0".as_ptr().cast(),
+            &mut translated as *mut libc::c_int as *mut libc::c_void,
+            &mut size,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    // The name doesn't exist on Intel machines.
+    if ret != 0 {
+        return Some(triple_arch(env!("TARGET")));
+    }
+    Some(if translated == 1 {
+        "aarch64"
+    } else {
+        triple_arch(env!("TARGET"))
+    })
+}
+
+/// Get the CPU architecture of this machine, return `None` if unknown.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn native_arch() -> Option<&'static str> {
+    let mut name = std::mem::MaybeUninit::<libc::utsname>::uninit();
+    // SAFETY: `name` is only read after `uname` returned successfully, meaning it was initialized,
+    // and its `machine` field is a nul-terminated string.
+    let machine = unsafe {
+        if libc::uname(name.as_mut_ptr()) != 0 {
+            return None;
+        }
+        std::ffi::CStr::from_ptr(name.assume_init_ref().machine.as_ptr())
+    };
+    match machine.to_str().ok()? {
+        "x86_64" | "amd64" => Some("x86_64"),
+        "aarch64" | "arm64" => Some("aarch64"),
+        _ => None,
+    }
+}
+
+/// Get the host triple with another environment on Windows, such as `x86_64-pc-windows-gnu`
+/// for `gnu` on `x86_64-pc-windows-msvc`, which is the host triple to use for a flavor of
+/// toolchain.
+///
+/// The host triple is returned as is if it's not a Windows one,
+/// or it was already set by [`set_host_triple`].
+pub fn host_triple_with_env(env: &str) -> String {
+    let host = host_triple();
+    if HOST_TRIPLE.get().is_some() || triple_os(host) != "windows" {
+        return host.to_string();
+    }
+    triple_with_env(host, env)
+}
+
+/// Replace the CPU architecture of a target triple, such as `aarch64` of `aarch64-apple-darwin`.
+fn triple_with_arch(triple: &str, arch: &str) -> String {
+    match triple.split_once('-') {
+        Some((_, rest)) => format!("{arch}-{rest}"),
+        None => arch.to_string(),
+    }
+}
+
+/// Replace the environment of a target triple, such as `gnu` of `x86_64-pc-windows-gnu`.
+fn triple_with_env(triple: &str, env: &str) -> String {
+    let mut parts = triple.split('-').collect::<Vec<_>>();
+    match parts.get_mut(3) {
+        Some(part) => *part = env,
+        None => parts.push(env),
+    }
+    parts.join("-")
+}
+
+/// Get the CPU architecture of a target triple, such as `x86_64` of `x86_64-pc-windows-msvc`.
+pub fn triple_arch(triple: &str) -> &str {
+    triple.split('-').next().unwrap_or_default()
+}
+
+/// Get the operating system of a target triple, such as `windows`, `linux` or `macos`.
+pub fn triple_os(triple: &str) -> &str {
+    match triple.split('-').nth(2) {
+        Some("darwin") => "macos",
+        Some(os) => os,
+        None => "unknown",
+    }
+}
+
+/// Get the environment (C library or ABI) of a target triple, such as `gnu` or `msvc`,
+/// return `None` if it doesn't have one, such as `aarch64-apple-darwin`.
+pub fn triple_env(triple: &str) -> Option<&str> {
+    triple.split('-').nth(3)
+}
+
+/// Parse a target triple, making sure it's one of the [`SUPPORTED_TRIPLES`].
+pub fn parse_host_triple(s: &str) -> Result<String> {
+    let s = s.trim();
+    if !SUPPORTED_TRIPLES.contains(&s) {
+        bail!(
+            "unsupported target triple '{s}', expecting one of:\n    {}",
+            SUPPORTED_TRIPLES.join("\n    ")
+        );
+    }
+    Ok(s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_triples() {
+        assert_eq!(
+            parse_host_triple("x86_64-pc-windows-gnu").unwrap(),
+            "x86_64-pc-windows-gnu"
+        );
+        assert_eq!(
+            parse_host_triple(" aarch64-apple-darwin ").unwrap(),
+            "aarch64-apple-darwin"
+        );
+        let err = parse_host_triple("x86_64-pc-windows-mvsc").unwrap_err();
+        assert!(err.to_string().contains("x86_64-pc-windows-msvc"));
+    }
+
+    #[test]
+    fn parts_of_triples() {
+        assert_eq!(triple_arch("x86_64-pc-windows-msvc"), "x86_64");
+        assert_eq!(triple_os("x86_64-pc-windows-msvc"), "windows");
+        assert_eq!(triple_env("x86_64-pc-windows-msvc"), Some("msvc"));
+        assert_eq!(triple_os("aarch64-unknown-linux-gnu"), "linux");
+        assert_eq!(triple_env("aarch64-unknown-linux-gnu"), Some("gnu"));
+        assert_eq!(triple_arch("aarch64-apple-darwin"), "aarch64");
+        assert_eq!(triple_os("aarch64-apple-darwin"), "macos");
+        assert_eq!(triple_env("aarch64-apple-darwin"), None);
+    }
+
+    #[test]
+    fn replace_triple_arch() {
+        assert_eq!(
+            triple_with_arch("x86_64-pc-windows-msvc", "aarch64"),
+            "aarch64-pc-windows-msvc"
+        );
+        assert_eq!(
+            triple_with_arch("x86_64-apple-darwin", "aarch64"),
+            "aarch64-apple-darwin"
+        );
+    }
+
+    #[test]
+    fn native_triple_is_supported() {
+        let native = native_triple();
+        assert!(SUPPORTED_TRIPLES.contains(&native) || native == env!("TARGET"));
+        assert_eq!(triple_os(native), triple_os(env!("TARGET")));
+    }
+
+    #[test]
+    fn replace_triple_env() {
+        assert_eq!(
+            triple_with_env("x86_64-pc-windows-msvc", "gnu"),
+            "x86_64-pc-windows-gnu"
+        );
+        assert_eq!(
+            triple_with_env("aarch64-pc-windows-msvc", "msvc"),
+            "aarch64-pc-windows-msvc"
+        );
+    }
+}
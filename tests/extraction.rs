@@ -177,3 +177,167 @@ fn extracting_7z_with_prefix() {
     assert!(temp_dir.path().join("f3").join("bbb.md").is_file());
     assert!(temp_dir.path().join("f3").join("ccc").is_file());
 }
+
+#[test]
+fn extracting_large_generated_gz() {
+    use flate2::{write::GzEncoder, Compression};
+
+    const FILE_SIZE: usize = 16 * 1024 * 1024;
+
+    let src_dir = tempfile::tempdir().unwrap();
+    let tarball = src_dir.path().join("large.tar.gz");
+    {
+        let encoder = GzEncoder::new(
+            std::fs::File::create(&tarball).unwrap(),
+            Compression::fast(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        let content = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        for name in ["prefix/bin/aaa", "prefix/lib/bbb", "prefix/ccc"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(FILE_SIZE as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_slice())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    let dest = tempfile::tempdir().unwrap();
+    Extractable::try_from(tarball.as_path())
+        .unwrap()
+        .extract_to(dest.path())
+        .expect("failed to extract");
+
+    for file in ["bin/aaa", "lib/bbb", "ccc"] {
+        let extracted = std::fs::read(dest.path().join(file)).unwrap();
+        assert_eq!(extracted.len(), FILE_SIZE);
+        assert!(extracted
+            .iter()
+            .enumerate()
+            .all(|(i, b)| *b == (i % 251) as u8));
+    }
+}
+
+/// Compress the given files into a `zstd` compressed tarball at `dest`.
+fn create_tar_zst(dest: &std::path::Path, files: &[(&str, &[u8])]) {
+    let encoder = zstd::Encoder::new(std::fs::File::create(dest).unwrap(), 0).unwrap();
+    let mut builder = tar::Builder::new(encoder);
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, *content).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+#[test]
+fn extracting_tar_zst() {
+    let src_dir = tempfile::tempdir().unwrap();
+    let tarball = src_dir.path().join("tool.tar.zst");
+    create_tar_zst(
+        &tarball,
+        &[("prefix/aaa.txt", b"aaa"), ("prefix/f1/bbb.txt", b"bbb")],
+    );
+
+    let dest = tempfile::tempdir().unwrap();
+    Extractable::try_from(tarball.as_path())
+        .unwrap()
+        .extract_to(dest.path())
+        .expect("failed to extract");
+
+    assert_eq!(std::fs::read(dest.path().join("aaa.txt")).unwrap(), b"aaa");
+    assert_eq!(
+        std::fs::read(dest.path().join("f1").join("bbb.txt")).unwrap(),
+        b"bbb"
+    );
+}
+
+#[test]
+fn extracting_single_file_zst() {
+    let src_dir = tempfile::tempdir().unwrap();
+    let compressed = src_dir.path().join("tool.exe.zst");
+    let content = b"not really an executable".repeat(100);
+    std::fs::write(
+        &compressed,
+        zstd::encode_all(content.as_slice(), 0).unwrap(),
+    )
+    .unwrap();
+
+    let dest = tempfile::tempdir().unwrap();
+    Extractable::try_from(compressed.as_path())
+        .unwrap()
+        .extract_to(dest.path())
+        .expect("failed to extract");
+
+    assert_eq!(
+        std::fs::read(dest.path().join("tool.exe")).unwrap(),
+        content
+    );
+}
+
+#[test]
+fn extracting_archives_without_extension() {
+    let src_dir = tempfile::tempdir().unwrap();
+
+    let sevenz = src_dir.path().join("sevenz");
+    let mut data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    data_dir.push("tests");
+    data_dir.push("data");
+    std::fs::copy(data_dir.join("simple_7z.7z"), &sevenz).unwrap();
+    let dest = tempfile::tempdir().unwrap();
+    Extractable::try_from(sevenz.as_path())
+        .unwrap()
+        .extract_to(dest.path())
+        .expect("failed to extract");
+    assert!(dest.path().join("aaa.txt").is_file());
+
+    let tarball = src_dir.path().join("tarball");
+    create_tar_zst(&tarball, &[("aaa.txt", b"aaa"), ("bbb.txt", b"bbb")]);
+    let dest = tempfile::tempdir().unwrap();
+    Extractable::try_from(tarball.as_path())
+        .unwrap()
+        .extract_to(dest.path())
+        .expect("failed to extract");
+    assert!(dest.path().join("aaa.txt").is_file());
+    assert!(dest.path().join("bbb.txt").is_file());
+
+    let plain = src_dir.path().join("plain");
+    std::fs::write(&plain, "just some text").unwrap();
+    assert!(Extractable::try_from(plain.as_path()).is_err());
+}
+
+#[test]
+fn extracting_from_stream() {
+    use custom_rust::utils::ExtractableKind;
+
+    assert!(matches!(
+        ExtractableKind::streamable("foo.tar.gz"),
+        Some(ExtractableKind::Gz)
+    ));
+    assert!(matches!(
+        ExtractableKind::streamable("foo.tzst"),
+        Some(ExtractableKind::Zst)
+    ));
+    assert!(ExtractableKind::streamable("foo.zst").is_none());
+    assert!(ExtractableKind::streamable("foo.zip").is_none());
+    assert!(ExtractableKind::streamable("foo").is_none());
+
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests");
+    path.push("data");
+    path.push("xz_with_prefixes.tar.xz");
+    let temp_dir = tempfile::tempdir().unwrap();
+    ExtractableKind::Xz
+        .extract_stream(std::fs::File::open(&path).unwrap(), temp_dir.path())
+        .expect("failed to extract");
+
+    assert!(temp_dir.path().join("aaa.txt").is_file());
+    assert!(temp_dir.path().join("bbb.txt").is_file());
+    assert!(temp_dir.path().join("f1").join("aaa.txt").is_file());
+    assert!(temp_dir.path().join("f3").join("ccc").is_file());
+}
@@ -0,0 +1,23 @@
+// The host triple can only be overridden once per process, which affects every other test,
+// so it's tested in this separate binary instead of along with the other utilities.
+
+use custom_rust::manifest::baked_in_manifest;
+use custom_rust::utils;
+
+#[test]
+fn override_host_triple() {
+    utils::set_host_triple("x86_64-pc-windows-gnu".into());
+    assert_eq!(utils::host_triple(), "x86_64-pc-windows-gnu");
+
+    // Subsequent overrides are ignored, including the one of toolchain flavor.
+    utils::set_host_triple("aarch64-apple-darwin".into());
+    assert_eq!(utils::host_triple(), "x86_64-pc-windows-gnu");
+    assert_eq!(utils::host_triple_with_env("msvc"), "x86_64-pc-windows-gnu");
+
+    // Tools are selected for the overridden target instead of the native one.
+    let manifest = baked_in_manifest().unwrap();
+    assert_eq!(manifest.current_target(), "x86_64-pc-windows-gnu");
+    let tools = manifest.current_target_tools().unwrap();
+    assert!(tools.contains_key("mingw64"));
+    assert!(!tools.contains_key("buildtools"));
+}
@@ -41,3 +41,93 @@ fn walk_dir_shallow() {
         assert!(entries.contains(&exp));
     }
 }
+
+#[test]
+fn sha256_of_file() {
+    let mut file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    file.push("tests");
+    file.push("data");
+    file.push("dir_to_walk");
+    file.push("file_in_root");
+
+    // This is an empty file.
+    let checksum = utils::sha256_file(&file).unwrap();
+    assert_eq!(
+        checksum,
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_and_remove_without_following() {
+    let root = tempfile::tempdir().unwrap();
+    let src = root.path().join("tool");
+    let bin_dir = root.path().join("bin");
+    std::fs::write(&src, "content").unwrap();
+    std::fs::create_dir(&bin_dir).unwrap();
+
+    let link = utils::symlink_or_copy_file_to(&src, &bin_dir).unwrap();
+    assert_eq!(link, bin_dir.join("tool"));
+    assert!(utils::is_symlink(&link));
+    assert_eq!(std::fs::read_to_string(&link).unwrap(), "content");
+
+    // Copying onto the link replaces the link instead of writing to its target.
+    let other = root.path().join("other");
+    std::fs::write(&other, "other content").unwrap();
+    utils::copy_as(&other, &link).unwrap();
+    assert!(!utils::is_symlink(&link));
+    assert_eq!(std::fs::read_to_string(&src).unwrap(), "content");
+
+    let link = utils::symlink_or_copy_file_to(&src, &bin_dir).unwrap();
+    utils::remove(&link).unwrap();
+    assert!(!utils::is_symlink(&link));
+    assert!(src.is_file());
+}
+
+#[cfg(unix)]
+#[test]
+fn spawn_with_non_utf8_env_var() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let root = tempfile::tempdir().unwrap();
+    let non_utf8_dir = root.path().join(OsStr::from_bytes(b"install_\xff_dir"));
+    std::fs::create_dir(&non_utf8_dir).unwrap();
+    let output = root.path().join("output");
+
+    utils::execute_program_with_env(
+        "sh",
+        &[
+            OsStr::new("-c"),
+            OsStr::new("printf %s \"$CARGO_HOME\" > \"$1\""),
+            OsStr::new("sh"),
+            output.as_os_str(),
+        ],
+        [("CARGO_HOME", non_utf8_dir.as_os_str())],
+    )
+    .unwrap();
+
+    let written = std::fs::read(&output).unwrap();
+    assert_eq!(written, non_utf8_dir.as_os_str().as_bytes());
+}
+
+#[test]
+fn verify_minisign_signature() {
+    // The test vector of `minisign-verify`, signing the content `test`.
+    const PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==";
+
+    let root = tempfile::tempdir().unwrap();
+    let file = root.path().join("test");
+    let sig = root.path().join("test.minisig");
+    std::fs::write(&file, "test").unwrap();
+    std::fs::write(&sig, SIGNATURE).unwrap();
+    utils::verify_minisign(&file, &sig, PUBLIC_KEY).unwrap();
+
+    std::fs::write(&file, "Test").unwrap();
+    assert!(utils::verify_minisign(&file, &sig, PUBLIC_KEY).is_err());
+}